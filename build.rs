@@ -1,15 +1,4 @@
 fn main() {
-    {
-        use std::env;
-
-        let sqlite = env::var("CARGO_FEATURE_SQLITE").is_ok();
-        let mysql = env::var("CARGO_FEATURE_MYSQL").is_ok();
-        let postgres = env::var("CARGO_FEATURE_POSTGRES").is_ok();
-
-        if (sqlite as i32 + mysql as i32 + postgres as i32) > 1 {
-            panic!("Features 'sqlite', 'mysql' and 'postgres' cannot be enabled together.");
-        }
-    }
     #[cfg(feature = "industry-camera")]
     {
         use std::env;
@@ -1,7 +1,13 @@
 use directories::ProjectDirs;
-use serde::{Deserialize, Serialize};
-use std::fs::File;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+
+#[cfg(feature = "serialport")]
+use crate::service::serialport::SerialPort;
+
+mod secret;
+pub use secret::{SecretError, config_key, decrypt_value, encrypt_value, generate_key};
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct DatabaseConfig {
     pub url: String,
@@ -25,6 +31,36 @@ pub struct Sys {
     pub rtc_i2c_dev: String,
     #[serde(default)]
     pub rtc_i2c_addr: u16,
+    /// UTC offset in minutes for serialized timestamps, e.g. 480 for UTC+8.
+    /// Unset uses the device's local timezone.
+    #[serde(default)]
+    pub tz_offset_minutes: Option<i32>,
+    /// Time sources tried in order at startup: `rtc`, `ntp`, `system`.
+    /// Empty derives the order from `sync_time_from_rtc`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub time_source_priority: Vec<crate::utils::time_source::TimeSource>,
+}
+
+impl Sys {
+    pub fn tz_offset(&self) -> Option<chrono::FixedOffset> {
+        self.tz_offset_minutes
+            .and_then(|minutes| chrono::FixedOffset::east_opt(minutes.checked_mul(60)?))
+    }
+
+    /// Configured time source order, or RTC (if `sync_time_from_rtc`), NTP, then the system clock
+    pub fn time_source_priority(&self) -> Vec<crate::utils::time_source::TimeSource> {
+        use crate::utils::time_source::TimeSource;
+
+        if !self.time_source_priority.is_empty() {
+            return self.time_source_priority.clone();
+        }
+        let mut priority = Vec::new();
+        if self.sync_time_from_rtc {
+            priority.push(TimeSource::Rtc);
+        }
+        priority.extend([TimeSource::Ntp, TimeSource::SystemOnly]);
+        priority
+    }
 }
 
 impl Default for Sys {
@@ -34,6 +70,8 @@ impl Default for Sys {
             sync_time_from_rtc: false,
             rtc_i2c_dev: "/dev/i2c-1".to_string(),
             rtc_i2c_addr: 0x68,
+            tz_offset_minutes: None,
+            time_source_priority: Vec::new(),
         }
     }
 }
@@ -57,8 +95,14 @@ pub struct ServerConfig {
     pub mqtt: Vec<crate::service::mqtt::MqttConfig>,
     #[serde(default)]
     pub sys: Sys,
+    #[serde(default)]
+    pub logging: crate::utils::logging::LogConfig,
     #[cfg(feature = "socket")]
     pub socket: Vec<crate::service::socket::SocketConfig>,
+    /// Top-level sections not known to lean-link, for application defined services.
+    /// Read them with [`ServerConfig::section`].
+    #[serde(flatten, default)]
+    pub extensions: BTreeMap<String, serde_yaml_bw::Value>,
 }
 
 impl Default for ServerConfig {
@@ -80,12 +124,87 @@ impl Default for ServerConfig {
             #[cfg(feature = "mqtt")]
             mqtt: vec![],
             sys: Sys::default(),
+            logging: crate::utils::logging::LogConfig::default(),
             #[cfg(feature = "socket")]
             socket: vec![],
+            extensions: BTreeMap::new(),
         }
     }
 }
 
+impl ServerConfig {
+    /// Deserialize the application section `name`, `None` if it is missing or invalid.
+    pub fn section<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let value = self.extensions.get(name)?.clone();
+        serde_yaml_bw::from_value(value)
+            .inspect_err(|e| tracing::warn!("Invalid config section {}: {}", name, e))
+            .ok()
+    }
+
+    /// One service per configured modbus device, labelled `host:port/slave` (TCP) or
+    /// `path/slave` (RTU). A gateway or bus without `devices` gives one service for the
+    /// default unit id, `255` on TCP and `1` on RTU. Nothing is connected yet.
+    #[cfg(feature = "modbus")]
+    pub fn modbus_services(&self) -> Vec<(String, crate::service::modbus::ModbusService)> {
+        let mut services = Vec::new();
+        for tcp in &self.modbus_tcp {
+            for slave in device_slaves(&tcp.devices, 0xFF) {
+                let label = format!("{}:{}/{}", tcp.host, tcp.port, slave);
+                services.push((label, tcp.builder(slave).build()));
+            }
+        }
+        for rtu in &self.modbus_rtu {
+            for slave in device_slaves(&rtu.devices, 1) {
+                services.push((format!("{}/{}", rtu.path, slave), rtu.builder(slave).build()));
+            }
+        }
+        services
+    }
+
+    /// One unopened port per `serialport` entry, labelled by its path or USB filter
+    #[cfg(feature = "serialport")]
+    pub fn serial_ports<T, C>(&self) -> Vec<(String, SerialPort<T, C>)> {
+        use crate::service::serialport::SerialPortBuilder;
+
+        self.serialport
+            .iter()
+            .map(|config| {
+                let label = match config.usb_filter {
+                    Some(filter) if config.path.is_empty() => {
+                        format!("usb vid={:04x?} pid={:04x?}", filter.vid, filter.pid)
+                    }
+                    _ => config.path.clone(),
+                };
+                (label, SerialPortBuilder::new_with_config(config).build())
+            })
+            .collect()
+    }
+
+    /// Client and event loop per `mqtt` entry, labelled by client id. Pass them to
+    /// [`crate::service::mqtt::MqttService::start`], the broker is contacted on the first poll.
+    /// `dedup` is only applied by [`crate::service::mqtt::MqttService::from_config`].
+    #[cfg(feature = "mqtt")]
+    pub fn mqtt_clients(&self) -> Vec<(String, (rumqttc::AsyncClient, rumqttc::EventLoop))> {
+        use crate::service::mqtt::client::ClientBuilder;
+
+        self.mqtt
+            .iter()
+            .map(|config| {
+                let client = ClientBuilder::new("", 0).with_config(config).build();
+                (config.client_id.clone(), client)
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "modbus")]
+fn device_slaves(devices: &[crate::service::modbus::ModbusDeviceConfig], default: u8) -> Vec<u8> {
+    if devices.is_empty() {
+        return vec![default];
+    }
+    devices.iter().map(|device| device.slave).collect()
+}
+
 /// Get the cross-platform configuration file path
 pub fn get_config_path(app_name: &str) -> Option<PathBuf> {
     // Differentiate operating systems
@@ -112,15 +231,27 @@ pub fn load_config(app_name: &str) -> std::io::Result<ServerConfig> {
 
     let normalized_path = normpath::PathExt::normalize(config_path.as_path())?;
 
-    // 打开文件并解析
-    let file = File::open(normalized_path.as_path())?;
-    let config: ServerConfig = serde_yaml_bw::from_reader(file).map_err(|e| {
+    // 读取文件并解析
+    let text = std::fs::read_to_string(normalized_path.as_path())?;
+    parse_config(&text)
+}
+
+/// Parse a config file, decrypting `enc:` values (see [`encrypt_value`])
+pub fn parse_config(text: &str) -> std::io::Result<ServerConfig> {
+    let parse_error = |e: serde_yaml_bw::Error| {
         std::io::Error::new(
             std::io::ErrorKind::InvalidData,
             format!("Failed to parse config: {}", e),
         )
-    })?;
-    Ok(config)
+    };
+    if !text.contains(secret::ENC_PREFIX) {
+        return serde_yaml_bw::from_str(text).map_err(parse_error);
+    }
+
+    let mut value: serde_yaml_bw::Value = serde_yaml_bw::from_str(text).map_err(parse_error)?;
+    secret::decrypt_secrets(&mut value)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    serde_yaml_bw::from_value(value).map_err(parse_error)
 }
 
 #[cfg(test)]
@@ -179,6 +310,85 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_custom_section() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Printer {
+            host: String,
+            port: u16,
+        }
+
+        let mut value = serde_yaml_bw::to_value(ServerConfig::default()).unwrap();
+        let printer: serde_yaml_bw::Value =
+            serde_yaml_bw::from_str("host: 192.168.1.20\nport: 9100").unwrap();
+        value
+            .as_mapping_mut()
+            .unwrap()
+            .insert("printer".into(), printer);
+        let config: ServerConfig = serde_yaml_bw::from_value(value).unwrap();
+
+        assert_eq!(
+            config.section::<Printer>("printer"),
+            Some(Printer {
+                host: "192.168.1.20".into(),
+                port: 9100,
+            })
+        );
+        assert!(config.section::<Printer>("scanner").is_none());
+        assert!(config.section::<u32>("printer").is_none());
+        assert!(!config.extensions.contains_key("database"));
+    }
+
+    #[cfg(feature = "modbus")]
+    #[test]
+    fn test_modbus_services() {
+        let mut value = serde_yaml_bw::to_value(ServerConfig::default()).unwrap();
+        let sections: serde_yaml_bw::Value = serde_yaml_bw::from_str(
+            r#"
+modbus_tcp:
+  - host: 192.168.1.10
+    port: 502
+    devices:
+      - slave: 1
+      - slave: 2
+        timeout: 500ms
+  - host: 192.168.1.11
+    port: 1502
+modbus_rtu:
+  - path: /dev/ttyUSB0
+    baud_rate: 9600
+    data_bits: Eight
+    stop_bits: One
+    parity: None
+    flow_control: None
+    timeout: 1s
+    devices:
+      - slave: 3
+"#,
+        )
+        .unwrap();
+        let mapping = value.as_mapping_mut().unwrap();
+        for (key, section) in sections.as_mapping().unwrap() {
+            mapping.insert(key.clone(), section.clone());
+        }
+        let config: ServerConfig = serde_yaml_bw::from_value(value).unwrap();
+
+        let labels: Vec<String> = config
+            .modbus_services()
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                "192.168.1.10:502/1",
+                "192.168.1.10:502/2",
+                "192.168.1.11:1502/255",
+                "/dev/ttyUSB0/3",
+            ]
+        );
+    }
+
     #[test]
     #[ignore] // This test requires a real config file
     fn test_load_config() {
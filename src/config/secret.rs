@@ -0,0 +1,188 @@
+// Encrypted values in the config file, written as `enc:<base64>`.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde_yaml_bw::Value;
+
+/// Prefix of encrypted config values
+pub const ENC_PREFIX: &str = "enc:";
+
+/// Base64 of the 32-byte key for encrypted values, see [`generate_key`]
+pub const CONFIG_KEY_ENV: &str = "LEAN_LINK_CONFIG_KEY";
+
+/// Path of a file holding the base64 key, read when [`CONFIG_KEY_ENV`] is unset
+pub const CONFIG_KEY_FILE_ENV: &str = "LEAN_LINK_CONFIG_KEY_FILE";
+
+const NONCE_LEN: usize = 12;
+
+const KEY_LEN: usize = 32;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecretError {
+    #[error("No key for encrypted config values, set {CONFIG_KEY_ENV} or {CONFIG_KEY_FILE_ENV}")]
+    MissingKey,
+    #[error("Failed to read config key file: {0}")]
+    KeyFile(#[from] std::io::Error),
+    /// Not the base64 of 32 bytes, passphrases are rejected
+    #[error("Config key must be the base64 of {KEY_LEN} random bytes")]
+    InvalidKey,
+    #[error("Malformed encrypted config value")]
+    Malformed,
+    /// Wrong key or tampered value
+    #[error("Failed to decrypt config value")]
+    Decrypt,
+}
+
+fn cipher(key: &str) -> Result<Aes256Gcm, SecretError> {
+    // 密钥直接使用随机字节，不从口令派生，避免被离线暴力破解
+    let key = STANDARD
+        .decode(key.trim())
+        .map_err(|_| SecretError::InvalidKey)?;
+    if key.len() != KEY_LEN {
+        return Err(SecretError::InvalidKey);
+    }
+    Ok(Aes256Gcm::new_from_slice(&key).expect("32-byte AES-256 key"))
+}
+
+/// New random key for [`encrypt_value`], as the base64 expected in `LEAN_LINK_CONFIG_KEY`
+pub fn generate_key() -> String {
+    STANDARD.encode(Aes256Gcm::generate_key(&mut OsRng))
+}
+
+/// Encrypt `plaintext` into an `enc:` value to paste in the config file.
+///
+/// `key` is the base64 key later given through `LEAN_LINK_CONFIG_KEY` or the key file,
+/// surrounding whitespace is ignored.
+pub fn encrypt_value(plaintext: &str, key: &str) -> Result<String, SecretError> {
+    let cipher = cipher(key)?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .expect("AES-GCM encryption of a config value");
+    let mut data = nonce.to_vec();
+    data.extend_from_slice(&ciphertext);
+    Ok(format!("{}{}", ENC_PREFIX, STANDARD.encode(data)))
+}
+
+/// Decrypt a value produced by [`encrypt_value`], values without the `enc:` prefix are
+/// returned unchanged.
+pub fn decrypt_value(value: &str, key: &str) -> Result<String, SecretError> {
+    let Some(encoded) = value.strip_prefix(ENC_PREFIX) else {
+        return Ok(value.to_string());
+    };
+    let data = STANDARD
+        .decode(encoded.trim())
+        .map_err(|_| SecretError::Malformed)?;
+    if data.len() < NONCE_LEN {
+        return Err(SecretError::Malformed);
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let plaintext = cipher(key)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| SecretError::Decrypt)?;
+    String::from_utf8(plaintext).map_err(|_| SecretError::Malformed)
+}
+
+/// Key from `LEAN_LINK_CONFIG_KEY`, or the file at `LEAN_LINK_CONFIG_KEY_FILE`
+pub fn config_key() -> Result<Option<String>, SecretError> {
+    if let Ok(key) = std::env::var(CONFIG_KEY_ENV) {
+        return Ok(Some(key));
+    }
+    match std::env::var(CONFIG_KEY_FILE_ENV) {
+        Ok(path) => Ok(Some(std::fs::read_to_string(path)?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Replace every `enc:` string in `value` by its plaintext. The key is only looked up
+/// when an encrypted value is found.
+pub(crate) fn decrypt_secrets(value: &mut Value) -> Result<(), SecretError> {
+    let mut key = None;
+    decrypt_in(value, &mut key)
+}
+
+fn decrypt_in(value: &mut Value, key: &mut Option<String>) -> Result<(), SecretError> {
+    match value {
+        Value::String(text, _) if text.starts_with(ENC_PREFIX) => {
+            if key.is_none() {
+                *key = Some(config_key()?.ok_or(SecretError::MissingKey)?);
+            }
+            *text = decrypt_value(text, key.as_deref().unwrap_or_default())?;
+        }
+        Value::Sequence(sequence) => {
+            for element in sequence.elements.iter_mut() {
+                decrypt_in(element, key)?;
+            }
+        }
+        Value::Mapping(mapping) => {
+            for element in mapping.values_mut() {
+                decrypt_in(element, key)?;
+            }
+        }
+        Value::Tagged(tagged) => decrypt_in(&mut tagged.value, key)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let key = generate_key();
+        let encrypted = encrypt_value("s3cret-pa55", &format!("{key}\n")).unwrap();
+        assert!(encrypted.starts_with(ENC_PREFIX));
+        // 每次加密使用新的 nonce
+        assert_ne!(encrypted, encrypt_value("s3cret-pa55", &key).unwrap());
+
+        assert_eq!(decrypt_value(&encrypted, &key).unwrap(), "s3cret-pa55");
+        assert!(matches!(
+            decrypt_value(&encrypted, &generate_key()),
+            Err(SecretError::Decrypt)
+        ));
+        assert!(matches!(
+            decrypt_value("enc:not base64!", &key),
+            Err(SecretError::Malformed)
+        ));
+        assert_eq!(decrypt_value("plain", &key).unwrap(), "plain");
+    }
+
+    #[test]
+    fn test_reject_weak_key() {
+        // 口令和长度不对的密钥都被拒绝
+        let short = STANDARD.encode([7u8; 16]);
+        for key in ["device key", "", short.as_str()] {
+            assert!(matches!(encrypt_value("x", key), Err(SecretError::InvalidKey)));
+        }
+        let encrypted = encrypt_value("x", &generate_key()).unwrap();
+        assert!(matches!(
+            decrypt_value(&encrypted, "device key"),
+            Err(SecretError::InvalidKey)
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_config_tree() {
+        let k = generate_key();
+        let yaml = format!(
+            "database:\n  url: {}\nmqtt:\n  - password: plain\n    token: {}\n",
+            encrypt_value("postgres://app:pw@db/app", &k).unwrap(),
+            encrypt_value("t0ken", &k).unwrap(),
+        );
+        let mut value: Value = serde_yaml_bw::from_str(&yaml).unwrap();
+        let mut key = Some(k);
+        decrypt_in(&mut value, &mut key).unwrap();
+
+        assert_eq!(value["database"]["url"].as_str(), Some("postgres://app:pw@db/app"));
+        assert_eq!(value["mqtt"][0]["password"].as_str(), Some("plain"));
+        assert_eq!(value["mqtt"][0]["token"].as_str(), Some("t0ken"));
+
+        // 没有加密值时不需要密钥
+        let mut plain: Value = serde_yaml_bw::from_str("database:\n  url: sqlite://a.db").unwrap();
+        decrypt_in(&mut plain, &mut None).unwrap();
+        assert_eq!(plain["database"]["url"].as_str(), Some("sqlite://a.db"));
+    }
+}
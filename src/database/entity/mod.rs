@@ -4,6 +4,8 @@ pub mod prelude;
 pub mod t_logs;
 pub mod t_settings;
 pub mod t_users;
+#[cfg(feature = "web")]
+pub mod t_revoked_tokens;
 #[cfg(feature = "inspection")]
 pub mod t_inspection_records;
 #[cfg(feature = "inspection")]
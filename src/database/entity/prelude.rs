@@ -1,6 +1,8 @@
 pub use super::t_users::Entity as TUsers;
 pub use super::t_settings::Entity as TSettings;
 pub use super::t_logs::Entity as TLogs;
+#[cfg(feature = "web")]
+pub use super::t_revoked_tokens::Entity as TRevokedTokens;
 
 #[cfg(feature = "inspection")]
 pub use super::t_defect_details::Entity as TDefectDetails;
@@ -301,7 +301,7 @@ impl ActiveModelBehavior for ActiveModel {
     fn new() -> Self {
         let now = DateTimeWithTimeZone::from(Local::now());
         Self {
-            id: Set(Uuid::now_v7()),
+            id: Set(crate::new_id()),
             trigger_mode: Set(TriggerMode::default()),
             is_enabled: Set(true),
             confidence_threshold: Set(0.5),
@@ -101,7 +101,7 @@ pub enum Relation {}
 impl ActiveModelBehavior for ActiveModel {
     fn new() -> Self {
         Self {
-            id: Set(uuid::Uuid::now_v7()),
+            id: Set(crate::new_id()),
             ..ActiveModelTrait::default()
         }
     }
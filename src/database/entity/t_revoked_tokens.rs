@@ -0,0 +1,21 @@
+use crate::utils::datetime::to_local_time;
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// JWT ids revoked by logout, kept until the token would have expired anyway.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Deserialize, Serialize)]
+#[sea_orm(table_name = "t_revoked_tokens")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub jti: uuid::Uuid,
+    #[serde(serialize_with = "to_local_time")]
+    pub expires_at: DateTimeWithTimeZone,
+    #[serde(serialize_with = "to_local_time")]
+    pub created_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -7,7 +7,7 @@ use serde::{Deserialize, Serialize};
 #[sea_orm(table_name = "t_settings")]
 #[serde(rename_all = "camelCase")]
 pub struct Model {
-    #[sea_orm(primary_key, auto_increment = false, default = "Uuid::now_v7()")]
+    #[sea_orm(primary_key, auto_increment = false, default = "crate::new_id()")]
     pub id: uuid::Uuid,
     #[sea_orm(unique)]
     pub key: String,
@@ -27,7 +27,7 @@ pub enum Relation {}
 impl ActiveModelBehavior for ActiveModel {
     fn new() -> Self {
         Self {
-            id: Set(uuid::Uuid::now_v7()),
+            id: Set(crate::new_id()),
             created_at: Set(DateTimeWithTimeZone::from(Local::now().fixed_offset())),
             updated_at: Set(DateTimeWithTimeZone::from(Local::now().fixed_offset())),
             ..ActiveModelTrait::default()
@@ -40,7 +40,7 @@ impl ActiveModelBehavior for ActiveModel {
     {
         tracing::info!("before save");
         if insert {
-            self.id = Set(uuid::Uuid::now_v7());
+            self.id = Set(crate::new_id());
             self.created_at = Set(DateTimeWithTimeZone::from(Local::now()));
             self.updated_at = Set(DateTimeWithTimeZone::from(Local::now()));
         }
@@ -146,7 +146,7 @@ impl ActiveModelBehavior for ActiveModel {
     fn new() -> Self {
         let now = DateTimeWithTimeZone::from(Local::now());
         Self {
-            id: Set(Uuid::now_v7()),
+            id: Set(crate::new_id()),
             station_id: Set(Uuid::nil()),
             purpose: Set(RoiPurpose::Detection),
             enabled: Set(true),
@@ -12,6 +12,12 @@ pub struct Model {
     #[sea_orm(unique_index)]
     pub username: String,
     pub password: String,
+    /// Set for the seeded admin until its default password is changed
+    pub must_change_password: bool,
+    /// Consecutive bad passwords since the last successful login or lockout
+    pub failed_attempts: i32,
+    #[serde(serialize_with = "to_local_time_option")]
+    pub locked_until: Option<DateTimeWithTimeZone>,
     #[serde(serialize_with = "to_local_time")]
     pub created_at: DateTimeWithTimeZone,
     #[serde(serialize_with = "to_local_time")]
@@ -26,7 +32,7 @@ pub enum Relation {}
 impl ActiveModelBehavior for ActiveModel {
     fn new() -> Self {
         Self {
-            id: Set(uuid::Uuid::now_v7()),
+            id: Set(crate::new_id()),
             created_at: Set(Local::now().fixed_offset()),
             updated_at: Set(Local::now().fixed_offset()),
             ..ActiveModelTrait::default()
@@ -5,8 +5,9 @@ use crate::database::entity::{
 };
 use chrono::Local;
 use sea_orm::{
-    ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, InsertResult, PaginatorTrait,
-    QueryFilter, QueryOrder, UpdateResult, prelude::{DateTimeWithTimeZone, Json},
+    ActiveValue, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, InsertResult,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, TransactionTrait, UpdateResult,
+    prelude::{DateTimeWithTimeZone, Json},
 };
 use uuid::Uuid;
 
@@ -23,7 +24,7 @@ pub async fn insert_log(
         ActiveValue::set(Some(user_id))
     };
     TLogs::insert(t_logs::ActiveModel {
-        id: ActiveValue::set(Uuid::now_v7()),
+        id: ActiveValue::set(crate::new_id()),
         user_id,
         action: ActiveValue::set(action),
         details: ActiveValue::set(details),
@@ -36,6 +37,44 @@ pub async fn insert_log(
     .await
 }
 
+/// Rows per INSERT statement of [`insert_logs_batch`], 8 bind parameters each
+pub const INSERT_CHUNK_ROWS: usize = 500;
+
+/// Insert many `Info` logs in one transaction, `(user_id, action, details)` per entry.
+///
+/// Meant for high-frequency sources such as a serial collector: instead of calling
+/// [`insert_log`] per frame, push entries into a buffer (e.g. from an mpsc channel) and
+/// flush it when it reaches N entries or every T, whichever comes first. Rows are written
+/// with multi-row INSERTs of [`INSERT_CHUNK_ROWS`] each, so any N stays under the bind
+/// parameter limit of the database; either all entries are stored or none.
+pub async fn insert_logs_batch(
+    conn: &DatabaseConnection,
+    entries: &[(Option<Uuid>, String, Json)],
+) -> Result<(), DbErr> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let now = DateTimeWithTimeZone::from(Local::now());
+    let txn = conn.begin().await?;
+    for chunk in entries.chunks(INSERT_CHUNK_ROWS) {
+        let models = chunk
+            .iter()
+            .map(|(user_id, action, details)| t_logs::ActiveModel {
+                id: ActiveValue::set(crate::new_id()),
+                user_id: ActiveValue::set(user_id.filter(|id| !id.is_nil())),
+                action: ActiveValue::set(action.clone()),
+                details: ActiveValue::set(details.clone()),
+                level: ActiveValue::set(LogLevel::Info),
+                created_at: ActiveValue::set(now),
+                updated_at: ActiveValue::set(now),
+                deleted_at: ActiveValue::not_set(),
+            });
+        TLogs::insert_many(models).exec(&txn).await?;
+    }
+    txn.commit().await
+}
+
 pub async fn clear_all_logs(conn: &DatabaseConnection) -> Result<UpdateResult, DbErr> {
     TLogs::update_many()
         .set(t_logs::ActiveModel {
@@ -47,6 +86,10 @@ pub async fn clear_all_logs(conn: &DatabaseConnection) -> Result<UpdateResult, D
         .await
 }
 
+/// Page of non-deleted logs, newest first, with total counts for page-number UIs.
+///
+/// Each page costs an `OFFSET` scan plus a `COUNT`, so deep pages get slow on large
+/// tables; use [`page_logs_after`] for infinite scroll or exports.
 pub async fn page_logs(
     conn: &DatabaseConnection,
     mut page_index: u64,
@@ -91,3 +134,181 @@ pub async fn page_logs(
         Err(e) => Err(e),
     }
 }
+
+/// Position after the last log of a [`page_logs_after`] page: its `created_at` and id
+pub type LogCursor = (DateTimeWithTimeZone, Uuid);
+
+/// Keyset page of non-deleted logs, newest first, starting after `cursor` (`None` for the
+/// first page). Returns the logs and the cursor of the next page, `None` on the last page.
+///
+/// Unlike [`page_logs`] there is no `OFFSET` or `COUNT`, so every page costs the same and
+/// logs inserted while paging do not shift or duplicate the following pages.
+pub async fn page_logs_after(
+    conn: &DatabaseConnection,
+    cursor: Option<LogCursor>,
+    mut limit: u64,
+) -> Result<(Vec<t_logs::Model>, Option<LogCursor>), DbErr> {
+    if limit == 0 {
+        limit = 10;
+    }
+
+    let mut query = TLogs::find().filter(t_logs::Column::DeletedAt.is_null());
+    if let Some((created_at, id)) = cursor {
+        // created_at 相同时按 id 继续，批量插入的日志时间戳相同
+        query = query.filter(
+            Condition::any()
+                .add(t_logs::Column::CreatedAt.lt(created_at))
+                .add(
+                    Condition::all()
+                        .add(t_logs::Column::CreatedAt.eq(created_at))
+                        .add(t_logs::Column::Id.lt(id)),
+                ),
+        );
+    }
+
+    let records = query
+        .order_by_desc(t_logs::Column::CreatedAt)
+        .order_by_desc(t_logs::Column::Id)
+        .limit(limit)
+        .all(conn)
+        .await?;
+    let next_cursor = (records.len() as u64 == limit)
+        .then(|| records.last().map(|log| (log.created_at, log.id)))
+        .flatten();
+    Ok((records, next_cursor))
+}
+
+/// One batch of non-deleted logs created within `[start_time, end_time]`, oldest first.
+///
+/// Pass the `created_at` and id of the last log of the previous batch as `after` to walk
+/// the whole table without OFFSET scans. Ids alone are not time ordered: older rows have
+/// UUIDv4 ids.
+pub async fn find_logs_batch(
+    conn: &DatabaseConnection,
+    start_time: Option<DateTimeWithTimeZone>,
+    end_time: Option<DateTimeWithTimeZone>,
+    after: Option<LogCursor>,
+    limit: u64,
+) -> Result<Vec<t_logs::Model>, DbErr> {
+    let mut query = TLogs::find().filter(t_logs::Column::DeletedAt.is_null());
+    if let Some(start_time) = start_time {
+        query = query.filter(t_logs::Column::CreatedAt.gte(start_time));
+    }
+    if let Some(end_time) = end_time {
+        query = query.filter(t_logs::Column::CreatedAt.lte(end_time));
+    }
+    if let Some((created_at, id)) = after {
+        query = query.filter(
+            Condition::any()
+                .add(t_logs::Column::CreatedAt.gt(created_at))
+                .add(
+                    Condition::all()
+                        .add(t_logs::Column::CreatedAt.eq(created_at))
+                        .add(t_logs::Column::Id.gt(id)),
+                ),
+        );
+    }
+
+    query
+        .order_by_asc(t_logs::Column::CreatedAt)
+        .order_by_asc(t_logs::Column::Id)
+        .limit(limit)
+        .all(conn)
+        .await
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    #[tokio::test]
+    async fn test_insert_logs_batch() {
+        use sea_orm::{EntityTrait, PaginatorTrait};
+
+        use super::*;
+
+        let conn = crate::database::test_db(TLogs).await;
+
+        // 超过单条语句的参数上限，分多条 INSERT 写入
+        let entries: Vec<_> = (0..5001)
+            .map(|i| (None, format!("frame {}", i), serde_json::json!({ "seq": i })))
+            .collect();
+        insert_logs_batch(&conn, &entries).await.unwrap();
+        insert_logs_batch(&conn, &[]).await.unwrap();
+
+        assert_eq!(TLogs::find().count(&conn).await.unwrap(), 5001);
+    }
+
+    #[tokio::test]
+    async fn test_page_logs_after_stable_across_inserts() {
+        use std::collections::HashSet;
+
+        use super::*;
+
+        let conn = crate::database::test_db(TLogs).await;
+
+        let entries: Vec<_> = (0..25)
+            .map(|i| (None, format!("old {}", i), serde_json::json!({})))
+            .collect();
+        insert_logs_batch(&conn, &entries).await.unwrap();
+
+        let (first, mut cursor) = page_logs_after(&conn, None, 10).await.unwrap();
+        assert_eq!(first.len(), 10);
+        let mut seen: HashSet<Uuid> = first.iter().map(|log| log.id).collect();
+
+        // 翻页过程中插入的新日志不影响后续页
+        let entries: Vec<_> = (0..5)
+            .map(|i| (None, format!("new {}", i), serde_json::json!({})))
+            .collect();
+        insert_logs_batch(&conn, &entries).await.unwrap();
+
+        while let Some(next) = cursor {
+            let (records, next_cursor) = page_logs_after(&conn, Some(next), 10).await.unwrap();
+            for log in &records {
+                assert!(log.action.starts_with("old"));
+                assert!(seen.insert(log.id), "duplicate log {}", log.id);
+            }
+            cursor = next_cursor;
+        }
+        assert_eq!(seen.len(), 25);
+    }
+
+    #[tokio::test]
+    async fn test_find_logs_batch_time_order() {
+        use chrono::Duration;
+
+        use super::*;
+
+        let conn = crate::database::test_db(TLogs).await;
+
+        // 旧数据的 id 是 UUIDv4，与时间顺序无关
+        let start = DateTimeWithTimeZone::from(Local::now());
+        for i in 0..7 {
+            let created_at = start + Duration::seconds(i);
+            TLogs::insert(t_logs::ActiveModel {
+                id: ActiveValue::set(Uuid::new_v4()),
+                user_id: ActiveValue::set(None),
+                action: ActiveValue::set(format!("log {}", i)),
+                details: ActiveValue::set(serde_json::json!({})),
+                level: ActiveValue::set(LogLevel::Info),
+                created_at: ActiveValue::set(created_at),
+                updated_at: ActiveValue::set(created_at),
+                deleted_at: ActiveValue::not_set(),
+            })
+            .exec(&conn)
+            .await
+            .unwrap();
+        }
+
+        let mut actions = Vec::new();
+        let mut after = None;
+        loop {
+            let batch = find_logs_batch(&conn, None, None, after, 3).await.unwrap();
+            actions.extend(batch.iter().map(|log| log.action.clone()));
+            match batch.last() {
+                Some(log) if batch.len() == 3 => after = Some((log.created_at, log.id)),
+                _ => break,
+            }
+        }
+        let expected: Vec<_> = (0..7).map(|i| format!("log {}", i)).collect();
+        assert_eq!(actions, expected);
+    }
+}
@@ -5,7 +5,7 @@ use crate::database::entity::{prelude::TUsers, t_settings, t_users};
 use sea_orm::Statement;
 #[cfg(any(feature = "sqlite", feature = "mysql"))]
 use sea_orm::prelude::DateTimeWithTimeZone;
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, Set};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QuerySelect, Set};
 use sea_orm_migration::prelude::*;
 
 #[derive(DeriveMigrationName)]
@@ -215,13 +215,18 @@ SELECT create_t_logs_current_month_partition();
         }
 
         {
+            // 只查询 id：实体中由后续迁移新增的列此时还不存在
             match TUsers::find()
+                .select_only()
+                .column(t_users::Column::Id)
                 .filter(t_users::Column::Username.eq("admin"))
+                .into_tuple::<uuid::Uuid>()
                 .one(manager.get_connection())
                 .await
             {
                 Ok(Some(_)) => {}
                 Ok(None) => {
+                    // 迁移无法读取配置的 bcrypt_cost；默认密码需在首次登录后修改，届时按配置重新哈希
                     let password = bcrypt::hash("admin", bcrypt::DEFAULT_COST)
                         .map_err(|e| sea_orm::DbErr::Custom(format!("Failed to hash password: {}", e)))?;
                     let user = t_users::ActiveModel {
@@ -239,7 +244,10 @@ SELECT create_t_logs_current_month_partition();
 
         {
             match TUsers::find()
+                .select_only()
+                .column(t_users::Column::Id)
                 .filter(t_users::Column::Username.eq("sys"))
+                .into_tuple::<uuid::Uuid>()
                 .one(manager.get_connection())
                 .await
             {
@@ -0,0 +1,103 @@
+use crate::database::entity::{prelude::TUsers, t_users};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QuerySelect, sea_query::Expr};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table("t_users")
+                    .add_column_if_not_exists(
+                        ColumnDef::new("must_change_password")
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 默认账号 admin/admin 仍未修改密码时，要求首次登录后修改
+        let admin = TUsers::find()
+            .select_only()
+            .columns([t_users::Column::Id, t_users::Column::Password])
+            .filter(t_users::Column::Username.eq("admin"))
+            .into_tuple::<(uuid::Uuid, String)>()
+            .one(manager.get_connection())
+            .await?;
+        if let Some((id, password)) = admin
+            && bcrypt::verify("admin", &password).unwrap_or(false)
+        {
+            TUsers::update_many()
+                .col_expr(t_users::Column::MustChangePassword, Expr::value(true))
+                .filter(t_users::Column::Id.eq(id))
+                .exec(manager.get_connection())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table("t_users")
+                    .drop_column("must_change_password")
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use sea_orm::Database;
+
+    use super::*;
+    use crate::database::{
+        migrator::{m20250814_000001_create_tables, m20261016_000002_add_login_lockout},
+        users,
+    };
+
+    struct TestMigrator;
+
+    impl MigratorTrait for TestMigrator {
+        fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+            vec![
+                Box::new(m20250814_000001_create_tables::Migration),
+                Box::new(Migration),
+                Box::new(m20261016_000002_add_login_lockout::Migration),
+            ]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_must_change_password_migration() {
+        let conn = Database::connect("sqlite::memory:").await.unwrap();
+        TestMigrator::up(&conn, None).await.unwrap();
+
+        let admin = users::find_user_by_name(&conn, "admin".into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(admin.must_change_password);
+        let sys = users::find_user_by_name(&conn, "sys".into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!sys.must_change_password);
+
+        let password = bcrypt::hash("new-password", 4).unwrap();
+        let admin = users::change_password(&conn, admin.id, password)
+            .await
+            .unwrap();
+        assert!(!admin.must_change_password);
+        assert!(bcrypt::verify("new-password", &admin.password).unwrap());
+    }
+}
@@ -0,0 +1,97 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // page_logs / page_logs_after: deleted_at IS NULL ORDER BY created_at DESC
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-logs-deleted-at-created-at")
+                    .table("t_logs")
+                    .col("deleted_at")
+                    .col("created_at")
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-logs-user-id")
+                    .table("t_logs")
+                    .col("user_id")
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-logs-user-id")
+                    .table("t_logs")
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-logs-deleted-at-created-at")
+                    .table("t_logs")
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use sea_orm::Database;
+
+    use super::*;
+    use crate::database::{
+        migrator::{m20250814_000001_create_tables, m20260121_000001_modify_t_logs},
+        query::query_json,
+    };
+
+    struct TestMigrator;
+
+    impl MigratorTrait for TestMigrator {
+        fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+            vec![
+                Box::new(m20250814_000001_create_tables::Migration),
+                Box::new(m20260121_000001_modify_t_logs::Migration),
+                Box::new(Migration),
+            ]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_page_logs_query_uses_index() {
+        let conn = Database::connect("sqlite::memory:").await.unwrap();
+        TestMigrator::up(&conn, None).await.unwrap();
+
+        let plan = query_json(
+            &conn,
+            "EXPLAIN QUERY PLAN SELECT * FROM t_logs WHERE deleted_at IS NULL ORDER BY created_at DESC LIMIT 10",
+            vec![],
+        )
+        .await
+        .unwrap();
+        let details: Vec<&str> = plan.iter().filter_map(|row| row["detail"].as_str()).collect();
+        assert!(
+            details.iter().any(|d| d.contains("idx-logs-deleted-at-created-at")),
+            "{:?}",
+            details
+        );
+        assert!(!details.iter().any(|d| d.contains("TEMP B-TREE")), "{:?}", details);
+
+        TestMigrator::down(&conn, Some(1)).await.unwrap();
+    }
+}
@@ -12,4 +12,75 @@ pub mod m20260412_000003_create_tables;
 #[cfg(feature = "industry-camera")]
 pub mod m20260412_000004_create_tables;
 #[cfg(feature = "inspection")]
-pub mod m20260412_000005_create_tables;
\ No newline at end of file
+pub mod m20260412_000005_create_tables;
+pub mod m20261016_000001_add_must_change_password;
+pub mod m20261016_000002_add_login_lockout;
+#[cfg(feature = "web")]
+pub mod m20261016_000003_create_t_revoked_tokens;
+pub mod m20261016_000004_add_t_logs_indexes;
+
+use sea_orm_migration::MigrationTrait;
+
+/// Every migration of the crate for the enabled features, in the order they must run.
+///
+/// Entities such as `t_users` expect all of them, so return them from the application's
+/// `Migrator` before its own:
+///
+/// ```ignore
+/// impl MigratorTrait for Migrator {
+///     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+///         let mut migrations = lean_link::database::migrator::migrations();
+///         migrations.push(Box::new(m20261101_000001_create_devices::Migration));
+///         migrations
+///     }
+/// }
+/// ```
+pub fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+    let mut migrations: Vec<Box<dyn MigrationTrait>> = vec![
+        Box::new(m20250814_000001_create_tables::Migration),
+        Box::new(m20260121_000001_modify_t_logs::Migration),
+    ];
+    #[cfg(feature = "inspection")]
+    migrations.push(Box::new(m20260412_000001_create_tables::Migration));
+    #[cfg(feature = "serialport")]
+    migrations.push(Box::new(m20260412_000002_create_tables::Migration));
+    #[cfg(feature = "modbus")]
+    migrations.push(Box::new(m20260412_000003_create_tables::Migration));
+    #[cfg(feature = "industry-camera")]
+    migrations.push(Box::new(m20260412_000004_create_tables::Migration));
+    #[cfg(feature = "inspection")]
+    migrations.push(Box::new(m20260412_000005_create_tables::Migration));
+    migrations.push(Box::new(m20261016_000001_add_must_change_password::Migration));
+    migrations.push(Box::new(m20261016_000002_add_login_lockout::Migration));
+    #[cfg(feature = "web")]
+    migrations.push(Box::new(m20261016_000003_create_t_revoked_tokens::Migration));
+    migrations.push(Box::new(m20261016_000004_add_t_logs_indexes::Migration));
+    migrations
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use sea_orm::Database;
+    use sea_orm_migration::MigratorTrait;
+
+    use super::*;
+    use crate::database::users;
+
+    struct Migrator;
+
+    impl MigratorTrait for Migrator {
+        fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+            migrations()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrations_up() {
+        let conn = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&conn, None).await.unwrap();
+
+        // 迁移后的表与实体一致，查询 t_users 不会缺列
+        let admin = users::find_user_by_name(&conn, "admin".into()).await.unwrap();
+        assert!(admin.is_none_or(|admin| admin.failed_attempts == 0));
+    }
+}
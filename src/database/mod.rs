@@ -3,6 +3,9 @@ pub mod migrator;
 pub mod users;
 pub mod logs;
 pub mod settings;
+pub mod query;
+#[cfg(feature = "web")]
+pub mod revoked_tokens;
 #[cfg(feature = "modbus")]
 pub mod modbus_configs;
 #[cfg(feature = "serialport")]
@@ -11,3 +14,16 @@ pub mod serialport_configs;
 pub mod camera_configs;
 #[cfg(feature = "inspection")]
 pub mod inspection_stations;
+
+/// In-memory SQLite database holding the table of `entity`
+#[cfg(all(test, feature = "sqlite"))]
+pub(crate) async fn test_db<E: sea_orm::EntityTrait>(entity: E) -> sea_orm::DatabaseConnection {
+    use sea_orm::{ConnectionTrait, Database, Schema};
+
+    let conn = Database::connect("sqlite::memory:").await.unwrap();
+    let backend = conn.get_database_backend();
+    conn.execute(backend.build(&Schema::new(backend).create_table_from_entity(entity)))
+        .await
+        .unwrap();
+    conn
+}
@@ -0,0 +1,76 @@
+use sea_orm::{ConnectionTrait, DbErr, FromQueryResult, JsonValue, QueryResult, Statement, Value};
+
+/// Run a raw parameterized query and return each row as a JSON object keyed by column name.
+///
+/// Escape hatch for reporting queries that don't fit the query builder. Bind every user
+/// supplied value through `params` (`?` for SQLite/MySQL, `$1` for Postgres) instead of
+/// formatting it into `sql`.
+pub async fn query_json<C>(conn: &C, sql: &str, params: Vec<Value>) -> Result<Vec<JsonValue>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    let statement = Statement::from_sql_and_values(conn.get_database_backend(), sql, params);
+    conn.query_all(statement)
+        .await?
+        .iter()
+        .map(row_to_json)
+        .collect()
+}
+
+fn row_to_json(row: &QueryResult) -> Result<JsonValue, DbErr> {
+    let mut value = JsonValue::from_query_result(row, "")?;
+    // SQLite 表达式列（COUNT(*) 等）没有声明类型，sea-orm 会跳过它们，这里按值补上
+    if let JsonValue::Object(map) = &mut value {
+        for column in row.column_names() {
+            if map.contains_key(&column) {
+                continue;
+            }
+            let cell = if let Ok(v) = row.try_get::<Option<i64>>("", &column) {
+                JsonValue::from(v)
+            } else if let Ok(v) = row.try_get::<Option<f64>>("", &column) {
+                JsonValue::from(v)
+            } else if let Ok(v) = row.try_get::<Option<String>>("", &column) {
+                JsonValue::from(v)
+            } else {
+                JsonValue::from(row.try_get::<Option<Vec<u8>>>("", &column)?)
+            };
+            map.insert(column, cell);
+        }
+    }
+    Ok(value)
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::database::{entity::prelude::TLogs, logs::insert_logs_batch};
+
+    #[tokio::test]
+    async fn test_query_json() {
+        let conn = crate::database::test_db(TLogs).await;
+        let entries: Vec<_> = ["read", "write", "read"]
+            .into_iter()
+            .map(|action| (None, action.to_string(), serde_json::json!({})))
+            .collect();
+        insert_logs_batch(&conn, &entries).await.unwrap();
+
+        let rows = query_json(
+            &conn,
+            "SELECT action, COUNT(*) AS total FROM t_logs WHERE action = ? GROUP BY action",
+            vec!["read".into()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(rows, vec![serde_json::json!({ "action": "read", "total": 2 })]);
+
+        // 参数不会被当作 SQL 执行
+        let rows = query_json(
+            &conn,
+            "SELECT id FROM t_logs WHERE action = ?",
+            vec!["read' OR '1'='1".into()],
+        )
+        .await
+        .unwrap();
+        assert!(rows.is_empty());
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use crate::database::entity::{prelude::TRevokedTokens, t_revoked_tokens};
+use chrono::Local;
+use sea_orm::{
+    ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryFilter,
+    prelude::DateTimeWithTimeZone, sea_query::OnConflict,
+};
+use uuid::Uuid;
+
+/// Revoke the token `jti` until `expires_at`, revoking twice is a no-op.
+pub async fn revoke_token(
+    conn: &DatabaseConnection,
+    jti: Uuid,
+    expires_at: DateTimeWithTimeZone,
+) -> Result<(), DbErr> {
+    let model = t_revoked_tokens::ActiveModel {
+        jti: ActiveValue::set(jti),
+        expires_at: ActiveValue::set(expires_at),
+        created_at: ActiveValue::set(Local::now().fixed_offset()),
+    };
+    TRevokedTokens::insert(model)
+        .on_conflict(
+            OnConflict::column(t_revoked_tokens::Column::Jti)
+                .do_nothing()
+                .to_owned(),
+        )
+        .do_nothing()
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn is_token_revoked(conn: &DatabaseConnection, jti: Uuid) -> Result<bool, DbErr> {
+    let count = TRevokedTokens::find()
+        .filter(t_revoked_tokens::Column::Jti.eq(jti))
+        .count(conn)
+        .await?;
+    Ok(count > 0)
+}
+
+/// Drop revocations whose token has expired, returns the number of rows removed.
+pub async fn delete_expired_revoked_tokens(conn: &DatabaseConnection) -> Result<u64, DbErr> {
+    let result = TRevokedTokens::delete_many()
+        .filter(t_revoked_tokens::Column::ExpiresAt.lt(Local::now().fixed_offset()))
+        .exec(conn)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+/// Periodically run [`delete_expired_revoked_tokens`] in the background.
+pub fn spawn_revoked_tokens_cleanup(
+    conn: DatabaseConnection,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match delete_expired_revoked_tokens(&conn).await {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!("removed {} expired revoked tokens", count),
+                Err(e) => tracing::error!("cleanup revoked tokens failed: {}", e),
+            }
+        }
+    })
+}
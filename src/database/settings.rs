@@ -1,9 +1,32 @@
+use std::{
+    any::Any,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
 use crate::database::entity::{prelude::TSettings, t_settings};
-use sea_orm::{ActiveValue, ColumnTrait, DbErr, EntityTrait, QueryFilter};
+use dashmap::DashMap;
+use sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
 use serde::{Serialize, de::DeserializeOwned};
-use uuid::Uuid;
 
-pub async fn setting_get_x<T, C>(conn: &C, key: &str) -> Result<T, DbErr>
+#[derive(thiserror::Error, Debug)]
+pub enum SettingsError {
+    #[error("Database Error: {0}")]
+    DbErr(#[from] DbErr),
+    /// The stored value does not deserialize as the requested type
+    #[error("Setting '{key}' expected {expected}, found {found}")]
+    TypeMismatch {
+        key: String,
+        expected: &'static str,
+        found: String,
+    },
+}
+
+/// Value of `key`, `T::default()` when it is missing.
+///
+/// A stored value that does not match `T` is an error rather than silently becoming the
+/// default, use [`setting_get_or_default`] for that.
+pub async fn setting_get_x<T, C>(conn: &C, key: &str) -> Result<T, SettingsError>
 where
     T: DeserializeOwned + Default,
     C: sea_orm::ConnectionTrait,
@@ -17,10 +40,43 @@ where
         .one(conn)
         .await?;
 
-    Ok(match model {
-        None => T::default(),
-        Some(config) => serde_json::from_value(config.value).unwrap_or_default(),
-    })
+    match model {
+        None => Ok(T::default()),
+        Some(config) => serde_json::from_value(config.value.clone()).map_err(|_| {
+            SettingsError::TypeMismatch {
+                key: key.to_string(),
+                expected: std::any::type_name::<T>(),
+                found: found_value(&config.value),
+            }
+        }),
+    }
+}
+
+/// [`setting_get_x`] falling back to `T::default()` (with a warning) on a type mismatch
+pub async fn setting_get_or_default<T, C>(conn: &C, key: &str) -> Result<T, DbErr>
+where
+    T: DeserializeOwned + Default,
+    C: sea_orm::ConnectionTrait,
+{
+    match setting_get_x(conn, key).await {
+        Ok(value) => Ok(value),
+        Err(SettingsError::DbErr(e)) => Err(e),
+        Err(e) => {
+            tracing::warn!("{}, using the default", e);
+            Ok(T::default())
+        }
+    }
+}
+
+/// Stored value for error messages, cut to keep large JSON out of the logs
+fn found_value(value: &serde_json::Value) -> String {
+    const MAX_LEN: usize = 64;
+
+    let text = value.to_string();
+    match text.char_indices().nth(MAX_LEN) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
 }
 
 pub async fn setting_set_x<T, C>(conn: &C, key: &str, value: T) -> Result<(), DbErr>
@@ -45,7 +101,7 @@ where
     };
 
     if setting_model.is_none() {
-        model.id = ActiveValue::set(Uuid::now_v7());
+        model.id = ActiveValue::set(crate::new_id());
         TSettings::insert(model).exec(conn).await?;
     } else {
         model.id = ActiveValue::set(setting_model.ok_or_else(|| {
@@ -56,3 +112,243 @@ where
 
     Ok(())
 }
+
+/// A settings key bound to its value type, declared with [`define_setting!`](crate::define_setting).
+///
+/// Keys are zero-sized, so `get(conn, &keys::HEARTBEAT)` checks both the key name and the
+/// value type at compile time. Use [`setting_get_x`]/[`setting_set_x`] for dynamic keys.
+pub trait SettingsKey {
+    type Value: Serialize + DeserializeOwned + Default;
+
+    const KEY: &'static str;
+}
+
+/// Declare typed settings keys:
+///
+/// ```ignore
+/// pub mod keys {
+///     lean_link::define_setting! {
+///         /// Heartbeat interval in seconds
+///         pub HEARTBEAT: u64 = "heartbeat";
+///         pub STATION_NAME: String = "station.name";
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! define_setting {
+    ($($(#[$meta:meta])* $vis:vis $name:ident: $ty:ty = $key:literal;)*) => {
+        $(
+            $(#[$meta])*
+            #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+            #[derive(Debug, Clone, Copy)]
+            $vis struct $name;
+
+            impl $crate::database::settings::SettingsKey for $name {
+                type Value = $ty;
+
+                const KEY: &'static str = $key;
+            }
+        )*
+    };
+}
+
+/// Typed [`setting_get_x`], the default value when the key is missing.
+pub async fn get<K, C>(conn: &C, _key: &K) -> Result<K::Value, SettingsError>
+where
+    K: SettingsKey,
+    C: sea_orm::ConnectionTrait,
+{
+    setting_get_x(conn, K::KEY).await
+}
+
+/// Typed [`setting_get_or_default`]
+pub async fn get_or_default<K, C>(conn: &C, _key: &K) -> Result<K::Value, DbErr>
+where
+    K: SettingsKey,
+    C: sea_orm::ConnectionTrait,
+{
+    setting_get_or_default(conn, K::KEY).await
+}
+
+/// Typed [`setting_set_x`]
+pub async fn set<K, C>(conn: &C, _key: &K, value: K::Value) -> Result<(), DbErr>
+where
+    K: SettingsKey,
+    C: sea_orm::ConnectionTrait,
+{
+    setting_set_x(conn, K::KEY, value).await
+}
+
+/// In-memory cache of deserialized settings in front of [`setting_get_x`].
+///
+/// Values written through [`CachedSettings::set`] invalidate their key. Writes made by
+/// other instances are only picked up after the optional TTL expires.
+pub struct CachedSettings {
+    conn: DatabaseConnection,
+    values: DashMap<String, (Arc<dyn Any + Send + Sync>, Instant)>,
+    ttl: Option<Duration>,
+}
+
+impl CachedSettings {
+    pub fn new(conn: DatabaseConnection) -> Self {
+        Self {
+            conn,
+            values: DashMap::new(),
+            ttl: None,
+        }
+    }
+
+    /// Reload a cached value from the database once it is older than `ttl`
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Cached value of `key` without a database round-trip, `None` if not cached,
+    /// expired or cached as another type.
+    pub fn get_cached<T>(&self, key: &str) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        let entry = self.values.get(key)?;
+        let (value, cached_at) = entry.value();
+        if self.ttl.is_some_and(|ttl| cached_at.elapsed() > ttl) {
+            return None;
+        }
+        value.downcast_ref::<T>().cloned()
+    }
+
+    /// Cached value of `key`, loaded from the database on a miss.
+    pub async fn get<T>(&self, key: &str) -> Result<T, SettingsError>
+    where
+        T: DeserializeOwned + Default + Clone + Send + Sync + 'static,
+    {
+        if let Some(value) = self.get_cached::<T>(key) {
+            return Ok(value);
+        }
+
+        let value: T = setting_get_x(&self.conn, key).await?;
+        self.values
+            .insert(key.to_string(), (Arc::new(value.clone()), Instant::now()));
+        Ok(value)
+    }
+
+    /// Persist `value` and drop the cached entry, the next `get` reloads it.
+    pub async fn set<T>(&self, key: &str, value: T) -> Result<(), DbErr>
+    where
+        T: Serialize + Default,
+    {
+        setting_set_x(&self.conn, key, value).await?;
+        self.invalidate(key);
+        Ok(())
+    }
+
+    /// Typed [`get`](Self::get)
+    pub async fn get_key<K>(&self, _key: &K) -> Result<K::Value, SettingsError>
+    where
+        K: SettingsKey,
+        K::Value: Clone + Send + Sync + 'static,
+    {
+        self.get(K::KEY).await
+    }
+
+    /// Typed [`set`](Self::set)
+    pub async fn set_key<K: SettingsKey>(&self, _key: &K, value: K::Value) -> Result<(), DbErr> {
+        self.set(K::KEY, value).await
+    }
+
+    pub fn invalidate(&self, key: &str) {
+        self.values.remove(key);
+    }
+
+    pub fn clear(&self) {
+        self.values.clear();
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cached_settings() {
+        let conn = crate::database::test_db(TSettings).await;
+
+        let settings = CachedSettings::new(conn.clone());
+        assert_eq!(settings.get_cached::<u32>("interval"), None);
+        assert_eq!(settings.get::<u32>("interval").await.unwrap(), 0);
+
+        settings.set("interval", 100u32).await.unwrap();
+        assert_eq!(settings.get_cached::<u32>("interval"), None);
+        assert_eq!(settings.get::<u32>("interval").await.unwrap(), 100);
+
+        // 绕过缓存直接写库，缓存值保持不变
+        setting_set_x(&conn, "interval", 200u32).await.unwrap();
+        assert_eq!(settings.get_cached::<u32>("interval"), Some(100));
+        assert_eq!(settings.get::<u32>("interval").await.unwrap(), 100);
+    }
+
+    mod keys {
+        crate::define_setting! {
+            /// Heartbeat interval in seconds
+            pub HEARTBEAT: u64 = "heartbeat";
+            pub STATION_NAME: String = "station.name";
+        }
+    }
+
+    #[tokio::test]
+    async fn test_typed_keys() {
+        let conn = crate::database::test_db(TSettings).await;
+
+        assert_eq!(get(&conn, &keys::HEARTBEAT).await.unwrap(), 0);
+        set(&conn, &keys::HEARTBEAT, 30).await.unwrap();
+        assert_eq!(get(&conn, &keys::HEARTBEAT).await.unwrap(), 30);
+        assert_eq!(setting_get_x::<u64, _>(&conn, "heartbeat").await.unwrap(), 30);
+
+        let settings = CachedSettings::new(conn);
+        settings.set_key(&keys::STATION_NAME, "A1".into()).await.unwrap();
+        assert_eq!(settings.get_key(&keys::STATION_NAME).await.unwrap(), "A1");
+    }
+
+    #[tokio::test]
+    async fn test_cached_settings_ttl() {
+        let conn = crate::database::test_db(TSettings).await;
+
+        let settings = CachedSettings::new(conn.clone()).with_ttl(Duration::ZERO);
+        settings.set("name", "a".to_string()).await.unwrap();
+        assert_eq!(settings.get::<String>("name").await.unwrap(), "a");
+
+        setting_set_x(&conn, "name", "b".to_string()).await.unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(settings.get_cached::<String>("name"), None);
+        assert_eq!(settings.get::<String>("name").await.unwrap(), "b");
+    }
+
+    #[tokio::test]
+    async fn test_type_mismatch() {
+        let conn = crate::database::test_db(TSettings).await;
+
+        setting_set_x(&conn, "heartbeat", "30s").await.unwrap();
+        match get(&conn, &keys::HEARTBEAT).await {
+            Err(SettingsError::TypeMismatch {
+                key,
+                expected,
+                found,
+            }) => {
+                assert_eq!(key, "heartbeat");
+                assert_eq!(expected, "u64");
+                assert_eq!(found, "\"30s\"");
+            }
+            other => panic!("expected a type mismatch, got {:?}", other),
+        }
+        assert_eq!(get_or_default(&conn, &keys::HEARTBEAT).await.unwrap(), 0);
+
+        // 超出 u64 范围的数字同样报错
+        setting_set_x(&conn, "heartbeat", -1i64).await.unwrap();
+        assert!(matches!(
+            setting_get_x::<u64, _>(&conn, "heartbeat").await,
+            Err(SettingsError::TypeMismatch { .. })
+        ));
+        assert_eq!(setting_get_x::<i64, _>(&conn, "heartbeat").await.unwrap(), -1);
+    }
+}
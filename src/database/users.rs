@@ -1,5 +1,11 @@
+use std::time::Duration;
+
 use crate::database::entity::{prelude::TUsers, t_users};
-use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use chrono::Local;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, prelude::DateTimeWithTimeZone, sea_query::Expr,
+};
 use uuid::Uuid;
 
 pub async fn find_user_by_name(
@@ -29,3 +35,169 @@ pub async fn find_user_by_id(
         .one(conn)
         .await
 }
+
+/// All non-deleted users, oldest first.
+pub async fn find_all_users(conn: &DatabaseConnection) -> Result<Vec<t_users::Model>, DbErr> {
+    TUsers::find()
+        .filter(t_users::Column::DeletedAt.is_null())
+        .order_by_asc(t_users::Column::CreatedAt)
+        .all(conn)
+        .await
+}
+
+/// Replace the password hash and clear `must_change_password`.
+pub async fn change_password(
+    conn: &DatabaseConnection,
+    user_id: Uuid,
+    password_hash: String,
+) -> Result<t_users::Model, DbErr> {
+    let existing = find_user_by_id(conn, user_id)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound(format!("user {} not found", user_id)))?;
+
+    let mut user: t_users::ActiveModel = existing.into();
+    user.password = ActiveValue::set(password_hash);
+    user.must_change_password = ActiveValue::set(false);
+    user.update(conn).await
+}
+
+/// End of the user's lockout, `None` if the account is not locked at `now`.
+pub fn locked_until(user: &t_users::Model, now: DateTimeWithTimeZone) -> Option<DateTimeWithTimeZone> {
+    user.locked_until.filter(|until| *until > now)
+}
+
+/// Count a bad password, locking the account for `lockout_duration` once
+/// `max_failed_attempts` consecutive failures are reached, `0` never locks.
+///
+/// Both steps are single UPDATE statements, so concurrent failures are all counted.
+pub async fn record_login_failure(
+    conn: &DatabaseConnection,
+    user: t_users::Model,
+    max_failed_attempts: u32,
+    lockout_duration: Duration,
+) -> Result<t_users::Model, DbErr> {
+    let now = Local::now().fixed_offset();
+    TUsers::update_many()
+        .col_expr(
+            t_users::Column::FailedAttempts,
+            Expr::col(t_users::Column::FailedAttempts).add(1),
+        )
+        .col_expr(t_users::Column::UpdatedAt, Expr::value(now))
+        .filter(t_users::Column::Id.eq(user.id))
+        .exec(conn)
+        .await?;
+
+    if max_failed_attempts > 0 {
+        let lockout = chrono::Duration::from_std(lockout_duration).unwrap_or(chrono::Duration::MAX);
+        let until = now.checked_add_signed(lockout).unwrap_or(now);
+        // 只有达到阈值的那次更新会命中，并发的失败不会重复锁定或丢失计数
+        TUsers::update_many()
+            .col_expr(t_users::Column::FailedAttempts, Expr::value(0))
+            .col_expr(t_users::Column::LockedUntil, Expr::value(Some(until)))
+            .filter(
+                t_users::Column::Id
+                    .eq(user.id)
+                    .and(t_users::Column::FailedAttempts.gte(max_failed_attempts)),
+            )
+            .exec(conn)
+            .await?;
+    }
+
+    find_user_by_id(conn, user.id)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound(format!("user {} not found", user.id)))
+}
+
+/// Clear the failure count and any expired lockout after a successful login.
+pub async fn reset_login_failures(
+    conn: &DatabaseConnection,
+    user: t_users::Model,
+) -> Result<t_users::Model, DbErr> {
+    if user.failed_attempts == 0 && user.locked_until.is_none() {
+        return Ok(user);
+    }
+
+    let mut user: t_users::ActiveModel = user.into();
+    user.failed_attempts = ActiveValue::set(0);
+    user.locked_until = ActiveValue::set(None);
+    user.update(conn).await
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+
+    async fn setup() -> (DatabaseConnection, t_users::Model) {
+        let conn = crate::database::test_db(TUsers).await;
+
+        let user = t_users::ActiveModel {
+            username: ActiveValue::set("operator".into()),
+            password: ActiveValue::set("hash".into()),
+            must_change_password: ActiveValue::set(false),
+            failed_attempts: ActiveValue::set(0),
+            locked_until: ActiveValue::set(None),
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+        (conn, user)
+    }
+
+    #[tokio::test]
+    async fn test_login_lockout() {
+        let (conn, mut user) = setup().await;
+        let lockout = Duration::from_secs(60);
+
+        for attempt in 1..3 {
+            user = record_login_failure(&conn, user, 3, lockout).await.unwrap();
+            assert_eq!(user.failed_attempts, attempt);
+            assert!(locked_until(&user, Local::now().fixed_offset()).is_none());
+        }
+
+        user = record_login_failure(&conn, user, 3, lockout).await.unwrap();
+        let user = find_user_by_id(&conn, user.id).await.unwrap().unwrap();
+        assert!(locked_until(&user, Local::now().fixed_offset()).is_some());
+        assert_eq!(user.failed_attempts, 0);
+
+        let user = reset_login_failures(&conn, user).await.unwrap();
+        assert!(user.locked_until.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_failures_counted() {
+        let (conn, user) = setup().await;
+        let lockout = Duration::from_secs(60);
+
+        // 两次失败基于同一份旧数据，计数仍须累加
+        let (first, second) = tokio::join!(
+            record_login_failure(&conn, user.clone(), 5, lockout),
+            record_login_failure(&conn, user.clone(), 5, lockout),
+        );
+        first.unwrap();
+        second.unwrap();
+        let user = record_login_failure(&conn, user, 5, lockout).await.unwrap();
+        assert_eq!(user.failed_attempts, 3);
+
+        // 0 表示不锁定
+        let mut user = user;
+        for _ in 0..5 {
+            user = record_login_failure(&conn, user, 0, lockout).await.unwrap();
+        }
+        assert!(user.locked_until.is_none());
+        assert_eq!(user.failed_attempts, 8);
+    }
+
+    #[tokio::test]
+    async fn test_lockout_expires() {
+        let (conn, user) = setup().await;
+
+        let user = record_login_failure(&conn, user, 1, Duration::from_secs(60))
+            .await
+            .unwrap();
+        let now = Local::now().fixed_offset();
+        assert!(locked_until(&user, now).is_some());
+        // 锁定时间窗口过后自动解锁
+        assert!(locked_until(&user, now + chrono::Duration::seconds(61)).is_none());
+    }
+}
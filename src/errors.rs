@@ -12,6 +12,9 @@ pub enum Error {
     #[error("Modbus Exception Code: {0}")]
     #[cfg(feature = "modbus")]
     ModbusExceptionCode(#[from] tokio_modbus::ExceptionCode),
+    #[cfg(feature = "modbus")]
+    #[error("Modbus Error: {0}")]
+    ModbusService(#[from] crate::service::modbus::ModbusError),
     #[cfg(feature = "web")]
     #[error("JWT Error: {0}")]
     Jwt(#[from] jsonwebtoken::errors::Error),
@@ -22,16 +25,29 @@ pub enum Error {
     DbErr(#[from] sea_orm::DbErr),
     #[error("Json Error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("Settings Error: {0}")]
+    Settings(#[from] crate::database::settings::SettingsError),
+    #[error("Invalid Id: {0}")]
+    InvalidId(#[from] uuid::Error),
     #[cfg(feature = "web")]
     #[error("Authorization Fail")]
     AuthorizationFail(ErrorCode),
     #[cfg(feature = "web")]
     #[error("Internal Error")]
     InternalError(ErrorCode),
+    #[cfg(feature = "web")]
+    #[error("Account Locked until {0}")]
+    AccountLocked(chrono::DateTime<chrono::FixedOffset>),
     #[error("TSink Error: {0}")]
     Tsink(#[from] tsink::TsinkError),
+    #[error("Storage Error: {0}")]
+    Storage(#[from] crate::storage::StorageError),
     #[error("Configure Error")]
     Configure,
+    #[error("Logging Error: {0}")]
+    Logging(String),
+    #[error("Server Start Error: {0}")]
+    ServerStart(#[from] ServerStartError),
     #[cfg(any(feature = "industry-camera"))]
     #[error("Camera Error Code: {0}")]
     Camera(#[from] crate::service::camera::CameraError),
@@ -50,6 +66,64 @@ pub enum Error {
     DetectorError(#[from] crate::service::inspection::detector::DetectorError),
 }
 
+/// Failure to bind a server listener, with a hint for the common deployment mistakes.
+#[derive(thiserror::Error, Debug)]
+pub enum ServerStartError {
+    #[error("Address {addr} is already in use, is another instance running? ({source})")]
+    AddrInUse {
+        addr: String,
+        source: std::io::Error,
+    },
+    #[error("Permission denied binding {addr}, ports below 1024 need elevated privileges ({source})")]
+    PermissionDenied {
+        addr: String,
+        source: std::io::Error,
+    },
+    #[error("Address {addr} is not available on this host, check the configured host ({source})")]
+    AddrNotAvailable {
+        addr: String,
+        source: std::io::Error,
+    },
+    #[error("Failed to start server on {addr}: {source}")]
+    Bind {
+        addr: String,
+        source: std::io::Error,
+    },
+}
+
+impl ServerStartError {
+    pub fn new(addr: impl Into<String>, source: std::io::Error) -> Self {
+        let addr = addr.into();
+        match source.kind() {
+            std::io::ErrorKind::AddrInUse => Self::AddrInUse { addr, source },
+            std::io::ErrorKind::PermissionDenied => Self::PermissionDenied { addr, source },
+            std::io::ErrorKind::AddrNotAvailable => Self::AddrNotAvailable { addr, source },
+            _ => Self::Bind { addr, source },
+        }
+    }
+
+    pub fn addr(&self) -> &str {
+        match self {
+            Self::AddrInUse { addr, .. }
+            | Self::PermissionDenied { addr, .. }
+            | Self::AddrNotAvailable { addr, .. }
+            | Self::Bind { addr, .. } => addr,
+        }
+    }
+}
+
+impl From<ServerStartError> for std::io::Error {
+    fn from(value: ServerStartError) -> Self {
+        let kind = match &value {
+            ServerStartError::AddrInUse { source, .. }
+            | ServerStartError::PermissionDenied { source, .. }
+            | ServerStartError::AddrNotAvailable { source, .. }
+            | ServerStartError::Bind { source, .. } => source.kind(),
+        };
+        std::io::Error::new(kind, value.to_string())
+    }
+}
+
 #[cfg(feature = "web")]
 impl actix_web::error::ResponseError for Error {
     fn status_code(&self) -> actix_web::http::StatusCode {
@@ -60,6 +134,8 @@ impl actix_web::error::ResponseError for Error {
             Error::AuthorizationFail(_) => actix_web::http::StatusCode::UNAUTHORIZED,
             #[cfg(feature = "web")]
             Error::InternalError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            Error::AccountLocked(_) => actix_web::http::StatusCode::LOCKED,
+            Error::InvalidId(_) => actix_web::http::StatusCode::BAD_REQUEST,
             _ => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -80,27 +156,30 @@ impl actix_web::error::ResponseError for Error {
                 actix_web::HttpResponse::build(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR)
                     .json(WebResponse::<()>::with_error_code(code))
             }
+            Error::AccountLocked(until) => {
+                actix_web::HttpResponse::build(actix_web::http::StatusCode::LOCKED).json(
+                    WebResponse::<()>::with_error_code_and_message(
+                        &ErrorCode::AccountLocked,
+                        crate::utils::datetime::convert_to_local_rfc3339(until),
+                    ),
+                )
+            }
+            Error::InvalidId(_) => {
+                actix_web::HttpResponse::build(actix_web::http::StatusCode::BAD_REQUEST).json(
+                    WebResponse::<()>::with_error_code_and_message(
+                        &ErrorCode::ValidationError,
+                        self.to_string(),
+                    ),
+                )
+            }
             Error::BadRequest(code, message) => {
                 actix_web::HttpResponse::build(actix_web::http::StatusCode::BAD_REQUEST).json(
                     WebResponse::<()>::with_error_code_and_message(code, message.clone()),
                 )
             }
-            Error::Io(e) => {
-                actix_web::HttpResponse::build(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR)
-                    .json(WebResponse::<()>::with_error_code_and_message(
-                        &ErrorCode::InternalError,
-                        e.to_string(),
-                    ))
-            }
-            #[cfg(feature = "industry-camera")]
-            Error::Camera(e) => {
-                actix_web::HttpResponse::build(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR)
-                    .json(WebResponse::<()>::with_error_code_and_message(
-                        &ErrorCode::InternalError,
-                        e.to_string(),
-                    ))
-            }
-            _ => actix_web::HttpResponse::new(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR),
+            _ => actix_web::HttpResponse::build(self.status_code()).json(
+                WebResponse::<()>::from_error(&ErrorCode::InternalError, self),
+            ),
         }
     }
 }
@@ -1,3 +1,13 @@
+// Invalid feature combinations, reported here instead of as confusing errors in sea-orm
+#[cfg(all(feature = "sqlite", feature = "mysql"))]
+compile_error!("Features 'sqlite' and 'mysql' cannot be enabled together, pick one database driver.");
+#[cfg(all(feature = "sqlite", feature = "postgres"))]
+compile_error!("Features 'sqlite' and 'postgres' cannot be enabled together, pick one database driver.");
+#[cfg(all(feature = "mysql", feature = "postgres"))]
+compile_error!(
+    "Features 'mysql' and 'postgres' cannot be enabled together, pick one database driver. Note that 'all' enables 'postgres'."
+);
+
 use crate::config::ServerConfig;
 #[cfg(feature = "industry-camera")]
 use crate::service::camera::manager::{ArcCameraManager, CameraManager};
@@ -7,6 +17,8 @@ use crate::service::inspection::{
     manager::{ArcStationManager, StationManager},
 };
 #[cfg(feature = "web")]
+use crate::service::web::auth::{AuthBackend, DbAuthBackend};
+#[cfg(feature = "web")]
 use crate::service::websocket::{ArcWebSocketServer, WebSocketMessage, WebSocketServer};
 use sea_orm::{Database, DatabaseConnection};
 #[cfg(feature = "web")]
@@ -88,13 +100,20 @@ pub mod errors;
 pub mod ffi;
 pub mod service;
 pub mod storage;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
 pub mod utils;
+pub use utils::id::{new_id, parse_id};
+pub use utils::logging::init_tracing;
 // pub use lean_link_macros::*;
 
 pub struct AppStateBuilder {
     app_name: Option<String>,
     load_config: bool,
     server_config: Option<ServerConfig>,
+    self_check: bool,
+    #[cfg(feature = "web")]
+    auth_backend: Option<std::sync::Arc<dyn AuthBackend>>,
 }
 
 impl AppStateBuilder {
@@ -103,6 +122,9 @@ impl AppStateBuilder {
             app_name: None,
             load_config: true,
             server_config: None,
+            self_check: false,
+            #[cfg(feature = "web")]
+            auth_backend: None,
         }
     }
 
@@ -121,6 +143,19 @@ impl AppStateBuilder {
         self
     }
 
+    /// Run [`AppState::self_check`] once built and log the summary table
+    pub fn with_self_check(mut self, self_check: bool) -> Self {
+        self.self_check = self_check;
+        self
+    }
+
+    /// Check logins with `backend` instead of the `t_users` table
+    #[cfg(feature = "web")]
+    pub fn with_auth_backend(mut self, backend: std::sync::Arc<dyn AuthBackend>) -> Self {
+        self.auth_backend = Some(backend);
+        self
+    }
+
     pub async fn build(&self) -> std::io::Result<AppState> {
         if self.load_config && self.app_name.is_none() {
             return Err(std::io::Error::new(
@@ -136,7 +171,8 @@ impl AppStateBuilder {
                     "App name must be provided when load_config is true",
                 )
             })?;
-            AppState::new(app_name.as_str()).await
+            let app_state = AppState::new(app_name.as_str()).await?;
+            Ok(self.apply(app_state).await)
         } else {
             let server_config = self.server_config.as_ref().ok_or_else(|| {
                 std::io::Error::new(
@@ -144,15 +180,34 @@ impl AppStateBuilder {
                     "Server config must be provided when load_config is false",
                 )
             })?;
-            AppState::new_with_config(
+            let app_state = AppState::new_with_config(
                 server_config.clone(),
                 self.app_name
                     .as_ref()
                     .map(|s| s.to_string())
                     .unwrap_or_else(|| "lean-link-service".to_string()),
             )
-            .await
+            .await?;
+            Ok(self.apply(app_state).await)
+        }
+    }
+
+    #[cfg_attr(not(feature = "web"), allow(unused_mut))]
+    async fn apply(&self, mut app_state: AppState) -> AppState {
+        #[cfg(feature = "web")]
+        if let Some(backend) = &self.auth_backend {
+            app_state.auth_backend = backend.clone();
+        }
+        if self.self_check {
+            let results = app_state.self_check().await;
+            let table = service::self_check::summary_table(&results);
+            if results.iter().all(|result| result.passed) {
+                tracing::info!("Startup self-check passed:\n{}", table);
+            } else {
+                tracing::warn!("Startup self-check failed:\n{}", table);
+            }
         }
+        app_state
     }
 }
 
@@ -160,8 +215,13 @@ pub struct AppState {
     pub db_conn: DatabaseConnection,
     pub server_config: ServerConfig,
     pub server_name: String,
+    /// Where the system time came from at startup
+    pub time_source: crate::utils::time_source::TimeSource,
     #[cfg(feature = "web")]
     pub ws_server: ArcWebSocketServer,
+    /// Checks credentials of `POST /user/login`
+    #[cfg(feature = "web")]
+    pub auth_backend: std::sync::Arc<dyn AuthBackend>,
     #[cfg(feature = "industry-camera")]
     pub camera_manager: ArcCameraManager,
     #[cfg(feature = "inspection")]
@@ -185,32 +245,35 @@ impl AppState {
             .await
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
 
+        if server_config.sys.tz_offset_minutes.is_some() && server_config.sys.tz_offset().is_none() {
+            tracing::warn!(
+                "Ignoring invalid sys.tz_offset_minutes {:?}",
+                server_config.sys.tz_offset_minutes
+            );
+        }
+        crate::utils::datetime::set_output_offset(server_config.sys.tz_offset());
+
         #[cfg(feature = "web")]
         let web_socket_server =
             WebSocketServer::new_arc(server_config.web_socket.clone(), server_config.sys.clone());
 
-        #[cfg(target_os = "linux")]
-        {
-            use crate::utils::datetime::set_local_time_from_ds1307;
-            use crate::utils::i2c::path_to_i2c_bus;
-
-            let sync_time_from_rtc = server_config.sys.sync_time_from_rtc;
-            let rtc_i2c_dev = server_config.sys.rtc_i2c_dev.clone();
-            let rtc_i2c_addr = server_config.sys.rtc_i2c_addr;
-            if sync_time_from_rtc {
-                // sync system time from RTC
-                match path_to_i2c_bus(&rtc_i2c_dev) {
-                    Ok(bus) => {
-                        let output = set_local_time_from_ds1307(bus, rtc_i2c_addr);
-                        tracing::info!("syncSysTime command output: {:?}", output);
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to sync time from RTC: {}", e);
-                    }
-                }
-            }
+        // 未启用吊销的应用可能没有 t_revoked_tokens 表
+        #[cfg(feature = "web")]
+        if server_config.jwt.revoke_on_logout {
+            database::revoked_tokens::spawn_revoked_tokens_cleanup(
+                db_conn.clone(),
+                std::time::Duration::from_secs(3600),
+            );
         }
 
+        let time_source = crate::utils::time_source::resolve_time_source(&server_config.sys);
+
+        #[cfg(feature = "web")]
+        let auth_backend = std::sync::Arc::new(DbAuthBackend::new(
+            db_conn.clone(),
+            &server_config.jwt,
+        ));
+
         #[cfg(feature = "industry-camera")]
         let camera_manager = CameraManager::new_arc(db_conn.clone());
 
@@ -230,8 +293,11 @@ impl AppState {
             db_conn,
             server_config,
             server_name: server_name.into(),
+            time_source,
             #[cfg(feature = "web")]
             ws_server: web_socket_server,
+            #[cfg(feature = "web")]
+            auth_backend,
             #[cfg(feature = "industry-camera")]
             camera_manager,
             #[cfg(feature = "inspection")]
@@ -241,9 +307,17 @@ impl AppState {
         })
     }
 
+    /// Probe the database and every configured serial port, MQTT broker and Modbus endpoint.
+    ///
+    /// Serial paths are only checked for existence, brokers get a short connect with the
+    /// configured client id: run it before the services start.
+    pub async fn self_check(&self) -> Vec<service::self_check::CheckResult> {
+        service::self_check::run(&self.db_conn, &self.server_config).await
+    }
+
     #[cfg(feature = "web")]
     pub async fn start_web_socket(&self) -> std::io::Result<Receiver<WebSocketMessage>> {
-        self.ws_server.start().await
+        Ok(self.ws_server.start().await?)
     }
 }
 
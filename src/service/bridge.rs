@@ -0,0 +1,431 @@
+// Message router between WebSocket clients and an MQTT broker.
+
+use rumqttc::{AsyncClient, ClientError, Event, EventLoop, Packet, Publish, QoS};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::mpsc,
+    time::{Instant, sleep_until},
+};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{
+    service::{
+        mqtt::string_to_qos,
+        websocket::{ArcWebSocketServer, WebSocketMessage, WsMessage},
+    },
+    utils::{hex, retry::ReconnectPolicy},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum BridgeDirection {
+    /// MQTT messages are published to WebSocket clients subscribed to the `ws_topic`
+    MqttToWs,
+    /// WebSocket messages are published to MQTT
+    WsToMqtt,
+    #[default]
+    Both,
+}
+
+impl BridgeDirection {
+    fn mqtt_to_ws(&self) -> bool {
+        matches!(self, BridgeDirection::MqttToWs | BridgeDirection::Both)
+    }
+
+    fn ws_to_mqtt(&self) -> bool {
+        matches!(self, BridgeDirection::WsToMqtt | BridgeDirection::Both)
+    }
+}
+
+/// How the MQTT payload of a mapping is represented in the WebSocket `payload` field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PayloadFormat {
+    /// MQTT payload is a JSON document, forwarded as is
+    #[default]
+    Json,
+    /// MQTT payload is UTF-8 text, forwarded as a JSON string
+    Text,
+    /// MQTT payload is binary, forwarded as a hex string such as `"010AFF"`
+    Hex,
+}
+
+impl PayloadFormat {
+    fn to_json(self, payload: &[u8]) -> Option<serde_json::Value> {
+        match self {
+            PayloadFormat::Json => serde_json::from_slice(payload).ok(),
+            PayloadFormat::Text => std::str::from_utf8(payload)
+                .ok()
+                .map(|text| serde_json::Value::String(text.to_string())),
+            PayloadFormat::Hex => Some(serde_json::Value::String(hex::format(payload, ""))),
+        }
+    }
+
+    fn to_bytes(self, payload: &serde_json::Value) -> Option<Vec<u8>> {
+        match (self, payload) {
+            (PayloadFormat::Json, payload) => serde_json::to_vec(payload).ok(),
+            (PayloadFormat::Text, serde_json::Value::String(text)) => Some(text.as_bytes().to_vec()),
+            (PayloadFormat::Text, payload) => Some(payload.to_string().into_bytes()),
+            (PayloadFormat::Hex, serde_json::Value::String(text)) => {
+                hex::parse(text).ok().map(Vec::from)
+            }
+            (PayloadFormat::Hex, _) => None,
+        }
+    }
+}
+
+/// One row of the topic mapping table.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BridgeMapping {
+    /// MQTT topic; may be a filter with `+`/`#` wildcards for `MqttToWs`
+    pub mqtt_topic: String,
+    /// `topic` field of the WebSocket `WsMessage`
+    pub ws_topic: String,
+    #[serde(default)]
+    pub direction: BridgeDirection,
+    #[serde(default)]
+    pub format: PayloadFormat,
+    #[serde(with = "string_to_qos", default = "default_qos")]
+    pub qos: QoS,
+}
+
+fn default_qos() -> QoS {
+    QoS::AtLeastOnce
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BridgeConfig {
+    pub mappings: Vec<BridgeMapping>,
+}
+
+/// Forwards messages between MQTT and WebSocket according to [`BridgeConfig`].
+///
+/// MQTT messages become `{"topic": ws_topic, "payload": ...}` messages published to the
+/// WebSocket clients subscribed to `ws_topic`, WebSocket messages whose `topic` matches a
+/// `ws_topic` are published to `mqtt_topic`.
+pub struct Bridge {
+    client: AsyncClient,
+    ws_server: ArcWebSocketServer,
+    config: BridgeConfig,
+    policy: ReconnectPolicy,
+}
+
+impl Bridge {
+    pub fn new(client: AsyncClient, ws_server: ArcWebSocketServer, config: BridgeConfig) -> Self {
+        Self {
+            client,
+            ws_server,
+            config,
+            policy: ReconnectPolicy::default(),
+        }
+    }
+
+    /// Backoff between MQTT reconnects in [`Bridge::run`]; `max_retries` is ignored, it never gives up
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Queue a subscription to the MQTT side of every `MqttToWs` mapping, without waiting for
+    /// room in the client's request queue
+    pub fn subscribe(&self) -> Result<(), ClientError> {
+        for mapping in &self.config.mappings {
+            if mapping.direction.mqtt_to_ws() {
+                self.client
+                    .try_subscribe(mapping.mqtt_topic.clone(), mapping.qos)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Broadcast an incoming MQTT publish to WebSocket clients, returns the number of matched mappings.
+    pub async fn handle_mqtt_publish(&self, publish: &Publish) -> usize {
+        let mut forwarded = 0;
+        for mapping in &self.config.mappings {
+            if !mapping.direction.mqtt_to_ws() || !rumqttc::matches(&publish.topic, &mapping.mqtt_topic)
+            {
+                continue;
+            }
+            let Some(payload) = mapping.format.to_json(&publish.payload) else {
+                tracing::warn!(
+                    "bridge: drop MQTT message on {}, payload is not {:?}",
+                    publish.topic,
+                    mapping.format
+                );
+                continue;
+            };
+            if let Err(e) = self
+                .ws_server
+                .publish(&WsMessage::new(mapping.ws_topic.clone(), payload))
+                .await
+            {
+                tracing::error!("bridge: encode WebSocket message failed: {}", e);
+                continue;
+            }
+            forwarded += 1;
+        }
+        forwarded
+    }
+
+    /// Publish a WebSocket text message to MQTT, returns the number of matched mappings.
+    ///
+    /// The message is queued with `try_publish`: while the MQTT request queue is full (e.g.
+    /// during a broker outage) it is dropped, so the caller never waits for the broker.
+    pub fn handle_ws_message(&self, message: &Message) -> usize {
+        let Message::Text(text) = message else {
+            return 0;
+        };
+        let Ok(message) = serde_json::from_str::<WsMessage<serde_json::Value>>(text) else {
+            return 0;
+        };
+
+        let mut forwarded = 0;
+        for mapping in &self.config.mappings {
+            if !mapping.direction.ws_to_mqtt() || mapping.ws_topic != message.topic {
+                continue;
+            }
+            let Some(payload) = mapping.format.to_bytes(&message.payload) else {
+                tracing::warn!(
+                    "bridge: drop WebSocket message on {}, payload is not {:?}",
+                    message.topic,
+                    mapping.format
+                );
+                continue;
+            };
+            if let Err(e) = self
+                .client
+                .try_publish(mapping.mqtt_topic.clone(), mapping.qos, false, payload)
+            {
+                tracing::error!("bridge: publish to {} failed: {}", mapping.mqtt_topic, e);
+                continue;
+            }
+            forwarded += 1;
+        }
+        forwarded
+    }
+
+    /// Drive the MQTT event loop and route messages until the WebSocket receiver closes.
+    ///
+    /// Takes over the receiver returned by `WebSocketServer::start`; applications that also
+    /// consume WebSocket messages should call [`Bridge::handle_ws_message`] themselves instead.
+    pub async fn run(
+        &self,
+        mut eventloop: EventLoop,
+        mut ws_receiver: mpsc::Receiver<WebSocketMessage>,
+    ) {
+        let mut failures = 0;
+        // 重连等待期间照常处理 WebSocket 消息
+        let mut retry_at: Option<Instant> = None;
+        loop {
+            tokio::select! {
+                event = eventloop.poll(), if retry_at.is_none() => match event {
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        self.handle_mqtt_publish(&publish).await;
+                    }
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        failures = 0;
+                        // 重连后重新订阅
+                        if let Err(e) = self.subscribe() {
+                            tracing::error!("bridge: subscribe failed: {}", e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        // 下一次 poll 时 rumqttc 自动重连
+                        failures += 1;
+                        let delay = self.policy.delay(failures);
+                        tracing::error!("bridge: MQTT connection error: {}, reconnect in {:?}", e, delay);
+                        retry_at = Some(Instant::now() + delay);
+                    }
+                },
+                _ = sleep_until(retry_at.unwrap_or_else(Instant::now)), if retry_at.is_some() => {
+                    retry_at = None;
+                }
+                message = ws_receiver.recv() => match message {
+                    Some(WebSocketMessage::Message(_, message)) => {
+                        self.handle_ws_message(&message);
+                    }
+                    Some(_) => {}
+                    None => break,
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::BytesMut;
+    use futures::{SinkExt, StreamExt};
+    use rumqttc::{ConnAck, ConnectReturnCode, SubAck, SubscribeReasonCode};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::{
+        config::Sys,
+        service::{
+            mqtt::client::ClientBuilder,
+            websocket::{SUBSCRIBE_TOPIC, WebSocketConfig, WebSocketServer},
+        },
+    };
+
+    /// Single-client MQTT 3.1.1 broker: acks CONNECT/SUBSCRIBE, reports client
+    /// publishes and delivers publishes pushed by the test.
+    async fn mock_broker(
+        listener: TcpListener,
+        mut to_client: mpsc::Receiver<Publish>,
+        from_client: mpsc::Sender<Publish>,
+        subscribed: mpsc::Sender<String>,
+    ) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buffer = BytesMut::new();
+        loop {
+            let packet = match Packet::read(&mut buffer, 1024 * 1024) {
+                Ok(packet) => Some(packet),
+                Err(rumqttc::Error::InsufficientBytes(_)) => None,
+                Err(e) => panic!("invalid packet: {e:?}"),
+            };
+            let reply = match packet {
+                Some(Packet::Connect(_)) => Some(Packet::ConnAck(ConnAck::new(
+                    ConnectReturnCode::Success,
+                    false,
+                ))),
+                Some(Packet::Subscribe(subscribe)) => {
+                    for filter in &subscribe.filters {
+                        subscribed.send(filter.path.clone()).await.unwrap();
+                    }
+                    Some(Packet::SubAck(SubAck::new(
+                        subscribe.pkid,
+                        vec![SubscribeReasonCode::Success(QoS::AtMostOnce)],
+                    )))
+                }
+                Some(Packet::Publish(publish)) => {
+                    from_client.send(publish).await.unwrap();
+                    None
+                }
+                Some(Packet::PingReq) => Some(Packet::PingResp),
+                Some(_) => None,
+                None => {
+                    tokio::select! {
+                        read = stream.read_buf(&mut buffer) => {
+                            if read.unwrap() == 0 {
+                                return;
+                            }
+                        }
+                        Some(publish) = to_client.recv() => {
+                            let mut out = BytesMut::new();
+                            Packet::Publish(publish).write(&mut out, 1024 * 1024).unwrap();
+                            stream.write_all(&out).await.unwrap();
+                        }
+                    }
+                    continue;
+                }
+            };
+            if let Some(reply) = reply {
+                let mut out = BytesMut::new();
+                reply.write(&mut out, 1024 * 1024).unwrap();
+                stream.write_all(&out).await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bridge_routes_both_ways() {
+        let broker = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let broker_port = broker.local_addr().unwrap().port();
+        let (to_client, to_client_rx) = mpsc::channel(8);
+        let (from_client_tx, mut from_client) = mpsc::channel(8);
+        let (subscribed_tx, mut subscribed) = mpsc::channel(8);
+        tokio::spawn(mock_broker(broker, to_client_rx, from_client_tx, subscribed_tx));
+
+        let ws_server = WebSocketServer::new_arc(WebSocketConfig::default(), Sys::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let ws_receiver = ws_server.start_with_listener(listener).await.unwrap();
+
+        let config = BridgeConfig {
+            mappings: vec![
+                BridgeMapping {
+                    mqtt_topic: "devices/+/telemetry".into(),
+                    ws_topic: "telemetry".into(),
+                    direction: BridgeDirection::MqttToWs,
+                    format: PayloadFormat::Json,
+                    qos: QoS::AtMostOnce,
+                },
+                BridgeMapping {
+                    mqtt_topic: "devices/plc/cmd".into(),
+                    ws_topic: "cmd".into(),
+                    direction: BridgeDirection::WsToMqtt,
+                    format: PayloadFormat::Hex,
+                    qos: QoS::AtMostOnce,
+                },
+            ],
+        };
+        let (client, eventloop) = ClientBuilder::new("127.0.0.1", broker_port).build();
+        let bridge = Bridge::new(client, ws_server.clone(), config);
+        tokio::spawn(async move { bridge.run(eventloop, ws_receiver).await });
+
+        let (peers_tx, mut peers) = mpsc::unbounded_channel();
+        ws_server.on_connect(move |peer| {
+            let peers_tx = peers_tx.clone();
+            async move {
+                let _ = peers_tx.send(peer);
+            }
+        });
+
+        assert_eq!(subscribed.recv().await.unwrap(), "devices/+/telemetry");
+        let (mut ws_client, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let peer = peers.recv().await.unwrap();
+        let (mut unsubscribed, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        peers.recv().await.unwrap();
+        let subscribe = WsMessage::new(SUBSCRIBE_TOPIC, "telemetry");
+        ws_client.send(subscribe.encode().unwrap()).await.unwrap();
+        while ws_server.subscriptions(peer.id).is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // MQTT -> WebSocket
+        to_client
+            .send(Publish::new(
+                "devices/plc/telemetry",
+                QoS::AtMostOnce,
+                r#"{"temp":21.5}"#,
+            ))
+            .await
+            .unwrap();
+        let message = ws_client.next().await.unwrap().unwrap();
+        assert_eq!(
+            message.into_text().unwrap().as_str(),
+            r#"{"topic":"telemetry","payload":{"temp":21.5},"v":1}"#
+        );
+        // 未订阅的客户端收不到
+        let skipped = tokio::time::timeout(Duration::from_millis(100), unsubscribed.next()).await;
+        assert!(skipped.is_err());
+
+        // WebSocket -> MQTT
+        ws_client
+            .send(Message::Text(r#"{"topic":"cmd","payload":"0105"}"#.into()))
+            .await
+            .unwrap();
+        let publish = from_client.recv().await.unwrap();
+        assert_eq!(publish.topic, "devices/plc/cmd");
+        assert_eq!(publish.payload.as_ref(), &[0x01, 0x05]);
+    }
+
+    #[test]
+    fn test_payload_format() {
+        assert_eq!(
+            PayloadFormat::Text.to_json(b"on"),
+            Some(serde_json::json!("on"))
+        );
+        assert_eq!(PayloadFormat::Json.to_json(b"not json"), None);
+        assert_eq!(
+            PayloadFormat::Hex.to_bytes(&serde_json::json!("0AFF")),
+            Some(vec![0x0A, 0xFF])
+        );
+        assert_eq!(PayloadFormat::Hex.to_bytes(&serde_json::json!(1)), None);
+    }
+}
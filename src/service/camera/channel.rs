@@ -0,0 +1,237 @@
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, watch};
+
+use crate::service::camera::CameraFrame;
+
+/// Default queue depth for the `Block` and `DropNewest` policies.
+pub const DEFAULT_FRAME_CHANNEL_CAPACITY: usize = 1024;
+
+/// Longest time the `Block` policy waits for room in the queue before dropping a frame.
+pub const BLOCK_SEND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Pause between two attempts of a `Block` delivery while the queue is full
+const BLOCK_RETRY_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How the camera callback behaves when the frame consumer falls behind.
+///
+/// - `Block`: every frame is queued and delivered in order; the producer (the SDK
+///   callback thread) waits for room, which stalls the grab while the consumer is
+///   behind. Use it for recording / inspection where no frame may be lost. The wait is
+///   bounded by [`BLOCK_SEND_TIMEOUT`]: a consumer stalled longer loses the frame, so a
+///   stuck consumer cannot hang the SDK thread or `stop_grab`.
+/// - `DropNewest`: frames arriving while the queue is full are discarded, so
+///   the producer never waits. Use it when occasional gaps are acceptable.
+/// - `KeepLatest`: a single slot that is overwritten by every new frame; the
+///   consumer always sees the freshest frame. Use it for live-view pipelines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrameChannelPolicy {
+    #[default]
+    Block,
+    DropNewest,
+    KeepLatest,
+}
+
+type QueueReceiver = Mutex<mpsc::Receiver<CameraFrame>>;
+
+#[derive(Clone)]
+pub(crate) enum FrameSender {
+    Block(mpsc::Sender<CameraFrame>, Weak<QueueReceiver>),
+    DropNewest(mpsc::Sender<CameraFrame>, Weak<QueueReceiver>),
+    KeepLatest(watch::Sender<Option<CameraFrame>>),
+}
+
+impl FrameSender {
+    /// Take the oldest queued frame out of the queue when `limit` or more frames wait in it,
+    /// so the next frame is kept instead of the backlog. `KeepLatest` never queues.
+    ///
+    /// The check and the removal happen under the receiver lock, a frame the consumer takes
+    /// in between is never evicted on top of it.
+    pub(crate) fn evict_oldest(&self, limit: usize) -> Option<CameraFrame> {
+        match self {
+            FrameSender::Block(_, receiver) | FrameSender::DropNewest(_, receiver) => {
+                // 接收端已释放时没有可丢弃的帧
+                let receiver = receiver.upgrade()?;
+                let mut receiver = receiver.lock().unwrap();
+                if receiver.len() < limit {
+                    return None;
+                }
+                receiver.try_recv().ok()
+            }
+            FrameSender::KeepLatest(_) => None,
+        }
+    }
+
+    /// Deliver a frame from the SDK callback thread according to the policy, returns false
+    /// when the frame was dropped because the queue is full.
+    ///
+    /// `Block` waits on the calling thread for up to [`BLOCK_SEND_TIMEOUT`], so it must not
+    /// be called from a tokio worker.
+    pub(crate) fn deliver(&self, frame: CameraFrame) -> bool {
+        self.deliver_within(frame, BLOCK_SEND_TIMEOUT)
+    }
+
+    fn deliver_within(&self, frame: CameraFrame, timeout: Duration) -> bool {
+        let (sender, wait) = match self {
+            FrameSender::Block(sender, _) => (sender, true),
+            FrameSender::DropNewest(sender, _) => (sender, false),
+            FrameSender::KeepLatest(sender) => {
+                sender.send_replace(Some(frame));
+                return true;
+            }
+        };
+
+        // 不使用 blocking_send：消费者卡住时 SDK 回调线程也会一直阻塞，停止采集随之卡死
+        let deadline = Instant::now() + timeout;
+        let mut frame = frame;
+        loop {
+            match sender.try_send(frame) {
+                Ok(()) => return true,
+                Err(mpsc::error::TrySendError::Full(rejected)) => {
+                    if !wait || Instant::now() >= deadline {
+                        tracing::debug!(
+                            "frame channel full, drop frame: block_id={}",
+                            rejected.block_id
+                        );
+                        return false;
+                    }
+                    frame = rejected;
+                    std::thread::sleep(BLOCK_RETRY_INTERVAL);
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    tracing::error!("send frame to channel failed: channel closed");
+                    return true;
+                }
+            }
+        }
+    }
+}
+
+pub enum FrameReceiver {
+    /// Shared with the sender, which evicts the oldest frame through it
+    Queue(Arc<QueueReceiver>),
+    Latest(watch::Receiver<Option<CameraFrame>>),
+}
+
+impl FrameReceiver {
+    /// Receive the next frame, or `None` once the camera side has been dropped.
+    pub async fn recv(&mut self) -> Option<CameraFrame> {
+        match self {
+            // 只在每次 poll 时持锁，等待期间发送端仍可取出最旧的帧
+            FrameReceiver::Queue(receiver) => {
+                std::future::poll_fn(|cx| receiver.lock().unwrap().poll_recv(cx)).await
+            }
+            FrameReceiver::Latest(receiver) => loop {
+                receiver.changed().await.ok()?;
+                if let Some(frame) = receiver.borrow_and_update().clone() {
+                    return Some(frame);
+                }
+            },
+        }
+    }
+}
+
+pub(crate) fn frame_channel(
+    policy: FrameChannelPolicy,
+    capacity: usize,
+) -> (FrameSender, FrameReceiver) {
+    match policy {
+        FrameChannelPolicy::Block => {
+            let (sender, receiver) = mpsc::channel(capacity);
+            let receiver = Arc::new(Mutex::new(receiver));
+            let sender = FrameSender::Block(sender, Arc::downgrade(&receiver));
+            (sender, FrameReceiver::Queue(receiver))
+        }
+        FrameChannelPolicy::DropNewest => {
+            let (sender, receiver) = mpsc::channel(capacity);
+            let receiver = Arc::new(Mutex::new(receiver));
+            let sender = FrameSender::DropNewest(sender, Arc::downgrade(&receiver));
+            (sender, FrameReceiver::Queue(receiver))
+        }
+        FrameChannelPolicy::KeepLatest => {
+            let (sender, receiver) = watch::channel(None);
+            (FrameSender::KeepLatest(sender), FrameReceiver::Latest(receiver))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::camera::tests::frame;
+
+    #[tokio::test]
+    async fn test_block_in_order() {
+        let (sender, mut receiver) = frame_channel(FrameChannelPolicy::Block, 1);
+        // 模拟 SDK 回调线程，队列满时在该线程上等待
+        let producer = std::thread::spawn(move || {
+            for block_id in 1..=3 {
+                sender.deliver(frame(block_id));
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!producer.is_finished());
+
+        for block_id in 1..=3 {
+            assert_eq!(receiver.recv().await.map(|f| f.block_id), Some(block_id));
+        }
+        producer.join().unwrap();
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[test]
+    fn test_block_timeout() {
+        let (sender, _receiver) = frame_channel(FrameChannelPolicy::Block, 1);
+        assert!(sender.deliver_within(frame(1), Duration::from_millis(20)));
+        // 消费者不取帧时，等待超时后丢弃而不是一直阻塞
+        let start = Instant::now();
+        assert!(!sender.deliver_within(frame(2), Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn test_drop_newest() {
+        let (sender, mut receiver) = frame_channel(FrameChannelPolicy::DropNewest, 1);
+        assert!(sender.deliver(frame(1)));
+        assert!(!sender.deliver(frame(2)));
+        drop(sender);
+        assert_eq!(receiver.recv().await.map(|f| f.block_id), Some(1));
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_evict_oldest() {
+        let (sender, mut receiver) = frame_channel(FrameChannelPolicy::DropNewest, 4);
+        sender.deliver(frame(1));
+        assert!(sender.evict_oldest(2).is_none());
+        sender.deliver(frame(2));
+        // 达到上限时丢弃最旧的帧，保留最新的帧
+        assert_eq!(sender.evict_oldest(2).map(|f| f.block_id), Some(1));
+        sender.deliver(frame(3));
+        drop(sender);
+        assert_eq!(receiver.recv().await.map(|f| f.block_id), Some(2));
+        assert_eq!(receiver.recv().await.map(|f| f.block_id), Some(3));
+        assert!(receiver.recv().await.is_none());
+
+        let (sender, receiver) = frame_channel(FrameChannelPolicy::DropNewest, 4);
+        sender.deliver(frame(1));
+        drop(receiver);
+        assert!(sender.evict_oldest(1).is_none());
+
+        let (sender, _receiver) = frame_channel(FrameChannelPolicy::KeepLatest, 4);
+        sender.deliver(frame(1));
+        assert!(sender.evict_oldest(1).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_keep_latest() {
+        let (sender, mut receiver) = frame_channel(FrameChannelPolicy::KeepLatest, 1);
+        sender.deliver(frame(1));
+        sender.deliver(frame(2));
+        assert_eq!(receiver.recv().await.map(|f| f.block_id), Some(2));
+        drop(sender);
+        assert!(receiver.recv().await.is_none());
+    }
+}
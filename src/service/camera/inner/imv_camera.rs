@@ -6,15 +6,19 @@ use std::ffi::{CStr, CString};
 use std::os::raw::c_void;
 use std::ptr::null_mut;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use tokio::sync::{RwLock, mpsc};
+use tokio::sync::RwLock;
 
 use crate::ffi::imv::*;
+use crate::service::camera::channel::{
+    DEFAULT_FRAME_CHANNEL_CAPACITY, FrameChannelPolicy, FrameReceiver, FrameSender, frame_channel,
+};
 use crate::service::camera::{
-    CameraConfig, CameraError, CameraFrame, CameraInfo, CameraSupplier, FrameSize, GrabMode,
-    IndustryCamera, PixelFormat,
+    BalanceChannel, CameraConfig, CameraError, CameraFrame, CameraInfo, CameraSettings,
+    CameraSupplier, FrameSize, GrabMode, IndustryCamera, PixelFormat, Roi, TriggerSource,
 };
 
 pub struct IMVCameraBuilder {
@@ -134,9 +138,14 @@ impl IMVCameraBuilder {
         Ok(IMVCamera {
             inner: Arc::new(RwLock::new(CameraHandler::new(handle))),
             grab_mode: GrabMode::Continuous,
+            trigger_source: TriggerSource::Software,
             exposure_auto: false,
             exposure_time: std::time::Duration::from_millis(1000),
             frame_sender: None,
+            timestamp_tick_hz: AtomicU64::new(DEFAULT_TIMESTAMP_TICK_HZ),
+            watchdog_timeout: None,
+            watchdog: None,
+            settings: None,
         })
     }
 }
@@ -234,6 +243,7 @@ fn convert_imv_frame_to_camera_frame(frame: &IMV_Frame) -> CameraFrame {
         padding_x: frame_info.paddingX as usize,
         padding_y: frame_info.paddingY as usize,
         recv_frame_time: frame_info.recvFrameTime as u64,
+        host_recv_time: chrono::Local::now(),
         data: data.into(),
     }
 }
@@ -246,58 +256,95 @@ fn convert_imv_frame_to_camera_frame(frame: &IMV_Frame) -> CameraFrame {
 /// 3. 生命周期由 Arc 引用计数管理
 struct GrabCallbackContext {
     handle: IMV_HANDLE,
-    frame_sender: Mutex<Option<mpsc::Sender<CameraFrame>>>,
-    runtime_handle: Mutex<Option<tokio::runtime::Handle>>,
+    frame_sender: Mutex<Option<FrameSender>>,
+    frame_counter: Arc<AtomicU64>,
+    stats: FrameStats,
 }
 
 impl GrabCallbackContext {
-    fn new(handle: IMV_HANDLE) -> Self {
+    fn new(handle: IMV_HANDLE, frame_counter: Arc<AtomicU64>, stats: FrameStats) -> Self {
         Self {
             handle,
+            frame_counter,
+            stats,
             frame_sender: Mutex::new(None),
-            runtime_handle: Mutex::new(None),
         }
     }
 
-    fn set_sender(&self, sender: mpsc::Sender<CameraFrame>) {
+    fn set_sender(&self, sender: FrameSender) {
         let mut guard = self.frame_sender.lock().unwrap();
         *guard = Some(sender);
     }
 
-    fn set_runtime_handle(&self, handle: Option<tokio::runtime::Handle>) {
-        let mut guard = self.runtime_handle.lock().unwrap();
-        *guard = handle;
-    }
-
     fn clear(&self) {
         let mut sender_guard = self.frame_sender.lock().unwrap();
         *sender_guard = None;
-        let mut runtime_guard = self.runtime_handle.lock().unwrap();
-        *runtime_guard = None;
     }
 
     fn handle_frame(&self, frame: &CameraFrame) {
-        let sender_guard = self.frame_sender.lock().unwrap();
-        if let Some(sender) = sender_guard.as_ref() {
-            let runtime_guard = self.runtime_handle.lock().unwrap();
-            if let Some(handle) = runtime_guard.as_ref() {
-                let sender_clone = sender.clone();
-                let frame = frame.clone();
-                handle.spawn(async move {
-                    if let Err(e) = sender_clone.send(frame).await {
-                        tracing::error!("send frame to channel failed: {:?}", e);
-                    }
-                });
-            } else {
-                tracing::error!("No tokio runtime available");
+        self.frame_counter.fetch_add(1, Ordering::Relaxed);
+        if !frame.is_complete() {
+            let corrupt = self.stats.corrupt_frames.fetch_add(1, Ordering::Relaxed) + 1;
+            tracing::warn!(
+                "Incomplete frame: block_id={}, {} of {:?} bytes, corrupt={}",
+                frame.block_id,
+                frame.data.len(),
+                frame.expected_len(),
+                corrupt
+            );
+            if self.stats.drop_incomplete.load(Ordering::Relaxed) {
+                return;
+            }
+        }
+        // Block 策略会在此线程上等待（最长 BLOCK_SEND_TIMEOUT），不能持锁，否则 clear 会被卡住
+        let sender = self.frame_sender.lock().unwrap().clone();
+        if let Some(sender) = sender {
+            let max_in_flight = self.stats.max_in_flight.load(Ordering::Relaxed);
+            if max_in_flight > 0
+                && let Some(stale) = sender.evict_oldest(max_in_flight)
+            {
+                let dropped = self.stats.dropped_frames.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::debug!(
+                    "{} frames in flight, drop oldest frame: block_id={}, dropped={}",
+                    max_in_flight,
+                    stale.block_id,
+                    dropped
+                );
+            }
+            // 投递期间看门狗不重连
+            self.stats.delivering.store(true, Ordering::Relaxed);
+            let delivered = sender.deliver(frame.clone());
+            self.stats.delivering.store(false, Ordering::Relaxed);
+            if !delivered {
+                let dropped = self.stats.dropped_frames.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::debug!(
+                    "Frame channel full, frame dropped: block_id={}, dropped={}",
+                    frame.block_id,
+                    dropped
+                );
             }
         }
     }
 }
 
+/// 连续采集的丢帧策略与计数，重连后保持不变
+#[derive(Clone, Default)]
+struct FrameStats {
+    /// 在途帧上限，0 表示不限制
+    max_in_flight: Arc<AtomicUsize>,
+    dropped_frames: Arc<AtomicU64>,
+    drop_incomplete: Arc<AtomicBool>,
+    corrupt_frames: Arc<AtomicU64>,
+    /// 回调线程正在等待向通道投递帧
+    delivering: Arc<AtomicBool>,
+}
+
 struct CameraHandler {
     handle: IMV_HANDLE,
     grab_context: Option<Arc<GrabCallbackContext>>,
+    /// 回调收到的帧计数，供看门狗判断是否断流
+    frame_counter: Arc<AtomicU64>,
+    stats: FrameStats,
 }
 
 // SAFETY: IMV_HANDLE 是 SDK 提供的句柄，SDK 保证其 API 是线程安全的。
@@ -311,6 +358,8 @@ impl CameraHandler {
         Self {
             handle,
             grab_context: None,
+            frame_counter: Arc::new(AtomicU64::new(0)),
+            stats: FrameStats::default(),
         }
     }
 
@@ -345,6 +394,12 @@ impl CameraHandler {
     }
 
     fn stop_grab(&mut self) -> Result<(), CameraError> {
+        // 先清理回调上下文，之后的回调不再投递帧；正在投递的帧最多等待 BLOCK_SEND_TIMEOUT，
+        // IMV_StopGrabbing 等待回调返回时不会被卡住的消费者挂起
+        if let Some(ctx) = &self.grab_context {
+            ctx.clear();
+        }
+
         unsafe {
             let ret = IMV_StopGrabbing(self.handle);
             if ret != IMV_OK {
@@ -353,11 +408,6 @@ impl CameraHandler {
 
             let _ = IMV_ClearFrameBuffer(self.handle);
         }
-
-        // 清理回调上下文
-        if let Some(ctx) = &self.grab_context {
-            ctx.clear();
-        }
         self.grab_context = None;
 
         Ok(())
@@ -366,18 +416,20 @@ impl CameraHandler {
     fn start_grab(
         &mut self,
         grab_mode: GrabMode,
-        sender: Option<mpsc::Sender<CameraFrame>>,
+        sender: Option<FrameSender>,
     ) -> Result<(), CameraError> {
         // 创建独立的回调上下文
-        let context = Arc::new(GrabCallbackContext::new(self.handle));
+        let context = Arc::new(GrabCallbackContext::new(
+            self.handle,
+            self.frame_counter.clone(),
+            self.stats.clone(),
+        ));
         
         // 设置 sender（如果提供）
         if let Some(s) = sender {
             context.set_sender(s);
         }
-        
-        // 捕获当前 Tokio 运行时句柄
-        context.set_runtime_handle(tokio::runtime::Handle::try_current().ok());
+
 
         if grab_mode == GrabMode::Continuous {
             // 传递 Arc 的原始指针给 C 回调
@@ -434,16 +486,75 @@ impl CameraHandler {
         Ok(())
     }
 
-    fn sync_grab_mode(&self, grab_mode: GrabMode) -> Result<(), CameraError> {
+    fn get_enum_feature_symbol(&self, feature_name: &str) -> Result<String, CameraError> {
+        unsafe {
+            let feature_name_c_str = CString::from_str(feature_name)
+                .map_err(|e| CameraError::SystemError(format!("{:?}", e)))?;
+            let feature_name_ptr = feature_name_c_str.as_ptr() as *const i8;
+            let mut symbol = IMV_String::default();
+            let ret = IMV_GetEnumFeatureSymbol(self.handle, feature_name_ptr, &mut symbol);
+            if ret != IMV_OK {
+                return Err(CameraError::Config(format!(
+                    "获取枚举属性: {} {:?}",
+                    feature_name, ret
+                )));
+            }
+
+            Ok(CStr::from_ptr(symbol.str.as_ptr())
+                .to_string_lossy()
+                .into_owned())
+        }
+    }
+
+    fn get_enum_feature_value(&self, feature_name: &str) -> Result<u64, CameraError> {
+        unsafe {
+            let feature_name_c_str = CString::from_str(feature_name)
+                .map_err(|e| CameraError::SystemError(format!("{:?}", e)))?;
+            let feature_name_ptr = feature_name_c_str.as_ptr() as *const i8;
+            let mut value: u64 = 0;
+            let ret = IMV_GetEnumFeatureValue(self.handle, feature_name_ptr, &mut value);
+            if ret != IMV_OK {
+                return Err(CameraError::Config(format!(
+                    "获取枚举属性值: {} {:?}",
+                    feature_name, ret
+                )));
+            }
+
+            Ok(value)
+        }
+    }
+
+    /// 设置枚举属性后读回确认相机已接受
+    fn set_enum_feature_symbol_verified(
+        &self,
+        feature_name: &str,
+        enum_symbol: &str,
+    ) -> Result<(), CameraError> {
+        self.set_enum_feature_symbol(feature_name, enum_symbol)?;
+        let actual = self.get_enum_feature_symbol(feature_name)?;
+        if actual != enum_symbol {
+            return Err(CameraError::Config(format!(
+                "枚举属性未生效: {} 期望: {} 实际: {}",
+                feature_name, enum_symbol, actual
+            )));
+        }
+        Ok(())
+    }
+
+    fn sync_grab_mode(
+        &self,
+        grab_mode: GrabMode,
+        trigger_source: TriggerSource,
+    ) -> Result<(), CameraError> {
         self.set_enum_feature_symbol("TriggerSelector", "FrameStart")?;
 
         match grab_mode {
             GrabMode::Continuous => {
-                self.set_enum_feature_symbol("TriggerMode", "Off")?;
+                self.set_enum_feature_symbol_verified("TriggerMode", "Off")?;
             }
             GrabMode::SingleFrame => {
-                self.set_enum_feature_symbol("TriggerSource", "Software")?;
-                self.set_enum_feature_symbol("TriggerMode", "On")?;
+                self.set_enum_feature_symbol_verified("TriggerSource", trigger_source.symbol())?;
+                self.set_enum_feature_symbol_verified("TriggerMode", "On")?;
             }
         }
 
@@ -555,6 +666,146 @@ impl CameraHandler {
         }
     }
 
+    fn set_int_feature_value(&self, feature_name: &str, int_value: i64) -> Result<(), CameraError> {
+        unsafe {
+            let feature_name_c_str = CString::from_str(feature_name)
+                .map_err(|e| CameraError::SystemError(format!("{:?}", e)))?;
+            let feature_name_ptr = feature_name_c_str.as_ptr();
+            let ret = IMV_SetIntFeatureValue(self.handle, feature_name_ptr, int_value);
+            if ret != IMV_OK {
+                return Err(CameraError::Config(format!(
+                    "设置整数属性值: {} 值: {} {:?}",
+                    feature_name, int_value, ret
+                )));
+            }
+
+            Ok(())
+        }
+    }
+
+    fn get_bool_feature_value(&self, feature_name: &str) -> Result<bool, CameraError> {
+        unsafe {
+            let feature_name_c_str = CString::from_str(feature_name)
+                .map_err(|e| CameraError::SystemError(format!("{:?}", e)))?;
+            let feature_name_ptr = feature_name_c_str.as_ptr();
+            let mut value: IMV_BOOL = 0;
+            let ret = IMV_GetBoolFeatureValue(self.handle, feature_name_ptr, &mut value);
+            if ret != IMV_OK {
+                return Err(CameraError::Config(format!(
+                    "获取布尔属性值: {} {:?}",
+                    feature_name, ret
+                )));
+            }
+
+            Ok(value != 0)
+        }
+    }
+
+    fn set_bool_feature_value(&self, feature_name: &str, bool_value: bool) -> Result<(), CameraError> {
+        unsafe {
+            let feature_name_c_str = CString::from_str(feature_name)
+                .map_err(|e| CameraError::SystemError(format!("{:?}", e)))?;
+            let feature_name_ptr = feature_name_c_str.as_ptr();
+            let ret = IMV_SetBoolFeatureValue(self.handle, feature_name_ptr, bool_value as IMV_BOOL);
+            if ret != IMV_OK {
+                return Err(CameraError::Config(format!(
+                    "设置布尔属性值: {} 值: {} {:?}",
+                    feature_name, bool_value, ret
+                )));
+            }
+
+            Ok(())
+        }
+    }
+
+    fn sync_roi(&self, roi: &Roi) -> Result<(), CameraError> {
+        // 先清零偏移，避免新宽高与旧偏移之和越界
+        self.set_int_feature_value("OffsetX", 0)?;
+        self.set_int_feature_value("OffsetY", 0)?;
+        self.set_int_feature_value("Width", roi.width)?;
+        self.set_int_feature_value("Height", roi.height)?;
+        self.set_int_feature_value("OffsetX", roi.offset_x)?;
+        self.set_int_feature_value("OffsetY", roi.offset_y)
+    }
+
+    /// 相机当前的帧率限制，未启用时为 None
+    fn frame_rate(&self) -> Result<Option<f64>, CameraError> {
+        if !self.get_bool_feature_value("AcquisitionFrameRateEnable")? {
+            return Ok(None);
+        }
+        self.get_double_feature_value("AcquisitionFrameRate").map(Some)
+    }
+
+    /// 按顺序下发参数：ROI -> 曝光 -> 增益 -> 白平衡 -> 帧率，采集模式在 start_grab 时同步
+    fn sync_settings(&self, settings: &CameraSettings) -> Result<(), CameraError> {
+        if let Some(roi) = &settings.roi {
+            self.sync_roi(roi)?;
+        }
+        self.sync_exposure_auto(settings.exposure_auto)?;
+        if !settings.exposure_auto {
+            self.sync_exposure_time(settings.exposure_duration())?;
+        }
+        if let Some(gain) = settings.gain {
+            self.set_double_feature_value("GainRaw", gain)?;
+        }
+        if let Some(auto) = settings.white_balance_auto {
+            self.sync_white_balance_auto(auto)?;
+        }
+        if let Some(ratio) = settings.balance_ratio
+            && settings.white_balance_auto != Some(true)
+        {
+            self.sync_balance_ratio(BalanceChannel::Red, ratio.red)?;
+            self.sync_balance_ratio(BalanceChannel::Green, ratio.green)?;
+            self.sync_balance_ratio(BalanceChannel::Blue, ratio.blue)?;
+        }
+        match settings.frame_rate {
+            Some(frame_rate) => {
+                self.set_bool_feature_value("AcquisitionFrameRateEnable", true)?;
+                self.set_double_feature_value("AcquisitionFrameRate", frame_rate)?;
+            }
+            None => {
+                let _ = self.set_bool_feature_value("AcquisitionFrameRateEnable", false);
+            }
+        }
+        Ok(())
+    }
+
+    /// 黑白相机没有白平衡属性，提前返回明确的错误
+    fn ensure_feature_available(&self, feature_name: &str) -> Result<(), CameraError> {
+        let feature_name_c_str = CString::from_str(feature_name)
+            .map_err(|e| CameraError::SystemError(format!("{:?}", e)))?;
+        let available = unsafe { IMV_FeatureIsAvailable(self.handle, feature_name_c_str.as_ptr()) };
+        if available == 0 {
+            return Err(CameraError::FeatureUnavailable(feature_name.to_string()));
+        }
+        Ok(())
+    }
+
+    fn sync_white_balance_auto(&self, auto: bool) -> Result<(), CameraError> {
+        self.ensure_feature_available("BalanceWhiteAuto")?;
+        let symbol = if auto { "Continuous" } else { "Off" };
+        self.set_enum_feature_symbol_verified("BalanceWhiteAuto", symbol)
+    }
+
+    fn sync_balance_ratio(&self, channel: BalanceChannel, ratio: f64) -> Result<(), CameraError> {
+        self.ensure_feature_available("BalanceRatioSelector")?;
+        self.set_enum_feature_symbol("BalanceRatioSelector", channel.symbol())?;
+        let min = self.get_double_feature_min("BalanceRatio")?;
+        let max = self.get_double_feature_max("BalanceRatio")?;
+        let clamped = ratio.clamp(min, max.max(min));
+        if clamped != ratio {
+            tracing::warn!(
+                "Balance ratio {} of {:?} clamped to {} ({}..={})",
+                ratio,
+                channel,
+                clamped,
+                min,
+                max
+            );
+        }
+        self.set_double_feature_value("BalanceRatio", clamped)
+    }
+
     fn sync_exposure_time(&self, exposure_time: Duration) -> Result<(), CameraError> {
         let mut et = self.get_double_feature_value("ExposureTime")?;
         let exposure_min_value = self.get_double_feature_min("ExposureTime")?;
@@ -675,12 +926,157 @@ impl Drop for CameraHandler {
     }
 }
 
+/// U3V 相机时间戳单位为纳秒，GigE 相机读取 GevTimestampTickFrequency
+const DEFAULT_TIMESTAMP_TICK_HZ: u64 = 1_000_000_000;
+
 pub struct IMVCamera {
     inner: Arc<RwLock<CameraHandler>>,
     grab_mode: GrabMode,
+    trigger_source: TriggerSource,
     exposure_auto: bool,
     exposure_time: std::time::Duration,
-    frame_sender: Option<mpsc::Sender<CameraFrame>>,
+    frame_sender: Option<FrameSender>,
+    timestamp_tick_hz: AtomicU64,
+    watchdog_timeout: Option<Duration>,
+    watchdog: Option<tokio::task::JoinHandle<()>>,
+    /// 最近一次 apply_settings 成功下发的参数，相机关闭后会丢失，打开和重连后重新下发
+    settings: Option<CameraSettings>,
+}
+
+/// 断流后的重连参数
+#[derive(Clone)]
+struct GrabSettings {
+    grab_mode: GrabMode,
+    trigger_source: TriggerSource,
+    exposure_auto: bool,
+    exposure_time: Duration,
+    frame_sender: Option<FrameSender>,
+    camera_settings: Option<CameraSettings>,
+}
+
+impl IMVCamera {
+    fn grab_settings(&self) -> GrabSettings {
+        GrabSettings {
+            grab_mode: self.grab_mode,
+            trigger_source: self.trigger_source,
+            exposure_auto: self.exposure_auto,
+            exposure_time: self.exposure_time,
+            frame_sender: self.frame_sender.clone(),
+            camera_settings: self.settings.clone(),
+        }
+    }
+
+    /// 当前生效的参数，apply_settings 失败时据此回滚
+    async fn current_settings(&self) -> CameraSettings {
+        let inner = self.inner.read().await;
+        let max_in_flight = inner.stats.max_in_flight.load(Ordering::Relaxed);
+        let mut settings = CameraSettings {
+            grab_mode: self.grab_mode,
+            trigger_source: self.trigger_source,
+            exposure_auto: self.exposure_auto,
+            exposure_time_ms: self.exposure_time.as_secs_f64() * 1000.0,
+            max_in_flight: (max_in_flight > 0).then_some(max_in_flight),
+            drop_incomplete: inner.stats.drop_incomplete.load(Ordering::Relaxed),
+            ..self.settings.clone().unwrap_or_default()
+        };
+        // 帧率限制可能由相机自身的配置启用，以相机上的值为准
+        if inner.is_opened() {
+            match inner.frame_rate() {
+                Ok(frame_rate) => settings.frame_rate = frame_rate,
+                Err(e) => tracing::warn!("Read camera frame rate failed: {}", e),
+            }
+        }
+        settings
+    }
+
+    /// 下发全部参数，`restart` 为 true 时随后重新开始采集
+    async fn write_settings(
+        &mut self,
+        settings: &CameraSettings,
+        restart: bool,
+    ) -> Result<(), CameraError> {
+        self.grab_mode = settings.grab_mode;
+        self.trigger_source = settings.trigger_source;
+        self.exposure_auto = settings.exposure_auto;
+        self.exposure_time = settings.exposure_duration();
+        self.set_max_in_flight(settings.max_in_flight).await;
+        self.set_drop_incomplete(settings.drop_incomplete).await;
+        self.settings = Some(settings.clone());
+
+        {
+            let inner = self.inner.read().await;
+            if inner.is_opened() {
+                inner.sync_settings(settings)?;
+            }
+        }
+
+        if restart {
+            self.start_grab().await?;
+        }
+        Ok(())
+    }
+
+    fn start_grab_with(inner: &mut CameraHandler, settings: GrabSettings) -> Result<(), CameraError> {
+        inner.sync_grab_mode(settings.grab_mode, settings.trigger_source)?;
+        let _ = inner.sync_exposure_auto(settings.exposure_auto);
+        let _ = inner.sync_exposure_time(settings.exposure_time);
+
+        // 传递 frame_sender 到 CameraHandler
+        inner.start_grab(settings.grab_mode, settings.frame_sender)
+    }
+
+    fn stop_watchdog(&mut self) {
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.abort();
+        }
+    }
+
+    /// 连续采集看门狗：超时无帧时执行 close/open/start_grab 恢复
+    fn spawn_watchdog(&mut self, timeout: Duration) {
+        self.stop_watchdog();
+
+        let inner = self.inner.clone();
+        let settings = self.grab_settings();
+        self.watchdog = Some(tokio::spawn(async move {
+            let mut last_count = inner.read().await.frame_counter.load(Ordering::Relaxed);
+            loop {
+                tokio::time::sleep(timeout).await;
+
+                let mut inner = inner.write().await;
+                let count = inner.frame_counter.load(Ordering::Relaxed);
+                // 回调在等待消费者，不是断流；此时重连会与回调线程互相等待
+                if count != last_count || inner.stats.delivering.load(Ordering::Relaxed) {
+                    last_count = count;
+                    continue;
+                }
+
+                tracing::warn!(
+                    "No camera frame received for {:?}, reconnecting camera",
+                    timeout
+                );
+                let _ = inner.stop_grab();
+                let _ = inner.close();
+                let result = inner
+                    .open()
+                    .and_then(|_| match &settings.camera_settings {
+                        Some(camera_settings) => inner.sync_settings(camera_settings),
+                        None => Ok(()),
+                    })
+                    .and_then(|_| Self::start_grab_with(&mut inner, settings.clone()));
+                match result {
+                    Ok(()) => tracing::info!("Camera reconnected after frame timeout"),
+                    Err(e) => tracing::error!("Camera reconnect failed: {}", e),
+                }
+                last_count = inner.frame_counter.load(Ordering::Relaxed);
+            }
+        }));
+    }
+}
+
+impl Drop for IMVCamera {
+    fn drop(&mut self) {
+        self.stop_watchdog();
+    }
 }
 
 #[async_trait::async_trait]
@@ -691,7 +1087,23 @@ impl IndustryCamera for IMVCamera {
             return Ok(());
         }
 
-        inner.open()
+        inner.open()?;
+
+        // 打开后读取一次时间戳频率，非 GigE 设备使用默认值
+        let tick_hz = match inner.get_int_feature_value("GevTimestampTickFrequency") {
+            Ok(value) if value > 0 => value as u64,
+            _ => DEFAULT_TIMESTAMP_TICK_HZ,
+        };
+        self.timestamp_tick_hz.store(tick_hz, Ordering::Relaxed);
+
+        if let Some(settings) = &self.settings {
+            inner.sync_settings(settings)?;
+        }
+        Ok(())
+    }
+
+    async fn timestamp_tick_hz(&self) -> u64 {
+        self.timestamp_tick_hz.load(Ordering::Relaxed)
     }
 
     async fn is_opened(&self) -> bool {
@@ -707,6 +1119,7 @@ impl IndustryCamera for IMVCamera {
     }
 
     async fn stop_grab(&mut self) -> Result<(), CameraError> {
+        self.stop_watchdog();
         let mut inner = self.inner.write().await;
         if !inner.is_opened() {
             return Ok(());
@@ -730,13 +1143,15 @@ impl IndustryCamera for IMVCamera {
             return Ok(());
         }
 
-        inner.sync_grab_mode(self.grab_mode)?;
-        let _ = inner.sync_exposure_auto(self.exposure_auto);
-        let _ = inner.sync_exposure_time(self.exposure_time);
-        
-        // 传递 frame_sender 到 CameraHandler
-        let sender = self.frame_sender.clone();
-        inner.start_grab(self.grab_mode, sender)
+        Self::start_grab_with(&mut inner, self.grab_settings())?;
+        drop(inner);
+
+        if self.grab_mode == GrabMode::Continuous
+            && let Some(timeout) = self.watchdog_timeout
+        {
+            self.spawn_watchdog(timeout);
+        }
+        Ok(())
     }
 
     async fn close(&self) -> Result<(), CameraError> {
@@ -757,6 +1172,13 @@ impl IndustryCamera for IMVCamera {
     }
 
     async fn trigger_one_frame(&self) -> Result<CameraFrame, CameraError> {
+        if self.trigger_source.is_hardware() {
+            return Err(CameraError::TriggerError(format!(
+                "硬件触发源 {:?} 不支持软触发，请等待外部触发帧",
+                self.trigger_source
+            )));
+        }
+
         let inner = self.inner.read().await;
         inner.clear_frame_buffer()?;
 
@@ -783,8 +1205,8 @@ impl IndustryCamera for IMVCamera {
         Err(CameraError::GrabError(format!("{}", IMV_ERROR)))
     }
 
-    async fn create_frame_channel(&mut self) -> mpsc::Receiver<CameraFrame> {
-        let (sender, receiver) = mpsc::channel(1024);
+    async fn create_frame_channel(&mut self, policy: FrameChannelPolicy) -> FrameReceiver {
+        let (sender, receiver) = frame_channel(policy, DEFAULT_FRAME_CHANNEL_CAPACITY);
         self.frame_sender = Some(sender);
         receiver
     }
@@ -800,6 +1222,82 @@ impl IndustryCamera for IMVCamera {
     async fn set_exposure_time(&mut self, time: std::time::Duration) {
         self.exposure_time = time;
     }
+
+    async fn set_watchdog_timeout(&mut self, timeout: Option<Duration>) {
+        self.watchdog_timeout = timeout;
+    }
+
+    async fn set_max_in_flight(&mut self, max_in_flight: Option<usize>) {
+        let inner = self.inner.read().await;
+        inner
+            .stats
+            .max_in_flight
+            .store(max_in_flight.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    async fn dropped_frames(&self) -> u64 {
+        self.inner.read().await.stats.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    async fn set_drop_incomplete(&mut self, drop_incomplete: bool) {
+        let inner = self.inner.read().await;
+        inner
+            .stats
+            .drop_incomplete
+            .store(drop_incomplete, Ordering::Relaxed);
+    }
+
+    async fn corrupt_frames(&self) -> u64 {
+        self.inner.read().await.stats.corrupt_frames.load(Ordering::Relaxed)
+    }
+
+    async fn set_white_balance_auto(&mut self, auto: bool) -> Result<(), CameraError> {
+        let inner = self.inner.read().await;
+        if !inner.is_opened() {
+            return Err(CameraError::NotOpened("set_white_balance_auto".into()));
+        }
+        inner.sync_white_balance_auto(auto)
+    }
+
+    async fn set_balance_ratio(
+        &mut self,
+        selector: BalanceChannel,
+        ratio: f64,
+    ) -> Result<(), CameraError> {
+        let inner = self.inner.read().await;
+        if !inner.is_opened() {
+            return Err(CameraError::NotOpened("set_balance_ratio".into()));
+        }
+        inner.sync_balance_ratio(selector, ratio)
+    }
+
+    async fn get_enum_feature_symbol(&self, feature_name: &str) -> Result<String, CameraError> {
+        self.inner.read().await.get_enum_feature_symbol(feature_name)
+    }
+
+    async fn get_enum_feature_value(&self, feature_name: &str) -> Result<u64, CameraError> {
+        self.inner.read().await.get_enum_feature_value(feature_name)
+    }
+
+    async fn apply_settings(&mut self, settings: &CameraSettings) -> Result<(), CameraError> {
+        let was_grabbing = self.is_grabbing().await;
+        if was_grabbing {
+            self.stop_grab().await?;
+        }
+
+        let previous = self.current_settings().await;
+        let applied = self.settings.clone();
+        let result = self.write_settings(settings, was_grabbing).await;
+        if let Err(e) = &result {
+            // 部分参数可能已写入相机，恢复原参数并重新开始采集
+            tracing::warn!("Apply camera settings failed, restoring previous settings: {}", e);
+            if let Err(e) = self.write_settings(&previous, was_grabbing).await {
+                tracing::error!("Restore camera settings failed: {}", e);
+            }
+            self.settings = applied;
+        }
+        result
+    }
 }
 
 /// C 回调函数 - 线程安全
@@ -1,12 +1,13 @@
 use dashmap::DashMap;
 use sea_orm::{ActiveValue, DatabaseConnection};
 use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock, mpsc};
+use tokio::sync::{Mutex, RwLock};
 
 use crate::database::entity::t_camera_configs;
 use crate::service::camera::{
     CameraConfig, CameraError, CameraFrame, CameraInfo, CameraSupplier, FrameSize, GrabMode,
     IndustryCamera,
+    channel::{FrameChannelPolicy, FrameReceiver},
     inner::imv_camera,
     stream::{ActiveStream, CameraStreamConfig},
 };
@@ -120,7 +121,7 @@ impl CameraManager {
 
     /// Create a new camera config (insert to database and add to memory)
     pub async fn create_camera(&self, config: CameraConfig) -> Result<CameraConfig, CameraError> {
-        let id = uuid::Uuid::now_v7();
+        let id = crate::new_id();
         let active_model = t_camera_configs::ActiveModel {
             id: ActiveValue::set(id),
             device_user_id: ActiveValue::set(config.device_user_id.clone()),
@@ -399,7 +400,17 @@ impl CameraManager {
         &self,
         id: &uuid::Uuid,
         mode: GrabMode,
-    ) -> Result<mpsc::Receiver<CameraFrame>, CameraError> {
+    ) -> Result<FrameReceiver, CameraError> {
+        self.start_grabbing_with_policy(id, mode, FrameChannelPolicy::Block)
+            .await
+    }
+
+    pub async fn start_grabbing_with_policy(
+        &self,
+        id: &uuid::Uuid,
+        mode: GrabMode,
+        policy: FrameChannelPolicy,
+    ) -> Result<FrameReceiver, CameraError> {
         let mut managed = self
             .cameras
             .get_mut(id)
@@ -412,7 +423,7 @@ impl CameraManager {
                 camera.open().await?;
             }
 
-            let frame_rx = camera.create_frame_channel().await;
+            let frame_rx = camera.create_frame_channel(policy).await;
             camera.start_grab().await?;
             frame_rx
         } else {
@@ -1,8 +1,9 @@
 use std::{fmt::Display, str::FromStr, time::Duration};
 
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+use crate::service::camera::channel::{FrameChannelPolicy, FrameReceiver};
 
+pub mod channel;
 mod inner;
 pub mod manager;
 pub mod stream;
@@ -46,8 +47,9 @@ pub struct CameraInfo {
     pub camera_supplier: CameraSupplier,
 }
 
-#[derive(Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Copy, Debug, Default, Serialize, Deserialize)]
 pub enum GrabMode {
+    #[default]
     Continuous,
     SingleFrame,
 }
@@ -152,6 +154,36 @@ impl PixelFormat {
             _ => 1,
         }
     }
+
+    /// Bits of one pixel as transferred by the camera, packed formats included. `None` for
+    /// `Undefined` and the compressed `Mono1c`/`Mono1e`.
+    pub fn bits_per_pixel(&self) -> Option<u32> {
+        use PixelFormat::*;
+        let bits = match self {
+            Undefined | Mono1c | Mono1e => return None,
+            Mono1p => 1,
+            Mono2p => 2,
+            Mono4p => 4,
+            Mono8 | Mono8S | BayGR8 | BayRG8 | BayGB8 | BayBG8 => 8,
+            BayRG10p => 10,
+            Mono10Packed | Mono12Packed | BayGR10Packed | BayRG10Packed | BayGB10Packed
+            | BayBG10Packed | BayGR12Packed | BayRG12Packed | BayGB12Packed | BayBG12Packed
+            | BayRG12p => 12,
+            YUV4118UYYVYY | YCbCr4118CbYYCrYY | YCbCr6014118CbYYCrYY | YCbCr7094118CbYYCrYY
+            | YUV420SPNV12 => 12,
+            Mono10 | Mono12 | Mono14 | Mono16 => 16,
+            BayGR10 | BayRG10 | BayGB10 | BayBG10 | BayGR12 | BayRG12 | BayGB12 | BayBG12
+            | BayGR16 | BayRG16 | BayGB16 | BayBG16 => 16,
+            RGB565P | BGR565P | YUV4228UYVY | YUV4228 | YCbCr4228 | YCbCr4228CbYCrY
+            | YCbCr6014228 | YCbCr6014228CbYCrY | YCbCr7094228 | YCbCr7094228CbYCrY => 16,
+            RGB8 | BGR8 | YUV8UYV | YCbCr8CbYCr | YCbCr6018CbYCr | YCbCr7098CbYCr
+            | RGB8Planar => 24,
+            RGBA8 | BGRA8 | RGB10V1Packed | RGB10P32 => 32,
+            RGB12V1Packed => 36,
+            RGB10 | BGR10 | RGB12 | BGR12 | RGB16 | RGB10Planar | RGB12Planar | RGB16Planar => 48,
+        };
+        Some(bits)
+    }
 }
 
 impl std::fmt::Display for PixelFormat {
@@ -180,6 +212,42 @@ pub struct CameraFrame {
     pub padding_x: usize,
     pub padding_y: usize,
     pub recv_frame_time: u64,
+    /// host wall-clock time when the frame was copied out of the SDK
+    pub host_recv_time: chrono::DateTime<chrono::Local>,
+}
+
+impl CameraFrame {
+    /// Convert the device `timestamp` (in ticks of `tick_hz`) to a duration
+    /// since the device clock epoch.
+    pub fn timestamp_duration(&self, tick_hz: u64) -> Duration {
+        if tick_hz == 0 {
+            return Duration::ZERO;
+        }
+        let secs = self.timestamp / tick_hz;
+        let nanos = (self.timestamp % tick_hz) as u128 * 1_000_000_000 / tick_hz as u128;
+        Duration::new(secs, nanos as u32)
+    }
+
+    pub fn recv_wallclock(&self) -> chrono::DateTime<chrono::Local> {
+        self.host_recv_time
+    }
+
+    /// Bytes needed for `frame_size` in `pixel_format`, `None` when the format is unknown
+    pub fn expected_len(&self) -> Option<usize> {
+        let bits = self.pixel_format.bits_per_pixel()? as usize;
+        Some((self.frame_size.width * self.frame_size.height * bits).div_ceil(8))
+    }
+
+    /// `data` holds the `size` bytes reported by the SDK and at least a whole image, line
+    /// padding allowed. `false` for a truncated transfer. Only `size` is checked for unknown
+    /// pixel formats.
+    pub fn is_complete(&self) -> bool {
+        if self.data.len() != self.size {
+            return false;
+        }
+        self.expected_len()
+            .is_none_or(|expected| self.data.len() >= expected)
+    }
 }
 
 /// Frame encoding format
@@ -210,6 +278,115 @@ impl std::fmt::Display for FrameEncoding {
     }
 }
 
+/// Trigger source used in `SingleFrame` mode
+#[derive(Clone, PartialEq, Eq, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum TriggerSource {
+    /// Frames are triggered by [`IndustryCamera::trigger_one_frame`]
+    #[default]
+    Software,
+    Line0,
+    Line1,
+    Encoder,
+}
+
+impl TriggerSource {
+    /// GenICam `TriggerSource` symbol
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TriggerSource::Software => "Software",
+            TriggerSource::Line0 => "Line0",
+            TriggerSource::Line1 => "Line1",
+            TriggerSource::Encoder => "Encoder",
+        }
+    }
+
+    pub fn is_hardware(&self) -> bool {
+        *self != TriggerSource::Software
+    }
+}
+
+/// Region of interest, applied while the camera is not grabbing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Roi {
+    pub offset_x: i64,
+    pub offset_y: i64,
+    pub width: i64,
+    pub height: i64,
+}
+
+/// Color channel of `BalanceRatioSelector`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BalanceChannel {
+    Red,
+    Green,
+    Blue,
+}
+
+impl BalanceChannel {
+    /// GenICam `BalanceRatioSelector` symbol
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BalanceChannel::Red => "Red",
+            BalanceChannel::Green => "Green",
+            BalanceChannel::Blue => "Blue",
+        }
+    }
+}
+
+/// Manual white balance gains, only written while `BalanceWhiteAuto` is off
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BalanceRatio {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+}
+
+/// All camera tunables, applied together by [`IndustryCamera::apply_settings`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CameraSettings {
+    pub grab_mode: GrabMode,
+    pub trigger_source: TriggerSource,
+    pub exposure_auto: bool,
+    pub exposure_time_ms: f64,
+    pub gain: Option<f64>,
+    pub frame_rate: Option<f64>,
+    pub roi: Option<Roi>,
+    /// See [`IndustryCamera::set_max_in_flight`]
+    pub max_in_flight: Option<usize>,
+    /// See [`IndustryCamera::set_drop_incomplete`]
+    pub drop_incomplete: bool,
+    /// Color cameras only, leave unset for mono cameras
+    pub white_balance_auto: Option<bool>,
+    /// Color cameras only, ignored while `white_balance_auto` is on
+    pub balance_ratio: Option<BalanceRatio>,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            grab_mode: GrabMode::Continuous,
+            trigger_source: TriggerSource::Software,
+            exposure_auto: false,
+            exposure_time_ms: 1000.0,
+            gain: None,
+            frame_rate: None,
+            roi: None,
+            max_in_flight: None,
+            drop_incomplete: false,
+            white_balance_auto: None,
+            balance_ratio: None,
+        }
+    }
+}
+
+impl CameraSettings {
+    pub fn exposure_duration(&self) -> Duration {
+        Duration::from_micros((self.exposure_time_ms * 1000.0) as u64)
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CameraConfig {
@@ -292,6 +469,8 @@ impl From<CameraConfig> for crate::database::entity::t_camera_configs::ActiveMod
 #[async_trait::async_trait]
 pub trait IndustryCamera: Send + Sync {
     async fn open(&self) -> Result<(), CameraError>;
+    /// Device timestamp tick frequency, see [`CameraFrame::timestamp_duration`].
+    async fn timestamp_tick_hz(&self) -> u64;
     async fn is_opened(&self) -> bool;
     async fn is_grabbing(&self) -> bool;
     async fn stop_grab(&mut self) -> Result<(), CameraError>;
@@ -299,10 +478,54 @@ pub trait IndustryCamera: Send + Sync {
     async fn close(&self) -> Result<(), CameraError>;
     async fn frame_size(&self) -> Result<FrameSize, CameraError>;
     async fn trigger_one_frame(&self) -> Result<CameraFrame, CameraError>;
-    async fn create_frame_channel(&mut self) -> mpsc::Receiver<CameraFrame>;
+    async fn create_frame_channel(&mut self, policy: FrameChannelPolicy) -> FrameReceiver;
     async fn set_grab_mode(&mut self, grab_mode: GrabMode);
     async fn set_exposure_auto(&mut self, auto: bool);
     async fn set_exposure_time(&mut self, time: std::time::Duration);
+    /// Reconnect the camera when no frame arrives within `timeout` during
+    /// continuous grabbing; `None` disables the watchdog.
+    async fn set_watchdog_timeout(&mut self, timeout: Option<Duration>);
+    /// Keep at most `max_in_flight` frames of continuous grabbing waiting in the frame channel,
+    /// `None` (the default) keeps all of them.
+    ///
+    /// When the limit is reached the oldest queued frame is dropped to make room for the new
+    /// one, so memory stays bounded and live view catches up with fresh frames instead of a
+    /// backlog. Dropped frames are counted by [`IndustryCamera::dropped_frames`]. With
+    /// [`FrameChannelPolicy::Block`] the callback then only waits when the channel capacity is
+    /// below the limit, so recording or inspection that needs every frame must keep the limit
+    /// unset. The limit has no effect with [`FrameChannelPolicy::KeepLatest`], which never
+    /// queues.
+    async fn set_max_in_flight(&mut self, max_in_flight: Option<usize>);
+    /// Frames dropped since the camera was created, because of
+    /// [`IndustryCamera::set_max_in_flight`] or because the frame channel stayed full (at
+    /// once for [`FrameChannelPolicy::DropNewest`], after
+    /// [`channel::BLOCK_SEND_TIMEOUT`] for [`FrameChannelPolicy::Block`]).
+    async fn dropped_frames(&self) -> u64;
+    /// Discard frames of continuous grabbing that fail [`CameraFrame::is_complete`] instead
+    /// of delivering them. Incomplete frames are logged either way.
+    async fn set_drop_incomplete(&mut self, drop_incomplete: bool);
+    /// Incomplete frames received since the camera was created, counted apart from
+    /// [`IndustryCamera::dropped_frames`].
+    async fn corrupt_frames(&self) -> u64;
+    /// Continuous automatic white balance, or off to keep the current ratios. Fails with
+    /// [`CameraError::FeatureUnavailable`] on mono cameras.
+    async fn set_white_balance_auto(&mut self, auto: bool) -> Result<(), CameraError>;
+    /// Gain of one color channel, clamped to the range the camera accepts. Turn automatic
+    /// white balance off first. Fails with [`CameraError::FeatureUnavailable`] on mono cameras.
+    async fn set_balance_ratio(
+        &mut self,
+        selector: BalanceChannel,
+        ratio: f64,
+    ) -> Result<(), CameraError>;
+    /// Read back the current symbol of an enum feature, e.g. `TriggerMode` -> `"On"`.
+    async fn get_enum_feature_symbol(&self, feature_name: &str) -> Result<String, CameraError>;
+    async fn get_enum_feature_value(&self, feature_name: &str) -> Result<u64, CameraError>;
+    /// Apply all settings in one step, stopping and restarting the grab if needed.
+    ///
+    /// When a setting fails, the previous ones are restored and the grab restarted before the
+    /// error is returned. The applied settings are kept and written again each time the
+    /// camera is reopened, including reconnects of the frame watchdog.
+    async fn apply_settings(&mut self, settings: &CameraSettings) -> Result<(), CameraError>;
 }
 
 /// Camera error types
@@ -353,6 +576,9 @@ pub enum CameraError {
     AddCamera(String),
 
     SystemError(String),
+
+    /// The camera has no such feature, e.g. white balance on a mono camera
+    FeatureUnavailable(String),
 }
 
 impl std::fmt::Display for CameraError {
@@ -375,8 +601,116 @@ impl std::fmt::Display for CameraError {
             CameraError::Config(msg) => write!(f, "相机参数配置错误：{}", msg),
             CameraError::AddCamera(msg) => write!(f, "添加相机错误：{}", msg),
             CameraError::SystemError(msg) => write!(f, "系统错误：{}", msg),
+            CameraError::FeatureUnavailable(name) => write!(f, "相机不支持属性：{}", name),
         }
     }
 }
 
 impl std::error::Error for CameraError {}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Empty Mono8 frame, override fields with struct update syntax
+    pub(crate) fn frame(block_id: u64) -> CameraFrame {
+        CameraFrame {
+            data: bytes::Bytes::new(),
+            block_id,
+            status: 0,
+            frame_size: FrameSize {
+                width: 0,
+                height: 0,
+            },
+            size: 0,
+            pixel_format: PixelFormat::Mono8,
+            timestamp: 0,
+            chunk_count: 0,
+            padding_x: 0,
+            padding_y: 0,
+            recv_frame_time: 0,
+            host_recv_time: chrono::Local::now(),
+        }
+    }
+
+    #[test]
+    fn test_timestamp_duration() {
+        let frame = CameraFrame {
+            timestamp: 2_500_000_000,
+            ..frame(0)
+        };
+        assert_eq!(frame.timestamp_duration(1_000_000_000), Duration::from_millis(2500));
+        assert_eq!(frame.timestamp_duration(125_000_000), Duration::from_secs(20));
+        assert_eq!(frame.timestamp_duration(0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_frame_is_complete() {
+        let frame = CameraFrame {
+            data: bytes::Bytes::from(vec![0; 12]),
+            frame_size: FrameSize {
+                width: 4,
+                height: 2,
+            },
+            size: 12,
+            pixel_format: PixelFormat::Mono12Packed,
+            ..frame(0)
+        };
+        assert_eq!(frame.expected_len(), Some(12));
+        assert!(frame.is_complete());
+
+        let truncated = CameraFrame {
+            data: bytes::Bytes::from(vec![0; 8]),
+            size: 8,
+            ..frame.clone()
+        };
+        assert!(!truncated.is_complete());
+        // 数据长度与 SDK 上报的 size 不符
+        let short = CameraFrame {
+            size: 16,
+            ..frame.clone()
+        };
+        assert!(!short.is_complete());
+
+        let rgb = CameraFrame {
+            pixel_format: PixelFormat::RGB8,
+            ..frame.clone()
+        };
+        assert_eq!(rgb.expected_len(), Some(24));
+        assert!(!rgb.is_complete());
+        let unknown = CameraFrame {
+            pixel_format: PixelFormat::Undefined,
+            ..frame
+        };
+        assert!(unknown.is_complete());
+    }
+
+    #[test]
+    fn test_camera_settings_deserialize() {
+        let settings: CameraSettings = serde_json::from_str(
+            r#"{"exposureTimeMs": 5.0, "gain": 2.0, "roi": {"offsetX": 8, "offsetY": 4, "width": 640, "height": 480}}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.grab_mode, GrabMode::Continuous);
+        assert_eq!(settings.trigger_source, TriggerSource::Software);
+        assert!(!settings.exposure_auto);
+        assert_eq!(settings.exposure_duration(), Duration::from_micros(5000));
+        assert_eq!(settings.gain, Some(2.0));
+        assert_eq!(settings.frame_rate, None);
+        assert_eq!(settings.roi.map(|roi| roi.width), Some(640));
+    }
+
+    #[test]
+    fn test_white_balance_settings() {
+        let settings: CameraSettings = serde_json::from_value(serde_json::json!({
+            "whiteBalanceAuto": false,
+            "balanceRatio": {"red": 1.5, "green": 1.0, "blue": 2.1},
+        }))
+        .unwrap();
+        assert_eq!(settings.white_balance_auto, Some(false));
+        assert_eq!(settings.balance_ratio.map(|ratio| ratio.blue), Some(2.1));
+        assert_eq!(BalanceChannel::Green.symbol(), "Green");
+        // 黑白相机默认不下发白平衡
+        assert_eq!(CameraSettings::default().white_balance_auto, None);
+    }
+}
@@ -149,7 +149,7 @@ impl StationManager {
         &self,
         request: StationCreateRequest,
     ) -> Result<Uuid, errors::Error> {
-        let id = Uuid::now_v7();
+        let id = crate::new_id();
         let detection_types_json = serde_json::to_value(&request.detection_types)?;
 
         let is_enabled = request.is_enabled.unwrap_or(true);
@@ -329,7 +329,7 @@ impl StationManager {
         station_id: Uuid,
         request: RoiCreateRequest,
     ) -> Result<Uuid, errors::Error> {
-        let roi_id = Uuid::now_v7();
+        let roi_id = crate::new_id();
         let purpose = request
             .purpose
             .unwrap_or(t_station_rois::RoiPurpose::Detection);
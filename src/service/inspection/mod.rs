@@ -558,7 +558,7 @@ impl InspectionManager {
         let json_value = serde_json::to_value(&settings)?;
         if setting_model.is_none() {
             let model = t_settings::ActiveModel {
-                id: ActiveValue::set(Uuid::now_v7()),
+                id: ActiveValue::set(crate::new_id()),
                 key: ActiveValue::set(keys::INSPECTION.into()),
                 value: ActiveValue::set(json_value),
                 ..Default::default()
@@ -608,7 +608,7 @@ impl InspectionManager {
         detection_result: &DetectionResult,
         class_to_detection_type: &DashMap<String, DetectionType>,
     ) -> Result<(), errors::Error> {
-        let record_id = Uuid::now_v7();
+        let record_id = crate::new_id();
         let now = chrono::Utc::now();
 
         let overall_result = if detection_result.is_ok { "OK" } else { "NG" };
@@ -669,7 +669,7 @@ impl InspectionManager {
 
         // Insert t_inspection_details and t_defect_details for each detection
         for detection in &detection_result.detections {
-            let detail_id = Uuid::now_v7();
+            let detail_id = crate::new_id();
 
             let result = if detection.is_ok() { "OK" } else { "NG" };
 
@@ -764,7 +764,7 @@ impl InspectionManager {
                         .unwrap_or_else(|| base_class_name.to_string());
 
                     let defect_model = t_defect_details::ActiveModel {
-                        id: ActiveValue::set(Uuid::now_v7()),
+                        id: ActiveValue::set(crate::new_id()),
                         inspection_detail_id: ActiveValue::set(detail_id),
                         defect_type: ActiveValue::set(defect_type),
                         defect_code: ActiveValue::set(None),
@@ -546,7 +546,7 @@ mod tests {
 
     #[test]
     fn test_roi_config_creation() {
-        let roi_id = uuid::Uuid::now_v7();
+        let roi_id = crate::new_id();
         let roi = RoiConfig::detection_rect(roi_id.clone(), "检测区1", 0.1, 0.2, 0.3, 0.4);
         assert_eq!(roi.id, roi_id);
         assert_eq!(roi.name, "检测区1");
@@ -557,7 +557,7 @@ mod tests {
     #[test]
     fn test_station_config_roi_management() {
         let mut config = StationConfig {
-            id: uuid::Uuid::now_v7(),
+            id: crate::new_id(),
             name: "测试站".to_string(),
             camera_id: uuid::Uuid::nil(),
             trigger_mode: TriggerMode::default(),
@@ -574,13 +574,13 @@ mod tests {
         };
 
         // Add ROI
-        let roi1_id = uuid::Uuid::now_v7();
+        let roi1_id = crate::new_id();
         let roi1 = RoiConfig::detection_rect(roi1_id.clone(), "区域1", 0.1, 0.1, 0.2, 0.2);
         config.set_roi(roi1);
         assert_eq!(config.rois.len(), 1);
 
         // Add another ROI
-        let roi2_id = uuid::Uuid::now_v7();
+        let roi2_id = crate::new_id();
         let roi2 = RoiConfig::new(
             roi2_id.clone(),
             "排除区",
@@ -611,7 +611,7 @@ mod tests {
     #[test]
     fn test_station_config_legacy_roi_compatibility() {
         let config = StationConfig {
-            id: uuid::Uuid::now_v7(),
+            id: crate::new_id(),
             name: "测试站".to_string(),
             camera_id: uuid::Uuid::nil(),
             trigger_mode: TriggerMode::default(),
@@ -634,7 +634,7 @@ mod tests {
 
     #[test]
     fn test_roi_serialization() {
-        let roi_id = uuid::Uuid::now_v7();
+        let roi_id = crate::new_id();
         let roi = RoiConfig::detection_rect(roi_id.clone(), "检测区", 0.1, 0.2, 0.3, 0.4);
         let json = serde_json::to_string(&roi).unwrap();
         assert!(json.contains("\"id\":\"roi_1\""));
@@ -0,0 +1,176 @@
+use std::{
+    fmt::Write,
+    sync::{
+        LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// Process wide counters updated by the services at their existing log points.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub ws_messages_in: AtomicU64,
+    pub ws_messages_out: AtomicU64,
+    pub socket_bytes_in: AtomicU64,
+    pub socket_bytes_out: AtomicU64,
+    pub modbus_read_ok: AtomicU64,
+    pub modbus_read_errors: AtomicU64,
+    pub modbus_read_timeouts: AtomicU64,
+    pub modbus_write_ok: AtomicU64,
+    pub modbus_write_errors: AtomicU64,
+    pub modbus_write_timeouts: AtomicU64,
+    pub serial_frames_in: AtomicU64,
+    pub serial_frames_out: AtomicU64,
+    pub serial_errors: AtomicU64,
+    pub serial_timeouts: AtomicU64,
+}
+
+impl Metrics {
+    #[inline]
+    pub fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        MetricsSnapshot {
+            ws_messages_in: load(&self.ws_messages_in),
+            ws_messages_out: load(&self.ws_messages_out),
+            socket_bytes_in: load(&self.socket_bytes_in),
+            socket_bytes_out: load(&self.socket_bytes_out),
+            modbus_read_ok: load(&self.modbus_read_ok),
+            modbus_read_errors: load(&self.modbus_read_errors),
+            modbus_read_timeouts: load(&self.modbus_read_timeouts),
+            modbus_write_ok: load(&self.modbus_write_ok),
+            modbus_write_errors: load(&self.modbus_write_errors),
+            modbus_write_timeouts: load(&self.modbus_write_timeouts),
+            serial_frames_in: load(&self.serial_frames_in),
+            serial_frames_out: load(&self.serial_frames_out),
+            serial_errors: load(&self.serial_errors),
+            serial_timeouts: load(&self.serial_timeouts),
+        }
+    }
+
+    /// Record the outcome of a modbus call: timeouts are transport errors of kind `TimedOut`,
+    /// exceptions and other transport errors count as errors.
+    #[cfg(feature = "modbus")]
+    pub fn record_modbus<T>(&self, write: bool, result: &tokio_modbus::Result<T>) {
+        let (ok, errors, timeouts) = if write {
+            (
+                &self.modbus_write_ok,
+                &self.modbus_write_errors,
+                &self.modbus_write_timeouts,
+            )
+        } else {
+            (
+                &self.modbus_read_ok,
+                &self.modbus_read_errors,
+                &self.modbus_read_timeouts,
+            )
+        };
+        match result {
+            Ok(Ok(_)) => Self::incr(ok),
+            Err(tokio_modbus::Error::Transport(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
+                Self::incr(timeouts)
+            }
+            _ => Self::incr(errors),
+        }
+    }
+
+    /// Record a failed serial operation, separating timeouts from other errors.
+    pub fn record_serial_error(&self, error: &std::io::Error) {
+        if error.kind() == std::io::ErrorKind::TimedOut {
+            Self::incr(&self.serial_timeouts);
+        } else {
+            Self::incr(&self.serial_errors);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsSnapshot {
+    pub ws_messages_in: u64,
+    pub ws_messages_out: u64,
+    pub socket_bytes_in: u64,
+    pub socket_bytes_out: u64,
+    pub modbus_read_ok: u64,
+    pub modbus_read_errors: u64,
+    pub modbus_read_timeouts: u64,
+    pub modbus_write_ok: u64,
+    pub modbus_write_errors: u64,
+    pub modbus_write_timeouts: u64,
+    pub serial_frames_in: u64,
+    pub serial_frames_out: u64,
+    pub serial_errors: u64,
+    pub serial_timeouts: u64,
+}
+
+impl MetricsSnapshot {
+    /// Render the snapshot in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let counters = [
+            ("ws_messages_in", self.ws_messages_in),
+            ("ws_messages_out", self.ws_messages_out),
+            ("socket_bytes_in", self.socket_bytes_in),
+            ("socket_bytes_out", self.socket_bytes_out),
+            ("modbus_read_ok", self.modbus_read_ok),
+            ("modbus_read_errors", self.modbus_read_errors),
+            ("modbus_read_timeouts", self.modbus_read_timeouts),
+            ("modbus_write_ok", self.modbus_write_ok),
+            ("modbus_write_errors", self.modbus_write_errors),
+            ("modbus_write_timeouts", self.modbus_write_timeouts),
+            ("serial_frames_in", self.serial_frames_in),
+            ("serial_frames_out", self.serial_frames_out),
+            ("serial_errors", self.serial_errors),
+            ("serial_timeouts", self.serial_timeouts),
+        ];
+        let mut output = String::new();
+        for (name, value) in counters {
+            let _ = writeln!(output, "# TYPE lean_link_{}_total counter", name);
+            let _ = writeln!(output, "lean_link_{}_total {}", name, value);
+        }
+        output
+    }
+}
+
+/// Global metrics instance shared by all services.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+pub fn metrics_snapshot() -> MetricsSnapshot {
+    METRICS.snapshot()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_and_prometheus() {
+        let metrics = Metrics::default();
+        Metrics::incr(&metrics.ws_messages_in);
+        Metrics::add(&metrics.socket_bytes_out, 42);
+        metrics.record_serial_error(&std::io::Error::from(std::io::ErrorKind::TimedOut));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.ws_messages_in, 1);
+        assert_eq!(snapshot.socket_bytes_out, 42);
+        assert_eq!(snapshot.serial_timeouts, 1);
+        assert_eq!(snapshot.serial_errors, 0);
+
+        let text = snapshot.to_prometheus();
+        assert!(text.contains("lean_link_socket_bytes_out_total 42\n"));
+        assert!(text.contains("# TYPE lean_link_ws_messages_in_total counter\n"));
+    }
+}
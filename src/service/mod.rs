@@ -1,11 +1,17 @@
+#[cfg(all(feature = "mqtt", any(feature = "web", feature = "websocket")))]
+pub mod bridge;
 #[cfg(any(feature = "industry-camera", feature = "inspection"))]
 pub mod camera;
 #[cfg(feature = "inspection")]
 pub mod inspection;
+pub mod metrics;
 #[cfg(feature = "modbus")]
 pub mod modbus;
 #[cfg(feature = "mqtt")]
 pub mod mqtt;
+pub mod self_check;
+#[cfg(all(feature = "serialport", feature = "mqtt"))]
+pub mod serial_mqtt;
 #[cfg(feature = "serialport")]
 pub mod serialport;
 #[cfg(feature = "socket")]
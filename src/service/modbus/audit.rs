@@ -0,0 +1,136 @@
+// Audit trail of writes made through a `ModbusService`.
+
+use std::sync::Arc;
+
+use sea_orm::DatabaseConnection;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::database::{entity::t_logs::LogLevel, logs::insert_log};
+
+/// `t_logs.action` of entries written by [`db_write_hook`]
+pub const MODBUS_WRITE_ACTION: &str = "modbus.write";
+
+/// One write request and its outcome
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModbusWrite {
+    /// Serial path or `host:port`
+    pub device: String,
+    /// Name of the `ModbusService` method, e.g. `write_single_register`
+    pub function: &'static str,
+    pub addr: u16,
+    /// Written words, coils as 0/1, `[and_mask, or_mask]` for masked writes
+    pub values: Vec<u16>,
+    /// Transport error or exception code, `None` when the slave accepted the write
+    pub error: Option<String>,
+}
+
+impl ModbusWrite {
+    pub(crate) fn new<T>(
+        device: String,
+        function: &'static str,
+        addr: u16,
+        values: Vec<u16>,
+        result: &tokio_modbus::Result<T>,
+    ) -> Self {
+        let error = match result {
+            Ok(Ok(_)) => None,
+            Ok(Err(code)) => Some(code.to_string()),
+            Err(e) => Some(e.to_string()),
+        };
+        Self {
+            device,
+            function,
+            addr,
+            values,
+            error,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Called after every write of a `ModbusService`, see `ModbusService::with_write_hook`.
+///
+/// Runs inline on the polling task, so it should hand the record off rather than block.
+pub type WriteHook = Arc<dyn Fn(ModbusWrite) + Send + Sync>;
+
+/// Hook recording each write to `t_logs` (`Info`, or `Error` when the write failed),
+/// `user_id` is the operator on whose behalf the service writes.
+///
+/// Inserts run on spawned tasks so the bus is not held up by the database, a failed insert
+/// is logged and dropped.
+pub fn db_write_hook(conn: DatabaseConnection, user_id: Option<Uuid>) -> WriteHook {
+    Arc::new(move |write: ModbusWrite| {
+        let conn = conn.clone();
+        tokio::spawn(async move {
+            let level = if write.is_ok() {
+                LogLevel::Info
+            } else {
+                LogLevel::Error
+            };
+            let details = match serde_json::to_value(&write) {
+                Ok(details) => details,
+                Err(e) => {
+                    tracing::error!("Failed to serialize modbus write: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = insert_log(
+                &conn,
+                user_id.unwrap_or_default(),
+                MODBUS_WRITE_ACTION.to_string(),
+                details,
+                level,
+            )
+            .await
+            {
+                tracing::error!("Failed to record modbus write: {}", e);
+            }
+        });
+    })
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::time::Duration;
+
+    use sea_orm::EntityTrait;
+
+    use super::*;
+    use crate::database::entity::prelude::TLogs;
+
+    #[tokio::test]
+    async fn test_db_write_hook() {
+        let conn = crate::database::test_db(TLogs).await;
+
+        let user = crate::new_id();
+        let hook = db_write_hook(conn.clone(), Some(user));
+        let result: tokio_modbus::Result<()> = Ok(Err(tokio_modbus::ExceptionCode::IllegalDataValue));
+        hook(ModbusWrite::new(
+            "/dev/ttyUSB0".into(),
+            "write_single_register",
+            40,
+            vec![1500],
+            &result,
+        ));
+
+        let logs = loop {
+            let logs = TLogs::find().all(&conn).await.unwrap();
+            if !logs.is_empty() {
+                break logs;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        };
+        assert_eq!(logs[0].action, MODBUS_WRITE_ACTION);
+        assert_eq!(logs[0].level, LogLevel::Error);
+        assert_eq!(logs[0].user_id, Some(user));
+        assert_eq!(logs[0].details["function"], "write_single_register");
+        assert_eq!(logs[0].details["addr"], 40);
+        assert_eq!(logs[0].details["values"], serde_json::json!([1500]));
+        assert!(logs[0].details["error"].is_string());
+    }
+}
@@ -0,0 +1,108 @@
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+/// Last known value of one coil/register together with its age.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CachedValue<T> {
+    pub value: T,
+    pub age: Duration,
+    /// `age` exceeded the cache staleness threshold
+    pub stale: bool,
+}
+
+/// Per-address cache of coils (`bool`) or registers (`u16`).
+///
+/// The poller populates it through the read methods of `ModbusService`, writes
+/// invalidate the written range so a read never returns a value older than the
+/// last write. Readers can serve from the cache and only hit the bus on refresh.
+pub struct RegisterCache<T> {
+    values: DashMap<u16, (T, Instant)>,
+    stale_after: Duration,
+}
+
+impl<T: Copy> RegisterCache<T> {
+    pub fn new(stale_after: Duration) -> Self {
+        Self {
+            values: DashMap::new(),
+            stale_after,
+        }
+    }
+
+    pub fn stale_after(&self) -> Duration {
+        self.stale_after
+    }
+
+    /// Store consecutive values read starting at `addr`.
+    pub fn update(&self, addr: u16, values: &[T]) {
+        let now = Instant::now();
+        for (offset, value) in values.iter().enumerate() {
+            self.values
+                .insert(addr.wrapping_add(offset as u16), (*value, now));
+        }
+    }
+
+    /// Drop `cnt` cached values starting at `addr`.
+    pub fn invalidate(&self, addr: u16, cnt: u16) {
+        for offset in 0..cnt {
+            self.values.remove(&addr.wrapping_add(offset));
+        }
+    }
+
+    pub fn clear(&self) {
+        self.values.clear();
+    }
+
+    /// Last known value of `addr` and its age, `None` if never read or invalidated.
+    pub fn get_cached(&self, addr: u16) -> Option<CachedValue<T>> {
+        self.values.get(&addr).map(|entry| {
+            let (value, updated_at) = *entry;
+            let age = updated_at.elapsed();
+            CachedValue {
+                value,
+                age,
+                stale: age > self.stale_after,
+            }
+        })
+    }
+
+    /// Cached values for `cnt` addresses starting at `addr`, only if all are present and fresh.
+    pub fn get_fresh_range(&self, addr: u16, cnt: u16) -> Option<Vec<T>> {
+        (0..cnt)
+            .map(|offset| {
+                self.get_cached(addr.wrapping_add(offset))
+                    .filter(|cached| !cached.stale)
+                    .map(|cached| cached.value)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_and_invalidate() {
+        let cache = RegisterCache::new(Duration::from_secs(60));
+        cache.update(100, &[1u16, 2, 3]);
+        assert_eq!(cache.get_cached(101).map(|cached| cached.value), Some(2));
+        assert_eq!(cache.get_fresh_range(100, 3), Some(vec![1, 2, 3]));
+
+        cache.invalidate(101, 1);
+        assert!(cache.get_cached(101).is_none());
+        assert_eq!(cache.get_cached(102).map(|cached| cached.value), Some(3));
+        assert_eq!(cache.get_fresh_range(100, 3), None);
+    }
+
+    #[test]
+    fn test_staleness() {
+        let cache = RegisterCache::new(Duration::ZERO);
+        cache.update(0, &[true]);
+        std::thread::sleep(Duration::from_millis(1));
+        let cached = cache.get_cached(0).unwrap();
+        assert!(cached.value);
+        assert!(cached.stale);
+        assert_eq!(cache.get_fresh_range(0, 1), None);
+    }
+}
@@ -0,0 +1,279 @@
+// Decode a block of registers into typed fields, see `registers_to_f32` for the orders.
+
+use serde::{Deserialize, Serialize};
+
+/// Type of one field in a register block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    F64,
+    /// ASCII text packed two characters per register, the value is the register count
+    String(usize),
+}
+
+impl FieldType {
+    /// Number of registers the field occupies
+    pub fn registers(&self) -> usize {
+        match self {
+            FieldType::U16 | FieldType::I16 => 1,
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => 2,
+            FieldType::F64 => 4,
+            FieldType::String(n) => *n,
+        }
+    }
+}
+
+/// A decoded field, serialized as the bare value
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum DecodedValue {
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+    F64(f64),
+    String(String),
+}
+
+/// 按 `layout` 依次解码寄存器块，每个字段消耗对应数量的寄存器
+/// register_order: 多寄存器字段的寄存器顺序
+///   - 'high_first': 第一个寄存器为最高16位
+///   - 'low_first': 第一个寄存器为最低16位
+///
+/// byte_order: 字节顺序
+///   - 'big_endian': 大端序
+///   - 'little_endian': 小端序（字符串为每个寄存器低字节在前）
+///
+/// Registers left over after the last field are ignored.
+pub fn decode_block(
+    regs: &[u16],
+    layout: &[FieldType],
+    register_order: &str,
+    byte_order: &str,
+) -> std::result::Result<Vec<DecodedValue>, String> {
+    let needed: usize = layout.iter().map(FieldType::registers).sum();
+    if regs.len() < needed {
+        return Err(format!(
+            "Layout needs {} registers, got {}",
+            needed,
+            regs.len()
+        ));
+    }
+
+    let mut values = Vec::with_capacity(layout.len());
+    let mut offset = 0;
+    for field in layout {
+        let field_regs = &regs[offset..offset + field.registers()];
+        offset += field.registers();
+
+        if let FieldType::String(_) = field {
+            values.push(DecodedValue::String(registers_to_string(field_regs, byte_order)?));
+            continue;
+        }
+
+        let bytes = field_bytes(field_regs, register_order, byte_order)?;
+        let value = match field {
+            FieldType::U16 => DecodedValue::U16(u16::from_be_bytes([bytes[0], bytes[1]])),
+            FieldType::I16 => DecodedValue::I16(i16::from_be_bytes([bytes[0], bytes[1]])),
+            FieldType::U32 => DecodedValue::U32(u32::from_be_bytes(bytes[..4].try_into().unwrap())),
+            FieldType::I32 => DecodedValue::I32(i32::from_be_bytes(bytes[..4].try_into().unwrap())),
+            FieldType::F32 => DecodedValue::F32(f32::from_be_bytes(bytes[..4].try_into().unwrap())),
+            FieldType::F64 => DecodedValue::F64(f64::from_be_bytes(bytes[..8].try_into().unwrap())),
+            FieldType::String(_) => unreachable!(),
+        };
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// Bytes of a numeric field, most significant first
+fn field_bytes(
+    regs: &[u16],
+    register_order: &str,
+    byte_order: &str,
+) -> std::result::Result<Vec<u8>, String> {
+    // 1. 先以大端存
+    let mut bytes: Vec<u8> = match register_order {
+        "high_first" => regs.iter().flat_map(|reg| reg.to_be_bytes()).collect(),
+        "low_first" => regs.iter().rev().flat_map(|reg| reg.to_be_bytes()).collect(),
+        _ => return Err("Invalid register order".into()),
+    };
+
+    // 2. 根据所需的字节序调整
+    match byte_order {
+        "big_endian" => {}
+        "little_endian" => bytes.reverse(),
+        _ => return Err("Invalid byte order. Use 'big_endian' or 'little_endian'.".into()),
+    }
+    Ok(bytes)
+}
+
+/// 将按每寄存器两个字符存放的 ASCII 字符串解码，去掉末尾的 NUL
+/// byte_order: 寄存器内字节顺序
+///   - 'big_endian': 高字节为第一个字符
+///   - 'little_endian': 低字节为第一个字符
+///
+/// Bytes outside ASCII are replaced with U+FFFD, embedded NULs are kept.
+pub fn registers_to_string(regs: &[u16], byte_order: &str) -> std::result::Result<String, String> {
+    let bytes: Vec<u8> = match byte_order {
+        "big_endian" => regs.iter().flat_map(|reg| reg.to_be_bytes()).collect(),
+        "little_endian" => regs.iter().flat_map(|reg| reg.to_le_bytes()).collect(),
+        _ => return Err("Invalid byte order. Use 'big_endian' or 'little_endian'.".into()),
+    };
+    let text: String = bytes
+        .iter()
+        .map(|byte| {
+            if byte.is_ascii() {
+                *byte as char
+            } else {
+                char::REPLACEMENT_CHARACTER
+            }
+        })
+        .collect();
+    Ok(text.trim_end_matches('\0').to_string())
+}
+
+/// 将 ASCII 字符串按每寄存器两个字符打包（高字节在前），奇数长度末尾补 NUL
+///
+/// Non-ASCII characters are written as `?`.
+pub fn string_to_registers(text: &str) -> Vec<u16> {
+    let bytes: Vec<u8> = text
+        .chars()
+        .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+        .collect();
+    bytes
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_mixed_layout() {
+        // u16 | i16 | u32 | f32 (123.45) | "AB12"
+        let f = 123.45f32.to_bits();
+        let regs = [
+            0x0102,
+            0xFFFE,
+            0x0001,
+            0x0002,
+            (f >> 16) as u16,
+            f as u16,
+            0x4142,
+            0x3132,
+        ];
+        let layout = [
+            FieldType::U16,
+            FieldType::I16,
+            FieldType::U32,
+            FieldType::F32,
+            FieldType::String(2),
+        ];
+        let values = decode_block(&regs, &layout, "high_first", "big_endian").unwrap();
+        assert_eq!(
+            values,
+            vec![
+                DecodedValue::U16(0x0102),
+                DecodedValue::I16(-2),
+                DecodedValue::U32(0x0001_0002),
+                DecodedValue::F32(123.45),
+                DecodedValue::String("AB12".into()),
+            ]
+        );
+
+        // 低位寄存器在前：32 位字段的两个寄存器对调
+        let values = decode_block(&[0x0002, 0x0001], &[FieldType::U32], "low_first", "big_endian")
+            .unwrap();
+        assert_eq!(values, vec![DecodedValue::U32(0x0001_0002)]);
+    }
+
+    #[test]
+    fn test_decode_little_endian_and_wide_fields() {
+        let bits = (-1.5f64).to_bits();
+        let regs: Vec<u16> = (0..4).rev().map(|i| (bits >> (i * 16)) as u16).collect();
+        let mut block = regs.clone();
+        block.extend([0xFFFF, 0xFFFF, 0x0000]);
+        let values = decode_block(
+            &block,
+            &[FieldType::F64, FieldType::I32],
+            "high_first",
+            "big_endian",
+        )
+        .unwrap();
+        assert_eq!(values, vec![DecodedValue::F64(-1.5), DecodedValue::I32(-1)]);
+
+        // 小端：整个字段按字节反转，字符串每个寄存器低字节在前
+        let values = decode_block(
+            &[0x3412, 0x4241, 0x0043],
+            &[FieldType::U16, FieldType::String(2)],
+            "high_first",
+            "little_endian",
+        )
+        .unwrap();
+        assert_eq!(
+            values,
+            vec![DecodedValue::U16(0x1234), DecodedValue::String("ABC".into())]
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(decode_block(&[1], &[FieldType::U32], "high_first", "big_endian").is_err());
+        assert!(decode_block(&[1], &[FieldType::U16], "middle", "big_endian").is_err());
+        assert!(decode_block(&[1], &[FieldType::U16], "high_first", "middle").is_err());
+        assert_eq!(
+            decode_block(&[1, 2], &[], "high_first", "big_endian").unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_decoded_value_json() {
+        let values = vec![DecodedValue::U16(1), DecodedValue::String("A".into())];
+        assert_eq!(serde_json::to_value(values).unwrap(), serde_json::json!([1, "A"]));
+        let layout: Vec<FieldType> =
+            serde_json::from_value(serde_json::json!(["u16", "f32", { "string": 8 }])).unwrap();
+        assert_eq!(layout, vec![FieldType::U16, FieldType::F32, FieldType::String(8)]);
+    }
+
+    #[test]
+    fn test_registers_to_string() {
+        let regs = string_to_registers("LL-100");
+        assert_eq!(regs, vec![0x4C4C, 0x2D31, 0x3030]);
+        assert_eq!(registers_to_string(&regs, "big_endian").unwrap(), "LL-100");
+
+        // 奇数长度补 NUL，解码时去掉
+        let regs = string_to_registers("SN7");
+        assert_eq!(regs, vec![0x534E, 0x3700]);
+        assert_eq!(registers_to_string(&regs, "big_endian").unwrap(), "SN7");
+        assert_eq!(string_to_registers(""), Vec::<u16>::new());
+
+        // 中间的 NUL 保留，只去掉末尾的
+        let regs = [0x4100, 0x4200, 0x0000];
+        assert_eq!(registers_to_string(&regs, "big_endian").unwrap(), "A\0B");
+
+        assert_eq!(
+            registers_to_string(&[0x4241, 0x0043], "little_endian").unwrap(),
+            "ABC"
+        );
+        assert!(registers_to_string(&[0x4142], "middle").is_err());
+    }
+
+    #[test]
+    fn test_registers_to_string_non_ascii() {
+        assert_eq!(
+            registers_to_string(&[0x41C3, 0xA942], "big_endian").unwrap(),
+            "A\u{FFFD}\u{FFFD}B"
+        );
+        assert_eq!(string_to_registers("é1"), vec![0x3F31]);
+    }
+}
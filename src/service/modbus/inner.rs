@@ -8,6 +8,9 @@ pub trait ModbusContext {
     fn mut_context(&mut self) -> &mut tokio_modbus::client::Context;
     fn will_timeout(&self) -> bool;
     fn timeout(&self) -> Duration;
+    /// Device identifier used to tag tracing spans (serial path or `host:port`).
+    fn device(&self) -> String;
+    fn is_connected(&self) -> bool;
     async fn close(&mut self);
 }
 
@@ -67,6 +70,14 @@ impl ModbusContext for ModbusRTUContext {
         self.timeout
     }
 
+    fn device(&self) -> String {
+        self.path.clone()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.ctx.is_some()
+    }
+
     async fn close(&mut self) {
         if self.ctx.is_some() {
             let _ = self.ctx.as_mut().unwrap().disconnect().await;
@@ -78,6 +89,7 @@ impl ModbusContext for ModbusRTUContext {
 pub struct ModbusTCPContext {
     pub addr: String,
     pub port: u16,
+    pub slave: Slave,
     pub timeout: Duration,
     pub ctx: Option<tokio_modbus::client::Context>,
 }
@@ -98,7 +110,7 @@ impl ModbusContext for ModbusTCPContext {
                 ))
             })?;
 
-        let ctx = tcp::connect(socket_addr).await?;
+        let ctx = tcp::connect_slave(socket_addr, self.slave).await?;
         self.ctx = Some(ctx);
         Ok(Ok(()))
     }
@@ -119,6 +131,14 @@ impl ModbusContext for ModbusTCPContext {
         self.timeout
     }
 
+    fn device(&self) -> String {
+        format!("{}:{}", self.addr, self.port)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.ctx.is_some()
+    }
+
     async fn close(&mut self) {
         if self.ctx.is_some() {
             let _ = self.ctx.as_mut().unwrap().disconnect().await;
@@ -0,0 +1,145 @@
+// In-memory Modbus slave shared by the modbus tests.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio_modbus::prelude::*;
+
+use super::{ModbusService, inner};
+
+#[derive(Default)]
+pub(crate) struct SlaveState {
+    pub registers: Vec<u16>,
+    /// Time taken to answer each request
+    pub delay: Duration,
+    pub calls: u32,
+    /// Drop the connection on this call number
+    pub drop_on_call: Option<u32>,
+    pub connects: u32,
+    /// Acknowledge writes without applying them
+    pub ignore_writes: bool,
+    /// Answer every request with this exception
+    pub exception: Option<ExceptionCode>,
+}
+
+impl SlaveState {
+    pub fn new(registers: Vec<u16>) -> Self {
+        Self {
+            registers,
+            ..Default::default()
+        }
+    }
+}
+
+struct MockSlave {
+    state: Arc<Mutex<SlaveState>>,
+}
+
+impl SlaveContext for MockSlave {
+    fn set_slave(&mut self, _slave: Slave) {}
+}
+
+#[async_trait::async_trait]
+impl Client for MockSlave {
+    async fn call(&mut self, request: Request<'_>) -> tokio_modbus::Result<Response> {
+        let delay = self.state.lock().unwrap().delay;
+        tokio::time::sleep(delay).await;
+
+        let mut state = self.state.lock().unwrap();
+        state.calls += 1;
+        if state.drop_on_call == Some(state.calls) {
+            return Err(tokio_modbus::Error::Transport(std::io::Error::from(
+                std::io::ErrorKind::ConnectionReset,
+            )));
+        }
+        if let Some(exception) = state.exception {
+            return Ok(Err(exception));
+        }
+        let apply = !state.ignore_writes;
+        match request {
+            Request::ReadHoldingRegisters(addr, cnt) => {
+                let range = addr as usize..(addr + cnt) as usize;
+                Ok(Ok(Response::ReadHoldingRegisters(
+                    state.registers[range].to_vec(),
+                )))
+            }
+            Request::WriteSingleRegister(addr, word) => {
+                if apply {
+                    state.registers[addr as usize] = word;
+                }
+                Ok(Ok(Response::WriteSingleRegister(addr, word)))
+            }
+            Request::WriteMultipleRegisters(addr, words) => {
+                if apply {
+                    let range = addr as usize..addr as usize + words.len();
+                    state.registers[range].copy_from_slice(&words);
+                }
+                Ok(Ok(Response::WriteMultipleRegisters(addr, words.len() as u16)))
+            }
+            _ => Ok(Err(ExceptionCode::IllegalFunction)),
+        }
+    }
+
+    async fn disconnect(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+struct MockContext {
+    state: Arc<Mutex<SlaveState>>,
+    ctx: Option<client::Context>,
+    timeout: Duration,
+}
+
+#[async_trait::async_trait]
+impl inner::ModbusContext for MockContext {
+    async fn connect(&mut self) -> tokio_modbus::Result<()> {
+        if self.ctx.is_none() {
+            self.state.lock().unwrap().connects += 1;
+            let slave: Box<dyn Client> = Box::new(MockSlave {
+                state: self.state.clone(),
+            });
+            self.ctx = Some(slave.into());
+        }
+        Ok(Ok(()))
+    }
+
+    fn mut_context(&mut self) -> &mut client::Context {
+        self.ctx.as_mut().unwrap()
+    }
+
+    fn will_timeout(&self) -> bool {
+        !self.timeout.is_zero()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn device(&self) -> String {
+        "mock".into()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.ctx.is_some()
+    }
+
+    async fn close(&mut self) {
+        self.ctx = None;
+    }
+}
+
+/// Service talking to the slave in `state`, connecting on the first request. A zero
+/// `timeout` waits forever.
+pub(crate) fn mock_service(state: Arc<Mutex<SlaveState>>, timeout: Duration) -> ModbusService {
+    ModbusService {
+        inner: Box::new(MockContext {
+            state,
+            ctx: None,
+            timeout,
+        }),
+        coil_cache: None,
+        register_cache: None,
+        write_hook: None,
+    }
+}
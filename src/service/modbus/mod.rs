@@ -1,14 +1,63 @@
+use std::sync::Arc;
 use std::time::Duration;
 use serde::{Deserialize, Serialize};
 use serialport::{DataBits, FlowControl, Parity, StopBits};
+
+use crate::service::serialport::{DataBitsDef, FlowControlDef, ParityDef, StopBitsDef};
 use tokio::select;
 use tokio_modbus::{prelude::*, *};
 
+pub mod audit;
+pub mod cache;
+pub mod decode;
 mod inner;
+#[cfg(test)]
+mod mock;
+pub mod resilient;
+pub mod server;
+
+use crate::service::metrics::metrics;
+use audit::{ModbusWrite, WriteHook};
+use cache::RegisterCache;
+pub use decode::{
+    DecodedValue, FieldType, decode_block, registers_to_string, string_to_registers,
+};
+
+/// Errors of the verified write helpers, flattening the nested modbus result
+#[derive(thiserror::Error, Debug)]
+pub enum ModbusError {
+    #[error("Modbus Error: {0}")]
+    Transport(#[from] tokio_modbus::Error),
+    #[error("Modbus Exception Code: {0}")]
+    Exception(#[from] ExceptionCode),
+    #[error("Modbus Verify Mismatch: expected {expected:?}, actual {actual:?}")]
+    VerifyMismatch { expected: Vec<u16>, actual: Vec<u16> },
+}
+
+/// A slave (unit id) on a bus, optionally with its own timeout
+///
+/// `timeout` overrides the bus default for this slave only, e.g. a slow meter next to a
+/// fast PLC on the same RS-485 line.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ModbusDeviceConfig {
+    pub slave: u8,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::utils::datetime::string_to_duration_option"
+    )]
+    pub timeout: Option<Duration>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ModbusTCPConfig {
     pub host: String,
     pub port: u16,
+    /// Default timeout of every device, zero disables it
+    #[serde(default, with = "crate::utils::datetime::string_to_duration")]
+    pub timeout: Duration,
+    #[serde(default)]
+    pub devices: Vec<ModbusDeviceConfig>,
 }
 
 impl Default for ModbusTCPConfig {
@@ -16,20 +65,48 @@ impl Default for ModbusTCPConfig {
         ModbusTCPConfig {
             host: "192.168.1.100".to_string(),
             port: 502,
+            timeout: Duration::ZERO,
+            devices: Vec::new(),
         }
     }
 }
 
+impl ModbusTCPConfig {
+    /// Timeout of `slave`: its override in `devices`, else the gateway default
+    pub fn timeout_for(&self, slave: u8) -> Duration {
+        device_timeout(&self.devices, slave).unwrap_or(self.timeout)
+    }
+
+    /// Builder for `slave` with its resolved timeout
+    pub fn builder(&self, slave: u8) -> ModbusTCPBuilder {
+        ModbusTCPBuilder::new(self.host.clone(), self.port)
+            .with_slave(slave)
+            .timeout(self.timeout_for(slave))
+    }
+
+    /// One service per entry of `devices`, in order
+    pub fn build_devices(&self) -> Vec<(u8, ModbusService)> {
+        self.devices
+            .iter()
+            .map(|device| (device.slave, self.builder(device.slave).build()))
+            .collect()
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ModbusRTUConfig {
     pub path: String,
     pub baud_rate: u32,
-    pub data_bits: DataBits,
-    pub stop_bits: StopBits,
-    pub parity: Parity,
-    pub flow_control: FlowControl,
+    pub data_bits: DataBitsDef,
+    pub stop_bits: StopBitsDef,
+    pub parity: ParityDef,
+    pub flow_control: FlowControlDef,
+    /// Default timeout of every device, zero disables it
     #[serde(with = "crate::utils::datetime::string_to_duration")]
     pub timeout: Duration,
+    /// Slaves on this bus, each may override `timeout`
+    #[serde(default)]
+    pub devices: Vec<ModbusDeviceConfig>,
 }
 
 impl Default for ModbusRTUConfig {
@@ -37,15 +114,55 @@ impl Default for ModbusRTUConfig {
         ModbusRTUConfig {
             path: "/dev/ttyUSB0".to_string(),
             baud_rate: 9600,
-            data_bits: DataBits::Eight,
-            stop_bits: StopBits::One,
-            parity: Parity::None,
-            flow_control: FlowControl::None,
+            data_bits: DataBits::Eight.into(),
+            stop_bits: StopBits::One.into(),
+            parity: Parity::None.into(),
+            flow_control: FlowControl::None.into(),
             timeout: Duration::from_secs(1),
+            devices: Vec::new(),
         }
     }
 }
 
+impl ModbusRTUConfig {
+    /// Timeout of `slave`: its override in `devices`, else the bus default
+    ///
+    /// On a shared serial bus the timeout bounds a single transaction, not the whole
+    /// poll cycle, so a slow slave only delays the requests addressed to it.
+    pub fn timeout_for(&self, slave: u8) -> Duration {
+        device_timeout(&self.devices, slave).unwrap_or(self.timeout)
+    }
+
+    /// Builder for `slave` with the bus settings and its resolved timeout
+    pub fn builder(&self, slave: u8) -> ModbusRTUBuilder {
+        ModbusRTUBuilder::new(&self.path, self.baud_rate)
+            .with_slave(slave)
+            .with_data_bits(self.data_bits.into())
+            .with_parity(self.parity.into())
+            .with_stop_bits(self.stop_bits.into())
+            .with_flow_control(self.flow_control.into())
+            .with_timeout(self.timeout_for(slave))
+    }
+
+    /// One service per entry of `devices`, in order
+    ///
+    /// The services share the serial port, poll them one after another (see
+    /// [`poll_holding_registers`]) rather than concurrently.
+    pub fn build_devices(&self) -> Vec<(u8, ModbusService)> {
+        self.devices
+            .iter()
+            .map(|device| (device.slave, self.builder(device.slave).build()))
+            .collect()
+    }
+}
+
+fn device_timeout(devices: &[ModbusDeviceConfig], slave: u8) -> Option<Duration> {
+    devices
+        .iter()
+        .find(|device| device.slave == slave)
+        .and_then(|device| device.timeout)
+}
+
 pub struct ModbusRTUBuilder {
     path: String,
     slave: u8,
@@ -114,6 +231,9 @@ impl ModbusRTUBuilder {
                 slave: self.slave,
                 ctx: None,
             }),
+            coil_cache: None,
+            register_cache: None,
+            write_hook: None,
         }
     }
 }
@@ -121,6 +241,7 @@ impl ModbusRTUBuilder {
 pub struct ModbusTCPBuilder {
     addr: String,
     port: u16,
+    slave: Slave,
     timeout: Duration,
 }
 
@@ -129,10 +250,17 @@ impl ModbusTCPBuilder {
         Self {
             addr,
             port,
+            slave: Slave::tcp_device(),
             timeout: Duration::from_millis(0),
         }
     }
 
+    /// Unit id behind a TCP gateway, defaults to `0xFF`
+    pub fn with_slave(mut self, slave: u8) -> Self {
+        self.slave = Slave(slave);
+        self
+    }
+
     pub fn timeout(mut self, timeout: Duration) -> Self {
         self.timeout = timeout;
         self
@@ -143,163 +271,261 @@ impl ModbusTCPBuilder {
             inner: Box::new(inner::ModbusTCPContext {
                 addr: self.addr,
                 port: self.port,
+                slave: self.slave,
                 timeout: self.timeout,
                 ctx: None,
             }),
+            coil_cache: None,
+            register_cache: None,
+            write_hook: None,
         }
     }
 }
 
 pub struct ModbusService {
     inner: Box<dyn inner::ModbusContext + Send>,
+    coil_cache: Option<Arc<RegisterCache<bool>>>,
+    register_cache: Option<Arc<RegisterCache<u16>>>,
+    write_hook: Option<WriteHook>,
 }
 
 impl ModbusService {
-    /// Read multiple coils (0x01)
-    pub async fn read_coils(&mut self, addr: u16, cnt: u16) -> Result<Vec<bool>> {
-        let _ = self.inner.connect().await?;
-        let will_timeout = self.inner.will_timeout();
-        let timeout = self.inner.timeout();
-        let ctx = self.inner.mut_context();
-        if !will_timeout {
-            match ctx.read_coils(addr, cnt).await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    self.inner.close().await;
-                    return Err(e);
-                }
-            }
+    /// Populate `cache` from `read_coils` and invalidate it on coil writes
+    pub fn with_coil_cache(mut self, cache: Arc<RegisterCache<bool>>) -> Self {
+        self.coil_cache = Some(cache);
+        self
+    }
+
+    /// Populate `cache` from holding register reads and invalidate it on register writes
+    pub fn with_register_cache(mut self, cache: Arc<RegisterCache<u16>>) -> Self {
+        self.register_cache = Some(cache);
+        self
+    }
+
+    /// Report every write (registers, coils, masked and read/write) to `hook`, e.g.
+    /// [`audit::db_write_hook`] for an audit trail in `t_logs`. Reads are not reported.
+    pub fn with_write_hook(mut self, hook: WriteHook) -> Self {
+        self.write_hook = Some(hook);
+        self
+    }
+
+    fn audit<T>(
+        &self,
+        function: &'static str,
+        addr: u16,
+        values: impl FnOnce() -> Vec<u16>,
+        result: &Result<T>,
+    ) {
+        if let Some(hook) = &self.write_hook {
+            hook(ModbusWrite::new(
+                self.inner.device(),
+                function,
+                addr,
+                values(),
+                result,
+            ));
+        }
+    }
+
+    fn invalidate_coils(&self, addr: u16, cnt: u16) {
+        if let Some(cache) = &self.coil_cache {
+            cache.invalidate(addr, cnt);
+        }
+    }
+
+    fn invalidate_registers(&self, addr: u16, cnt: u16) {
+        if let Some(cache) = &self.register_cache {
+            cache.invalidate(addr, cnt);
         }
+    }
+
+    /// Close the underlying serial port / TCP connection, the next request reconnects.
+    ///
+    /// Lets pollers release the bus between bursts. Safe to call when already disconnected.
+    pub async fn disconnect(&mut self) {
+        self.inner.close().await
+    }
 
-        select! {
-            result = ctx.read_coils(addr, cnt) => {
-                match result {
-                    Ok(res) => Ok(res),
+    pub fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    /// Read multiple coils (0x01)
+    #[tracing::instrument(skip(self), fields(device = %self.inner.device()))]
+    pub async fn read_coils(&mut self, addr: u16, cnt: u16) -> Result<Vec<bool>> {
+        let result = async {
+            let _ = self.inner.connect().await?;
+            let will_timeout = self.inner.will_timeout();
+            let timeout = self.inner.timeout();
+            let ctx = self.inner.mut_context();
+            if !will_timeout {
+                match ctx.read_coils(addr, cnt).await {
+                    Ok(res) => return Ok(res),
                     Err(e) => {
                         self.inner.close().await;
-                        Err(e)
-                    },
+                        return Err(e);
+                    }
                 }
             }
-            _ = tokio::time::sleep(timeout) => {
-                Err(tokio_modbus::Error::Transport(std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    "read_coils timed out",
-                )))
+
+            select! {
+                result = ctx.read_coils(addr, cnt) => {
+                    match result {
+                        Ok(res) => Ok(res),
+                        Err(e) => {
+                            self.inner.close().await;
+                            Err(e)
+                        },
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    Err(tokio_modbus::Error::Transport(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "read_coils timed out",
+                    )))
+                }
             }
         }
+        .await;
+        metrics().record_modbus(false, &result);
+        if let (Some(cache), Ok(Ok(coils))) = (&self.coil_cache, &result) {
+            cache.update(addr, coils);
+        }
+        result
     }
 
     /// Read multiple discrete inputs (0x02)
+    #[tracing::instrument(skip(self), fields(device = %self.inner.device()))]
     pub async fn read_discrete_inputs(&mut self, addr: u16, cnt: u16) -> Result<Vec<bool>> {
-        let _ = self.inner.connect().await?;
-        let will_timeout = self.inner.will_timeout();
-        let timeout = self.inner.timeout();
-        let ctx = self.inner.mut_context();
-        if !will_timeout {
-            match ctx.read_discrete_inputs(addr, cnt).await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    self.inner.close().await;
-                    return Err(e);
-                }
-            }
-        }
-
-        select! {
-            result = ctx.read_discrete_inputs(addr, cnt) => {
-                match result {
-                    Ok(res) => Ok(res),
+        let result = async {
+            let _ = self.inner.connect().await?;
+            let will_timeout = self.inner.will_timeout();
+            let timeout = self.inner.timeout();
+            let ctx = self.inner.mut_context();
+            if !will_timeout {
+                match ctx.read_discrete_inputs(addr, cnt).await {
+                    Ok(res) => return Ok(res),
                     Err(e) => {
                         self.inner.close().await;
-                        Err(e)
-                    },
+                        return Err(e);
+                    }
                 }
             }
-            _ = tokio::time::sleep(timeout) => {
-                Err(tokio_modbus::Error::Transport(std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    "read_discrete_inputs timed out",
-                )))
+
+            select! {
+                result = ctx.read_discrete_inputs(addr, cnt) => {
+                    match result {
+                        Ok(res) => Ok(res),
+                        Err(e) => {
+                            self.inner.close().await;
+                            Err(e)
+                        },
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    Err(tokio_modbus::Error::Transport(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "read_discrete_inputs timed out",
+                    )))
+                }
             }
         }
+        .await;
+        metrics().record_modbus(false, &result);
+        result
     }
 
     /// Read multiple holding registers (0x03)
+    #[tracing::instrument(skip(self), fields(device = %self.inner.device()))]
     pub async fn read_holding_registers(&mut self, addr: u16, cnt: u16) -> Result<Vec<u16>> {
-        let _ = self.inner.connect().await?;
-        let will_timeout = self.inner.will_timeout();
-        let timeout = self.inner.timeout();
-        let ctx = self.inner.mut_context();
-        if !will_timeout {
-            match ctx.read_holding_registers(addr, cnt).await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    self.inner.close().await;
-                    return Err(e);
-                }
-            }
-        }
-
-        select! {
-            result = ctx.read_holding_registers(addr, cnt) => {
-                match result {
-                    Ok(res) => Ok(res),
+        let result = async {
+            let _ = self.inner.connect().await?;
+            let will_timeout = self.inner.will_timeout();
+            let timeout = self.inner.timeout();
+            let ctx = self.inner.mut_context();
+            if !will_timeout {
+                match ctx.read_holding_registers(addr, cnt).await {
+                    Ok(res) => return Ok(res),
                     Err(e) => {
                         self.inner.close().await;
-                        Err(e)
-                    },
+                        return Err(e);
+                    }
                 }
             }
-            _ = tokio::time::sleep(timeout) => {
-                Err(tokio_modbus::Error::Transport(std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    "read_holding_registers timed out",
-                )))
+
+            select! {
+                result = ctx.read_holding_registers(addr, cnt) => {
+                    match result {
+                        Ok(res) => Ok(res),
+                        Err(e) => {
+                            self.inner.close().await;
+                            Err(e)
+                        },
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    Err(tokio_modbus::Error::Transport(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "read_holding_registers timed out",
+                    )))
+                }
             }
         }
+        .await;
+        metrics().record_modbus(false, &result);
+        if let (Some(cache), Ok(Ok(words))) = (&self.register_cache, &result) {
+            cache.update(addr, words);
+        }
+        result
     }
 
     /// Read multiple input registers (0x04)
+    #[tracing::instrument(skip(self), fields(device = %self.inner.device()))]
     pub async fn read_input_registers(&mut self, addr: u16, cnt: u16) -> Result<Vec<u16>> {
-        let _ = self.inner.connect().await?;
-        let will_timeout = self.inner.will_timeout();
-        let timeout = self.inner.timeout();
-        let ctx = self.inner.mut_context();
-
-        if !will_timeout {
-            match ctx.read_input_registers(addr, cnt).await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    self.inner.close().await;
-                    return Err(e);
-                }
-            }
-        }
-
-        select! {
-            result = ctx.read_input_registers(addr, cnt) => {
-                match result {
-                    Ok(res) => Ok(res),
+        let result = async {
+            let _ = self.inner.connect().await?;
+            let will_timeout = self.inner.will_timeout();
+            let timeout = self.inner.timeout();
+            let ctx = self.inner.mut_context();
+
+            if !will_timeout {
+                match ctx.read_input_registers(addr, cnt).await {
+                    Ok(res) => return Ok(res),
                     Err(e) => {
                         self.inner.close().await;
-                        Err(e)
+                        return Err(e);
                     }
                 }
             }
-            _ = tokio::time::sleep(timeout) => {
-                Err(tokio_modbus::Error::Transport(std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    "read_input_registers timed out",
-                )))
+
+            select! {
+                result = ctx.read_input_registers(addr, cnt) => {
+                    match result {
+                        Ok(res) => Ok(res),
+                        Err(e) => {
+                            self.inner.close().await;
+                            Err(e)
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    Err(tokio_modbus::Error::Transport(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "read_input_registers timed out",
+                    )))
+                }
             }
         }
+        .await;
+        metrics().record_modbus(false, &result);
+        result
     }
 
     /// Read and write multiple holding registers (0x17)
     ///
     /// The write operation is performed before the read unlike
     /// the name of the operation might suggest!
+    #[tracing::instrument(skip(self), fields(device = %self.inner.device()))]
     pub async fn read_write_multiple_registers(
         &mut self,
         read_addr: u16,
@@ -307,213 +533,258 @@ impl ModbusService {
         write_addr: u16,
         write_data: &[u16],
     ) -> Result<Vec<u16>> {
-        let _ = self.inner.connect().await?;
-        let will_timeout = self.inner.will_timeout();
-        let timeout = self.inner.timeout();
-        let ctx = self.inner.mut_context();
-
-        if !will_timeout {
-            match ctx
-                .read_write_multiple_registers(read_addr, read_count, write_addr, write_data)
-                .await
-            {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    self.inner.close().await;
-                    return Err(e);
+        let result = async {
+            let _ = self.inner.connect().await?;
+            let will_timeout = self.inner.will_timeout();
+            let timeout = self.inner.timeout();
+            let ctx = self.inner.mut_context();
+
+            if !will_timeout {
+                match ctx
+                    .read_write_multiple_registers(read_addr, read_count, write_addr, write_data)
+                    .await
+                {
+                    Ok(res) => return Ok(res),
+                    Err(e) => {
+                        self.inner.close().await;
+                        return Err(e);
+                    }
                 }
             }
-        }
 
-        select! {
-            result = ctx.read_write_multiple_registers(read_addr, read_count, write_addr, write_data) => {
-                match result {
-                    Ok(res) => Ok(res),
-                    Err(e) => {
-                        self.inner.close().await;
-                        Err(e)
+            select! {
+                result = ctx.read_write_multiple_registers(read_addr, read_count, write_addr, write_data) => {
+                    match result {
+                        Ok(res) => Ok(res),
+                        Err(e) => {
+                            self.inner.close().await;
+                            Err(e)
+                        }
                     }
                 }
+                _ = tokio::time::sleep(timeout) => {
+                    Err(tokio_modbus::Error::Transport(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "read_write_multiple_registers timed out",
+                    )))
+                }
             }
-            _ = tokio::time::sleep(timeout) => {
-                Err(tokio_modbus::Error::Transport(std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    "read_write_multiple_registers timed out",
-                )))
+        }
+        .await;
+        metrics().record_modbus(true, &result);
+        self.audit(
+            "read_write_multiple_registers",
+            write_addr,
+            || write_data.to_vec(),
+            &result,
+        );
+        if let Some(cache) = &self.register_cache {
+            cache.invalidate(write_addr, write_data.len() as u16);
+            if let Ok(Ok(words)) = &result {
+                cache.update(read_addr, words);
             }
         }
+        result
     }
 
     /// Write a single coil (0x05)
+    #[tracing::instrument(skip(self), fields(device = %self.inner.device()))]
     pub async fn write_single_coil(&mut self, addr: u16, coil: bool) -> Result<()> {
-        let _ = self.inner.connect().await?;
-        let will_timeout = self.inner.will_timeout();
-        let timeout = self.inner.timeout();
-        let ctx = self.inner.mut_context();
-
-        if !will_timeout {
-            match ctx.write_single_coil(addr, coil).await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    self.inner.close().await;
-                    return Err(e);
-                }
-            }
-        }
-
-        select! {
-            result = ctx.write_single_coil(addr, coil) => {
-                match result {
-                    Ok(res) => Ok(res),
+        let result = async {
+            let _ = self.inner.connect().await?;
+            let will_timeout = self.inner.will_timeout();
+            let timeout = self.inner.timeout();
+            let ctx = self.inner.mut_context();
+
+            if !will_timeout {
+                match ctx.write_single_coil(addr, coil).await {
+                    Ok(res) => return Ok(res),
                     Err(e) => {
                         self.inner.close().await;
-                        Err(e)
+                        return Err(e);
                     }
                 }
             }
-            _ = tokio::time::sleep(timeout) => {
-                Err(tokio_modbus::Error::Transport(std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    "write_single_coil timed out",
-                )))
+
+            select! {
+                result = ctx.write_single_coil(addr, coil) => {
+                    match result {
+                        Ok(res) => Ok(res),
+                        Err(e) => {
+                            self.inner.close().await;
+                            Err(e)
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    Err(tokio_modbus::Error::Transport(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "write_single_coil timed out",
+                    )))
+                }
             }
         }
+        .await;
+        metrics().record_modbus(true, &result);
+        self.audit("write_single_coil", addr, || vec![coil as u16], &result);
+        self.invalidate_coils(addr, 1);
+        result
     }
 
     /// Write a single holding register (0x06)
+    #[tracing::instrument(skip(self), fields(device = %self.inner.device()))]
     pub async fn write_single_register(&mut self, addr: u16, word: u16) -> Result<()> {
-        let _ = self.inner.connect().await?;
-        let will_timeout = self.inner.will_timeout();
-        let timeout = self.inner.timeout();
-        let ctx = self.inner.mut_context();
-
-        if !will_timeout {
-            match ctx.write_single_register(addr, word).await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    self.inner.close().await;
-                    return Err(e);
-                }
-            }
-        }
-
-        select! {
-            result = ctx.write_single_register(addr, word) => {
-                match result {
-                    Ok(res) => Ok(res),
+        let result = async {
+            let _ = self.inner.connect().await?;
+            let will_timeout = self.inner.will_timeout();
+            let timeout = self.inner.timeout();
+            let ctx = self.inner.mut_context();
+
+            if !will_timeout {
+                match ctx.write_single_register(addr, word).await {
+                    Ok(res) => return Ok(res),
                     Err(e) => {
                         self.inner.close().await;
-                        Err(e)
+                        return Err(e);
                     }
                 }
             }
 
-            _ = tokio::time::sleep(timeout) => {
-                Err(tokio_modbus::Error::Transport(std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    "write_single_coil timed out",
-                )))
+            select! {
+                result = ctx.write_single_register(addr, word) => {
+                    match result {
+                        Ok(res) => Ok(res),
+                        Err(e) => {
+                            self.inner.close().await;
+                            Err(e)
+                        }
+                    }
+                }
+
+                _ = tokio::time::sleep(timeout) => {
+                    Err(tokio_modbus::Error::Transport(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "write_single_coil timed out",
+                    )))
+                }
             }
         }
+        .await;
+        metrics().record_modbus(true, &result);
+        self.audit("write_single_register", addr, || vec![word], &result);
+        self.invalidate_registers(addr, 1);
+        result
     }
 
     /// Write multiple coils (0x0F)
+    #[tracing::instrument(skip(self), fields(device = %self.inner.device()))]
     pub async fn write_multiple_coils(&mut self, addr: u16, coils: &[bool]) -> Result<()> {
-        let _ = self.inner.connect().await?;
-        let will_timeout = self.inner.will_timeout();
-        let timeout = self.inner.timeout();
-        let ctx = self.inner.mut_context();
-
-        if !will_timeout {
-            match ctx.write_multiple_coils(addr, coils).await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    self.inner.close().await;
-                    return Err(e);
-                }
-            }
-        }
-
-        select! {
-            result = ctx.write_multiple_coils(addr, coils) => {
-                match result {
-                    Ok(res) => Ok(res),
+        let result = async {
+            let _ = self.inner.connect().await?;
+            let will_timeout = self.inner.will_timeout();
+            let timeout = self.inner.timeout();
+            let ctx = self.inner.mut_context();
+
+            if !will_timeout {
+                match ctx.write_multiple_coils(addr, coils).await {
+                    Ok(res) => return Ok(res),
                     Err(e) => {
                         self.inner.close().await;
-                        Err(e)
+                        return Err(e);
                     }
                 }
             }
-            _ = tokio::time::sleep(timeout) => {
-                Err(tokio_modbus::Error::Transport(std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    "write_multiple_coils timed out",
-                )))
+
+            select! {
+                result = ctx.write_multiple_coils(addr, coils) => {
+                    match result {
+                        Ok(res) => Ok(res),
+                        Err(e) => {
+                            self.inner.close().await;
+                            Err(e)
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    Err(tokio_modbus::Error::Transport(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "write_multiple_coils timed out",
+                    )))
+                }
             }
         }
+        .await;
+        metrics().record_modbus(true, &result);
+        self.audit(
+            "write_multiple_coils",
+            addr,
+            || coils.iter().map(|coil| *coil as u16).collect(),
+            &result,
+        );
+        self.invalidate_coils(addr, coils.len() as u16);
+        result
     }
 
     /// Write multiple holding registers (0x10)
+    #[tracing::instrument(skip(self), fields(device = %self.inner.device()))]
     pub async fn write_multiple_registers(&mut self, addr: u16, words: &[u16]) -> Result<()> {
-        let _ = self.inner.connect().await?;
-        let will_timeout = self.inner.will_timeout();
-        let timeout = self.inner.timeout();
-        let ctx = self.inner.mut_context();
-
-        if !will_timeout {
-            match ctx.write_multiple_registers(addr, words).await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    self.inner.close().await;
-                    return Err(e);
-                }
-            }
-        }
-
-        select! {
-            result = ctx.write_multiple_registers(addr, words) => {
-                match result {
-                    Ok(res) => Ok(res),
+        let result = async {
+            let _ = self.inner.connect().await?;
+            let will_timeout = self.inner.will_timeout();
+            let timeout = self.inner.timeout();
+            let ctx = self.inner.mut_context();
+
+            if !will_timeout {
+                match ctx.write_multiple_registers(addr, words).await {
+                    Ok(res) => return Ok(res),
                     Err(e) => {
                         self.inner.close().await;
-                        Err(e)
+                        return Err(e);
                     }
                 }
             }
-            _ = tokio::time::sleep(timeout) => {
-                Err(tokio_modbus::Error::Transport(std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    "write_multiple_registers timed out",
-                )))
+
+            select! {
+                result = ctx.write_multiple_registers(addr, words) => {
+                    match result {
+                        Ok(res) => Ok(res),
+                        Err(e) => {
+                            self.inner.close().await;
+                            Err(e)
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    Err(tokio_modbus::Error::Transport(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "write_multiple_registers timed out",
+                    )))
+                }
             }
         }
+        .await;
+        metrics().record_modbus(true, &result);
+        self.audit("write_multiple_registers", addr, || words.to_vec(), &result);
+        self.invalidate_registers(addr, words.len() as u16);
+        result
     }
 
     /// Set or clear individual bits of a holding register (0x16)
+    #[tracing::instrument(skip(self), fields(device = %self.inner.device()))]
     pub async fn masked_write_register(
         &mut self,
         addr: u16,
         and_mask: u16,
         or_mask: u16,
     ) -> Result<()> {
-        let _ = self.inner.connect().await?;
-        let will_timeout = self.inner.will_timeout();
-        let timeout = self.inner.timeout();
-        let ctx = self.inner.mut_context();
-
-        if !will_timeout {
-            match ctx.masked_write_register(addr, and_mask, or_mask).await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    self.inner.close().await;
-                    return Err(e);
-                }
-            }
-        }
-
-        select! {
-            result = ctx.masked_write_register(addr, and_mask, or_mask) => {
-                match result {
+        let result = async {
+            let _ = self.inner.connect().await?;
+            let will_timeout = self.inner.will_timeout();
+            let timeout = self.inner.timeout();
+            let ctx = self.inner.mut_context();
+
+            if !will_timeout {
+                match ctx.masked_write_register(addr, and_mask, or_mask).await {
                     Ok(res) => return Ok(res),
                     Err(e) => {
                         self.inner.close().await;
@@ -521,14 +792,92 @@ impl ModbusService {
                     }
                 }
             }
-            _ = tokio::time::sleep(timeout) => {
-                Err(tokio_modbus::Error::Transport(std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    "masked_write_register timed out",
-                )))
+
+            select! {
+                result = ctx.masked_write_register(addr, and_mask, or_mask) => {
+                    match result {
+                        Ok(res) => Ok(res),
+                        Err(e) => {
+                            self.inner.close().await;
+                            Err(e)
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    Err(tokio_modbus::Error::Transport(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "masked_write_register timed out",
+                    )))
+                }
             }
         }
+        .await;
+        metrics().record_modbus(true, &result);
+        self.audit("masked_write_register", addr, || vec![and_mask, or_mask], &result);
+        self.invalidate_registers(addr, 1);
+        result
+    }
+
+    /// Write single register (0x06), then read it back (0x03) and compare.
+    /// Verification doubles the bus traffic, use it for setpoints that must not be lost.
+    pub async fn write_single_register_verified(
+        &mut self,
+        addr: u16,
+        word: u16,
+    ) -> std::result::Result<(), ModbusError> {
+        self.write_single_register(addr, word).await??;
+        self.verify_registers(addr, &[word]).await
+    }
+
+    /// Write multiple registers (0x10), then read them back (0x03) and compare.
+    pub async fn write_multiple_registers_verified(
+        &mut self,
+        addr: u16,
+        words: &[u16],
+    ) -> std::result::Result<(), ModbusError> {
+        self.write_multiple_registers(addr, words).await??;
+        self.verify_registers(addr, words).await
     }
+
+    async fn verify_registers(
+        &mut self,
+        addr: u16,
+        expected: &[u16],
+    ) -> std::result::Result<(), ModbusError> {
+        let actual = self
+            .read_holding_registers(addr, expected.len() as u16)
+            .await??;
+        if actual != expected {
+            tracing::warn!(
+                "modbus write verify mismatch at {}: expected {:?}, actual {:?}",
+                addr,
+                expected,
+                actual
+            );
+            return Err(ModbusError::VerifyMismatch {
+                expected: expected.to_vec(),
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Read `cnt` holding registers at `addr` from every target, one after another
+///
+/// Requests are never interleaved, so on a shared serial bus each target's own timeout
+/// (see [`ModbusRTUConfig::timeout_for`]) applies per transaction and a slow or absent
+/// slave only costs its own deadline.
+pub async fn poll_holding_registers<C: resilient::ModbusClient>(
+    targets: &mut [(u8, C)],
+    addr: u16,
+    cnt: u16,
+) -> Vec<(u8, Result<Vec<u16>>)> {
+    let mut results = Vec::with_capacity(targets.len());
+    for (slave, client) in targets.iter_mut() {
+        results.push((*slave, client.read_holding_registers(addr, cnt).await));
+    }
+    results
 }
 
 /// 将两个 u16 寄存器转换为 f32 浮点数
@@ -615,9 +964,212 @@ pub fn registers_to_u32(
     }
 }
 
+/// 将多个 u16 寄存器组成的无符号整数按 10 的幂缩放为精确的 Decimal，适用于电能计量等场景
+/// regs: 寄存器值，最多 6 个（Decimal 尾数为 96 位）
+/// scale: 小数位数，结果为 整数值 / 10^scale，最大 28
+/// register_order: 寄存器顺序
+///   - 'high_first': regs[0] 为最高16位
+///   - 'low_first': regs[0] 为最低16位
+/// byte_order: 字节顺序
+///   - 'big_endian': 大端序
+///   - 'little_endian': 小端序
+pub fn registers_to_decimal(
+    regs: &[u16],
+    scale: u32,
+    register_order: &str,
+    byte_order: &str,
+) -> std::result::Result<rust_decimal::Decimal, String> {
+    if regs.is_empty() || regs.len() > 6 {
+        return Err("Invalid register count. Use 1 to 6 registers.".into());
+    }
+    if scale > rust_decimal::Decimal::MAX_SCALE {
+        return Err(format!(
+            "Invalid scale. Use 0 to {}.",
+            rust_decimal::Decimal::MAX_SCALE
+        ));
+    }
+
+    // 1. 先以大端存
+    let mut bytes: Vec<u8> = match register_order {
+        "high_first" => regs.iter().flat_map(|reg| reg.to_be_bytes()).collect(),
+        "low_first" => regs.iter().rev().flat_map(|reg| reg.to_be_bytes()).collect(),
+        _ => return Err("Invalid register order".into()),
+    };
+
+    // 2. 根据所需的字节序组装整数
+    match byte_order {
+        "big_endian" => {}
+        "little_endian" => bytes.reverse(),
+        _ => return Err("Invalid byte order. Use 'big_endian' or 'little_endian'.".into()),
+    }
+    let value = bytes
+        .iter()
+        .fold(0i128, |acc, byte| (acc << 8) | *byte as i128);
+
+    Ok(rust_decimal::Decimal::from_i128_with_scale(value, scale))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::service::modbus::{ModbusRTUBuilder, registers_to_f32, registers_to_u32};
+    use rust_decimal_macros::dec;
+
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::service::modbus::cache::RegisterCache;
+    use crate::service::modbus::mock::{SlaveState, mock_service};
+    use crate::service::modbus::{
+        ModbusError, ModbusRTUBuilder, ModbusRTUConfig, ModbusService, ModbusWrite,
+        poll_holding_registers, registers_to_decimal, registers_to_f32, registers_to_u32,
+    };
+    use tokio_modbus::ExceptionCode;
+
+    #[tokio::test]
+    async fn test_register_cache_write_invalidation() {
+        let state = Arc::new(Mutex::new(SlaveState::new(vec![10, 20, 30])));
+        let cache = Arc::new(RegisterCache::new(Duration::from_secs(60)));
+        let mut service = mock_service(state, Duration::ZERO).with_register_cache(cache.clone());
+
+        service.read_holding_registers(0, 3).await.unwrap().unwrap();
+        assert_eq!(cache.get_fresh_range(0, 3), Some(vec![10, 20, 30]));
+
+        service.write_single_register(1, 21).await.unwrap().unwrap();
+        assert!(cache.get_cached(1).is_none());
+        assert_eq!(cache.get_cached(0).map(|cached| cached.value), Some(10));
+
+        service.read_holding_registers(1, 1).await.unwrap().unwrap();
+        assert_eq!(cache.get_cached(1).map(|cached| cached.value), Some(21));
+    }
+
+    #[tokio::test]
+    async fn test_write_hook() {
+        let writes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = writes.clone();
+        let mut service = mock_slave(Duration::ZERO, Duration::ZERO)
+            .with_write_hook(Arc::new(move |write| recorded.lock().unwrap().push(write)));
+
+        service.read_holding_registers(0, 2).await.unwrap().unwrap();
+        service.write_single_register(1, 7).await.unwrap().unwrap();
+        // mock 不支持 0x16，返回 IllegalFunction
+        let _ = service.masked_write_register(0, 0x00FF, 0x0100).await;
+
+        let writes = writes.lock().unwrap();
+        assert_eq!(writes.len(), 2);
+        assert_eq!(
+            writes[0],
+            ModbusWrite {
+                device: "mock".into(),
+                function: "write_single_register",
+                addr: 1,
+                values: vec![7],
+                error: None,
+            }
+        );
+        assert_eq!(writes[1].function, "masked_write_register");
+        assert_eq!(writes[1].values, vec![0x00FF, 0x0100]);
+        assert!(!writes[1].is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_write_verified() {
+        let state = Arc::new(Mutex::new(SlaveState::new(vec![0; 4])));
+        let mut service = mock_service(state.clone(), Duration::ZERO);
+
+        service.write_single_register_verified(0, 5).await.unwrap();
+        service.write_multiple_registers_verified(1, &[6, 7, 8]).await.unwrap();
+        assert_eq!(state.lock().unwrap().registers, vec![5, 6, 7, 8]);
+
+        // 从站确认写入但没有生效，回读的值不同
+        state.lock().unwrap().ignore_writes = true;
+        match service.write_single_register_verified(0, 9).await {
+            Err(ModbusError::VerifyMismatch { expected, actual }) => {
+                assert_eq!((expected, actual), (vec![9], vec![5]));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match service.write_multiple_registers_verified(2, &[1, 2]).await {
+            Err(ModbusError::VerifyMismatch { expected, actual }) => {
+                assert_eq!((expected, actual), (vec![1, 2], vec![7, 8]));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        state.lock().unwrap().exception = Some(ExceptionCode::IllegalDataAddress);
+        assert!(matches!(
+            service.write_single_register_verified(0, 1).await,
+            Err(ModbusError::Exception(ExceptionCode::IllegalDataAddress))
+        ));
+        assert!(matches!(
+            service.write_multiple_registers_verified(0, &[1]).await,
+            Err(ModbusError::Exception(ExceptionCode::IllegalDataAddress))
+        ));
+    }
+
+    fn mock_slave(delay: Duration, timeout: Duration) -> ModbusService {
+        let state = SlaveState {
+            registers: vec![1, 2],
+            delay,
+            ..Default::default()
+        };
+        mock_service(Arc::new(Mutex::new(state)), timeout)
+    }
+
+    #[tokio::test]
+    async fn test_disconnect() {
+        let state = Arc::new(Mutex::new(SlaveState::new(vec![1, 2, 3])));
+        let mut service = mock_service(state.clone(), Duration::ZERO);
+        assert!(!service.is_connected());
+        // 未连接时断开是安全的
+        service.disconnect().await;
+
+        service.read_holding_registers(0, 1).await.unwrap().unwrap();
+        assert!(service.is_connected());
+        service.disconnect().await;
+        assert!(!service.is_connected());
+
+        service.read_holding_registers(0, 1).await.unwrap().unwrap();
+        assert_eq!(state.lock().unwrap().connects, 2);
+    }
+
+    #[tokio::test]
+    async fn test_per_slave_timeout() {
+        let config: ModbusRTUConfig = serde_json::from_value(serde_json::json!({
+            "path": "/dev/ttyUSB0",
+            "baud_rate": 9600,
+            "data_bits": "Eight",
+            "stop_bits": "One",
+            "parity": "None",
+            "flow_control": "None",
+            "timeout": "50ms",
+            "devices": [
+                { "slave": 1 },
+                { "slave": 2, "timeout": "500ms" }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(config.timeout_for(1), Duration::from_millis(50));
+        assert_eq!(config.timeout_for(2), Duration::from_millis(500));
+        assert_eq!(config.timeout_for(3), Duration::from_millis(50));
+
+        // 慢从站 200ms 才应答：只有带覆盖超时的 2 号能读到
+        let slow = Duration::from_millis(200);
+        let mut targets = vec![
+            (1, mock_slave(Duration::ZERO, config.timeout_for(1))),
+            (2, mock_slave(slow, config.timeout_for(2))),
+            (3, mock_slave(slow, config.timeout_for(3))),
+        ];
+        let results = poll_holding_registers(&mut targets, 0, 2).await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0, 1);
+        assert_eq!(results[0].1.as_ref().unwrap().as_ref().unwrap(), &vec![1, 2]);
+        assert_eq!(results[1].1.as_ref().unwrap().as_ref().unwrap(), &vec![1, 2]);
+        match &results[2].1 {
+            Err(tokio_modbus::Error::Transport(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::TimedOut)
+            }
+            other => panic!("expected timeout, got {:?}", other),
+        }
+    }
 
     #[tokio::test]
     async fn test_modbus() {
@@ -786,4 +1338,43 @@ mod tests {
             Err(e) => eprintln!("转换错误: {}", e),
         }
     }
+
+    #[test]
+    fn test_registers_to_decimal() {
+        // 电能表正向有功总电能 12345.67 kWh，两位小数
+        assert_eq!(
+            registers_to_decimal(&[0x0012, 0xD687], 2, "high_first", "big_endian"),
+            Ok(dec!(12345.67))
+        );
+        assert_eq!(
+            registers_to_decimal(&[0xD687, 0x0012], 2, "low_first", "big_endian"),
+            Ok(dec!(12345.67))
+        );
+        assert_eq!(
+            registers_to_decimal(&[0x87D6, 0x1200], 2, "high_first", "little_endian"),
+            Ok(dec!(12345.67))
+        );
+
+        // 64 位累计量 9876543210.123 (0x0000_08FB_8FD9_828B)，三位小数
+        assert_eq!(
+            registers_to_decimal(
+                &[0x0000, 0x08FB, 0x8FD9, 0x828B],
+                3,
+                "high_first",
+                "big_endian"
+            ),
+            Ok(dec!(9876543210.123))
+        );
+
+        // 单寄存器电压 220.5 V，一位小数
+        assert_eq!(
+            registers_to_decimal(&[2205], 1, "high_first", "big_endian"),
+            Ok(dec!(220.5))
+        );
+
+        assert!(registers_to_decimal(&[], 0, "high_first", "big_endian").is_err());
+        assert!(registers_to_decimal(&[0; 7], 0, "high_first", "big_endian").is_err());
+        assert!(registers_to_decimal(&[1], 29, "high_first", "big_endian").is_err());
+        assert!(registers_to_decimal(&[1], 0, "middle", "big_endian").is_err());
+    }
 }
@@ -0,0 +1,253 @@
+use tokio_modbus::Result;
+
+use crate::service::modbus::ModbusService;
+pub use crate::utils::retry::ReconnectPolicy;
+use crate::utils::retry::retry_if;
+
+/// Modbus master operations, implemented by [`ModbusService`] and [`ResilientModbus`]
+/// so application code can depend on the trait and choose the resilience it needs.
+#[async_trait::async_trait]
+pub trait ModbusClient: Send {
+    async fn read_coils(&mut self, addr: u16, cnt: u16) -> Result<Vec<bool>>;
+    async fn read_discrete_inputs(&mut self, addr: u16, cnt: u16) -> Result<Vec<bool>>;
+    async fn read_holding_registers(&mut self, addr: u16, cnt: u16) -> Result<Vec<u16>>;
+    async fn read_input_registers(&mut self, addr: u16, cnt: u16) -> Result<Vec<u16>>;
+    async fn read_write_multiple_registers(
+        &mut self,
+        read_addr: u16,
+        read_count: u16,
+        write_addr: u16,
+        write_data: &[u16],
+    ) -> Result<Vec<u16>>;
+    async fn write_single_coil(&mut self, addr: u16, coil: bool) -> Result<()>;
+    async fn write_single_register(&mut self, addr: u16, word: u16) -> Result<()>;
+    async fn write_multiple_coils(&mut self, addr: u16, coils: &[bool]) -> Result<()>;
+    async fn write_multiple_registers(&mut self, addr: u16, words: &[u16]) -> Result<()>;
+    async fn masked_write_register(&mut self, addr: u16, and_mask: u16, or_mask: u16)
+    -> Result<()>;
+    /// Drop the connection, the next call reconnects
+    async fn disconnect(&mut self);
+}
+
+#[async_trait::async_trait]
+impl ModbusClient for ModbusService {
+    async fn read_coils(&mut self, addr: u16, cnt: u16) -> Result<Vec<bool>> {
+        ModbusService::read_coils(self, addr, cnt).await
+    }
+
+    async fn read_discrete_inputs(&mut self, addr: u16, cnt: u16) -> Result<Vec<bool>> {
+        ModbusService::read_discrete_inputs(self, addr, cnt).await
+    }
+
+    async fn read_holding_registers(&mut self, addr: u16, cnt: u16) -> Result<Vec<u16>> {
+        ModbusService::read_holding_registers(self, addr, cnt).await
+    }
+
+    async fn read_input_registers(&mut self, addr: u16, cnt: u16) -> Result<Vec<u16>> {
+        ModbusService::read_input_registers(self, addr, cnt).await
+    }
+
+    async fn read_write_multiple_registers(
+        &mut self,
+        read_addr: u16,
+        read_count: u16,
+        write_addr: u16,
+        write_data: &[u16],
+    ) -> Result<Vec<u16>> {
+        ModbusService::read_write_multiple_registers(
+            self, read_addr, read_count, write_addr, write_data,
+        )
+        .await
+    }
+
+    async fn write_single_coil(&mut self, addr: u16, coil: bool) -> Result<()> {
+        ModbusService::write_single_coil(self, addr, coil).await
+    }
+
+    async fn write_single_register(&mut self, addr: u16, word: u16) -> Result<()> {
+        ModbusService::write_single_register(self, addr, word).await
+    }
+
+    async fn write_multiple_coils(&mut self, addr: u16, coils: &[bool]) -> Result<()> {
+        ModbusService::write_multiple_coils(self, addr, coils).await
+    }
+
+    async fn write_multiple_registers(&mut self, addr: u16, words: &[u16]) -> Result<()> {
+        ModbusService::write_multiple_registers(self, addr, words).await
+    }
+
+    async fn masked_write_register(
+        &mut self,
+        addr: u16,
+        and_mask: u16,
+        or_mask: u16,
+    ) -> Result<()> {
+        ModbusService::masked_write_register(self, addr, and_mask, or_mask).await
+    }
+
+    async fn disconnect(&mut self) {
+        ModbusService::disconnect(self).await
+    }
+}
+
+/// Connection level errors worth a reconnect; exceptions and protocol errors are returned as is.
+fn is_transient(error: &tokio_modbus::Error) -> bool {
+    use std::io::ErrorKind;
+
+    match error {
+        tokio_modbus::Error::Transport(e) => matches!(
+            e.kind(),
+            ErrorKind::TimedOut
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::ConnectionRefused
+                | ErrorKind::NotConnected
+                | ErrorKind::BrokenPipe
+                | ErrorKind::UnexpectedEof
+        ),
+        _ => false,
+    }
+}
+
+/// Runs `$call` on `$client` under the policy, reconnecting before every retry.
+macro_rules! with_retry {
+    ($self:ident, |$client:ident| $call:expr) => {{
+        let client = &$self.client;
+        let mut attempt = 0;
+        retry_if(&$self.policy, is_transient, move || {
+            let reconnect = attempt > 0;
+            attempt += 1;
+            async move {
+                let mut $client = client.lock().await;
+                if reconnect {
+                    $client.disconnect().await;
+                }
+                $call
+            }
+        })
+        .await
+    }};
+}
+
+/// Wraps a [`ModbusClient`] and transparently reconnects and retries transient transport errors.
+pub struct ResilientModbus<C = ModbusService> {
+    // 仅用于在重试闭包间共享，调用方持有 `&mut self`，不会发生竞争
+    client: tokio::sync::Mutex<C>,
+    policy: ReconnectPolicy,
+}
+
+impl<C: ModbusClient> ResilientModbus<C> {
+    pub fn new(client: C, policy: ReconnectPolicy) -> Self {
+        Self {
+            client: tokio::sync::Mutex::new(client),
+            policy,
+        }
+    }
+
+    pub fn inner(&mut self) -> &mut C {
+        self.client.get_mut()
+    }
+
+    pub fn into_inner(self) -> C {
+        self.client.into_inner()
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: ModbusClient> ModbusClient for ResilientModbus<C> {
+    async fn read_coils(&mut self, addr: u16, cnt: u16) -> Result<Vec<bool>> {
+        with_retry!(self, |client| client.read_coils(addr, cnt).await)
+    }
+
+    async fn read_discrete_inputs(&mut self, addr: u16, cnt: u16) -> Result<Vec<bool>> {
+        with_retry!(self, |client| client.read_discrete_inputs(addr, cnt).await)
+    }
+
+    async fn read_holding_registers(&mut self, addr: u16, cnt: u16) -> Result<Vec<u16>> {
+        with_retry!(self, |client| client.read_holding_registers(addr, cnt).await)
+    }
+
+    async fn read_input_registers(&mut self, addr: u16, cnt: u16) -> Result<Vec<u16>> {
+        with_retry!(self, |client| client.read_input_registers(addr, cnt).await)
+    }
+
+    async fn read_write_multiple_registers(
+        &mut self,
+        read_addr: u16,
+        read_count: u16,
+        write_addr: u16,
+        write_data: &[u16],
+    ) -> Result<Vec<u16>> {
+        with_retry!(
+            self,
+            |client| client
+                .read_write_multiple_registers(read_addr, read_count, write_addr, write_data)
+                .await
+        )
+    }
+
+    async fn write_single_coil(&mut self, addr: u16, coil: bool) -> Result<()> {
+        with_retry!(self, |client| client.write_single_coil(addr, coil).await)
+    }
+
+    async fn write_single_register(&mut self, addr: u16, word: u16) -> Result<()> {
+        with_retry!(self, |client| client.write_single_register(addr, word).await)
+    }
+
+    async fn write_multiple_coils(&mut self, addr: u16, coils: &[bool]) -> Result<()> {
+        with_retry!(self, |client| client.write_multiple_coils(addr, coils).await)
+    }
+
+    async fn write_multiple_registers(&mut self, addr: u16, words: &[u16]) -> Result<()> {
+        with_retry!(self, |client| client.write_multiple_registers(addr, words).await)
+    }
+
+    async fn masked_write_register(
+        &mut self,
+        addr: u16,
+        and_mask: u16,
+        or_mask: u16,
+    ) -> Result<()> {
+        with_retry!(self, |client| client.masked_write_register(addr, and_mask, or_mask).await)
+    }
+
+    async fn disconnect(&mut self) {
+        self.client.get_mut().disconnect().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio_modbus::prelude::*;
+
+    use super::*;
+    use crate::service::modbus::mock::{SlaveState, mock_service};
+
+    #[tokio::test]
+    async fn test_reconnect_after_connection_drop() {
+        let state = Arc::new(Mutex::new(SlaveState {
+            registers: vec![1, 2, 3],
+            drop_on_call: Some(2),
+            ..Default::default()
+        }));
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let service = mock_service(state.clone(), Duration::ZERO);
+        let mut client = ResilientModbus::new(service, policy);
+
+        assert_eq!(client.read_holding_registers(0, 3).await.unwrap(), Ok(vec![1, 2, 3]));
+        // 第二次请求时从站断开，重连后重试成功
+        assert_eq!(client.read_holding_registers(1, 2).await.unwrap(), Ok(vec![2, 3]));
+        assert_eq!(state.lock().unwrap().connects, 2);
+
+        // 异常码不重试
+        let result = client.write_single_coil(0, true).await.unwrap();
+        assert_eq!(result, Err(ExceptionCode::IllegalFunction));
+        assert_eq!(state.lock().unwrap().connects, 2);
+    }
+}
@@ -0,0 +1,231 @@
+// Access control for serving Modbus requests (slave mode).
+
+use std::collections::HashSet;
+use std::future::{Ready, ready};
+
+use futures::future::Either;
+use serde::{Deserialize, Serialize};
+use tokio_modbus::FunctionCode;
+use tokio_modbus::prelude::*;
+use tokio_modbus::server::Service;
+
+/// Standard allowlists for [`ModbusServerConfig`]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FunctionPreset {
+    /// Only the four read functions (0x01-0x04), for gateways that expose data
+    /// without allowing remote writes
+    ReadOnly,
+    /// Every function code defined by the specification
+    #[default]
+    Full,
+}
+
+const READ_FUNCTIONS: [FunctionCode; 4] = [
+    FunctionCode::ReadCoils,
+    FunctionCode::ReadDiscreteInputs,
+    FunctionCode::ReadHoldingRegisters,
+    FunctionCode::ReadInputRegisters,
+];
+
+const WRITE_FUNCTIONS: [FunctionCode; 7] = [
+    FunctionCode::WriteSingleCoil,
+    FunctionCode::WriteSingleRegister,
+    FunctionCode::WriteMultipleCoils,
+    FunctionCode::WriteMultipleRegisters,
+    FunctionCode::WriteFileRecord,
+    FunctionCode::MaskWriteRegister,
+    FunctionCode::ReadWriteMultipleRegisters,
+];
+
+const OTHER_FUNCTIONS: [FunctionCode; 8] = [
+    FunctionCode::ReadExceptionStatus,
+    FunctionCode::Diagnostics,
+    FunctionCode::GetCommEventCounter,
+    FunctionCode::GetCommEventLog,
+    FunctionCode::ReportServerId,
+    FunctionCode::ReadFileRecord,
+    FunctionCode::ReadFifoQueue,
+    FunctionCode::EncapsulatedInterfaceTransport,
+];
+
+impl FunctionPreset {
+    /// Function code values allowed by this preset
+    pub fn functions(self) -> HashSet<u8> {
+        let reads = READ_FUNCTIONS.iter();
+        match self {
+            FunctionPreset::ReadOnly => reads.map(|code| code.value()).collect(),
+            FunctionPreset::Full => reads
+                .chain(WRITE_FUNCTIONS.iter())
+                .chain(OTHER_FUNCTIONS.iter())
+                .map(|code| code.value())
+                .collect(),
+        }
+    }
+}
+
+/// Server side settings
+///
+/// `allowed_functions` holds function code values (`FunctionCode` is not `Hash`), e.g.
+/// `[1, 2, 3, 4]` for a read-only gateway. Requests for any other code are answered
+/// with [`ExceptionCode::IllegalFunction`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ModbusServerConfig {
+    #[serde(default = "full_functions")]
+    pub allowed_functions: HashSet<u8>,
+}
+
+fn full_functions() -> HashSet<u8> {
+    FunctionPreset::Full.functions()
+}
+
+impl Default for ModbusServerConfig {
+    fn default() -> Self {
+        FunctionPreset::Full.into()
+    }
+}
+
+impl From<FunctionPreset> for ModbusServerConfig {
+    fn from(preset: FunctionPreset) -> Self {
+        ModbusServerConfig {
+            allowed_functions: preset.functions(),
+        }
+    }
+}
+
+impl ModbusServerConfig {
+    pub fn is_allowed(&self, function: FunctionCode) -> bool {
+        self.allowed_functions.contains(&function.value())
+    }
+
+    /// `Err(IllegalFunction)` when the function of `request` is not allowed
+    pub fn check(&self, request: &Request<'_>) -> Result<(), ExceptionCode> {
+        let function = request.function_code();
+        if self.is_allowed(function) {
+            Ok(())
+        } else {
+            tracing::warn!("Refused Modbus function 0x{:02X}", function.value());
+            Err(ExceptionCode::IllegalFunction)
+        }
+    }
+}
+
+/// Requests a [`FilteredService`] can check, `Request` or `SlaveRequest`
+pub trait FunctionRequest {
+    fn request(&self) -> &Request<'_>;
+}
+
+impl FunctionRequest for Request<'_> {
+    fn request(&self) -> &Request<'_> {
+        self
+    }
+}
+
+impl FunctionRequest for SlaveRequest<'_> {
+    fn request(&self) -> &Request<'_> {
+        &self.request
+    }
+}
+
+/// Wraps a server [`Service`], refusing requests not allowed by the config before they
+/// reach it
+pub struct FilteredService<S> {
+    inner: S,
+    config: ModbusServerConfig,
+}
+
+impl<S> FilteredService<S> {
+    pub fn new(inner: S, config: ModbusServerConfig) -> Self {
+        Self { inner, config }
+    }
+
+    pub fn config(&self) -> &ModbusServerConfig {
+        &self.config
+    }
+}
+
+impl<S> Service for FilteredService<S>
+where
+    S: Service,
+    S::Request: FunctionRequest,
+    S::Response: Send,
+    S::Exception: From<ExceptionCode> + Send,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Exception = S::Exception;
+    type Future = Either<Ready<Result<S::Response, S::Exception>>, S::Future>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        match self.config.check(req.request()) {
+            Ok(()) => Either::Right(self.inner.call(req)),
+            Err(e) => Either::Left(ready(Err(e.into()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Registers(Mutex<Vec<u16>>);
+
+    impl Service for Registers {
+        type Request = Request<'static>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = Ready<Result<Response, ExceptionCode>>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            let mut registers = self.0.lock().unwrap();
+            ready(match req {
+                Request::ReadHoldingRegisters(addr, cnt) => Ok(Response::ReadHoldingRegisters(
+                    registers[addr as usize..(addr + cnt) as usize].to_vec(),
+                )),
+                Request::WriteSingleRegister(addr, word) => {
+                    registers[addr as usize] = word;
+                    Ok(Response::WriteSingleRegister(addr, word))
+                }
+                _ => Err(ExceptionCode::IllegalFunction),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_only_refuses_writes() {
+        let registers = Registers(Mutex::new(vec![1, 2]));
+        let service = FilteredService::new(registers, FunctionPreset::ReadOnly.into());
+
+        let response = service.call(Request::ReadHoldingRegisters(0, 2)).await;
+        assert_eq!(response, Ok(Response::ReadHoldingRegisters(vec![1, 2])));
+
+        let response = service.call(Request::WriteSingleRegister(0, 9)).await;
+        assert_eq!(response, Err(ExceptionCode::IllegalFunction));
+        assert_eq!(*service.inner.0.lock().unwrap(), vec![1, 2]);
+
+        let service = FilteredService::new(service.inner, ModbusServerConfig::default());
+        let response = service.call(Request::WriteSingleRegister(0, 9)).await;
+        assert_eq!(response, Ok(Response::WriteSingleRegister(0, 9)));
+        assert_eq!(*service.inner.0.lock().unwrap(), vec![9, 2]);
+    }
+
+    #[test]
+    fn test_allowed_functions_config() {
+        let config: ModbusServerConfig =
+            serde_json::from_value(serde_json::json!({ "allowed_functions": [3, 4] })).unwrap();
+        assert!(config.is_allowed(FunctionCode::ReadHoldingRegisters));
+        assert!(!config.is_allowed(FunctionCode::ReadCoils));
+        assert_eq!(
+            config.check(&Request::WriteMultipleRegisters(0, vec![1].into())),
+            Err(ExceptionCode::IllegalFunction)
+        );
+
+        let config: ModbusServerConfig = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(config, ModbusServerConfig::from(FunctionPreset::Full));
+        assert!(config.is_allowed(FunctionCode::MaskWriteRegister));
+        assert!(!config.is_allowed(FunctionCode::Custom(0x41)));
+    }
+}
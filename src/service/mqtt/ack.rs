@@ -0,0 +1,167 @@
+// Publishes that resolve once the broker acknowledged them.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use rumqttc::{AsyncClient, ClientError, Event, Outgoing, Packet, QoS};
+use tokio::sync::oneshot;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PublishAckError {
+    #[error("MQTT client error: {0}")]
+    Client(#[from] ClientError),
+    /// The publisher was dropped, or its events stopped being forwarded, before the ack
+    #[error("Publish dropped before the broker acknowledged it")]
+    Dropped,
+}
+
+#[derive(Default)]
+struct PendingAcks {
+    /// Waiters of QoS 1/2 publishes handed to the event loop but not sent yet, in order
+    queued: VecDeque<oneshot::Sender<()>>,
+    /// Waiters by packet id once the publish went out
+    sent: HashMap<u16, oneshot::Sender<()>>,
+}
+
+/// Wraps an [`AsyncClient`] to publish with confirmed delivery.
+///
+/// The event loop stays with the caller, who must pass every event it polls to
+/// [`AckPublisher::handle_event`]. Publish packet ids are assigned by the event loop, so
+/// waiters are matched to ids in the order publishes were queued: other QoS 1/2 publishes
+/// on the same client must go through this publisher as well.
+#[derive(Clone)]
+pub struct AckPublisher {
+    client: AsyncClient,
+    pending: Arc<Mutex<PendingAcks>>,
+    /// Keeps queuing a waiter and sending its request in the same order
+    send_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl AckPublisher {
+    pub fn new(client: AsyncClient) -> Self {
+        Self {
+            client,
+            pending: Arc::new(Mutex::new(PendingAcks::default())),
+            send_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    pub fn client(&self) -> &AsyncClient {
+        &self.client
+    }
+
+    /// Publish and wait until the broker acknowledged the packet: PUBACK for QoS 1,
+    /// PUBCOMP for QoS 2. QoS 0 resolves as soon as the request is queued.
+    ///
+    /// There is no timeout, wrap the call in `tokio::time::timeout` when needed.
+    pub async fn publish_awaitable(
+        &self,
+        topic: impl Into<String>,
+        qos: QoS,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<(), PublishAckError> {
+        if qos == QoS::AtMostOnce {
+            self.client.publish(topic, qos, false, payload).await?;
+            return Ok(());
+        }
+
+        let (ack_sender, ack) = oneshot::channel();
+        {
+            let _send = self.send_lock.lock().await;
+            self.pending.lock().unwrap().queued.push_back(ack_sender);
+            if let Err(e) = self.client.publish(topic, qos, false, payload).await {
+                self.pending.lock().unwrap().queued.pop_back();
+                return Err(e.into());
+            }
+        }
+        ack.await.map_err(|_| PublishAckError::Dropped)
+    }
+
+    /// Feed an event polled from the client's event loop
+    pub fn handle_event(&self, event: &Event) {
+        let mut pending = self.pending.lock().unwrap();
+        match event {
+            // pkid 0 为 QoS 0；已登记的 pkid 是重连后的重发
+            Event::Outgoing(Outgoing::Publish(pkid))
+                if *pkid != 0 && !pending.sent.contains_key(pkid) =>
+            {
+                if let Some(waiter) = pending.queued.pop_front() {
+                    pending.sent.insert(*pkid, waiter);
+                }
+            }
+            Event::Incoming(Packet::PubAck(ack)) => {
+                if let Some(waiter) = pending.sent.remove(&ack.pkid) {
+                    let _ = waiter.send(());
+                }
+            }
+            Event::Incoming(Packet::PubComp(comp)) => {
+                if let Some(waiter) = pending.sent.remove(&comp.pkid) {
+                    let _ = waiter.send(());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::service::mqtt::client::ClientBuilder;
+    use crate::service::mqtt::mock::{accept_client, read_packet};
+
+    #[tokio::test]
+    async fn test_publish_awaitable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // 模拟 broker：CONNACK，收到 QoS 1 publish 后延迟 50ms 再 PUBACK
+        let (publish_sender, mut published) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut stream = accept_client(&listener).await;
+            loop {
+                let (header, body) = read_packet(&mut stream).await;
+                if header >> 4 != 3 {
+                    continue;
+                }
+                let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                let qos = (header >> 1) & 0x03;
+                publish_sender.send(qos).unwrap();
+                if qos == 1 {
+                    let pkid = &body[2 + topic_len..4 + topic_len];
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    stream.write_all(&[0x40, 0x02, pkid[0], pkid[1]]).await.unwrap();
+                }
+            }
+        });
+
+        let (client, mut eventloop) = ClientBuilder::new("127.0.0.1", port).build();
+        let publisher = AckPublisher::new(client);
+        let events = publisher.clone();
+        tokio::spawn(async move {
+            while let Ok(event) = eventloop.poll().await {
+                events.handle_event(&event);
+            }
+        });
+
+        publisher
+            .publish_awaitable("leanlink/test", QoS::AtMostOnce, "a")
+            .await
+            .unwrap();
+
+        let started = tokio::time::Instant::now();
+        publisher
+            .publish_awaitable("leanlink/test", QoS::AtLeastOnce, "b")
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(published.recv().await, Some(0));
+        assert_eq!(published.recv().await, Some(1));
+        assert!(publisher.pending.lock().unwrap().sent.is_empty());
+    }
+}
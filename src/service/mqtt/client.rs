@@ -2,7 +2,7 @@ use rand::{Rng, distr::Alphanumeric};
 use rumqttc::{AsyncClient, EventLoop, MqttOptions};
 use std::time::Duration;
 
-use crate::config::MqttConfig;
+use crate::service::mqtt::MqttConfig;
 
 pub struct ClientBuilder {
     host: String,
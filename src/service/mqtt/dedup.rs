@@ -0,0 +1,123 @@
+// Drop QoS 1 redeliveries of messages already handled.
+
+use std::collections::{HashSet, VecDeque};
+
+use rumqttc::Publish;
+use serde::{Deserialize, Serialize};
+
+/// What identifies a message for [`Deduplicator`]. There is no packet id key: the broker
+/// reuses a packet id once acknowledged, so it does not identify a message
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupKey {
+    /// Top-level field of a JSON payload, e.g. `msgId`; messages without it pass through
+    JsonField(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MqttDedupConfig {
+    pub key: DedupKey,
+    /// Number of recent message ids remembered
+    #[serde(default = "default_window")]
+    pub window: usize,
+}
+
+fn default_window() -> usize {
+    1024
+}
+
+/// Best-effort duplicate filter over the last `window` message ids (least recently seen
+/// are forgotten first).
+///
+/// Ids are only kept in memory, so duplicates across restarts or outside the window still
+/// get through: handlers with side effects must stay idempotent.
+pub struct Deduplicator {
+    key: DedupKey,
+    window: usize,
+    /// Oldest first
+    recent: VecDeque<String>,
+    seen: HashSet<String>,
+}
+
+impl Deduplicator {
+    pub fn new(config: &MqttDedupConfig) -> Self {
+        Self {
+            key: config.key.clone(),
+            window: config.window.max(1),
+            recent: VecDeque::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// `true` when `publish` was seen within the window and should be dropped, otherwise
+    /// its id is remembered
+    pub fn is_duplicate(&mut self, publish: &Publish) -> bool {
+        let Some(id) = self.message_id(publish) else {
+            return false;
+        };
+        if self.seen.contains(&id) {
+            if let Some(pos) = self.recent.iter().position(|recent| *recent == id) {
+                self.recent.remove(pos);
+            }
+            self.recent.push_back(id);
+            tracing::debug!("Dropping duplicate MQTT message on {}", publish.topic);
+            return true;
+        }
+
+        if self.seen.insert(id.clone()) {
+            self.recent.push_back(id);
+            if self.recent.len() > self.window
+                && let Some(oldest) = self.recent.pop_front()
+            {
+                self.seen.remove(&oldest);
+            }
+        }
+        false
+    }
+
+    fn message_id(&self, publish: &Publish) -> Option<String> {
+        let DedupKey::JsonField(field) = &self.key;
+        let payload: serde_json::Value = serde_json::from_slice(&publish.payload).ok()?;
+        match payload.get(field)? {
+            serde_json::Value::String(id) => Some(id.clone()),
+            serde_json::Value::Null => None,
+            id => Some(id.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rumqttc::QoS;
+
+    use super::*;
+
+    fn publish(payload: &str) -> Publish {
+        Publish::new("leanlink/in", QoS::AtLeastOnce, payload)
+    }
+
+    #[test]
+    fn test_json_field_dedup() {
+        let config: MqttDedupConfig =
+            serde_yaml_bw::from_str("key:\n  json_field: msgId\nwindow: 2").unwrap();
+        let mut dedup = Deduplicator::new(&config);
+
+        assert!(!dedup.is_duplicate(&publish(r#"{"msgId": "a"}"#)));
+        assert!(dedup.is_duplicate(&publish(r#"{"msgId": "a"}"#)));
+        assert!(!dedup.is_duplicate(&publish(r#"{"msgId": 7}"#)));
+        assert!(!dedup.is_duplicate(&publish("not json")));
+        assert!(!dedup.is_duplicate(&publish("not json")));
+
+        // 窗口为 2："a" 刚被访问过，最久未见的 7 被淘汰
+        assert!(dedup.is_duplicate(&publish(r#"{"msgId": "a"}"#)));
+        assert!(!dedup.is_duplicate(&publish(r#"{"msgId": "b"}"#)));
+        assert!(dedup.is_duplicate(&publish(r#"{"msgId": "a"}"#)));
+        assert!(!dedup.is_duplicate(&publish(r#"{"msgId": 7}"#)));
+    }
+
+    #[test]
+    fn test_packet_id_key_rejected() {
+        // broker 会复用已确认的 packet id，不能用来去重
+        assert!(serde_yaml_bw::from_str::<MqttDedupConfig>("key: packet_id").is_err());
+    }
+}
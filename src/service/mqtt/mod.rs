@@ -1,7 +1,14 @@
-use rumqttc::QoS;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
 
 pub use rumqttc::*;
+pub mod ack;
 pub mod client;
+pub mod dedup;
+mod service;
+
+pub use service::MqttService;
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct MqttTopic {
@@ -29,6 +36,9 @@ pub struct MqttConfig {
     pub topic: Vec<MqttTopic>,
     #[serde(with = "crate::utils::datetime::string_to_duration")]
     pub keep_alive: Duration,
+    /// Drop redelivered messages, see [`dedup::Deduplicator`]
+    #[serde(default)]
+    pub dedup: Option<dedup::MqttDedupConfig>,
 }
 
 impl Default for MqttConfig {
@@ -41,11 +51,12 @@ impl Default for MqttConfig {
             client_id: "leanlink_client".to_string(),
             topic: vec![MqttTopic::default()],
             keep_alive: Duration::from_secs(60),
+            dedup: None,
         }
     }
 }
 
-mod string_to_qos {
+pub(crate) mod string_to_qos {
     use rumqttc::QoS;
     use serde::{Deserialize, Deserializer, Serializer, de::Error};
 
@@ -72,4 +83,36 @@ mod string_to_qos {
             _ => Err(D::Error::custom(format!("Invalid QoS: {}", s))),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Just enough of a broker to accept one client in tests
+#[cfg(test)]
+pub(crate) mod mock {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Read one MQTT packet, returns the fixed header byte and the body
+    pub async fn read_packet(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+        let header = stream.read_u8().await.unwrap();
+        let (mut len, mut shift) = (0usize, 0);
+        loop {
+            let byte = stream.read_u8().await.unwrap();
+            len |= ((byte & 0x7F) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; len];
+        stream.read_exact(&mut body).await.unwrap();
+        (header, body)
+    }
+
+    /// Accept a client and answer its CONNECT
+    pub async fn accept_client(listener: &TcpListener) -> TcpStream {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        read_packet(&mut stream).await;
+        stream.write_all(&[0x20, 0x02, 0x00, 0x00]).await.unwrap();
+        stream
+    }
+}
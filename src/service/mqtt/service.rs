@@ -0,0 +1,201 @@
+// Runs the event loop of an MQTT client on a background task.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, ClientError, Event, EventLoop, Incoming, Outgoing};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use super::MqttConfig;
+use super::client::ClientBuilder;
+use super::dedup::Deduplicator;
+
+/// How long `disconnect` waits for the DISCONNECT packet to be written
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before polling again after a connection error, the event loop reconnects on poll
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// An MQTT client whose event loop is polled by a spawned task, every event is passed to
+/// the handler given to [`MqttService::start`].
+pub struct MqttService {
+    client: AsyncClient,
+    task: Mutex<Option<JoinHandle<()>>>,
+    /// Notified once the event loop sent DISCONNECT
+    disconnected: Arc<Notify>,
+}
+
+impl MqttService {
+    pub fn start<F>(client: AsyncClient, eventloop: EventLoop, on_event: F) -> Self
+    where
+        F: FnMut(&Event) + Send + 'static,
+    {
+        Self::spawn(client, eventloop, None, on_event)
+    }
+
+    /// Connect with `config`. Incoming publishes found duplicate by `config.dedup` are not
+    /// passed to `on_event`.
+    pub fn from_config<F>(config: &MqttConfig, on_event: F) -> Self
+    where
+        F: FnMut(&Event) + Send + 'static,
+    {
+        let (client, eventloop) = ClientBuilder::new("", 0).with_config(config).build();
+        let dedup = config.dedup.as_ref().map(Deduplicator::new);
+        Self::spawn(client, eventloop, dedup, on_event)
+    }
+
+    fn spawn<F>(
+        client: AsyncClient,
+        mut eventloop: EventLoop,
+        mut dedup: Option<Deduplicator>,
+        mut on_event: F,
+    ) -> Self
+    where
+        F: FnMut(&Event) + Send + 'static,
+    {
+        let disconnected = Arc::new(Notify::new());
+        let notify = disconnected.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match eventloop.poll().await {
+                    Ok(event) => {
+                        if let (Event::Incoming(Incoming::Publish(publish)), Some(dedup)) =
+                            (&event, dedup.as_mut())
+                            && dedup.is_duplicate(publish)
+                        {
+                            continue;
+                        }
+                        on_event(&event);
+                        if event == Event::Outgoing(Outgoing::Disconnect) {
+                            notify.notify_one();
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("MQTT connection error: {}", e);
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        });
+        Self {
+            client,
+            task: Mutex::new(Some(task)),
+            disconnected,
+        }
+    }
+
+    pub fn client(&self) -> &AsyncClient {
+        &self.client
+    }
+
+    /// Send DISCONNECT and stop the event loop once it went out, so the broker sees a clean
+    /// disconnect and does not publish the last will.
+    ///
+    /// Requests queued before this call are sent first. If the packet is not written within
+    /// 5 seconds (e.g. the broker is unreachable) the loop is stopped anyway.
+    pub async fn disconnect(&self) -> Result<(), ClientError> {
+        let Some(task) = self.task.lock().unwrap().take() else {
+            return Ok(());
+        };
+        let disconnected = self.disconnected.notified();
+        self.client.disconnect().await?;
+        if tokio::time::timeout(DISCONNECT_TIMEOUT, disconnected)
+            .await
+            .is_err()
+        {
+            tracing::warn!("MQTT DISCONNECT not sent within {:?}", DISCONNECT_TIMEOUT);
+        }
+        task.abort();
+        Ok(())
+    }
+}
+
+impl Drop for MqttService {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::service::mqtt::client::ClientBuilder;
+    use crate::service::mqtt::mock::{accept_client, read_packet};
+
+    #[tokio::test]
+    async fn test_disconnect_sends_packet() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker = tokio::spawn(async move {
+            let mut stream = accept_client(&listener).await;
+            read_packet(&mut stream).await
+        });
+
+        let (client, eventloop) = ClientBuilder::new("127.0.0.1", port).build();
+        let (event_sender, mut events) = tokio::sync::mpsc::unbounded_channel();
+        let service = MqttService::start(client, eventloop, move |event| {
+            let _ = event_sender.send(event.clone());
+        });
+
+        service.disconnect().await.unwrap();
+        assert_eq!(broker.await.unwrap(), (0xE0, vec![]));
+        let mut last = None;
+        while let Some(event) = events.recv().await {
+            last = Some(event);
+        }
+        assert_eq!(last, Some(Event::Outgoing(Outgoing::Disconnect)));
+        // 再次调用不做任何事
+        service.disconnect().await.unwrap();
+    }
+
+    /// QoS 0 PUBLISH to `leanlink/in`
+    fn publish_packet(payload: &str) -> Vec<u8> {
+        let topic = b"leanlink/in";
+        let mut packet = vec![0x30, (2 + topic.len() + payload.len()) as u8, 0];
+        packet.push(topic.len() as u8);
+        packet.extend_from_slice(topic);
+        packet.extend_from_slice(payload.as_bytes());
+        packet
+    }
+
+    #[tokio::test]
+    async fn test_from_config_drops_duplicates() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = MqttConfig {
+            host: "127.0.0.1".into(),
+            port: listener.local_addr().unwrap().port(),
+            username: "".into(),
+            password: "".into(),
+            dedup: Some(serde_yaml_bw::from_str("key:\n  json_field: msgId").unwrap()),
+            ..Default::default()
+        };
+        let broker = tokio::spawn(async move {
+            let mut stream = accept_client(&listener).await;
+            for payload in [r#"{"msgId": "a"}"#, r#"{"msgId": "a"}"#, r#"{"msgId": "b"}"#] {
+                stream.write_all(&publish_packet(payload)).await.unwrap();
+            }
+            // 保持连接直到客户端断开
+            read_packet(&mut stream).await
+        });
+
+        let (event_sender, mut events) = tokio::sync::mpsc::unbounded_channel();
+        let service = MqttService::from_config(&config, move |event| {
+            if let Event::Incoming(Incoming::Publish(publish)) = event {
+                let _ = event_sender.send(publish.payload.clone());
+            }
+        });
+
+        assert_eq!(events.recv().await.unwrap(), r#"{"msgId": "a"}"#);
+        assert_eq!(events.recv().await.unwrap(), r#"{"msgId": "b"}"#);
+        service.disconnect().await.unwrap();
+        broker.await.unwrap();
+        assert!(events.recv().await.is_none());
+    }
+}
@@ -0,0 +1,211 @@
+// Startup probes of the configured endpoints, see `AppState::self_check`.
+
+use std::fmt::Write;
+#[cfg(any(feature = "mqtt", feature = "modbus"))]
+use std::time::Duration;
+
+use sea_orm::{ConnectionTrait, DatabaseConnection};
+use serde::{Deserialize, Serialize};
+
+use crate::config::ServerConfig;
+
+/// Time given to each network probe
+#[cfg(any(feature = "mqtt", feature = "modbus"))]
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Outcome of one probe
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CheckResult {
+    /// `database`, `serialport`, `mqtt`, `modbus_tcp` or `modbus_rtu`
+    pub component: String,
+    /// Device path or `host:port` probed
+    pub target: String,
+    pub passed: bool,
+    pub message: String,
+}
+
+impl CheckResult {
+    fn new(component: &str, target: impl Into<String>, outcome: Result<String, String>) -> Self {
+        let passed = outcome.is_ok();
+        Self {
+            component: component.to_string(),
+            target: target.into(),
+            passed,
+            message: outcome.unwrap_or_else(|e| e),
+        }
+    }
+}
+
+/// Probe the database and every endpoint of `config`, one after the other
+pub async fn run(db_conn: &DatabaseConnection, config: &ServerConfig) -> Vec<CheckResult> {
+    #[cfg_attr(not(any(feature = "serialport", feature = "mqtt")), allow(unused_mut))]
+    let mut results = vec![check_database(db_conn).await];
+
+    #[cfg(feature = "serialport")]
+    for port in &config.serialport {
+        let (target, outcome) = match port.resolve_path() {
+            Ok(path) => (path.clone(), check_path(&path)),
+            Err(e) => (port.path.clone(), Err(e.to_string())),
+        };
+        results.push(CheckResult::new("serialport", target, outcome));
+    }
+
+    #[cfg(feature = "mqtt")]
+    for mqtt in &config.mqtt {
+        let target = format!("{}:{}", mqtt.host, mqtt.port);
+        results.push(CheckResult::new("mqtt", target, check_mqtt(mqtt).await));
+    }
+
+    #[cfg(feature = "modbus")]
+    {
+        for modbus in &config.modbus_tcp {
+            let target = format!("{}:{}", modbus.host, modbus.port);
+            let outcome = check_tcp(&modbus.host, modbus.port).await;
+            results.push(CheckResult::new("modbus_tcp", target, outcome));
+        }
+        for modbus in &config.modbus_rtu {
+            let outcome = check_path(&modbus.path);
+            results.push(CheckResult::new("modbus_rtu", modbus.path.clone(), outcome));
+        }
+    }
+
+    #[cfg(not(any(feature = "serialport", feature = "mqtt")))]
+    let _ = config;
+    results
+}
+
+async fn check_database(db_conn: &DatabaseConnection) -> CheckResult {
+    let backend = format!("{:?}", db_conn.get_database_backend());
+    let outcome = match db_conn.ping().await {
+        Ok(()) => Ok("reachable".to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    CheckResult::new("database", backend, outcome)
+}
+
+/// The device node exists, the port is not opened so a running service keeps it
+#[cfg(feature = "serialport")]
+fn check_path(path: &str) -> Result<String, String> {
+    // Windows 的 COM 口不是文件，只能从已枚举的端口中查找
+    if cfg!(target_os = "windows") {
+        let ports = serialport::available_ports().map_err(|e| e.to_string())?;
+        if ports.iter().any(|port| port.port_name.eq_ignore_ascii_case(path)) {
+            return Ok("present".to_string());
+        }
+        return Err("not found".to_string());
+    }
+    match std::fs::metadata(path) {
+        Ok(_) => Ok("present".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(feature = "modbus")]
+async fn check_tcp(host: &str, port: u16) -> Result<String, String> {
+    let connect = tokio::net::TcpStream::connect((host, port));
+    match tokio::time::timeout(PROBE_TIMEOUT, connect).await {
+        Ok(Ok(_)) => Ok("connected".to_string()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("no connection within {:?}", PROBE_TIMEOUT)),
+    }
+}
+
+/// Connect with the configured credentials until CONNACK, then disconnect. The configured
+/// client id is used, run the check before the MQTT services connect.
+#[cfg(feature = "mqtt")]
+async fn check_mqtt(config: &crate::service::mqtt::MqttConfig) -> Result<String, String> {
+    use rumqttc::{Event, Packet};
+
+    let (client, mut eventloop) = crate::service::mqtt::client::ClientBuilder::new("", 0)
+        .with_config(config)
+        .build();
+    let connack = tokio::time::timeout(PROBE_TIMEOUT, async {
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => return Ok(()),
+                Ok(_) => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| Err(format!("no CONNACK within {:?}", PROBE_TIMEOUT)));
+
+    if connack.is_ok() {
+        // 尽量发送 DISCONNECT，避免 broker 发布遗嘱
+        let _ = client.try_disconnect();
+        let _ = tokio::time::timeout(Duration::from_millis(500), eventloop.poll()).await;
+    }
+    connack.map(|()| "connected".to_string())
+}
+
+/// Render `results` as a plain text table, one row per probe
+pub fn summary_table(results: &[CheckResult]) -> String {
+    let component_width = column_width("COMPONENT", results.iter().map(|r| &r.component));
+    let target_width = column_width("TARGET", results.iter().map(|r| &r.target));
+
+    let mut table = String::new();
+    let _ = writeln!(
+        table,
+        "{:<component_width$}  {:<target_width$}  STATUS  MESSAGE",
+        "COMPONENT", "TARGET"
+    );
+    for result in results {
+        let status = if result.passed { "PASS" } else { "FAIL" };
+        let _ = writeln!(
+            table,
+            "{:<component_width$}  {:<target_width$}  {:<6}  {}",
+            result.component, result.target, status, result.message
+        );
+    }
+    table
+}
+
+fn column_width<'a>(header: &str, values: impl Iterator<Item = &'a String>) -> usize {
+    values
+        .map(|value| value.chars().count())
+        .fold(header.len(), usize::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_table() {
+        let results = vec![
+            CheckResult::new("database", "Sqlite", Ok("reachable".into())),
+            CheckResult::new("serialport", "/dev/ttyUSB9", Err("not found".into())),
+        ];
+        assert_eq!(
+            summary_table(&results),
+            "COMPONENT   TARGET        STATUS  MESSAGE\n\
+             database    Sqlite        PASS    reachable\n\
+             serialport  /dev/ttyUSB9  FAIL    not found\n"
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_run_checks_database() {
+        let conn = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        let results = run(&conn, &ServerConfig::default()).await;
+        assert_eq!(
+            results,
+            vec![CheckResult::new("database", "Sqlite", Ok("reachable".into()))]
+        );
+    }
+
+    #[cfg(feature = "modbus")]
+    #[tokio::test]
+    async fn test_probe_endpoints() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(check_tcp("127.0.0.1", port).await.is_ok());
+        drop(listener);
+        assert!(check_tcp("127.0.0.1", port).await.is_err());
+
+        assert!(check_path("/dev/null").is_ok());
+        assert!(check_path("/dev/lean-link-missing").is_err());
+    }
+}
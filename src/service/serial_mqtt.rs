@@ -0,0 +1,310 @@
+// Forward frames decoded from a serial port to MQTT, and MQTT commands back to the port.
+
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use rumqttc::{AsyncClient, ClientError, Event, EventLoop, Packet, Publish, QoS};
+use serde::{Deserialize, Serialize};
+use tokio::time::{Instant, sleep_until};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    service::{
+        mqtt::{MqttConfig, client::ClientBuilder, string_to_qos},
+        serialport::{SerialPort, SerialPortBuilder, SerialPortConfig},
+    },
+    utils::{hex, retry::ReconnectPolicy},
+};
+
+/// Encoding of frames in MQTT payloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum FrameFormat {
+    /// Hex text such as `"010AFF"`
+    #[default]
+    Hex,
+    /// JSON document; byte frames are encoded as an array of numbers
+    Json,
+}
+
+/// A frame type that can be published to MQTT and parsed back from a command payload.
+///
+/// Implemented for the raw byte frames of `BytesCodec`-like codecs. Codecs that decode
+/// structured frames implement it for their item type, usually through `serde_json`.
+pub trait MqttFrame: Sized {
+    fn to_payload(&self, format: FrameFormat) -> Option<Vec<u8>>;
+
+    fn from_payload(payload: &[u8], format: FrameFormat) -> Option<Self>;
+}
+
+macro_rules! impl_mqtt_frame_for_bytes {
+    ($($ty:ty => $from_vec:expr),*) => {
+        $(
+            impl MqttFrame for $ty {
+                fn to_payload(&self, format: FrameFormat) -> Option<Vec<u8>> {
+                    match format {
+                        FrameFormat::Hex => Some(hex::format(self, "").into_bytes()),
+                        FrameFormat::Json => serde_json::to_vec(&self[..]).ok(),
+                    }
+                }
+
+                fn from_payload(payload: &[u8], format: FrameFormat) -> Option<Self> {
+                    let bytes = match format {
+                        FrameFormat::Hex => hex::parse(std::str::from_utf8(payload).ok()?).ok()?.into(),
+                        FrameFormat::Json => serde_json::from_slice::<Vec<u8>>(payload).ok()?,
+                    };
+                    Some($from_vec(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_mqtt_frame_for_bytes!(
+    Vec<u8> => std::convert::identity,
+    Bytes => Bytes::from,
+    BytesMut => |bytes: Vec<u8>| BytesMut::from(&bytes[..])
+);
+
+/// Delay before reading the serial port again after a read error
+const SERIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+fn default_qos() -> QoS {
+    QoS::AtLeastOnce
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SerialToMqttConfig {
+    pub serialport: SerialPortConfig,
+    pub mqtt: MqttConfig,
+    /// Topic every decoded frame is published to
+    pub topic: String,
+    /// Publishes on this topic are decoded and written to the serial port
+    #[serde(default)]
+    pub command_topic: Option<String>,
+    #[serde(default)]
+    pub format: FrameFormat,
+    #[serde(with = "string_to_qos", default = "default_qos")]
+    pub qos: QoS,
+}
+
+/// Publishes every frame decoded by the port's codec to `topic`, and writes commands
+/// received on `command_topic` back to the port through the same codec.
+pub struct SerialToMqtt<T, C> {
+    port: SerialPort<T, C>,
+    client: AsyncClient,
+    eventloop: EventLoop,
+    topic: String,
+    command_topic: Option<String>,
+    format: FrameFormat,
+    qos: QoS,
+    policy: ReconnectPolicy,
+}
+
+impl<T, C> SerialToMqtt<T, C>
+where
+    T: MqttFrame + Clone,
+    C: Decoder<Item = T, Error: std::fmt::Debug>
+        + Encoder<T, Error = std::io::Error>
+        + Unpin
+        + Default,
+{
+    /// Build the serial port and MQTT client from `config`; nothing is opened until [`run`](Self::run).
+    pub fn new(config: &SerialToMqttConfig) -> Self {
+        let port = SerialPortBuilder::new_with_config(&config.serialport).build::<T, C>();
+        let (client, eventloop) = ClientBuilder::new(&config.mqtt.host, config.mqtt.port)
+            .with_config(&config.mqtt)
+            .build();
+        Self {
+            port,
+            client,
+            eventloop,
+            topic: config.topic.clone(),
+            command_topic: config.command_topic.clone(),
+            format: config.format,
+            qos: config.qos,
+            policy: ReconnectPolicy::default(),
+        }
+    }
+
+    /// Backoff between MQTT reconnects in [`run`](Self::run); `max_retries` is ignored, it never gives up
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn client(&self) -> &AsyncClient {
+        &self.client
+    }
+
+    /// Queue one decoded frame for `topic` without waiting, fails when the client's request
+    /// queue is full (e.g. during a broker outage)
+    pub fn publish_frame(&self, frame: &T) -> Result<(), ClientError> {
+        let Some(payload) = frame.to_payload(self.format) else {
+            tracing::warn!("serial_mqtt: drop frame, cannot encode as {:?}", self.format);
+            return Ok(());
+        };
+        self.client
+            .try_publish(self.topic.clone(), self.qos, false, payload)
+    }
+
+    /// Decode a publish received on `command_topic`, `None` for other topics or bad payloads.
+    pub fn command_frame(&self, publish: &Publish) -> Option<T> {
+        let command_topic = self.command_topic.as_ref()?;
+        if !rumqttc::matches(&publish.topic, command_topic) {
+            return None;
+        }
+        let frame = T::from_payload(&publish.payload, self.format);
+        if frame.is_none() {
+            tracing::warn!(
+                "serial_mqtt: drop command on {}, payload is not {:?}",
+                publish.topic,
+                self.format
+            );
+        }
+        frame
+    }
+
+    /// Forward frames and commands until the task is dropped.
+    ///
+    /// Serial errors are retried after a second and broker errors after the reconnect policy's
+    /// backoff, while the other side keeps running; the port is reopened by the next read and
+    /// the command topic is resubscribed on every CONNACK. Frames read while the MQTT request
+    /// queue is full are dropped and counted, publishing never waits for the broker.
+    pub async fn run(mut self) {
+        let mut failures = 0;
+        let mut dropped: u64 = 0;
+        // 出错后的重试时间，等待期间另一侧照常运行，不会卡住 MQTT 心跳
+        let mut serial_retry_at: Option<Instant> = None;
+        let mut mqtt_retry_at: Option<Instant> = None;
+        loop {
+            tokio::select! {
+                frame = self.port.next(), if serial_retry_at.is_none() => match frame {
+                    Ok(Some(frame)) => match self.publish_frame(&frame) {
+                        Ok(()) if dropped > 0 => {
+                            tracing::info!(
+                                "serial_mqtt: publishing resumed, {} frames dropped",
+                                dropped
+                            );
+                            dropped = 0;
+                        }
+                        Ok(()) => {}
+                        Err(e) => {
+                            if dropped == 0 {
+                                tracing::warn!(
+                                    "serial_mqtt: publish to {} failed: {}, dropping frames",
+                                    self.topic,
+                                    e
+                                );
+                            }
+                            dropped += 1;
+                        }
+                    },
+                    Ok(None) => {}
+                    Err(e) => {
+                        tracing::error!("serial_mqtt: read serial port failed: {}", e);
+                        serial_retry_at = Some(Instant::now() + SERIAL_RETRY_DELAY);
+                    }
+                },
+                _ = sleep_until(serial_retry_at.unwrap_or_else(Instant::now)),
+                    if serial_retry_at.is_some() =>
+                {
+                    serial_retry_at = None;
+                }
+                event = self.eventloop.poll(), if mqtt_retry_at.is_none() => match event {
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        if let Some(frame) = self.command_frame(&publish)
+                            && let Err(e) = self.port.send(frame).await
+                        {
+                            tracing::error!("serial_mqtt: write serial port failed: {}", e);
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        failures = 0;
+                        if let Some(command_topic) = &self.command_topic
+                            && let Err(e) = self.client.try_subscribe(command_topic.clone(), self.qos)
+                        {
+                            tracing::error!("serial_mqtt: subscribe failed: {}", e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        failures += 1;
+                        let delay = self.policy.delay(failures);
+                        tracing::error!("serial_mqtt: MQTT connection error: {}, reconnect in {:?}", e, delay);
+                        mqtt_retry_at = Some(Instant::now() + delay);
+                    }
+                },
+                _ = sleep_until(mqtt_retry_at.unwrap_or_else(Instant::now)),
+                    if mqtt_retry_at.is_some() =>
+                {
+                    mqtt_retry_at = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_util::codec::BytesCodec;
+
+    use super::*;
+
+    #[test]
+    fn test_byte_frame_payloads() {
+        let frame = BytesMut::from(&[0x01, 0x0a, 0xff][..]);
+        assert_eq!(frame.to_payload(FrameFormat::Hex).unwrap(), b"010AFF");
+        assert_eq!(frame.to_payload(FrameFormat::Json).unwrap(), b"[1,10,255]");
+
+        assert_eq!(Bytes::from_payload(b"01 0a ff", FrameFormat::Hex).unwrap(), frame);
+        assert_eq!(Vec::<u8>::from_payload(b"[1,10,255]", FrameFormat::Json).unwrap(), frame);
+        assert!(Bytes::from_payload(b"0g", FrameFormat::Hex).is_none());
+        assert!(Bytes::from_payload(b"{}", FrameFormat::Json).is_none());
+    }
+
+    fn test_config() -> SerialToMqttConfig {
+        serde_json::from_value(serde_json::json!({
+            "serialport": { "path": "/dev/ttyUSB0", "timeout": "100ms" },
+            "mqtt": {
+                "host": "127.0.0.1",
+                "port": 1883,
+                "username": "",
+                "password": "",
+                "client_id": "serial",
+                "topic": [],
+                "keep_alive": "10s"
+            },
+            "topic": "serial/rx",
+            "commandTopic": "serial/tx/+",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_from_config() {
+        let config = test_config();
+        assert_eq!(config.format, FrameFormat::Hex);
+        assert_eq!(config.qos, QoS::AtLeastOnce);
+
+        let forwarder = SerialToMqtt::<BytesMut, BytesCodec>::new(&config);
+        let publish = Publish::new("serial/tx/1", QoS::AtLeastOnce, "0102");
+        assert_eq!(forwarder.command_frame(&publish).unwrap(), &[1, 2][..]);
+        let publish = Publish::new("serial/rx", QoS::AtLeastOnce, "0102");
+        assert!(forwarder.command_frame(&publish).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_publish_frame_never_waits() {
+        let forwarder = SerialToMqtt::<BytesMut, BytesCodec>::new(&test_config());
+        // 事件循环未运行（如 broker 断开），请求队列满后立即失败而不是一直等待
+        let frame = BytesMut::from(&[0x01][..]);
+        for _ in 0..1024 {
+            forwarder.publish_frame(&frame).unwrap();
+        }
+        assert!(matches!(
+            forwarder.publish_frame(&frame),
+            Err(ClientError::TryRequest(_))
+        ));
+    }
+}
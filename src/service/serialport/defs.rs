@@ -0,0 +1,166 @@
+// Serial settings with a wire format of their own, independent of the serialport crate.
+//
+// Serialized as `"8"`, `"none"`, `"1"` and `"none"`. Deserialization also takes numbers and
+// the variant names of the serialport crate (`Eight`, `None`, `One`), the format of
+// existing config files.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+use serialport::{DataBits, FlowControl, Parity, StopBits};
+
+/// Number or string, YAML and JSON clients send either
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Repr {
+    Number(u64),
+    Text(String),
+}
+
+impl Repr {
+    fn into_text(self) -> String {
+        match self {
+            Repr::Number(number) => number.to_string(),
+            Repr::Text(text) => text,
+        }
+    }
+}
+
+macro_rules! serial_def {
+    ($(#[$meta:meta])* $name:ident($inner:ty) { $($variant:ident => [$($text:literal),+],)+ }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct $name(pub $inner);
+
+        impl $name {
+            /// Wire format of the setting
+            pub fn as_str(&self) -> &'static str {
+                match self.0 {
+                    $(<$inner>::$variant => [$($text),+][0],)+
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $(
+                    if [$($text),+, stringify!($variant)]
+                        .iter()
+                        .any(|text| text.eq_ignore_ascii_case(s.trim()))
+                    {
+                        return Ok(Self(<$inner>::$variant));
+                    }
+                )+
+                Err(format!("Invalid {}: {}", stringify!($name), s))
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Repr::deserialize(deserializer)?
+                    .into_text()
+                    .parse()
+                    .map_err(D::Error::custom)
+            }
+        }
+
+        impl From<$inner> for $name {
+            fn from(value: $inner) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for $inner {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+    };
+}
+
+serial_def! {
+    /// `"5"` to `"8"`
+    DataBitsDef(DataBits) {
+        Five => ["5"],
+        Six => ["6"],
+        Seven => ["7"],
+        Eight => ["8"],
+    }
+}
+
+serial_def! {
+    /// `"none"`, `"odd"` or `"even"`
+    ParityDef(Parity) {
+        None => ["none"],
+        Odd => ["odd"],
+        Even => ["even"],
+    }
+}
+
+serial_def! {
+    /// `"1"` or `"2"`
+    StopBitsDef(StopBits) {
+        One => ["1"],
+        Two => ["2"],
+    }
+}
+
+serial_def! {
+    /// `"none"`, `"software"` (XON/XOFF) or `"hardware"` (RTS/CTS)
+    FlowControlDef(FlowControl) {
+        None => ["none"],
+        Software => ["software", "xonxoff"],
+        Hardware => ["hardware", "rtscts"],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire_format() {
+        let json = serde_json::to_string(&(
+            DataBitsDef(DataBits::Eight),
+            ParityDef(Parity::None),
+            StopBitsDef(StopBits::One),
+            FlowControlDef(FlowControl::Hardware),
+        ))
+        .unwrap();
+        assert_eq!(json, r#"["8","none","1","hardware"]"#);
+
+        let parsed: (DataBitsDef, ParityDef, StopBitsDef, FlowControlDef) =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(DataBits::from(parsed.0), DataBits::Eight);
+        assert_eq!(FlowControl::from(parsed.3), FlowControl::Hardware);
+    }
+
+    #[test]
+    fn test_legacy_names() {
+        let data_bits: DataBitsDef = serde_yaml_bw::from_str("Seven").unwrap();
+        assert_eq!(data_bits.0, DataBits::Seven);
+        let data_bits: DataBitsDef = serde_yaml_bw::from_str("7").unwrap();
+        assert_eq!(data_bits.0, DataBits::Seven);
+        let stop_bits: StopBitsDef = serde_json::from_str("2").unwrap();
+        assert_eq!(stop_bits.0, StopBits::Two);
+        let parity: ParityDef = serde_yaml_bw::from_str("Even").unwrap();
+        assert_eq!(parity.0, Parity::Even);
+        assert!(serde_json::from_str::<DataBitsDef>(r#""9""#).is_err());
+        assert!("mark".parse::<ParityDef>().is_err());
+    }
+}
@@ -1,6 +1,6 @@
-use super::SerialPort;
+use super::{SerialPort, SerialStats};
 use futures::stream::FuturesUnordered;
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::{select, sync::RwLock};
 use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
@@ -44,6 +44,15 @@ where
         groups.remove(path);
     }
 
+    /// Link counters of every port, keyed by path
+    pub async fn stats(&self) -> HashMap<String, SerialStats> {
+        let groups = self.groups.read().await;
+        groups
+            .iter()
+            .map(|(path, port)| (path.clone(), port.stats()))
+            .collect()
+    }
+
     pub async fn send(&self, frame: T) -> std::io::Result<()> {
         // if self.ack_counter.load(Ordering::Acquire) >= 0 {
         //     return Err(std::io::Error::new(
@@ -65,6 +74,21 @@ where
         Ok(())
     }
 
+    /// Like `send`, but waits at most `timeout` for the group to become free
+    /// (e.g. while `next` is waiting for a frame) instead of blocking indefinitely.
+    /// Returns `TimedOut` if the group stays busy.
+    pub async fn send_timeout(&self, frame: T, timeout: Duration) -> std::io::Result<()> {
+        let mut groups = tokio::time::timeout(timeout, self.groups.write())
+            .await
+            .map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::TimedOut, "Serial port group is busy")
+            })?;
+        for port in groups.values_mut() {
+            port.send(frame.clone()).await?;
+        }
+        Ok(())
+    }
+
     // fn reset_ack_counter(&self) {
     //     self.ack_counter.store(-1, Ordering::Release);
     // }
@@ -1,51 +1,143 @@
 use std::time::Duration;
 
+pub use defs::{DataBitsDef, FlowControlDef, ParityDef, StopBitsDef};
 pub use group::*;
 pub use port::*;
 use serde::{Deserialize, Serialize};
-use serialport::{DataBits, FlowControl, Parity, StopBits};
+use serialport::{DataBits, FlowControl, Parity, SerialPortInfo, SerialPortType, StopBits};
 
 use crate::database::entity::t_serialport_configs;
 
+mod defs;
 mod group;
 mod port;
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SerialPortConfig {
+    #[serde(default)]
     pub path: String,
+    /// Match the adapter by USB VID/PID instead of `path`, resolved at open time
+    #[serde(default)]
+    pub usb_filter: Option<UsbPortFilter>,
     #[serde(default = "default_baud_rate")]
     pub baud_rate: u32,
     #[serde(default = "default_data_bits")]
-    pub data_bits: DataBits,
+    pub data_bits: DataBitsDef,
     #[serde(default = "default_stop_bits")]
-    pub stop_bits: StopBits,
+    pub stop_bits: StopBitsDef,
     #[serde(default = "default_parity")]
-    pub parity: Parity,
+    pub parity: ParityDef,
     #[serde(default = "default_flow_control")]
-    pub flow_control: FlowControl,
+    pub flow_control: FlowControlDef,
     #[serde(with = "crate::utils::datetime::string_to_duration")]
     pub timeout: Duration,
+    /// Report a timeout when the device sends nothing for this long, see
+    /// `SerialPortBuilder::with_read_timeout`
+    #[serde(default, with = "crate::utils::datetime::string_to_duration_option")]
+    pub read_timeout: Option<Duration>,
+    /// Constant command written while the line is idle, see `SerialPortBuilder::with_heartbeat`
+    #[serde(default)]
+    pub heartbeat: Option<HeartbeatConfig>,
+}
+
+impl SerialPortConfig {
+    /// Device path to open: the first port matching `usb_filter`, or `path`.
+    pub fn resolve_path(&self) -> std::io::Result<String> {
+        match &self.usb_filter {
+            Some(filter) => filter.find_port(),
+            None => Ok(self.path.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeartbeatConfig {
+    /// Raw bytes to send, e.g. `"AA 55 01 00"`, see [`crate::utils::hex::parse`]
+    pub command_hex: String,
+    #[serde(with = "crate::utils::datetime::string_to_duration")]
+    pub interval: Duration,
+}
+
+impl HeartbeatConfig {
+    pub fn command(&self) -> Result<bytes::Bytes, crate::utils::hex::HexError> {
+        crate::utils::hex::parse(&self.command_hex)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsbPortFilter {
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+}
+
+impl UsbPortFilter {
+    /// Name of the first available port matching the filter.
+    pub fn find_port(&self) -> std::io::Result<String> {
+        list_ports_filtered(self.vid, self.pid)
+            .into_iter()
+            .next()
+            .map(|port| port.port_name)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!(
+                        "No serial port matches vid={:04x?} pid={:04x?}",
+                        self.vid, self.pid
+                    ),
+                )
+            })
+    }
+}
+
+/// List available serial ports, keeping only USB adapters with the given VID/PID.
+/// With both filters `None` every port is returned.
+pub fn list_ports_filtered(vid: Option<u16>, pid: Option<u16>) -> Vec<SerialPortInfo> {
+    match serialport::available_ports() {
+        Ok(ports) => filter_ports(ports, vid, pid),
+        Err(e) => {
+            tracing::error!("List serial ports failed: {:?}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn filter_ports(
+    ports: Vec<SerialPortInfo>,
+    vid: Option<u16>,
+    pid: Option<u16>,
+) -> Vec<SerialPortInfo> {
+    ports
+        .into_iter()
+        .filter(|port| match &port.port_type {
+            SerialPortType::UsbPort(usb) => {
+                vid.is_none_or(|vid| usb.vid == vid) && pid.is_none_or(|pid| usb.pid == pid)
+            }
+            _ => vid.is_none() && pid.is_none(),
+        })
+        .collect()
 }
 
 fn default_baud_rate() -> u32 {
     9600
 }
 
-fn default_data_bits() -> DataBits {
-    DataBits::Eight
+fn default_data_bits() -> DataBitsDef {
+    DataBits::Eight.into()
 }
 
-fn default_flow_control() -> FlowControl {
-    FlowControl::None
+fn default_flow_control() -> FlowControlDef {
+    FlowControl::None.into()
 }
 
-fn default_parity() -> Parity {
-    Parity::None
+fn default_parity() -> ParityDef {
+    Parity::None.into()
 }
 
-fn default_stop_bits() -> StopBits {
-    StopBits::One
+fn default_stop_bits() -> StopBitsDef {
+    StopBits::One.into()
 }
 
 #[cfg(feature = "serialport")]
@@ -53,12 +145,15 @@ impl Default for SerialPortConfig {
     fn default() -> Self {
         SerialPortConfig {
             path: "/dev/ttyUSB0".to_string(),
+            usb_filter: None,
             baud_rate: 9600,
-            data_bits: DataBits::Eight,
-            stop_bits: StopBits::One,
-            parity: Parity::None,
-            flow_control: FlowControl::None,
+            data_bits: default_data_bits(),
+            stop_bits: default_stop_bits(),
+            parity: default_parity(),
+            flow_control: default_flow_control(),
             timeout: Duration::from_secs(1),
+            read_timeout: None,
+            heartbeat: None,
         }
     }
 }
@@ -67,12 +162,76 @@ impl From<t_serialport_configs::Model> for SerialPortConfig {
     fn from(value: t_serialport_configs::Model) -> Self {
         Self {
             path: value.path.clone(),
+            usb_filter: None,
             baud_rate: value.baud_rate,
-            data_bits: value.data_bits_enum().unwrap_or(DataBits::Eight),
-            stop_bits: value.stop_bits_enum().unwrap_or(StopBits::One),
-            parity: value.parity_enum().unwrap_or(Parity::None),
-            flow_control: value.flow_control_enum().unwrap_or(FlowControl::None),
+            data_bits: value.data_bits_enum().unwrap_or(DataBits::Eight).into(),
+            stop_bits: value.stop_bits_enum().unwrap_or(StopBits::One).into(),
+            parity: value.parity_enum().unwrap_or(Parity::None).into(),
+            flow_control: value.flow_control_enum().unwrap_or(FlowControl::None).into(),
             timeout: value.timeout(),
+            read_timeout: None,
+            heartbeat: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_config() {
+        let yaml = "path: /dev/ttyS1\ntimeout: 1s\n\
+                    heartbeat:\n  commandHex: \"0xAA 55:01\"\n  interval: 5s";
+        let config: SerialPortConfig = serde_yaml_bw::from_str(yaml).unwrap();
+        let heartbeat = config.heartbeat.unwrap();
+        assert_eq!(heartbeat.interval, Duration::from_secs(5));
+        assert_eq!(heartbeat.command().unwrap(), bytes::Bytes::from_static(&[0xAA, 0x55, 0x01]));
+
+        let config: SerialPortConfig =
+            serde_yaml_bw::from_str("path: /dev/ttyS1\ntimeout: 1s").unwrap();
+        assert_eq!(config.heartbeat, None);
+        let invalid = HeartbeatConfig {
+            command_hex: "AA 5".into(),
+            interval: Duration::from_secs(5),
+        };
+        assert!(invalid.command().is_err());
+    }
+
+    #[test]
+    fn test_list_ports_filtered() {
+        let usb = |name: &str, vid, pid| SerialPortInfo {
+            port_name: name.into(),
+            port_type: SerialPortType::UsbPort(serialport::UsbPortInfo {
+                vid,
+                pid,
+                serial_number: None,
+                manufacturer: None,
+                product: None,
+            }),
+        };
+        let ports = vec![
+            usb("/dev/ttyUSB0", 0x1A86, 0x7523),
+            usb("/dev/ttyUSB1", 0x0403, 0x6001),
+            usb("/dev/ttyUSB2", 0x1A86, 0x55D4),
+            SerialPortInfo {
+                port_name: "/dev/ttyS0".into(),
+                port_type: SerialPortType::Unknown,
+            },
+        ];
+        let names = |vid, pid| {
+            filter_ports(ports.clone(), vid, pid)
+                .into_iter()
+                .map(|port| port.port_name)
+                .collect::<Vec<_>>()
+        };
+
+        // 不过滤时保留全部串口，包括非 USB 串口
+        assert_eq!(names(None, None).len(), 4);
+        assert_eq!(names(Some(0x1A86), None), ["/dev/ttyUSB0", "/dev/ttyUSB2"]);
+        assert_eq!(names(None, Some(0x6001)), ["/dev/ttyUSB1"]);
+        assert_eq!(names(Some(0x1A86), Some(0x55D4)), ["/dev/ttyUSB2"]);
+        // 任意不存在的 VID/PID 不应匹配到任何串口
+        assert!(names(Some(0xFFFF), Some(0xFFFF)).is_empty());
+    }
+}
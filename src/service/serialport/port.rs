@@ -1,33 +1,209 @@
+use bytes::{Bytes, BytesMut};
 use futures_util::sink::SinkExt;
+use serde::{Deserialize, Serialize};
 use serialport::{DataBits, FlowControl, Parity, StopBits};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::time::Instant;
+use tokio_serial::SerialPort as _;
 use tokio_serial::SerialPortBuilderExt;
 use tokio_stream::StreamExt;
 use tokio_util::codec::Framed;
 
+use crate::service::metrics::{Metrics, metrics};
+use crate::service::serialport::{SerialPortConfig, UsbPortFilter};
+use crate::utils::history::{Direction, History, HistoryEntry};
+use crate::utils::recorder::Recorder;
+
+/// Link quality counters of one port, shared so they can be read while the port is busy.
+#[derive(Debug, Default)]
+pub struct SerialCounters {
+    frames_in: AtomicU64,
+    frames_out: AtomicU64,
+    opens: AtomicU64,
+    errors: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+impl SerialCounters {
+    pub fn snapshot(&self) -> SerialStats {
+        SerialStats {
+            frames_in: self.frames_in.load(Ordering::Relaxed),
+            frames_out: self.frames_out.load(Ordering::Relaxed),
+            reconnects: self.opens.load(Ordering::Relaxed).saturating_sub(1),
+            errors: self.errors.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_error(&self, error: &std::io::Error) {
+        metrics().record_serial_error(error);
+        if error.kind() == std::io::ErrorKind::TimedOut {
+            Metrics::incr(&self.timeouts);
+        } else {
+            Metrics::incr(&self.errors);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SerialStats {
+    pub frames_in: u64,
+    pub frames_out: u64,
+    /// Successful opens after the first one
+    pub reconnects: u64,
+    pub errors: u64,
+    pub timeouts: u64,
+}
+
+/// What an error of a port means for reconnecting, see [`SerialFault::of`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialFault {
+    /// Device held by another process or not accessible, retried at the longest interval
+    PermissionDenied,
+    /// Device path gone, e.g. the adapter was unplugged
+    Unplugged,
+    /// An open port stopped working (broken pipe, end of stream), it is reopened
+    Disconnected,
+    TimedOut,
+    /// Anything else, retried with the normal backoff
+    Other,
+}
+
+impl SerialFault {
+    pub fn of(error: &std::io::Error) -> Self {
+        // ENODEV：设备在打开或读写过程中被拔出
+        const ENODEV: i32 = 19;
+        use std::io::ErrorKind;
+        match error.kind() {
+            ErrorKind::PermissionDenied => SerialFault::PermissionDenied,
+            ErrorKind::NotFound => SerialFault::Unplugged,
+            _ if cfg!(unix) && error.raw_os_error() == Some(ENODEV) => SerialFault::Unplugged,
+            ErrorKind::BrokenPipe
+            | ErrorKind::NotConnected
+            | ErrorKind::UnexpectedEof
+            | ErrorKind::ConnectionReset => SerialFault::Disconnected,
+            ErrorKind::TimedOut => SerialFault::TimedOut,
+            _ => SerialFault::Other,
+        }
+    }
+
+    /// Whether the port has to be closed and reopened after this error
+    pub fn needs_reopen(&self) -> bool {
+        matches!(self, SerialFault::Unplugged | SerialFault::Disconnected)
+    }
+}
+
+/// Frames that acknowledge whatever request is pending, e.g. a bare `ACK` byte
+pub trait FrameAck {
+    fn is_ack(&self) -> bool;
+}
+
+/// Frames answering one specific request, e.g. by sequence number, used by
+/// [`SerialPort::request`]. Every [`FrameAck`] matches any request.
+pub trait FrameMatch<Req = Self> {
+    fn matches_request(&self, request: &Req) -> bool;
+}
+
+impl<T: FrameAck> FrameMatch<T> for T {
+    fn matches_request(&self, _request: &T) -> bool {
+        self.is_ack()
+    }
+}
+
+/// Frames kept for `next()` while `request` waits for its reply
+const MAX_UNMATCHED: usize = 64;
+
+/// Makes the raw bytes of each heartbeat, see [`SerialPortBuilder::with_heartbeat`]
+pub type HeartbeatCommand = Box<dyn Fn() -> Bytes + Send + Sync>;
+
+/// Checks the reply to a heartbeat, see [`SerialPort::set_heartbeat_validator`]
+pub type HeartbeatValidator<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+
+struct Heartbeat {
+    interval: Duration,
+    command: HeartbeatCommand,
+}
+
 pub struct SerialPortBuilder {
     path: String,
+    usb_filter: Option<UsbPortFilter>,
     baud_rate: u32,
     data_bits: DataBits,
     flow_control: FlowControl,
     parity: Parity,
     stop_bits: StopBits,
     timeout: Duration,
+    dtr: Option<bool>,
+    rts: Option<bool>,
+    min_write_gap: Duration,
+    pre_write_delay: Duration,
+    post_write_delay: Duration,
+    reconnect_min: Duration,
+    reconnect_max: Duration,
+    read_timeout: Duration,
+    noise_gate: Option<NoiseGate>,
+    heartbeat: Option<Heartbeat>,
 }
 
 impl SerialPortBuilder {
     pub fn new(path: &str, baud_rate: u32) -> Self {
         Self {
             path: path.to_string(),
+            usb_filter: None,
             baud_rate,
             data_bits: DataBits::Eight,
             flow_control: FlowControl::None,
             parity: Parity::None,
             stop_bits: StopBits::One,
             timeout: Duration::from_millis(0),
+            dtr: None,
+            rts: None,
+            min_write_gap: Duration::ZERO,
+            pre_write_delay: Duration::ZERO,
+            post_write_delay: Duration::ZERO,
+            reconnect_min: DEFAULT_RECONNECT_MIN,
+            reconnect_max: DEFAULT_RECONNECT_MAX,
+            read_timeout: Duration::ZERO,
+            noise_gate: None,
+            heartbeat: None,
         }
     }
 
+    pub fn new_with_config(config: &SerialPortConfig) -> Self {
+        let builder = Self::new(&config.path, config.baud_rate)
+            .with_data_bits(config.data_bits.into())
+            .with_flow_control(config.flow_control.into())
+            .with_parity(config.parity.into())
+            .with_stop_bits(config.stop_bits.into())
+            .with_timeout(config.timeout)
+            .with_read_timeout(config.read_timeout.unwrap_or_default());
+        let builder = match &config.heartbeat {
+            Some(heartbeat) => match heartbeat.command() {
+                Ok(command) => builder.with_heartbeat(heartbeat.interval, move || command.clone()),
+                Err(e) => {
+                    tracing::error!("Heartbeat of {} disabled: {}", config.path, e);
+                    builder
+                }
+            },
+            None => builder,
+        };
+        match config.usb_filter {
+            Some(filter) => builder.with_usb_filter(filter),
+            None => builder,
+        }
+    }
+
+    /// Resolve the device path from USB VID/PID every time the port is opened
+    pub fn with_usb_filter(mut self, filter: UsbPortFilter) -> Self {
+        self.usb_filter = Some(filter);
+        self
+    }
+
     pub fn with_data_bits(mut self, data_bits: DataBits) -> Self {
         self.data_bits = data_bits;
         self
@@ -53,16 +229,107 @@ impl SerialPortBuilder {
         self
     }
 
+    /// Initial DTR line state, applied every time the port is (re)opened
+    pub fn with_dtr(mut self, level: bool) -> Self {
+        self.dtr = Some(level);
+        self
+    }
+
+    /// Initial RTS line state, applied every time the port is (re)opened
+    pub fn with_rts(mut self, level: bool) -> Self {
+        self.rts = Some(level);
+        self
+    }
+
+    /// Minimum idle time between the end of one write and the start of the next,
+    /// gives RS-485 transceivers time to switch direction
+    pub fn with_min_write_gap(mut self, gap: Duration) -> Self {
+        self.min_write_gap = gap;
+        self
+    }
+
+    /// Delay before / after every write, for RS-485 DE toggling timing
+    pub fn with_write_delays(mut self, pre_write_delay: Duration, post_write_delay: Duration) -> Self {
+        self.pre_write_delay = pre_write_delay;
+        self.post_write_delay = post_write_delay;
+        self
+    }
+
+    /// Wait between failed opens: starts at `min`, doubles per failure up to `max`, with
+    /// random jitter so many ports don't retry in lockstep. Defaults to 500ms..5s.
+    pub fn with_reconnect_interval(mut self, min: Duration, max: Duration) -> Self {
+        self.reconnect_min = min;
+        self.reconnect_max = max.max(min);
+        self
+    }
+
+    /// Fail `next()` with `TimedOut` when no frame arrived for `window`, whether or not a
+    /// reply is expected. Lets passive listeners notice a device that went quiet; the port
+    /// stays open. `Duration::ZERO` (the default) waits forever.
+    pub fn with_read_timeout(mut self, window: Duration) -> Self {
+        self.read_timeout = window;
+        self
+    }
+
+    /// Assemble received bytes into bursts ended by `gap` of silence before decoding, and
+    /// discard bursts shorter than `min_frame_len` as line noise (stray bytes on RS-485).
+    ///
+    /// Suits protocols whose frames arrive in one go and are separated by idle time, such as
+    /// Modbus RTU. A burst cut short by a timeout of `next()` or `request()` is dropped.
+    pub fn with_noise_gate(mut self, gap: Duration, min_frame_len: usize) -> Self {
+        self.noise_gate = Some(NoiseGate { gap, min_frame_len });
+        self
+    }
+
+    /// Write `command()` to the device whenever nothing was sent for `interval`, while
+    /// `next()` or `request()` is waiting.
+    ///
+    /// The bytes go to the line as is, bypassing the codec; replies come back as frames from
+    /// `next()`. The closure runs for every heartbeat, for commands carrying a counter or a
+    /// timestamp. Constant commands can be set in config, see `HeartbeatConfig`.
+    pub fn with_heartbeat<F>(mut self, interval: Duration, command: F) -> Self
+    where
+        F: Fn() -> Bytes + Send + Sync + 'static,
+    {
+        self.heartbeat = Some(Heartbeat {
+            interval,
+            command: Box::new(command),
+        });
+        self
+    }
+
     pub fn build<T, C>(self) -> SerialPort<T, C> {
         SerialPort {
             framed: None,
             path: self.path,
+            usb_filter: self.usb_filter,
             baud_rate: self.baud_rate,
             data_bits: self.data_bits,
             flow_control: self.flow_control,
             parity: self.parity,
             stop_bits: self.stop_bits,
             timeout: self.timeout,
+            dtr: self.dtr,
+            rts: self.rts,
+            min_write_gap: self.min_write_gap,
+            pre_write_delay: self.pre_write_delay,
+            post_write_delay: self.post_write_delay,
+            last_write: None,
+            counters: Arc::new(SerialCounters::default()),
+            history: None,
+            recorder: None,
+            reconnect_min: self.reconnect_min,
+            reconnect_max: self.reconnect_max,
+            failed_opens: 0,
+            next_open: None,
+            read_timeout: self.read_timeout,
+            last_read: None,
+            unmatched: VecDeque::new(),
+            noise_gate: self.noise_gate,
+            gated: BytesMut::new(),
+            heartbeat: self.heartbeat,
+            heartbeat_validator: None,
+            heartbeat_reply: None,
             _marker: std::marker::PhantomData,
             // busy: Arc::new(AtomicBool::new(false)),
             // send_notify: Arc::new(Notify::new()),
@@ -70,15 +337,110 @@ impl SerialPortBuilder {
     }
 }
 
+const DEFAULT_RECONNECT_MIN: Duration = Duration::from_millis(500);
+const DEFAULT_RECONNECT_MAX: Duration = Duration::from_secs(5);
+
+/// Backoff before open attempt `failures + 1`: `min * 2^(failures - 1)` capped at `max`,
+/// then scaled by `jitter` (0.5..=1.0 in practice).
+fn reconnect_delay(min: Duration, max: Duration, failures: u32, jitter: f64) -> Duration {
+    if failures == 0 {
+        return Duration::ZERO;
+    }
+    let factor = 2u32.saturating_pow(failures - 1);
+    min.saturating_mul(factor).min(max).mul_f64(jitter)
+}
+
+/// Backoff after a failed open, access errors wait the longest interval right away since
+/// retrying sooner won't help.
+fn open_retry_delay(
+    fault: SerialFault,
+    min: Duration,
+    max: Duration,
+    failures: u32,
+    jitter: f64,
+) -> Duration {
+    match fault {
+        SerialFault::PermissionDenied => max,
+        _ => reconnect_delay(min, max, failures, jitter),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NoiseGate {
+    gap: Duration,
+    min_frame_len: usize,
+}
+
+/// Append the next burst of at least `min_frame_len` bytes to `buf`, a burst ends when
+/// nothing arrives for `gap`. `Ok(false)` at end of stream.
+async fn read_burst<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buf: &mut BytesMut,
+    gate: NoiseGate,
+) -> std::io::Result<bool> {
+    let mut burst = BytesMut::new();
+    loop {
+        // 第一个字节不限时等待，之后每次读取最多等 gap
+        if reader.read_buf(&mut burst).await? == 0 {
+            return Ok(false);
+        }
+        while let Ok(read) = tokio::time::timeout(gate.gap, reader.read_buf(&mut burst)).await {
+            if read? == 0 {
+                return Ok(false);
+            }
+        }
+        if burst.len() >= gate.min_frame_len {
+            buf.extend_from_slice(&burst);
+            return Ok(true);
+        }
+        tracing::debug!("Discarding {} byte burst as line noise", burst.len());
+        burst.clear();
+    }
+}
+
+/// Recorder plus the accessor to the raw bytes of a frame.
+type FrameRecorder<T> = (Arc<Recorder>, fn(&T) -> &[u8]);
+
+/// History plus the clone of a frame, so reading needs no `Clone` bound.
+type FrameHistory<T> = (Arc<History<HistoryEntry<T>>>, fn(&T) -> T);
+
 pub struct SerialPort<T, C> {
     framed: Option<Framed<tokio_serial::SerialStream, C>>,
     path: String,
+    usb_filter: Option<UsbPortFilter>,
     baud_rate: u32,
     data_bits: DataBits,
     flow_control: FlowControl,
     parity: Parity,
     stop_bits: StopBits,
     timeout: Duration,
+    dtr: Option<bool>,
+    rts: Option<bool>,
+    min_write_gap: Duration,
+    pre_write_delay: Duration,
+    post_write_delay: Duration,
+    last_write: Option<Instant>,
+    counters: Arc<SerialCounters>,
+    history: Option<FrameHistory<T>>,
+    recorder: Option<FrameRecorder<T>>,
+    reconnect_min: Duration,
+    reconnect_max: Duration,
+    /// Failed opens in a row, drives the reconnect backoff
+    failed_opens: u32,
+    /// Earliest time of the next open attempt after a failure
+    next_open: Option<Instant>,
+    read_timeout: Duration,
+    /// Last received frame or open, start of the read timeout window
+    last_read: Option<Instant>,
+    /// Frames received by `request` that did not answer it
+    unmatched: VecDeque<T>,
+    noise_gate: Option<NoiseGate>,
+    /// Accepted bursts not decoded yet, used instead of the `Framed` buffer with a noise gate
+    gated: BytesMut,
+    heartbeat: Option<Heartbeat>,
+    heartbeat_validator: Option<HeartbeatValidator<T>>,
+    /// Deadline of the reply to the last heartbeat, only tracked with a validator
+    heartbeat_reply: Option<Instant>,
     _marker: std::marker::PhantomData<T>,
     // busy: Arc<AtomicBool>,
     // send_notify: Arc<Notify>,
@@ -92,14 +454,188 @@ impl<T, C> SerialPort<T, C> {
     // pub fn will_timeout(&self) -> bool {
     //     self.timeout != Duration::from_millis(0)
     // }
+
+    pub fn stats(&self) -> SerialStats {
+        self.counters.snapshot()
+    }
+
+    /// Shared counters, readable without holding the port
+    pub fn counters(&self) -> Arc<SerialCounters> {
+        self.counters.clone()
+    }
+
+    /// Recent frames, `None` unless enabled with `SerialPort::with_history`
+    pub fn history(&self) -> Option<Arc<History<HistoryEntry<T>>>> {
+        self.history.as_ref().map(|(history, _)| history.clone())
+    }
+
+    fn record(&self, direction: Direction, frame: &T) {
+        if let Some((history, clone)) = &self.history {
+            history.push(HistoryEntry::new(direction, None, clone(frame)));
+        }
+        if let Some((recorder, frame_bytes)) = &self.recorder {
+            recorder.record_or_log(direction, None, frame_bytes(frame));
+        }
+    }
+
+    fn stream(&mut self) -> std::io::Result<&mut tokio_serial::SerialStream> {
+        match self.framed.as_mut() {
+            Some(framed) => Ok(framed.get_mut()),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "SerialPort is not open",
+            )),
+        }
+    }
+
+    /// Set the DTR line. Errors with `NotConnected` while the port is closed or reconnecting.
+    pub fn set_dtr(&mut self, level: bool) -> std::io::Result<()> {
+        self.stream()?.write_data_terminal_ready(level)?;
+        Ok(())
+    }
+
+    /// Set the RTS line. Errors with `NotConnected` while the port is closed or reconnecting.
+    pub fn set_rts(&mut self, level: bool) -> std::io::Result<()> {
+        self.stream()?.write_request_to_send(level)?;
+        Ok(())
+    }
+
+    /// Read the CTS line. Errors with `NotConnected` while the port is closed or reconnecting.
+    pub fn read_cts(&mut self) -> std::io::Result<bool> {
+        Ok(self.stream()?.read_clear_to_send()?)
+    }
+
+    /// Read the DSR line. Errors with `NotConnected` while the port is closed or reconnecting.
+    pub fn read_dsr(&mut self) -> std::io::Result<bool> {
+        Ok(self.stream()?.read_data_set_ready()?)
+    }
+}
+
+impl<T, C> SerialPort<T, C>
+where
+    T: AsRef<[u8]>,
+{
+    /// Write every received/sent frame to `recorder`, see [`crate::utils::recorder`]
+    pub fn with_recorder(mut self, recorder: Arc<Recorder>) -> Self {
+        self.recorder = Some((recorder, T::as_ref));
+        self
+    }
+}
+
+impl<T, C> SerialPort<T, C>
+where
+    T: Clone,
+{
+    /// Keep the last `capacity` received/sent frames, see [`SerialPort::history`]
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.history = Some((Arc::new(History::new(capacity)), T::clone));
+        self
+    }
+}
+
+impl<T, C> SerialPort<T, C> {
+    /// Check the reply to each heartbeat set with `SerialPortBuilder::with_heartbeat`.
+    ///
+    /// The first frame after a heartbeat is its reply: when `validator` accepts it the frame
+    /// is consumed, otherwise `next()` fails with `TimedOut` and returns the frame on the next
+    /// call. No reply within the port timeout (or the heartbeat interval when none is set)
+    /// fails with `TimedOut` as well. This tells a device that is alive but faulted from a
+    /// healthy one.
+    pub fn set_heartbeat_validator<F>(&mut self, validator: F)
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        self.heartbeat_validator = Some(Box::new(validator));
+    }
 }
 
 impl<T, C> SerialPort<T, C>
 where
     C: Default,
 {
+    /// Sleep out the reconnect backoff of a closed port
+    async fn wait_reconnect(&self) {
+        if self.framed.is_none()
+            && let Some(next_open) = self.next_open
+        {
+            tokio::time::sleep_until(next_open).await;
+        }
+    }
+
     fn connect_port(&mut self) -> std::io::Result<()> {
+        if self.framed.is_some() {
+            return Ok(());
+        }
+        match self.open_port(C::default()) {
+            Ok(()) => {
+                self.failed_opens = 0;
+                self.next_open = None;
+                Ok(())
+            }
+            Err(e) => {
+                self.failed_opens = self.failed_opens.saturating_add(1);
+                let fault = SerialFault::of(&e);
+                let delay = open_retry_delay(
+                    fault,
+                    self.reconnect_min,
+                    self.reconnect_max,
+                    self.failed_opens,
+                    rand::random_range(0.5..=1.0),
+                );
+                self.next_open = Some(Instant::now() + delay);
+                match fault {
+                    SerialFault::PermissionDenied => tracing::error!(
+                        "No access to {} (in use by another process?): {}, retry in {:?}",
+                        self.path,
+                        e,
+                        delay
+                    ),
+                    SerialFault::Unplugged => tracing::warn!(
+                        "Serial device {} not present: {}, retry in {:?}",
+                        self.path,
+                        e,
+                        delay
+                    ),
+                    _ => {
+                        tracing::warn!("Failed to open {}: {}, retry in {:?}", self.path, e, delay)
+                    }
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Switch baud rate and framing of the port, e.g. after a handshake negotiated a faster
+    /// mode. The port is closed and reopened right away, keeping the codec.
+    ///
+    /// Bytes not yet decoded and frames queued by `request` are dropped. If the reopen
+    /// fails the port reconnects with the new settings and a fresh codec on the next call.
+    pub fn reconfigure(
+        &mut self,
+        baud_rate: u32,
+        data_bits: DataBits,
+        parity: Parity,
+        stop_bits: StopBits,
+    ) -> std::io::Result<()> {
+        self.baud_rate = baud_rate;
+        self.data_bits = data_bits;
+        self.parity = parity;
+        self.stop_bits = stop_bits;
+        self.unmatched.clear();
+        self.gated.clear();
+        self.heartbeat_reply = None;
+        let codec = match self.framed.take() {
+            Some(framed) => framed.into_parts().codec,
+            None => C::default(),
+        };
+        self.open_port(codec)
+    }
+
+    fn open_port(&mut self, codec: C) -> std::io::Result<()> {
         if self.framed.is_none() {
+            if let Some(filter) = &self.usb_filter {
+                self.path = filter.find_port()?;
+            }
             #[cfg(target_os = "android")]
             {
                 use std::{
@@ -146,8 +682,16 @@ where
                 .timeout(self.timeout)
                 .open_native_async();
             match serial_port {
-                Ok(stream) => {
-                    self.framed = Some(Framed::new(stream, C::default()));
+                Ok(mut stream) => {
+                    if let Some(level) = self.dtr {
+                        stream.write_data_terminal_ready(level)?;
+                    }
+                    if let Some(level) = self.rts {
+                        stream.write_request_to_send(level)?;
+                    }
+                    self.framed = Some(Framed::new(stream, codec));
+                    self.last_read = Some(Instant::now());
+                    Metrics::incr(&self.counters.opens);
                 }
                 Err(e) => {
                     self.framed = None;
@@ -185,11 +729,153 @@ where
         }
     }
 
+    async fn read_decoded(&mut self) -> std::io::Result<Option<T>> {
+        let framed = self.framed.as_mut().unwrap();
+        let Some(gate) = self.noise_gate else {
+            return Self::handle_read_result(framed.next().await);
+        };
+        loop {
+            if !self.gated.is_empty() {
+                match framed.codec_mut().decode(&mut self.gated) {
+                    Ok(Some(frame)) => return Ok(Some(frame)),
+                    Ok(None) => {}
+                    Err(e) => return Self::handle_read_result(Some(Err(e))),
+                }
+            }
+            if !read_burst(framed.get_mut(), &mut self.gated, gate).await? {
+                return Self::handle_read_result(None);
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "serial_next", skip_all, fields(device = %self.path))]
     pub async fn next(&mut self) -> std::io::Result<Option<T>> {
+        if let Some(frame) = self.unmatched.pop_front() {
+            return Ok(Some(frame));
+        }
+        self.read_frame().await
+    }
+
+    async fn read_frame(&mut self) -> std::io::Result<Option<T>> {
+        self.wait_reconnect().await;
         self.connect_port()?;
 
-        let framed = self.framed.as_mut().unwrap();
-        Self::handle_read_result(framed.next().await)
+        let result = loop {
+            if let Err(e) = self.send_heartbeat_if_due().await {
+                break Err(e);
+            }
+            let read_deadline = (!self.read_timeout.is_zero())
+                .then(|| self.last_read.unwrap_or_else(Instant::now) + self.read_timeout);
+            let reply_deadline = self.heartbeat_reply;
+            let deadline = read_deadline
+                .into_iter()
+                .chain(self.heartbeat_due())
+                .chain(reply_deadline)
+                .min();
+            let read = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, self.read_decoded()).await,
+                None => Ok(self.read_decoded().await),
+            };
+            match read {
+                Ok(Ok(Some(frame))) => {
+                    self.received(&frame);
+                    if self.heartbeat_reply.take().is_none() {
+                        break Ok(Some(frame));
+                    }
+                    let valid = self
+                        .heartbeat_validator
+                        .as_ref()
+                        .is_none_or(|validator| validator(&frame));
+                    if valid {
+                        continue;
+                    }
+                    // 设备在线但应答异常，帧仍交给 next()
+                    self.unmatched.push_back(frame);
+                    break Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "Heartbeat reply rejected",
+                    ));
+                }
+                Ok(read) => break read,
+                Err(_) if reply_deadline.is_some_and(|reply| Some(reply) == deadline) => {
+                    self.heartbeat_reply = None;
+                    break Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("No heartbeat reply within {:?}", self.heartbeat_reply_window()),
+                    ));
+                }
+                Err(_) if read_deadline.is_some_and(|timeout| Some(timeout) == deadline) => {
+                    // 静默超时不关闭串口，下一个窗口从现在开始计
+                    self.last_read = Some(Instant::now());
+                    break Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("No data received for {:?}", self.read_timeout),
+                    ));
+                }
+                // 心跳到期，发送后继续读
+                Err(_) => {}
+            }
+        };
+        if let Err(e) = &result {
+            self.counters.record_error(e);
+            let fault = SerialFault::of(e);
+            if fault.needs_reopen() {
+                tracing::warn!("Serial port {} {:?}, reopening", self.path, fault);
+                self.framed = None;
+            }
+        }
+        result
+    }
+
+    fn received(&mut self, frame: &T) {
+        self.last_read = Some(Instant::now());
+        Metrics::incr(&metrics().serial_frames_in);
+        Metrics::incr(&self.counters.frames_in);
+        self.record(Direction::In, frame);
+    }
+}
+
+impl<T, C> SerialPort<T, C> {
+    /// When the next heartbeat is due: `interval` after the last write, or after the open.
+    /// Never while the reply to the previous one is awaited.
+    fn heartbeat_due(&self) -> Option<Instant> {
+        if self.heartbeat_reply.is_some() {
+            return None;
+        }
+        let heartbeat = self.heartbeat.as_ref()?;
+        let idle_since = self.last_write.or(self.last_read)?;
+        Some(idle_since + heartbeat.interval)
+    }
+
+    /// How long a validated heartbeat waits for its reply: the port timeout, else `interval`
+    fn heartbeat_reply_window(&self) -> Duration {
+        match &self.heartbeat {
+            Some(_) if !self.timeout.is_zero() => self.timeout,
+            Some(heartbeat) => heartbeat.interval,
+            None => Duration::ZERO,
+        }
+    }
+
+    async fn send_heartbeat_if_due(&mut self) -> std::io::Result<()> {
+        if self.heartbeat_due().is_none_or(|due| due > Instant::now()) {
+            return Ok(());
+        }
+        let (Some(heartbeat), Some(framed)) = (&self.heartbeat, self.framed.as_mut()) else {
+            return Ok(());
+        };
+        let command = (heartbeat.command)();
+        tracing::trace!("Heartbeat to {}: {:02X?}", self.path, command.as_ref());
+        let result = framed.get_mut().write_all(&command).await;
+        self.last_write = Some(Instant::now());
+        if let Err(e) = result {
+            self.counters.record_error(&e);
+            self.framed = None;
+            return Err(e);
+        }
+        if self.heartbeat_validator.is_some() {
+            self.heartbeat_reply = Some(Instant::now() + self.heartbeat_reply_window());
+        }
+        Ok(())
     }
 }
 
@@ -198,7 +884,9 @@ where
     T: Clone,
     C: tokio_util::codec::Encoder<T, Error = std::io::Error> + Unpin + Default,
 {
+    #[tracing::instrument(name = "serial_send", skip_all, fields(device = %self.path))]
     pub async fn send(&mut self, frame: T) -> std::io::Result<()> {
+        self.wait_reconnect().await;
         self.connect_port()?;
 
         // if self.is_busy() {
@@ -208,52 +896,420 @@ where
         //     ));
         // }
 
+        if let Some(last_write) = self.last_write
+            && !self.min_write_gap.is_zero()
+        {
+            tokio::time::sleep_until(last_write + self.min_write_gap).await;
+        }
+        if !self.pre_write_delay.is_zero() {
+            tokio::time::sleep(self.pre_write_delay).await;
+        }
+
+        self.record(Direction::Out, &frame);
+
         let framed = self.framed.as_mut().unwrap();
-        match framed.send(frame).await {
-            Ok(()) => Ok(()),
+        let result = framed.send(frame).await;
+
+        if !self.post_write_delay.is_zero() {
+            tokio::time::sleep(self.post_write_delay).await;
+        }
+        self.last_write = Some(Instant::now());
+
+        match result {
+            Ok(()) => {
+                Metrics::incr(&metrics().serial_frames_out);
+                Metrics::incr(&self.counters.frames_out);
+                Ok(())
+            }
             Err(e) => {
+                self.counters.record_error(&e);
                 self.framed = None;
                 Err(e)
             }
         }
     }
+}
 
-    // pub async fn send_with_timeout(&mut self, frame: T) -> std::io::Result<()> {
-    //     self.connect_port()?;
-
-    //     if self.is_busy() {
-    //         return Err(std::io::Error::new(
-    //             std::io::ErrorKind::ResourceBusy,
-    //             "SerialPort is busy",
-    //         ));
-    //     }
-
-    //     if !self.will_timeout() {
-    //         return Err(std::io::Error::new(
-    //             std::io::ErrorKind::Other,
-    //             "SerialPort timeout not set",
-    //         ));
-    //     }
-
-    //     let framed = self.framed.as_mut().unwrap();
-    //     match framed.send(frame).await {
-    //         Ok(()) => match tokio::time::timeout(self.timeout, self.send_notify.notified()).await {
-    //             Ok(_) => Ok(()),
-    //             Err(e) => Err(std::io::Error::new(std::io::ErrorKind::TimedOut, e)),
-    //         },
-    //         Err(e) => {
-    //             self.framed = None;
-    //             Err(e)
-    //         }
-    //     }
-    // }
+impl<T, C> SerialPort<T, C>
+where
+    T: Clone + FrameMatch,
+    C: tokio_util::codec::Decoder<Item = T, Error: std::fmt::Debug>
+        + tokio_util::codec::Encoder<T, Error = std::io::Error>
+        + Unpin
+        + Default,
+{
+    /// Send `frame` and wait up to the port timeout for the frame matching it.
+    ///
+    /// Like [`SerialPort::request`] but fails when no timeout was set on the builder.
+    pub async fn send_with_timeout(&mut self, frame: T) -> std::io::Result<T> {
+        if self.timeout.is_zero() {
+            return Err(std::io::Error::other("SerialPort timeout not set"));
+        }
+        self.request(frame).await
+    }
+
+    /// Send `frame` and read until the frame matching it arrives, see [`FrameMatch`].
+    ///
+    /// Waits up to the port timeout, or indefinitely when none is set (a read timeout set
+    /// with `with_read_timeout` still applies). Frames answering other requests are kept
+    /// and returned by later `next()` calls, so replies arriving out of order on a
+    /// multiplexed link are not lost.
+    pub async fn request(&mut self, frame: T) -> std::io::Result<T> {
+        let request = frame.clone();
+        self.send(frame).await?;
+        let deadline = (!self.timeout.is_zero()).then(|| Instant::now() + self.timeout);
+        loop {
+            let read = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, self.read_frame()).await,
+                None => Ok(self.read_frame().await),
+            };
+            let read = match read {
+                Ok(read) => read?,
+                Err(_) => {
+                    let e = std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("No reply within {:?}", self.timeout),
+                    );
+                    self.counters.record_error(&e);
+                    return Err(e);
+                }
+            };
+            match read {
+                Some(reply) if reply.matches_request(&request) => return Ok(reply),
+                Some(other) => {
+                    if self.unmatched.len() >= MAX_UNMATCHED {
+                        tracing::warn!("Dropping unmatched frame from {}", self.path);
+                        self.unmatched.pop_front();
+                    }
+                    self.unmatched.push_back(other);
+                }
+                None => {}
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
 
-    use crate::service::serialport::SerialPortBuilder;
+    use crate::service::metrics::Metrics;
+    use crate::service::serialport::port::{open_retry_delay, reconnect_delay};
+    use crate::service::serialport::{
+        FrameMatch, SerialCounters, SerialFault, SerialPortBuilder, SerialStats,
+    };
+
+    #[test]
+    fn test_serial_counters() {
+        let counters = SerialCounters::default();
+        assert_eq!(counters.snapshot(), SerialStats::default());
+
+        Metrics::incr(&counters.opens);
+        Metrics::incr(&counters.opens);
+        Metrics::incr(&counters.frames_in);
+        counters.record_error(&std::io::Error::from(std::io::ErrorKind::TimedOut));
+        counters.record_error(&std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+
+        let stats = counters.snapshot();
+        assert_eq!(stats.reconnects, 1);
+        assert_eq!(stats.frames_in, 1);
+        assert_eq!(stats.timeouts, 1);
+        assert_eq!(stats.errors, 1);
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        let min = Duration::from_millis(100);
+        let max = Duration::from_secs(1);
+        let delays: Vec<Duration> = (0..7).map(|n| reconnect_delay(min, max, n, 1.0)).collect();
+        assert_eq!(
+            delays,
+            [0, 100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+        );
+        assert_eq!(reconnect_delay(min, max, 40, 1.0), max);
+        assert_eq!(reconnect_delay(min, max, 3, 0.5), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_serial_fault() {
+        use std::io::{Error, ErrorKind};
+
+        let fault = |kind| SerialFault::of(&Error::from(kind));
+        assert_eq!(fault(ErrorKind::PermissionDenied), SerialFault::PermissionDenied);
+        assert_eq!(fault(ErrorKind::NotFound), SerialFault::Unplugged);
+        assert_eq!(fault(ErrorKind::BrokenPipe), SerialFault::Disconnected);
+        assert_eq!(fault(ErrorKind::NotConnected), SerialFault::Disconnected);
+        assert_eq!(fault(ErrorKind::TimedOut), SerialFault::TimedOut);
+        assert_eq!(fault(ErrorKind::InvalidData), SerialFault::Other);
+        #[cfg(unix)]
+        assert_eq!(
+            SerialFault::of(&Error::from_raw_os_error(19)),
+            SerialFault::Unplugged
+        );
+        assert!(SerialFault::Disconnected.needs_reopen());
+        assert!(!SerialFault::PermissionDenied.needs_reopen());
+
+        // 无权限时直接按最长间隔重试
+        let min = Duration::from_millis(100);
+        let max = Duration::from_secs(5);
+        assert_eq!(open_retry_delay(SerialFault::PermissionDenied, min, max, 1, 0.5), max);
+        assert_eq!(open_retry_delay(SerialFault::Unplugged, min, max, 1, 1.0), min);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_backoff() {
+        let mut port = SerialPortBuilder::new("/dev/lean-link-missing", 9600)
+            .with_reconnect_interval(Duration::from_millis(20), Duration::from_millis(40))
+            .build::<bytes::BytesMut, tokio_util::codec::BytesCodec>();
+
+        let e = port.next().await.unwrap_err();
+        assert_eq!(SerialFault::of(&e), SerialFault::Unplugged);
+        let started = tokio::time::Instant::now();
+        assert!(port.next().await.is_err());
+        assert!(port.next().await.is_err());
+        // 两次重试至少等待 10ms + 20ms（抖动下限为一半）
+        assert!(started.elapsed() >= Duration::from_millis(30));
+        assert_eq!(port.failed_opens, 3);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_read_timeout() {
+        use tokio::io::AsyncWriteExt;
+        use tokio_serial::SerialPort as _;
+
+        let (mut master, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let path = slave.name().unwrap();
+        drop(slave);
+        let mut port = SerialPortBuilder::new(&path, 9600)
+            .with_read_timeout(Duration::from_millis(50))
+            .build::<bytes::BytesMut, tokio_util::codec::BytesCodec>();
+
+        let started = tokio::time::Instant::now();
+        let e = port.next().await.unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::TimedOut);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(port.stats().timeouts, 1);
+
+        // 超时后串口保持打开，数据仍可读到
+        master.write_all(b"ping").await.unwrap();
+        let frame = port.next().await.unwrap().unwrap();
+        assert_eq!(&frame[..], b"ping");
+        assert_eq!(port.stats().reconnects, 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_heartbeat() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio_serial::SerialPort as _;
+
+        let (mut master, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let path = slave.name().unwrap();
+        drop(slave);
+        let mut port = SerialPortBuilder::new(&path, 9600)
+            .with_heartbeat(Duration::from_millis(30), || bytes::Bytes::from_static(b"HB"))
+            .build::<bytes::BytesMut, tokio_util::codec::BytesCodec>();
+
+        // 空闲 30ms 后发出心跳，设备的应答照常由 next() 返回
+        let started = tokio::time::Instant::now();
+        let device = async {
+            let mut heartbeat = [0u8; 2];
+            master.read_exact(&mut heartbeat).await.unwrap();
+            master.write_all(b"ok").await.unwrap();
+            heartbeat
+        };
+        let (frame, heartbeat) = tokio::join!(port.next(), device);
+        assert_eq!(&heartbeat, b"HB");
+        assert_eq!(&frame.unwrap().unwrap()[..], b"ok");
+        assert!(started.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_heartbeat_validator() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio_serial::SerialPort as _;
+
+        let (mut master, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let path = slave.name().unwrap();
+        drop(slave);
+        let mut port = SerialPortBuilder::new(&path, 9600)
+            .with_timeout(Duration::from_millis(100))
+            .with_heartbeat(Duration::from_millis(30), || bytes::Bytes::from_static(b"HB"))
+            .build::<bytes::BytesMut, tokio_util::codec::BytesCodec>();
+        port.set_heartbeat_validator(|reply| &reply[..] == b"OK");
+
+        let mut heartbeat = [0u8; 2];
+        // 应答不通过校验：报超时，应答帧随后仍可读到
+        let device = async {
+            master.read_exact(&mut heartbeat).await.unwrap();
+            master.write_all(b"FAULT").await.unwrap();
+        };
+        let (read, ()) = tokio::join!(port.next(), device);
+        let e = read.unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(e.to_string(), "Heartbeat reply rejected");
+        assert_eq!(&port.next().await.unwrap().unwrap()[..], b"FAULT");
+        assert_eq!(port.stats().timeouts, 1);
+
+        // 通过校验的应答被消费，next() 返回之后的数据
+        let device = async {
+            master.read_exact(&mut heartbeat).await.unwrap();
+            master.write_all(b"OK").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            master.write_all(b"data").await.unwrap();
+        };
+        let (read, ()) = tokio::join!(port.next(), device);
+        assert_eq!(&read.unwrap().unwrap()[..], b"data");
+
+        // 没有应答
+        let e = port.next().await.unwrap_err();
+        assert_eq!(e.to_string(), "No heartbeat reply within 100ms");
+        assert_eq!(port.stats().timeouts, 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_request() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio_serial::SerialPort as _;
+
+        let (mut master, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let path = slave.name().unwrap();
+        drop(slave);
+        let mut port = SerialPortBuilder::new(&path, 9600).build::<SeqFrame, SeqCodec>();
+
+        // 模拟设备：先推送一帧无关数据，再应答请求
+        let device = tokio::spawn(async move {
+            let mut request = [0u8; 2];
+            master.read_exact(&mut request).await.unwrap();
+            master.write_all(&[9, 90]).await.unwrap();
+            master.write_all(&[request[0], request[1] + 1]).await.unwrap();
+            master
+        });
+
+        let reply = port.request(SeqFrame([5, 50])).await.unwrap();
+        assert_eq!(reply, SeqFrame([5, 51]));
+        assert_eq!(port.next().await.unwrap(), Some(SeqFrame([9, 90])));
+        assert_eq!(port.stats().frames_out, 1);
+        drop(device.await.unwrap());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_reconfigure() {
+        use serialport::{DataBits, Parity, StopBits};
+        use tokio::io::AsyncWriteExt;
+        use tokio_serial::SerialPort as _;
+
+        let (mut master, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let path = slave.name().unwrap();
+        drop(slave);
+        let mut port = SerialPortBuilder::new(&path, 9600).build::<SeqFrame, SeqCodec>();
+        port.connect_port().unwrap();
+        port.reconfigure(115200, DataBits::Seven, Parity::Even, StopBits::Two)
+            .unwrap();
+        assert_eq!(port.baud_rate, 115200);
+        assert_eq!(port.stats().reconnects, 1);
+
+        let stream = port.stream().unwrap();
+        assert_eq!(stream.baud_rate().unwrap(), 115200);
+
+        master.write_all(&[2, 20]).await.unwrap();
+        assert_eq!(port.next().await.unwrap(), Some(SeqFrame([2, 20])));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_noise_gate() {
+        use tokio::io::AsyncWriteExt;
+        use tokio_serial::SerialPort as _;
+
+        let (mut master, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let path = slave.name().unwrap();
+        drop(slave);
+        let mut port = SerialPortBuilder::new(&path, 9600)
+            .with_noise_gate(Duration::from_millis(30), 2)
+            .build::<SeqFrame, SeqCodec>();
+        port.connect_port().unwrap();
+
+        // 单字节干扰被丢弃，分段到达的帧拼成一帧后解码
+        let device = tokio::spawn(async move {
+            master.write_all(&[0xFF]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(80)).await;
+            master.write_all(&[1]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            master.write_all(&[10, 2]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            master.write_all(&[20]).await.unwrap();
+            master
+        });
+
+        assert_eq!(port.next().await.unwrap(), Some(SeqFrame([1, 10])));
+        assert_eq!(port.next().await.unwrap(), Some(SeqFrame([2, 20])));
+        drop(device.await.unwrap());
+    }
+
+    /// `[seq, value]`
+    #[derive(Debug, Clone, PartialEq)]
+    struct SeqFrame([u8; 2]);
+
+    impl FrameMatch for SeqFrame {
+        fn matches_request(&self, request: &SeqFrame) -> bool {
+            self.0[0] == request.0[0]
+        }
+    }
+
+    #[derive(Default)]
+    struct SeqCodec;
+
+    impl tokio_util::codec::Decoder for SeqCodec {
+        type Item = SeqFrame;
+        type Error = std::io::Error;
+
+        fn decode(&mut self, src: &mut bytes::BytesMut) -> std::io::Result<Option<SeqFrame>> {
+            if src.len() < 2 {
+                return Ok(None);
+            }
+            let frame = src.split_to(2);
+            Ok(Some(SeqFrame([frame[0], frame[1]])))
+        }
+    }
+
+    impl tokio_util::codec::Encoder<SeqFrame> for SeqCodec {
+        type Error = std::io::Error;
+
+        fn encode(&mut self, item: SeqFrame, dst: &mut bytes::BytesMut) -> std::io::Result<()> {
+            dst.extend_from_slice(&item.0);
+            Ok(())
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_send_with_timeout_matches_request() {
+        use tokio::io::AsyncWriteExt;
+        use tokio_serial::SerialPort as _;
+
+        let (mut master, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let path = slave.name().unwrap();
+        drop(slave);
+        let mut port = SerialPortBuilder::new(&path, 9600)
+            .with_timeout(Duration::from_millis(100))
+            .build::<SeqFrame, SeqCodec>();
+
+        // 请求 2 的应答先到，请求 1 仍应拿到自己的应答
+        port.connect_port().unwrap();
+        master.write_all(&[2, 20, 1, 10]).await.unwrap();
+        let reply = port.send_with_timeout(SeqFrame([1, 0])).await.unwrap();
+        assert_eq!(reply, SeqFrame([1, 10]));
+        assert_eq!(port.next().await.unwrap(), Some(SeqFrame([2, 20])));
+
+        let e = port.send_with_timeout(SeqFrame([3, 0])).await.unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(port.stats().timeouts, 1);
+    }
 
     #[tokio::test]
     async fn test_serial_port() {
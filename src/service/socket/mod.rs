@@ -1,21 +1,41 @@
-use std::{sync::Arc, time::Duration};
+use std::{io::IoSlice, net::SocketAddr, sync::Arc, time::Duration};
 
-use bytes::{Bytes, BytesMut};
+use bytes::{Buf, Bytes, BytesMut};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
     select,
-    sync::{broadcast, mpsc},
+    sync::{Semaphore, broadcast, mpsc, oneshot},
 };
+use tracing::Instrument;
+
+use crate::errors::ServerStartError;
+use crate::service::metrics::{Metrics, metrics};
+use crate::utils::hex_dump::{HexFormat, hex_dump};
+use crate::utils::history::{Direction, History, HistoryEntry};
+use crate::utils::id::{ConnectionId, Peer};
+use crate::utils::recorder::Recorder;
+use crate::utils::retry::ReconnectPolicy;
+use crate::utils::supervise::{SupervisedSlot, TaskFailure, supervise};
+
+pub mod typed;
+
+pub type SocketHistory = History<HistoryEntry<Bytes>>;
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct SocketConfig {
     pub host: String,
     pub port: u16,
+    /// Connections served at once. Further connections are not accepted and wait in the
+    /// listen backlog until one closes.
     pub max_connections: u32,
     #[serde(with = "crate::utils::datetime::string_to_duration")]
     pub heartbeat_interval: Duration,
+    /// Keep the last N received/sent chunks in memory, 0 disables the history
+    #[serde(default)]
+    pub history_capacity: usize,
 }
 
 impl Default for SocketConfig {
@@ -25,114 +45,306 @@ impl Default for SocketConfig {
             port: 9000,
             max_connections: 100,
             heartbeat_interval: Duration::from_secs(30),
+            history_capacity: 0,
         }
     }
 }
 
+/// Connection events, each carrying the connection's id and peer address
 #[derive(Debug)]
 pub enum SocketMessage {
-    NewConnected(String),
-    Message(String, Bytes),
+    NewConnected(Peer),
+    Message(Peer, Bytes),
+}
+
+/// Write side of an open connection
+struct Connection {
+    addr: SocketAddr,
+    sender: mpsc::Sender<Bytes>,
+}
+
+type WriterMap = Arc<DashMap<ConnectionId, Connection>>;
+
+/// Payload carried on the broadcast channel.
+///
+/// `Vectored` keeps the parts of a framed message (e.g. header + payload)
+/// separate so they can be written with a single vectored write instead of
+/// being concatenated into a new buffer first.
+#[derive(Debug, Clone)]
+enum BroadcastFrame {
+    Single(Bytes),
+    Vectored(Arc<[Bytes]>),
 }
 
 #[derive(Clone)]
 pub struct SocketServer {
     socket_config: SocketConfig,
-    writer_map: Arc<DashMap<String, mpsc::Sender<Bytes>>>,
-    broadcast_sender: broadcast::Sender<Bytes>,
+    writer_map: WriterMap,
+    broadcast_sender: broadcast::Sender<BroadcastFrame>,
+    history: Option<Arc<SocketHistory>>,
+    recorder: Option<Arc<Recorder>>,
+    reconnect_policy: ReconnectPolicy,
+    listener: SupervisedSlot,
 }
 
 impl SocketServer {
     pub fn new(socket_config: SocketConfig) -> Self {
         let (tx, _) = broadcast::channel(16);
+        let history = (socket_config.history_capacity > 0)
+            .then(|| Arc::new(History::new(socket_config.history_capacity)));
         SocketServer {
             socket_config,
             writer_map: Arc::new(DashMap::new()),
             broadcast_sender: tx,
+            history,
+            recorder: None,
+            reconnect_policy: ReconnectPolicy::default(),
+            listener: SupervisedSlot::default(),
         }
     }
 
-    pub async fn start(&self) -> std::io::Result<mpsc::Receiver<SocketMessage>> {
-        let (read_sender, read_receiver) = mpsc::channel::<SocketMessage>(1024);
+    /// Write every received/sent chunk to `recorder`, see [`crate::utils::recorder`]
+    pub fn with_recorder(mut self, recorder: Arc<Recorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Restarts of the listen loop after it panics or exits, e.g. on a burst of accept
+    /// errors. Defaults to [`ReconnectPolicy::default`].
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    /// Whether the listen loop gave up after the restarts of its reconnect policy
+    pub fn listener_failed(&self) -> bool {
+        self.listener.is_finished()
+    }
+
+    /// Receiver of the [`TaskFailure`] sent when the listen loop gives up, `None` before
+    /// the server is started or once taken.
+    pub fn take_listener_failure(&self) -> Option<oneshot::Receiver<TaskFailure>> {
+        self.listener.take_failure()
+    }
+
+    /// Recent chunks from oldest to newest, empty when the history is disabled.
+    pub fn recent(&self) -> Vec<HistoryEntry<Bytes>> {
+        self.history
+            .as_ref()
+            .map(|history| history.recent())
+            .unwrap_or_default()
+    }
+
+    fn record_history(&self, direction: Direction, peer: Option<&str>, data: &Bytes) {
+        if let Some(history) = &self.history {
+            history.push(HistoryEntry::new(
+                direction,
+                peer.map(String::from),
+                data.clone(),
+            ));
+        }
+        if let Some(recorder) = &self.recorder {
+            recorder.record_or_log(direction, peer, data);
+        }
+    }
+
+    pub async fn start(&self) -> Result<mpsc::Receiver<SocketMessage>, ServerStartError> {
         let addr = format!("{}:{}", self.socket_config.host, self.socket_config.port);
-        let listener = TcpListener::bind(&addr).await?;
+        let listener = TcpListener::bind(&addr)
+            .await
+            .map_err(|e| ServerStartError::new(&addr, e))?;
+        self.start_with_listener(listener)
+            .await
+            .map_err(|e| ServerStartError::new(&addr, e))
+    }
+
+    /// Serve on an already bound listener, e.g. from socket activation or a port-0 bind in tests.
+    pub async fn start_with_listener(
+        &self,
+        listener: TcpListener,
+    ) -> std::io::Result<mpsc::Receiver<SocketMessage>> {
+        let (read_sender, read_receiver) = mpsc::channel::<SocketMessage>(1024);
 
-        tracing::info!("Socket server listening on {}", addr);
+        tracing::info!("Socket server listening on {}", listener.local_addr()?);
 
+        let listener = Arc::new(listener);
+        let admission = Arc::new(Semaphore::new(self.socket_config.max_connections as usize));
         let broadcast_sender = self.broadcast_sender.clone();
         let write_map = self.writer_map.clone();
-        tokio::spawn(async move {
-            start_listening(listener, broadcast_sender, write_map, read_sender).await;
+        let history = self.history.clone();
+        let recorder = self.recorder.clone();
+        // 监听任务异常退出时自动重启
+        let supervised = supervise("socket-listener", self.reconnect_policy.clone(), move || {
+            start_listening(
+                listener.clone(),
+                admission.clone(),
+                broadcast_sender.clone(),
+                write_map.clone(),
+                read_sender.clone(),
+                history.clone(),
+                recorder.clone(),
+            )
         });
+        self.listener.set(supervised);
         Ok(read_receiver)
     }
 
     pub async fn broadcast(&self, message: Bytes) {
-        let _ = self.broadcast_sender.send(message);
+        self.record_history(Direction::Out, None, &message);
+        let _ = self.broadcast_sender.send(BroadcastFrame::Single(message));
     }
 
-    pub async fn send(&self, id: &str, message: Bytes) {
-        if let Some(write) = self.writer_map.get_mut(id) {
-            let _ = write.send(message).await;
+    /// Broadcast a message made of several parts (e.g. header + payload)
+    /// without concatenating them. Each client receives the parts in order,
+    /// written with vectored writes.
+    pub async fn broadcast_vectored(&self, parts: &[Bytes]) {
+        if self.history.is_some() || self.recorder.is_some() {
+            self.record_history(Direction::Out, None, &Bytes::from(parts.concat()));
+        }
+        let _ = self
+            .broadcast_sender
+            .send(BroadcastFrame::Vectored(Arc::from(parts)));
+    }
+
+    pub async fn send(&self, id: ConnectionId, message: Bytes) {
+        let connection = self
+            .writer_map
+            .get(&id)
+            .map(|connection| (connection.addr, connection.sender.clone()));
+        if let Some((addr, sender)) = connection {
+            self.record_history(Direction::Out, Some(&addr.to_string()), &message);
+            let _ = sender.send(message).await;
         }
     }
 }
 
 async fn start_listening(
-    listener: TcpListener,
-    broadcast_sender: broadcast::Sender<Bytes>,
-    writer_map: Arc<DashMap<String, mpsc::Sender<Bytes>>>,
+    listener: Arc<TcpListener>,
+    admission: Arc<Semaphore>,
+    broadcast_sender: broadcast::Sender<BroadcastFrame>,
+    writer_map: WriterMap,
     read_sender: mpsc::Sender<SocketMessage>,
+    history: Option<Arc<SocketHistory>>,
+    recorder: Option<Arc<Recorder>>,
 ) {
-    while let Ok((stream, _)) = listener.accept().await {
-        tokio::spawn(handle_connection(
+    loop {
+        // 连接数达到上限时暂停 accept，多余的连接留在内核队列中
+        let Ok(permit) = admission.clone().acquire_owned().await else {
+            return;
+        };
+        let Ok((stream, peer_addr)) = listener.accept().await else {
+            return;
+        };
+        let peer = Peer::new(peer_addr);
+        let connection = handle_connection(
             stream,
+            peer,
             broadcast_sender.clone(),
             writer_map.clone(),
             read_sender.clone(),
-        ));
+            history.clone(),
+            recorder.clone(),
+        );
+        tokio::spawn(
+            async move {
+                connection.await;
+                drop(permit);
+            }
+            .instrument(tracing::info_span!("conn", peer = %peer_addr, id = %peer.id)),
+        );
     }
 }
 
+/// Write all `parts` to `stream` using vectored writes, advancing across
+/// part boundaries on short writes.
+async fn write_all_vectored<W>(stream: &mut W, parts: &[Bytes]) -> std::io::Result<()>
+where
+    W: AsyncWriteExt + Unpin,
+{
+    let mut parts: Vec<Bytes> = parts.iter().filter(|b| !b.is_empty()).cloned().collect();
+    let mut index = 0;
+    while index < parts.len() {
+        let slices: Vec<IoSlice<'_>> = parts[index..].iter().map(|b| IoSlice::new(b)).collect();
+        let mut written = stream.write_vectored(&slices).await?;
+        if written == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::WriteZero,
+                "failed to write whole buffer",
+            ));
+        }
+
+        while written > 0 {
+            let len = parts[index].len();
+            if written >= len {
+                written -= len;
+                index += 1;
+            } else {
+                parts[index].advance(written);
+                written = 0;
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn handle_connection(
     mut raw_stream: TcpStream,
-    broadcast_sender: broadcast::Sender<Bytes>,
-    writer_map: Arc<DashMap<String, mpsc::Sender<Bytes>>>,
+    peer: Peer,
+    broadcast_sender: broadcast::Sender<BroadcastFrame>,
+    writer_map: WriterMap,
     read_sender: mpsc::Sender<SocketMessage>,
+    history: Option<Arc<SocketHistory>>,
+    recorder: Option<Arc<Recorder>>,
 ) {
-    tracing::info!(
-        "New socket connection established: {}",
-        raw_stream.peer_addr().unwrap()
-    );
+    tracing::info!("New socket connection established: {}", peer);
 
-    let _ = read_sender
-        .send(SocketMessage::NewConnected(
-            raw_stream.peer_addr().unwrap().to_string(),
-        ))
-        .await;
+    let _ = read_sender.send(SocketMessage::NewConnected(peer)).await;
 
+    let peer_addr = peer.addr.to_string();
     let mut buffer = BytesMut::with_capacity(1024);
     let mut broadcast_receiver = broadcast_sender.subscribe();
     let (tx, mut rx) = mpsc::channel::<Bytes>(32);
-    writer_map.insert(raw_stream.peer_addr().unwrap().to_string(), tx);
+    writer_map.insert(
+        peer.id,
+        Connection {
+            addr: peer.addr,
+            sender: tx,
+        },
+    );
 
     loop {
         select! {
             read_result = raw_stream.read_buf(&mut buffer) => {
                 match read_result {
                     Ok(0) => {
-                        tracing::info!("Socket connection closed: {}", raw_stream.peer_addr().unwrap());
-                        writer_map.remove(&raw_stream.peer_addr().unwrap().to_string());
+                        tracing::info!("Socket connection closed: {}", peer);
+                        writer_map.remove(&peer.id);
                         break;
                     }
                     Ok(n) => {
-                        tracing::info!("Received {} bytes from {}", n, raw_stream.peer_addr().unwrap());
-                        tracing::debug!("Data: {:?}", &buffer[..n]);
+                        Metrics::add(&metrics().socket_bytes_in, n as u64);
+                        tracing::info!("Received {} bytes from {}", n, peer);
+                        tracing::debug!(
+                            "Data: {}",
+                            hex_dump(&buffer[..n], HexFormat::default().with_max_bytes(256))
+                        );
+                        let data = buffer.split_to(n).freeze();
+                        if let Some(history) = &history {
+                            history.push(HistoryEntry::new(
+                                Direction::In,
+                                Some(peer_addr.clone()),
+                                data.clone(),
+                            ));
+                        }
+                        if let Some(recorder) = &recorder {
+                            recorder.record_or_log(
+                                Direction::In,
+                                Some(&peer_addr),
+                                &data,
+                            );
+                        }
                         let _ = read_sender
-                            .send(SocketMessage::Message(
-                                raw_stream.peer_addr().unwrap().to_string(),
-                                buffer.split_to(n).freeze(),
-                            ))
+                            .send(SocketMessage::Message(peer, data))
                             .await;
                         buffer.clear();
                     }
@@ -144,9 +356,19 @@ async fn handle_connection(
 
             broadcast_msg = broadcast_receiver.recv() => {
                 match broadcast_msg {
-                    Ok(msg) => {
-                        if let Err(e) = raw_stream.write(&msg).await {
-                            tracing::error!("Error writing to socket: {}", e);
+                    Ok(BroadcastFrame::Single(msg)) => {
+                        match raw_stream.write_all(&msg).await {
+                            Ok(()) => Metrics::add(&metrics().socket_bytes_out, msg.len() as u64),
+                            Err(e) => tracing::error!("Error writing to socket: {}", e),
+                        }
+                    }
+                    Ok(BroadcastFrame::Vectored(parts)) => {
+                        match write_all_vectored(&mut raw_stream, &parts).await {
+                            Ok(()) => Metrics::add(
+                                &metrics().socket_bytes_out,
+                                parts.iter().map(|p| p.len() as u64).sum(),
+                            ),
+                            Err(e) => tracing::error!("Error writing to socket: {}", e),
                         }
                     }
                     Err(e) => {
@@ -158,13 +380,14 @@ async fn handle_connection(
             send_msg = rx.recv() => {
                 match send_msg {
                     Some(msg) => {
-                        if let Err(e) = raw_stream.write(&msg).await {
-                            tracing::error!("Error writing to socket: {}", e);
+                        match raw_stream.write(&msg).await {
+                            Ok(n) => Metrics::add(&metrics().socket_bytes_out, n as u64),
+                            Err(e) => tracing::error!("Error writing to socket: {}", e),
                         }
                     }
                     None => {
-                        tracing::info!("Sender dropped, closing connection: {}", raw_stream.peer_addr().unwrap());
-                        writer_map.remove(&raw_stream.peer_addr().unwrap().to_string());
+                        tracing::info!("Sender dropped, closing connection: {}", peer);
+                        writer_map.remove(&peer.id);
                         break;
                     }
                 }
@@ -172,3 +395,158 @@ async fn handle_connection(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::errors::ServerStartError;
+    use crate::service::socket::{SocketConfig, SocketMessage, SocketServer, write_all_vectored};
+    use crate::testkit::{spawn_socket_server, spawn_socket_server_with, tcp_client};
+
+    #[tokio::test]
+    async fn test_start_addr_in_use() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = SocketServer::new(SocketConfig {
+            host: "127.0.0.1".to_string(),
+            port: listener.local_addr().unwrap().port(),
+            ..Default::default()
+        });
+        match server.start().await {
+            Err(e @ ServerStartError::AddrInUse { .. }) => {
+                assert_eq!(e.addr(), listener.local_addr().unwrap().to_string());
+            }
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("bind should fail"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_start_with_listener() {
+        let (server, addr, mut receiver) = spawn_socket_server().await;
+
+        let mut client = tcp_client(addr).await;
+        let peer = match receiver.recv().await {
+            Some(SocketMessage::NewConnected(peer)) => peer,
+            other => panic!("unexpected message: {other:?}"),
+        };
+        assert_eq!(peer.addr, client.local_addr().unwrap());
+
+        client.write_all(b"ping").await.unwrap();
+        match receiver.recv().await {
+            Some(SocketMessage::Message(from, data)) => {
+                assert_eq!(from, peer);
+                assert_eq!(&data[..], b"ping");
+            }
+            other => panic!("unexpected message: {other:?}"),
+        }
+
+        server.send(peer.id, Bytes::from_static(b"pong")).await;
+        let mut reply = [0u8; 4];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"pong");
+    }
+
+    #[tokio::test]
+    async fn test_max_connections_queues_excess() {
+        let config = SocketConfig {
+            max_connections: 2,
+            ..Default::default()
+        };
+        let (_server, addr, mut receiver) = spawn_socket_server_with(config).await;
+
+        let mut clients = Vec::new();
+        for _ in 0..5 {
+            clients.push(tcp_client(addr).await);
+        }
+        for _ in 0..2 {
+            assert!(matches!(
+                receiver.recv().await,
+                Some(SocketMessage::NewConnected(_))
+            ));
+        }
+        // 其余连接停留在内核队列中，不会被 accept
+        let excess = tokio::time::timeout(Duration::from_millis(200), receiver.recv()).await;
+        assert!(excess.is_err(), "accepted beyond the limit: {excess:?}");
+
+        // 每关闭一个连接，才接受一个排队的连接
+        drop(clients.remove(0));
+        let queued = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap();
+        assert!(matches!(queued, Some(SocketMessage::NewConnected(_))));
+        let excess = tokio::time::timeout(Duration::from_millis(200), receiver.recv()).await;
+        assert!(excess.is_err(), "accepted beyond the limit: {excess:?}");
+    }
+
+    #[tokio::test]
+    async fn test_write_all_vectored() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let header = Bytes::from_static(&[0xAA, 0x55, 0x00, 0x04]);
+        let payload = Bytes::from(vec![7u8; 64 * 1024]);
+        let parts = vec![header.clone(), Bytes::new(), payload.clone()];
+
+        let writer = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            write_all_vectored(&mut stream, &parts).await.unwrap();
+            stream.shutdown().await.unwrap();
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        writer.await.unwrap();
+
+        let mut expected = header.to_vec();
+        expected.extend_from_slice(&payload);
+        assert_eq!(received, expected);
+    }
+
+    #[tokio::test]
+    #[ignore] // Rough comparison against concatenating the parts first
+    async fn bench_write_all_vectored() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let reader = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut sink = Vec::new();
+            stream.read_to_end(&mut sink).await.unwrap();
+            sink.len()
+        });
+
+        let header = Bytes::from_static(&[0u8; 16]);
+        let payload = Bytes::from(vec![1u8; 1024 * 1024]);
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        let start = std::time::Instant::now();
+        for _ in 0..100 {
+            let mut buf = Vec::with_capacity(header.len() + payload.len());
+            buf.extend_from_slice(&header);
+            buf.extend_from_slice(&payload);
+            stream.write_all(&buf).await.unwrap();
+        }
+        let concatenated = start.elapsed();
+
+        let start = std::time::Instant::now();
+        for _ in 0..100 {
+            write_all_vectored(&mut stream, &[header.clone(), payload.clone()])
+                .await
+                .unwrap();
+        }
+        let vectored = start.elapsed();
+        tracing::info!(?concatenated, ?vectored, "100 writes of a 16 B header + 1 MiB payload");
+
+        stream.shutdown().await.unwrap();
+        // 两种方式都完整写出所有字节
+        assert_eq!(reader.await.unwrap(), 200 * (header.len() + payload.len()));
+    }
+}
@@ -0,0 +1,128 @@
+// Typed request/response layer over the raw socket server: newline-delimited JSON.
+
+use std::{collections::HashMap, future::Future};
+
+use bytes::{Bytes, BytesMut};
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::sync::mpsc;
+
+use super::{SocketMessage, SocketServer};
+use crate::utils::id::{ConnectionId, Peer};
+
+/// Longest command line accepted by [`SocketServer::serve_typed`], longer input is dropped
+pub const MAX_COMMAND_LEN: usize = 64 * 1024;
+
+impl SocketServer {
+    /// Serve a request/response protocol of one JSON document per line.
+    ///
+    /// Every line received on `receiver` (from [`start`](Self::start)) is decoded into `C`,
+    /// passed to `handler` with the [`Peer`], and the returned `R` is written back to that
+    /// peer followed by `\n`. Commands are handled one at a time in arrival order; lines
+    /// that fail to decode are logged and skipped. Returns when the receiver closes.
+    pub async fn serve_typed<C, R, F, Fut>(
+        &self,
+        mut receiver: mpsc::Receiver<SocketMessage>,
+        handler: F,
+    ) where
+        C: DeserializeOwned,
+        R: Serialize,
+        F: Fn(Peer, C) -> Fut,
+        Fut: Future<Output = R>,
+    {
+        let mut buffers: HashMap<ConnectionId, BytesMut> = HashMap::new();
+        while let Some(message) = receiver.recv().await {
+            let SocketMessage::Message(peer, data) = message else {
+                continue;
+            };
+
+            let buffer = buffers.entry(peer.id).or_default();
+            buffer.extend_from_slice(&data);
+            let mut lines = Vec::new();
+            while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                lines.push(buffer.split_to(end + 1).freeze());
+            }
+            if buffer.len() > MAX_COMMAND_LEN {
+                tracing::warn!("Dropping {} bytes from {}: command too long", buffer.len(), peer);
+                buffer.clear();
+            }
+            if buffer.is_empty() {
+                buffers.remove(&peer.id);
+            }
+
+            for line in lines {
+                let line = line.trim_ascii();
+                if line.is_empty() {
+                    continue;
+                }
+                let command = match serde_json::from_slice::<C>(line) {
+                    Ok(command) => command,
+                    Err(e) => {
+                        tracing::warn!("Invalid command from {}: {}", peer, e);
+                        continue;
+                    }
+                };
+                let response = handler(peer, command).await;
+                match serde_json::to_vec(&response) {
+                    Ok(mut response) => {
+                        response.push(b'\n');
+                        self.send(peer.id, Bytes::from(response)).await;
+                    }
+                    Err(e) => tracing::error!("Failed to encode response for {}: {}", peer, e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    use super::*;
+    use crate::testkit::{spawn_socket_server, tcp_client};
+
+    #[derive(Deserialize)]
+    #[serde(tag = "cmd", rename_all = "camelCase")]
+    enum Command {
+        Add { a: i64, b: i64 },
+        Ping,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    enum Response {
+        Sum(i64),
+        Pong,
+    }
+
+    #[tokio::test]
+    async fn test_serve_typed() {
+        let (server, addr, receiver) = spawn_socket_server().await;
+        let serving = server.clone();
+        tokio::spawn(async move {
+            serving
+                .serve_typed(receiver, |_peer, command: Command| async move {
+                    match command {
+                        Command::Add { a, b } => Response::Sum(a + b),
+                        Command::Ping => Response::Pong,
+                    }
+                })
+                .await
+        });
+
+        let (reader, mut writer) = tcp_client(addr).await.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        // 两条命令在同一次写入中，第三条分两次写入，中间夹一行非法数据
+        writer
+            .write_all(b"{\"cmd\":\"ping\"}\n{\"cmd\":\"add\",\"a\":1,\"b\":2}\nnot json\n{\"cmd\":\"ad")
+            .await
+            .unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "\"pong\"");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "{\"sum\":3}");
+
+        writer.write_all(b"d\",\"a\":40,\"b\":2}\r\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "{\"sum\":42}");
+    }
+}
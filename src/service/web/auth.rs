@@ -0,0 +1,292 @@
+// Pluggable credential check used by the login route.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use sea_orm::{DatabaseConnection, DbErr};
+use uuid::Uuid;
+
+use crate::database::users;
+use crate::service::web::{JwtConfig, service::ErrorCode};
+use crate::utils::TtlCache;
+
+/// How long failures of an unknown username are remembered
+const UNKNOWN_USER_TTL: Duration = Duration::from_secs(24 * 3600);
+/// Unknown usernames tracked at once
+const MAX_UNKNOWN_USERS: usize = 10_000;
+
+type LockedUntil = chrono::DateTime<chrono::FixedOffset>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+    #[error("Account locked until {0}")]
+    Locked(chrono::DateTime<chrono::FixedOffset>),
+    #[error("Database Error: {0}")]
+    DbErr(#[from] DbErr),
+    /// Failure of the backend itself (directory unreachable, bad hash, ...)
+    #[error("Authentication backend error: {0}")]
+    Backend(String),
+}
+
+impl From<AuthError> for crate::errors::Error {
+    fn from(error: AuthError) -> Self {
+        match error {
+            AuthError::InvalidCredentials => {
+                crate::errors::Error::AuthorizationFail(ErrorCode::InvalidUsernameOrPassword)
+            }
+            AuthError::Locked(until) => crate::errors::Error::AccountLocked(until),
+            AuthError::DbErr(e) => crate::errors::Error::DbErr(e),
+            AuthError::Backend(e) => {
+                tracing::error!("Authentication backend error: {}", e);
+                crate::errors::Error::InternalError(ErrorCode::InternalError)
+            }
+        }
+    }
+}
+
+/// Checks login credentials, set on `AppState::auth_backend` (defaults to [`DbAuthBackend`]).
+///
+/// The returned id becomes the token subject. Users unknown to `t_users` can log in, the
+/// login response then only carries the id and username.
+#[async_trait::async_trait]
+pub trait AuthBackend: Send + Sync {
+    async fn authenticate(&self, username: &str, password: &str) -> Result<Uuid, AuthError>;
+}
+
+/// bcrypt hashes in `t_users`, with the lockout of `JwtConfig::max_failed_attempts`.
+///
+/// Unknown usernames answer like existing ones, including the lockout, so the responses
+/// don't tell which accounts exist. Their failures are only kept in memory.
+pub struct DbAuthBackend {
+    db_conn: DatabaseConnection,
+    max_failed_attempts: u32,
+    lockout_duration: Duration,
+    bcrypt_cost: u32,
+    /// Hash checked for unknown usernames, so they take as long as a wrong password
+    dummy_hash: OnceLock<String>,
+    /// Failure count and lockout of unknown usernames
+    unknown_users: TtlCache<String, (u32, Option<LockedUntil>)>,
+}
+
+impl DbAuthBackend {
+    pub fn new(db_conn: DatabaseConnection, jwt_config: &JwtConfig) -> Self {
+        Self {
+            db_conn,
+            max_failed_attempts: jwt_config.max_failed_attempts,
+            lockout_duration: jwt_config.lockout_duration,
+            bcrypt_cost: jwt_config.bcrypt_cost,
+            dummy_hash: OnceLock::new(),
+            unknown_users: TtlCache::new(UNKNOWN_USER_TTL).with_max_size(MAX_UNKNOWN_USERS),
+        }
+    }
+
+    fn lockout_end(&self, now: LockedUntil) -> LockedUntil {
+        let lockout =
+            chrono::Duration::from_std(self.lockout_duration).unwrap_or(chrono::Duration::MAX);
+        now.checked_add_signed(lockout).unwrap_or(now)
+    }
+
+    /// Same checks and answers as for a user in `t_users` whose password never matches
+    fn reject_unknown(&self, username: &str, password: &str) -> AuthError {
+        let now = chrono::Local::now().fixed_offset();
+        let locked = |locked_until: Option<LockedUntil>| locked_until.filter(|until| *until > now);
+        if let Some((_, locked_until)) = self.unknown_users.get(&username.to_string())
+            && let Some(until) = locked(locked_until)
+        {
+            return AuthError::Locked(until);
+        }
+
+        let dummy_hash = self
+            .dummy_hash
+            .get_or_init(|| bcrypt::hash("lean-link", self.bcrypt_cost).unwrap_or_default());
+        let _ = bcrypt::verify(password, dummy_hash);
+
+        // 计数与锁定在同一次更新中完成，并发的失败不会互相覆盖
+        let (_, locked_until) = self.unknown_users.update(username.to_string(), |current| {
+            let (failures, locked_until) = current.unwrap_or_default();
+            if locked(locked_until).is_some() {
+                return (failures, locked_until);
+            }
+            let failures = failures.saturating_add(1);
+            if self.max_failed_attempts > 0 && failures >= self.max_failed_attempts {
+                return (0, Some(self.lockout_end(now)));
+            }
+            (failures, None)
+        });
+        match locked(locked_until) {
+            Some(until) => AuthError::Locked(until),
+            None => AuthError::InvalidCredentials,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthBackend for DbAuthBackend {
+    async fn authenticate(&self, username: &str, password: &str) -> Result<Uuid, AuthError> {
+        let Some(user) = users::find_user_by_name(&self.db_conn, username.to_string()).await?
+        else {
+            return Err(self.reject_unknown(username, password));
+        };
+        if let Some(until) = users::locked_until(&user, chrono::Local::now().fixed_offset()) {
+            return Err(AuthError::Locked(until));
+        }
+
+        let verified = bcrypt::verify(password, &user.password)
+            .map_err(|e| AuthError::Backend(e.to_string()))?;
+        if !verified {
+            let user = users::record_login_failure(
+                &self.db_conn,
+                user,
+                self.max_failed_attempts,
+                self.lockout_duration,
+            )
+            .await?;
+            if let Some(until) = users::locked_until(&user, chrono::Local::now().fixed_offset()) {
+                tracing::warn!("user {} locked until {}", user.username, until);
+                return Err(AuthError::Locked(until));
+            }
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        let user = users::reset_login_failures(&self.db_conn, user).await?;
+        Ok(user.id)
+    }
+}
+
+/// Fixed plain-text credentials, for tests and demos only
+#[derive(Default)]
+pub struct StaticAuthBackend {
+    users: HashMap<String, (String, Uuid)>,
+}
+
+impl StaticAuthBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_user(mut self, username: &str, password: &str, id: Uuid) -> Self {
+        self.users
+            .insert(username.to_string(), (password.to_string(), id));
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthBackend for StaticAuthBackend {
+    async fn authenticate(&self, username: &str, password: &str) -> Result<Uuid, AuthError> {
+        match self.users.get(username) {
+            Some((expected, id)) if expected == password => Ok(*id),
+            _ => Err(AuthError::InvalidCredentials),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_static_backend() {
+        let id = crate::new_id();
+        let backend: Box<dyn AuthBackend> =
+            Box::new(StaticAuthBackend::new().with_user("operator", "secret", id));
+
+        assert_eq!(backend.authenticate("operator", "secret").await.unwrap(), id);
+        assert!(matches!(
+            backend.authenticate("operator", "wrong").await,
+            Err(AuthError::InvalidCredentials)
+        ));
+        assert!(matches!(
+            backend.authenticate("nobody", "secret").await,
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_db_backend() {
+        use sea_orm::{ActiveModelTrait, ActiveValue};
+
+        use crate::database::entity::{prelude::TUsers, t_users};
+
+        let conn = crate::database::test_db(TUsers).await;
+        let user = t_users::ActiveModel {
+            username: ActiveValue::set("operator".into()),
+            password: ActiveValue::set(bcrypt::hash("secret", 4).unwrap()),
+            must_change_password: ActiveValue::set(false),
+            failed_attempts: ActiveValue::set(0),
+            locked_until: ActiveValue::set(None),
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+
+        let jwt_config = JwtConfig {
+            max_failed_attempts: 2,
+            bcrypt_cost: 4,
+            ..Default::default()
+        };
+        let backend = DbAuthBackend::new(conn, &jwt_config);
+        assert_eq!(backend.authenticate("operator", "secret").await.unwrap(), user.id);
+        assert!(matches!(
+            backend.authenticate("operator", "wrong").await,
+            Err(AuthError::InvalidCredentials)
+        ));
+        assert!(matches!(
+            backend.authenticate("operator", "wrong").await,
+            Err(AuthError::Locked(_))
+        ));
+        // 锁定期间正确密码也被拒绝
+        assert!(matches!(
+            backend.authenticate("operator", "secret").await,
+            Err(AuthError::Locked(_))
+        ));
+
+        // 不存在的用户名与已有账号的响应相同，不能借此探测账号
+        assert!(matches!(
+            backend.authenticate("nobody", "wrong").await,
+            Err(AuthError::InvalidCredentials)
+        ));
+        assert!(matches!(
+            backend.authenticate("nobody", "wrong").await,
+            Err(AuthError::Locked(_))
+        ));
+        assert!(matches!(
+            backend.authenticate("nobody", "secret").await,
+            Err(AuthError::Locked(_))
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_unknown_user_failures_concurrent() {
+        use crate::database::entity::prelude::TUsers;
+
+        let jwt_config = JwtConfig {
+            max_failed_attempts: 41,
+            bcrypt_cost: 4,
+            ..Default::default()
+        };
+        let backend = DbAuthBackend::new(crate::database::test_db(TUsers).await, &jwt_config);
+
+        // 并发的失败全部计数，第 41 次才锁定
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..5 {
+                        let error = backend.reject_unknown("nobody", "wrong");
+                        assert!(matches!(error, AuthError::InvalidCredentials));
+                    }
+                });
+            }
+        });
+        assert!(matches!(
+            backend.reject_unknown("nobody", "wrong"),
+            AuthError::Locked(_)
+        ));
+    }
+}
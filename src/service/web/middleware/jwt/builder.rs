@@ -5,9 +5,13 @@ use actix_web::{
 };
 use futures::future::{Ready, ok};
 use jsonwebtoken::Algorithm;
+use sea_orm::DatabaseConnection;
 use std::rc::Rc;
 
-use crate::service::web::middleware::jwt::{inner::Inner, middleware::JwtMiddleware};
+use crate::{
+    AppState,
+    service::web::middleware::jwt::{inner::Inner, middleware::JwtMiddleware},
+};
 
 pub struct Jwt {
     inner: Rc<Inner>,
@@ -20,6 +24,18 @@ impl Jwt {
         Self { inner }
     }
 
+    /// Middleware for the secured scope of `app_state`: the `jwt.secret` of its config and,
+    /// unless `jwt.revoke_on_logout` is off, its database for revoked tokens
+    pub fn from_app_state(app_state: &AppState) -> Self {
+        let config = &app_state.server_config.jwt;
+        let jwt = Self::default().set_secret_key(config.secret.clone());
+        if config.revoke_on_logout {
+            jwt.set_revocation_db(app_state.db_conn.clone())
+        } else {
+            jwt
+        }
+    }
+
     pub fn set_secret_key(mut self, secret_key: String) -> Self {
         Rc::make_mut(&mut self.inner).secret_key = secret_key;
         self
@@ -29,6 +45,12 @@ impl Jwt {
         Rc::make_mut(&mut self.inner).algorithm = algorithm;
         self
     }
+
+    /// Reject tokens revoked by `POST /user/logout`
+    pub fn set_revocation_db(mut self, db_conn: DatabaseConnection) -> Self {
+        Rc::make_mut(&mut self.inner).revocation_db = Some(db_conn);
+        self
+    }
 }
 
 impl Default for Jwt {
@@ -53,7 +75,7 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ok(JwtMiddleware {
-            service: service,
+            service: Rc::new(service),
             inner: self.inner.clone(),
         })
     }
@@ -1,10 +1,15 @@
 use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode};
 use rand::distr::SampleString;
+use sea_orm::DatabaseConnection;
+
+use crate::{database::revoked_tokens, service::web::service::ErrorCode};
 
 #[derive(Clone)]
 pub(crate) struct Inner {
     pub secret_key: String,
     pub algorithm: Algorithm,
+    /// Reject tokens revoked in `t_revoked_tokens` when set
+    pub revocation_db: Option<DatabaseConnection>,
 }
 
 impl Inner {
@@ -12,10 +17,11 @@ impl Inner {
         Self {
             secret_key,
             algorithm,
+            revocation_db: None,
         }
     }
 
-    pub fn validate(&self, token: &str) -> Result<super::Claims, crate::errors::Error> {
+    pub async fn validate(&self, token: &str) -> Result<super::Claims, crate::errors::Error> {
         let validation = Validation::new(self.algorithm);
 
         let token_data = decode::<super::Claims>(
@@ -26,6 +32,14 @@ impl Inner {
 
         let claims = token_data.claims;
 
+        if let (Some(db_conn), Some(jti)) = (&self.revocation_db, claims.jti)
+            && revoked_tokens::is_token_revoked(db_conn, jti).await?
+        {
+            return Err(crate::errors::Error::AuthorizationFail(
+                ErrorCode::Unauthorized,
+            ));
+        }
+
         Ok(claims)
     }
 }
@@ -35,6 +49,7 @@ impl Default for Inner {
         Self {
             secret_key: rand::distr::Alphanumeric::default().sample_string(&mut rand::rng(), 32),
             algorithm: Algorithm::HS256,
+            revocation_db: None,
         }
     }
 }
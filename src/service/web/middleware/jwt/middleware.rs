@@ -8,10 +8,10 @@ use actix_web::{
 };
 use futures::future::{FutureExt as _, LocalBoxFuture};
 
-use crate::service::web::middleware::jwt::inner::Inner;
+use crate::service::web::middleware::jwt::{RevocationEnabled, inner::Inner};
 
 pub struct JwtMiddleware<S> {
-    pub(crate) service: S,
+    pub(crate) service: Rc<S>,
     pub(crate) inner: Rc<Inner>,
 }
 
@@ -49,21 +49,25 @@ where
             return ok(res.map_into_right_body()).boxed_local();
         }
 
-        match self.inner.validate(token.as_str()) {
-            Ok(claims) => {
-                // Attach user information to the request context for later access.
-                req.extensions_mut().insert(claims);
-                let fut = self.service.call(req);
-                Box::pin(async move {
-                    let res = fut.await?;
+        let service = self.service.clone();
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            match inner.validate(token.as_str()).await {
+                Ok(claims) => {
+                    // Attach user information to the request context for later access.
+                    req.extensions_mut().insert(claims);
+                    if inner.revocation_db.is_some() {
+                        req.extensions_mut().insert(RevocationEnabled);
+                    }
+                    let res = service.call(req).await?;
                     Ok(res.map_into_left_body())
-                })
-            }
-            Err(e) => {
-                // If the token validation fails, return a 401 error.
-                let res = req.error_response(e);
-                ok(res.map_into_right_body()).boxed_local()
+                }
+                Err(e) => {
+                    // If the token validation fails, return a 401 error.
+                    let res = req.error_response(e);
+                    Ok(res.map_into_right_body())
+                }
             }
-        }
+        })
     }
 }
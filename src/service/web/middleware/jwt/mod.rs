@@ -18,8 +18,15 @@ pub struct Claims {
     pub nbf: Option<usize>,
     pub aud: Option<String>,
     pub data: Option<serde_json::Value>,
+    /// Token id, checked against `t_revoked_tokens` so logout can revoke the token
+    #[serde(default)]
+    pub jti: Option<Uuid>,
 }
 
+/// Request extension set by [`Jwt`] when it rejects revoked tokens, logout fails without it
+#[derive(Debug, Clone, Copy)]
+pub struct RevocationEnabled;
+
 impl Claims {
     // Check if the token has expired
     pub fn is_expired(&self) -> bool {
@@ -28,11 +35,13 @@ impl Claims {
     }
 }
 
-/// generate JWT token
+/// generate JWT token, a random `jti` is filled in when missing
 pub fn generate_token(claims: &Claims, secret_key: &str) -> Result<String, crate::errors::Error> {
+    let mut claims = claims.clone();
+    claims.jti.get_or_insert_with(Uuid::new_v4);
     let token = encode(
         &Header::default(),
-        claims,
+        &claims,
         &EncodingKey::from_secret(secret_key.as_bytes()),
     )?;
 
@@ -57,6 +66,7 @@ pub fn generate_token_with_defaults(
         nbf: None,
         aud: None,
         data: None,
+        jti: Some(Uuid::new_v4()),
     };
 
     generate_token(&claims, secret_key)
@@ -66,18 +76,25 @@ pub fn generate_token_with_defaults(
 mod tests {
     use actix_web::{App, post, test, web};
 
-    use crate::service::web::middleware::jwt::{builder::Jwt, generate_token_with_defaults};
-    use uuid::Uuid;
+    use crate::service::web::middleware::jwt::{
+        RevocationEnabled, builder::Jwt, generate_token_with_defaults,
+    };
 
     #[post("hello")]
     async fn hello() -> actix_web::Result<String> {
         Ok("world".into())
     }
+
+    #[post("revocation")]
+    async fn revocation(enabled: Option<web::ReqData<RevocationEnabled>>) -> String {
+        if enabled.is_some() { "on" } else { "off" }.to_string()
+    }
     fn configure_secured_routes(cfg: &mut web::ServiceConfig) {
         cfg.service(
             web::scope("/api")
                 .wrap(Jwt::default().set_secret_key("secret_key".to_string()))
-                .service(hello),
+                .service(hello)
+                .service(revocation),
         );
     }
     #[actix_web::test]
@@ -92,6 +109,18 @@ mod tests {
         assert_eq!(resp.status(), 401);
     }
 
+    #[actix_web::test]
+    async fn test_revocation_disabled() {
+        // 未设置吊销数据库时不带标记，注销接口据此拒绝
+        let app = test::init_service(App::new().configure(configure_secured_routes)).await;
+        let token = generate_token_with_defaults(&crate::new_id(), "secret_key", 3600).unwrap();
+        let req = test::TestRequest::post()
+            .uri("/api/revocation")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "off");
+    }
+
     #[actix_web::test]
     async fn test_null_token() {
         let app = test::init_service(App::new().configure(configure_secured_routes)).await;
@@ -107,7 +136,7 @@ mod tests {
             .with_max_level(tracing::Level::DEBUG)
             .init();
         let app = test::init_service(App::new().configure(configure_secured_routes)).await;
-        let token = generate_token_with_defaults(&Uuid::now_v7(), "secret_key", 3600).unwrap();
+        let token = generate_token_with_defaults(&crate::new_id(), "secret_key", 3600).unwrap();
         let req = test::TestRequest::post()
             .uri("/api/hello")
             .insert_header(("Authorization", format!("Bearer {}", token).as_str()))
@@ -116,4 +145,50 @@ mod tests {
 
         assert_eq!(resp.status(), 200);
     }
+
+    #[cfg(feature = "sqlite")]
+    #[actix_web::test]
+    async fn test_revoked_token() {
+        use crate::database::{entity::prelude::TRevokedTokens, revoked_tokens};
+        use crate::service::web::middleware::jwt::Jwt;
+
+        let db_conn = crate::database::test_db(TRevokedTokens).await;
+
+        let jwt = Jwt::default()
+            .set_secret_key("secret_key".to_string())
+            .set_revocation_db(db_conn.clone());
+        let app = test::init_service(
+            App::new().service(web::scope("/api").wrap(jwt).service(hello).service(revocation)),
+        )
+        .await;
+        let token = generate_token_with_defaults(&crate::new_id(), "secret_key", 3600).unwrap();
+        let req = test::TestRequest::post()
+            .uri("/api/revocation")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "on");
+        let request = || {
+            test::TestRequest::post()
+                .uri("/api/hello")
+                .insert_header(("Authorization", format!("Bearer {}", token)))
+                .to_request()
+        };
+        assert_eq!(test::call_service(&app, request()).await.status(), 200);
+
+        let claims = jsonwebtoken::decode::<super::Claims>(
+            &token,
+            &jsonwebtoken::DecodingKey::from_secret(b"secret_key"),
+            &jsonwebtoken::Validation::default(),
+        )
+        .unwrap()
+        .claims;
+        let expires_at = chrono::Local::now().fixed_offset() + chrono::Duration::hours(1);
+        revoked_tokens::revoke_token(&db_conn, claims.jti.unwrap(), expires_at)
+            .await
+            .unwrap();
+        assert_eq!(test::call_service(&app, request()).await.status(), 401);
+
+        // 未过期的吊销记录不会被清理
+        assert_eq!(revoked_tokens::delete_expired_revoked_tokens(&db_conn).await.unwrap(), 0);
+    }
 }
@@ -1 +1,4 @@
 pub mod jwt;
+pub mod request_id;
+
+pub use request_id::{CorrelationId, RequestId};
@@ -0,0 +1,199 @@
+// Correlation id for each HTTP request, taken from `X-Request-Id` or generated.
+//
+// The id is put in the request extensions, on the tracing span the rest of the request runs
+// in, and on the response headers, so a client-reported id can be found in the API logs.
+
+use std::{fmt::Display, rc::Rc};
+
+use actix_web::{
+    Error, HttpMessage,
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    http::header::{HeaderName, HeaderValue},
+};
+use futures::future::{LocalBoxFuture, Ready, ok};
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest incoming id that is kept, longer ones are replaced by a generated id
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Id of the current request, read it in a handler with `Option<web::ReqData<CorrelationId>>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorrelationId(String);
+
+impl CorrelationId {
+    /// Use the client's id when it is short visible ASCII, so it can go into logs and headers
+    /// as is, otherwise a new UUID
+    fn from_header(value: Option<&HeaderValue>) -> Self {
+        let id = value
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= MAX_REQUEST_ID_LEN
+                    && id.bytes().all(|b| b.is_ascii_graphic())
+            });
+        match id {
+            Some(id) => Self(id.to_string()),
+            None => Self(crate::new_id().to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for CorrelationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<CorrelationId> for String {
+    fn from(id: CorrelationId) -> Self {
+        id.0
+    }
+}
+
+/// Middleware tagging every request with a [`CorrelationId`], wrap it outermost so the id
+/// also covers the other middlewares:
+///
+/// `App::new().wrap(Jwt::default()).wrap(RequestId)`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestIdMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<ServiceResponse<B>, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let id = CorrelationId::from_header(req.headers().get(&REQUEST_ID_HEADER));
+        let span = tracing::info_span!(
+            "request",
+            request_id = %id,
+            method = %req.method(),
+            path = %req.path()
+        );
+        req.extensions_mut().insert(id.clone());
+        let service = self.service.clone();
+
+        Box::pin(
+            async move {
+                // handler 返回的错误已是响应，同样带上 id
+                let mut res = service.call(req).await?;
+                if let Ok(value) = HeaderValue::from_str(id.as_str()) {
+                    res.headers_mut().insert(REQUEST_ID_HEADER, value);
+                }
+                Ok(res)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, HttpResponse, get, test, web};
+
+    use super::*;
+    use crate::service::web::service::WebResponse;
+
+    #[get("/echo")]
+    async fn echo(id: Option<web::ReqData<CorrelationId>>) -> HttpResponse {
+        HttpResponse::Ok().body(id.map(|id| id.to_string()).unwrap_or_default())
+    }
+
+    #[get("/json")]
+    async fn json(id: web::ReqData<CorrelationId>) -> web::Json<WebResponse<()>> {
+        WebResponse::with_result(()).with_request_id(id.into_inner()).into()
+    }
+
+    #[get("/fail")]
+    async fn fail() -> Result<HttpResponse, crate::errors::Error> {
+        Err(crate::errors::Error::MissingToken)
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_round_trip() {
+        let app = test::init_service(
+            App::new()
+                .wrap(RequestId)
+                .service(echo)
+                .service(json)
+                .service(fail),
+        )
+        .await;
+
+        // 客户端给出的 id 原样返回，并可在 handler 中取得
+        let req = test::TestRequest::get()
+            .uri("/echo")
+            .insert_header(("X-Request-Id", "client-42"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "client-42");
+        assert_eq!(test::read_body(resp).await, "client-42");
+
+        // 未给出或不合法时生成 UUID
+        for header in [None, Some("bad id"), Some(&*"x".repeat(MAX_REQUEST_ID_LEN + 1))] {
+            let mut req = test::TestRequest::get().uri("/echo");
+            if let Some(header) = header {
+                req = req.insert_header(("X-Request-Id", header));
+            }
+            let resp = test::call_service(&app, req.to_request()).await;
+            let id = resp.headers().get(REQUEST_ID_HEADER).unwrap().to_str().unwrap();
+            assert!(uuid::Uuid::parse_str(id).is_ok());
+            let id = id.to_string();
+            assert_eq!(test::read_body(resp).await, id);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/json")
+            .insert_header(("X-Request-Id", "client-44"))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["requestId"], "client-44");
+
+        // 错误响应同样带 id
+        let req = test::TestRequest::get()
+            .uri("/fail")
+            .insert_header(("X-Request-Id", "client-43"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+        assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "client-43");
+    }
+}
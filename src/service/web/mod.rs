@@ -2,6 +2,7 @@ use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
+pub mod auth;
 pub mod middleware;
 pub mod service;
 
@@ -9,6 +10,16 @@ pub mod service;
 pub struct WebConfig {
     pub host: String,
     pub port: u16,
+    /// Bearer token required by `GET /metrics`; the endpoint is open when unset.
+    #[serde(default)]
+    pub metrics_token: Option<String>,
+    /// Maximum accepted JSON request body in bytes, see [`service::json_config`]
+    #[serde(default = "default_json_limit")]
+    pub json_limit: usize,
+}
+
+fn default_json_limit() -> usize {
+    256 * 1024
 }
 
 impl Default for WebConfig {
@@ -16,6 +27,8 @@ impl Default for WebConfig {
         WebConfig {
             host: "127.0.0.1".to_string(),
             port: 8080,
+            metrics_token: None,
+            json_limit: default_json_limit(),
         }
     }
 }
@@ -25,6 +38,59 @@ pub struct JwtConfig {
     pub secret: String,
     #[serde(with = "crate::utils::datetime::string_to_duration")]
     pub expires_in: Duration,
+    /// Consecutive bad passwords before the account is locked, `0` disables the lockout
+    #[serde(default = "default_max_failed_attempts")]
+    pub max_failed_attempts: u32,
+    /// How long a locked account rejects logins, persisted so restarts don't reset it
+    #[serde(
+        default = "default_lockout_duration",
+        with = "crate::utils::datetime::string_to_duration"
+    )]
+    pub lockout_duration: Duration,
+    /// bcrypt work factor for password hashes, lower it on weak ARM boards
+    #[serde(
+        default = "default_bcrypt_cost",
+        deserialize_with = "deserialize_bcrypt_cost"
+    )]
+    pub bcrypt_cost: u32,
+    /// Rules checked whenever a password is changed
+    #[serde(default)]
+    pub password_policy: service::validate::PasswordPolicy,
+    /// Keep tokens revoked by `POST /user/logout` in `t_revoked_tokens` and reject them, see
+    /// [`middleware::jwt::Jwt::from_app_state`]. Turn it off when the app does not run the
+    /// `t_revoked_tokens` migration, logout then fails.
+    #[serde(default = "default_revoke_on_logout")]
+    pub revoke_on_logout: bool,
+}
+
+fn default_max_failed_attempts() -> u32 {
+    5
+}
+
+fn default_lockout_duration() -> Duration {
+    Duration::from_secs(15 * 60)
+}
+
+fn default_revoke_on_logout() -> bool {
+    true
+}
+
+fn default_bcrypt_cost() -> u32 {
+    bcrypt::DEFAULT_COST
+}
+
+fn deserialize_bcrypt_cost<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let cost = u32::deserialize(deserializer)?;
+    if !(4..=31).contains(&cost) {
+        return Err(serde::de::Error::custom(format!(
+            "bcrypt_cost must be within 4..=31, got {}",
+            cost
+        )));
+    }
+    Ok(cost)
 }
 
 impl Default for JwtConfig {
@@ -32,6 +98,37 @@ impl Default for JwtConfig {
         JwtConfig {
             secret: "secret".to_string(),
             expires_in: Duration::from_secs(3600),
+            max_failed_attempts: default_max_failed_attempts(),
+            lockout_duration: default_lockout_duration(),
+            bcrypt_cost: default_bcrypt_cost(),
+            password_policy: Default::default(),
+            revoke_on_logout: default_revoke_on_logout(),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bcrypt_cost() {
+        let config: JwtConfig =
+            serde_json::from_str(r#"{"secret": "s", "expires_in": "15m", "bcrypt_cost": 5}"#)
+                .unwrap();
+        assert_eq!(config.bcrypt_cost, 5);
+
+        let hash = bcrypt::hash("password", config.bcrypt_cost).unwrap();
+        assert!(hash.starts_with("$2b$05$"));
+        assert!(bcrypt::verify("password", &hash).unwrap());
+
+        let config: JwtConfig =
+            serde_json::from_str(r#"{"secret": "s", "expires_in": "15m"}"#).unwrap();
+        assert_eq!(config.bcrypt_cost, bcrypt::DEFAULT_COST);
+        assert!(config.revoke_on_logout);
+
+        let result = serde_json::from_str::<JwtConfig>(
+            r#"{"secret": "s", "expires_in": "15m", "bcrypt_cost": 3}"#,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::database::entity::t_camera_configs;
 use crate::service::camera::stream::CameraStreamConfig;
+use crate::service::camera::channel::FrameChannelPolicy;
 use crate::service::camera::{CameraConfig, CameraInfo, GrabMode};
 use crate::service::web::service::{ErrorCode, Pagination, WebResponse};
 use crate::{AppState, errors};
@@ -149,7 +150,7 @@ async fn stream_start_inner(
 
     let mut frame_rx = app_state
         .camera_manager
-        .start_grabbing(&req.id, GrabMode::Continuous)
+        .start_grabbing_with_policy(&req.id, GrabMode::Continuous, FrameChannelPolicy::KeepLatest)
         .await?;
 
     let stream = app_state.camera_manager.start_stream(&req.id).await?;
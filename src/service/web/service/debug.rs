@@ -0,0 +1,31 @@
+use actix_web::scope;
+
+#[scope("/debug")]
+pub mod api {
+    use actix_web::{get, web};
+
+    use crate::{
+        AppState,
+        service::web::{
+            middleware::jwt,
+            service::{ErrorCode, WebResponse},
+        },
+        utils::history::HistoryEntry,
+    };
+
+    /// Recent WebSocket messages, enabled by `web_socket.history_capacity`
+    #[get("/ws-history")]
+    pub async fn ws_history(
+        claims: Option<web::ReqData<jwt::Claims>>,
+        app_state: web::Data<AppState>,
+    ) -> actix_web::Result<web::Json<WebResponse<Vec<HistoryEntry<String>>>>, crate::errors::Error>
+    {
+        if claims.is_none() {
+            return Err(crate::errors::Error::AuthorizationFail(
+                ErrorCode::Unauthorized,
+            ));
+        }
+
+        Ok(WebResponse::with_result(app_state.ws_server.recent()).into())
+    }
+}
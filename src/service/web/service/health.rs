@@ -0,0 +1,23 @@
+use actix_web::{get, web};
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, service::web::service::WebResponse, utils::time_source::TimeSource};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthStatus {
+    pub db_up: bool,
+    /// `failed` or `system_only` mean the device time may be wrong
+    pub time_source: TimeSource,
+}
+
+#[get("/health")]
+pub async fn health(
+    app_state: web::Data<AppState>,
+) -> actix_web::Result<web::Json<WebResponse<HealthStatus>>, crate::errors::Error> {
+    Ok(WebResponse::with_result(HealthStatus {
+        db_up: app_state.db_conn.ping().await.is_ok(),
+        time_source: app_state.time_source,
+    })
+    .into())
+}
@@ -1,6 +1,15 @@
 use actix_web::scope;
+use chrono::{DateTime, FixedOffset};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    database::entity::t_logs,
+    utils::{
+        csv::{csv_columns, csv_header, to_csv_rows},
+        datetime::local_time_option,
+    },
+};
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PageLogsRequest {
@@ -10,16 +19,94 @@ pub struct PageLogsRequest {
     pub page_size: u64,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl LogExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            LogExportFormat::Csv => "text/csv; charset=utf-8",
+            LogExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// Pick the format from an `Accept` header, `None` if it names neither format.
+    pub fn from_accept(accept: &str) -> Option<Self> {
+        if accept.contains("text/csv") {
+            Some(LogExportFormat::Csv)
+        } else if accept.contains("application/x-ndjson") {
+            Some(LogExportFormat::Ndjson)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportLogsRequest {
+    /// Takes precedence over the `Accept` header, NDJSON if neither is given
+    #[serde(default)]
+    pub format: Option<LogExportFormat>,
+    #[serde(default, with = "local_time_option")]
+    pub start_time: Option<DateTime<FixedOffset>>,
+    #[serde(default, with = "local_time_option")]
+    pub end_time: Option<DateTime<FixedOffset>>,
+}
+
+/// Render a batch of logs as CSV rows or NDJSON lines, each with its trailing newline.
+///
+/// The CSV header is written before the first batch, `columns` keeps its columns for the
+/// batches after it. A log has no nested `Option` struct, so every batch has the same
+/// columns.
+pub(crate) fn format_logs(
+    logs: &[t_logs::Model],
+    format: LogExportFormat,
+    columns: &mut Option<Vec<String>>,
+) -> String {
+    match format {
+        LogExportFormat::Csv => match columns {
+            Some(columns) => to_csv_rows(logs, columns),
+            None => {
+                let columns = columns.insert(csv_columns(logs));
+                csv_header(columns) + &to_csv_rows(logs, columns)
+            }
+        },
+        LogExportFormat::Ndjson => logs
+            .iter()
+            .map(|log| {
+                let mut line = serde_json::to_string(log).unwrap_or_default();
+                line.push('\n');
+                line
+            })
+            .collect(),
+    }
+}
+
 #[scope("/log")]
 pub mod api {
-    use actix_web::{post, web};
+    use actix_web::{HttpRequest, HttpResponse, http::header, post, web};
+    use bytes::Bytes;
 
     use crate::{
         AppState,
         database::{entity::t_logs, logs},
-        service::web::service::{Pagination, WebResponse, log::PageLogsRequest},
+        service::web::{
+            middleware::jwt,
+            service::{
+                ErrorCode, Pagination, WebResponse,
+                log::{ExportLogsRequest, LogExportFormat, PageLogsRequest, format_logs},
+            },
+        },
     };
 
+    /// Rows fetched from the database per streamed chunk
+    const EXPORT_BATCH_SIZE: u64 = 500;
+
     #[post("/page-logs")]
     pub async fn page_logs(
         app_state: web::Data<AppState>,
@@ -32,4 +119,132 @@ pub mod api {
 
         Ok(WebResponse::with_result(result.into()).into())
     }
+
+    /// Stream all logs as CSV or NDJSON, batch by batch so large tables are never buffered.
+    /// The CSV has every field of the log, nested fields as `outer.inner` columns.
+    #[post("/export")]
+    pub async fn export_logs(
+        claims: Option<web::ReqData<jwt::Claims>>,
+        http_req: HttpRequest,
+        app_state: web::Data<AppState>,
+        req: web::Json<ExportLogsRequest>,
+    ) -> actix_web::Result<HttpResponse, crate::errors::Error> {
+        if claims.is_none() {
+            return Err(crate::errors::Error::AuthorizationFail(
+                ErrorCode::Unauthorized,
+            ));
+        }
+
+        let format = req
+            .format
+            .or_else(|| {
+                http_req
+                    .headers()
+                    .get(header::ACCEPT)
+                    .and_then(|accept| accept.to_str().ok())
+                    .and_then(LogExportFormat::from_accept)
+            })
+            .unwrap_or(LogExportFormat::Ndjson);
+
+        let ExportLogsRequest {
+            start_time,
+            end_time,
+            ..
+        } = req.into_inner();
+        let db_conn = app_state.db_conn.clone();
+
+        // 游标为 None 时导出结束，columns 为首批确定的 CSV 列
+        let state = (Some(None::<logs::LogCursor>), None);
+        let body = futures::stream::unfold(state, move |(after, mut columns)| {
+            let db_conn = db_conn.clone();
+            async move {
+                let after = after?;
+                let batch = match logs::find_logs_batch(
+                    &db_conn,
+                    start_time,
+                    end_time,
+                    after,
+                    EXPORT_BATCH_SIZE,
+                )
+                .await
+                {
+                    Ok(batch) => batch,
+                    Err(e) => {
+                        tracing::error!("export logs failed: {}", e);
+                        return Some((Err(crate::errors::Error::DbErr(e)), (None, columns)));
+                    }
+                };
+
+                if batch.is_empty() {
+                    return None;
+                }
+                let next = if (batch.len() as u64) < EXPORT_BATCH_SIZE {
+                    None
+                } else {
+                    batch.last().map(|log| Some((log.created_at, log.id)))
+                };
+                let chunk = format_logs(&batch, format, &mut columns);
+                Some((Ok(Bytes::from(chunk)), (next, columns)))
+            }
+        });
+
+        Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .streaming(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{database::entity::t_logs::LogLevel, utils::datetime::convert_to_local_rfc3339};
+
+    #[test]
+    fn test_format_logs() {
+        let created_at = DateTime::parse_from_rfc3339("2025-01-02T03:04:05+00:00").unwrap();
+        let log = t_logs::Model {
+            id: uuid::Uuid::nil(),
+            user_id: None,
+            action: "write, \"coil\"".into(),
+            details: serde_json::json!({"addr": 1}),
+            level: LogLevel::Warning,
+            created_at,
+            updated_at: created_at,
+            deleted_at: None,
+        };
+
+        let time = convert_to_local_rfc3339(&created_at);
+        let row = format!(
+            "00000000-0000-0000-0000-000000000000,,\"write, \"\"coil\"\"\",\"{{\"\"addr\"\":1}}\",Warning,{},{},\n",
+            time, time
+        );
+        // 首批带表头，之后的批次只有数据行
+        let mut columns = None;
+        assert_eq!(
+            format_logs(std::slice::from_ref(&log), LogExportFormat::Csv, &mut columns),
+            format!("id,userId,action,details,level,createdAt,updatedAt,deletedAt\n{}", row)
+        );
+        assert_eq!(
+            format_logs(std::slice::from_ref(&log), LogExportFormat::Csv, &mut columns),
+            row
+        );
+
+        let line = format_logs(&[log], LogExportFormat::Ndjson, &mut None);
+        assert!(line.ends_with('\n'));
+        let value: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(value["action"], "write, \"coil\"");
+    }
+
+    #[test]
+    fn test_export_format_from_accept() {
+        assert_eq!(
+            LogExportFormat::from_accept("text/csv"),
+            Some(LogExportFormat::Csv)
+        );
+        assert_eq!(
+            LogExportFormat::from_accept("application/x-ndjson, */*"),
+            Some(LogExportFormat::Ndjson)
+        );
+        assert_eq!(LogExportFormat::from_accept("*/*"), None);
+    }
 }
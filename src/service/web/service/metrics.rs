@@ -0,0 +1,66 @@
+use std::fmt::Write;
+
+use actix_web::{HttpRequest, HttpResponse, get, http::header, web};
+use subtle::ConstantTimeEq;
+
+use crate::{AppState, service::metrics::metrics_snapshot};
+
+/// Compares the bearer token in constant time, so the response time does not leak it
+fn authorized(req: &HttpRequest, token: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return true;
+    };
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|value| bool::from(value.as_bytes().ct_eq(token.as_bytes())))
+}
+
+fn write_gauge(output: &mut String, name: &str, value: u64) {
+    let _ = writeln!(output, "# TYPE lean_link_{} gauge", name);
+    let _ = writeln!(output, "lean_link_{} {}", name, value);
+}
+
+#[get("/metrics")]
+pub async fn prometheus_metrics(app_state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    if !authorized(&req, app_state.server_config.web.metrics_token.as_deref()) {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let mut output = String::new();
+    let db_up = app_state.db_conn.ping().await.is_ok();
+    write_gauge(&mut output, "db_up", db_up as u64);
+    write_gauge(
+        &mut output,
+        "ws_connections",
+        app_state.ws_server.connection_count() as u64,
+    );
+    output.push_str(&metrics_snapshot().to_prometheus());
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn test_authorized() {
+        let req = TestRequest::default().to_http_request();
+        assert!(authorized(&req, None));
+        assert!(!authorized(&req, Some("secret")));
+
+        let req = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Bearer secret"))
+            .to_http_request();
+        assert!(authorized(&req, Some("secret")));
+        assert!(!authorized(&req, Some("other")));
+        assert!(!authorized(&req, Some("secret2")));
+        assert!(!authorized(&req, Some("")));
+    }
+}
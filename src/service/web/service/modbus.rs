@@ -19,7 +19,7 @@ pub struct ModbusConfigCreateRequest {
 impl From<ModbusConfigCreateRequest> for t_modbus_configs::ActiveModel {
     fn from(req: ModbusConfigCreateRequest) -> Self {
         t_modbus_configs::ActiveModel {
-            id: ActiveValue::set(Uuid::now_v7()),
+            id: ActiveValue::set(crate::new_id()),
             r#type: ActiveValue::set(req.config_type),
             host: ActiveValue::set(req.host),
             port: ActiveValue::set(req.port),
@@ -3,7 +3,6 @@ use actix_web::scope;
 use sea_orm::ActiveValue;
 use serde::{Deserialize, Serialize};
 use serialport::SerialPortType;
-use uuid::Uuid;
 
 /// 枚举到的串口信息 (扁平化结构, 与前端 Dart 模型匹配)
 #[derive(Serialize, Deserialize, Debug)]
@@ -65,7 +64,7 @@ pub struct SerialportConfigCreateRequest {
 impl From<SerialportConfigCreateRequest> for t_serialport_configs::ActiveModel {
     fn from(req: SerialportConfigCreateRequest) -> Self {
         t_serialport_configs::ActiveModel {
-            id: ActiveValue::set(Uuid::now_v7()),
+            id: ActiveValue::set(crate::new_id()),
             path: ActiveValue::set(req.path),
             baud_rate: ActiveValue::set(req.baud_rate),
             data_bits: ActiveValue::set(req.data_bits),
@@ -1,14 +1,28 @@
-use crate::utils::datetime::{local_time, local_time_option};
+use crate::{
+    service::web::service::validate::{Validate, check_byte_length, check_length},
+    utils::datetime::{local_time, local_time_option},
+};
 use actix_web::scope;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+pub const MAX_USERNAME_LEN: usize = 64;
+/// bcrypt ignores everything past 72 bytes
+pub const MAX_PASSWORD_LEN: usize = 72;
+
 #[derive(Serialize, Deserialize)]
 pub struct UserLoginRequest {
     pub username: String,
     pub password: String,
 }
 
+impl Validate for UserLoginRequest {
+    fn validate(&self) -> Result<(), crate::errors::Error> {
+        check_length("username", &self.username, MAX_USERNAME_LEN)?;
+        check_byte_length("password", &self.password, MAX_PASSWORD_LEN)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct User {
@@ -35,25 +49,44 @@ impl From<crate::database::entity::t_users::Model> for User {
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UserLoginResponse {
     pub token: String,
     pub user: User,
+    /// Still using the seeded default password, the client should prompt for a new one
+    pub must_change_password: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangePasswordRequest {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+impl Validate for ChangePasswordRequest {
+    fn validate(&self) -> Result<(), crate::errors::Error> {
+        check_byte_length("oldPassword", &self.old_password, MAX_PASSWORD_LEN)?;
+        check_byte_length("newPassword", &self.new_password, MAX_PASSWORD_LEN)
+    }
 }
 
 #[scope("/user")]
 pub mod api {
     use crate::{
         AppState,
-        database::users,
+        database::{revoked_tokens, users},
         service::web::{
             middleware::jwt,
             service::{
                 ErrorCode, WebResponse,
-                user::{User, UserLoginRequest, UserLoginResponse},
+                user::{ChangePasswordRequest, User, UserLoginRequest, UserLoginResponse},
+                validate::Validate,
             },
         },
+        utils::csv::to_csv,
     };
-    use actix_web::{post, web};
+    use actix_web::{HttpResponse, post, web};
 
     #[post("/login")]
     async fn login(
@@ -61,40 +94,36 @@ pub mod api {
         req: web::Json<UserLoginRequest>,
     ) -> actix_web::Result<web::Json<WebResponse<UserLoginResponse>>, crate::errors::Error> {
         let db_conn = &app_state.db_conn;
+        req.validate()?;
 
-        let user = match users::find_user_by_name(db_conn, req.username.clone()).await {
-            Ok(Some(user)) => user,
-            Ok(None) => {
-                return Err(crate::errors::Error::AuthorizationFail(
-                    ErrorCode::InvalidUsernameOrPassword,
-                ));
-            }
-            Err(e) => {
-                tracing::error!(error = ?e);
-                return Err(crate::errors::Error::DbErr(e));
+        let user_id = app_state
+            .auth_backend
+            .authenticate(&req.username, &req.password)
+            .await?;
+        // 其它认证后端的用户不一定在 t_users 中
+        let (user, must_change_password) = match users::find_user_by_id(db_conn, user_id).await? {
+            Some(user) => {
+                let must_change_password = user.must_change_password;
+                (User::from(user), must_change_password)
             }
-        };
-
-        let verify_password = match bcrypt::verify(req.password.clone(), &user.password) {
-            Ok(verify) => verify,
-            Err(e) => {
-                tracing::error!(error = ?e);
-                return Err(crate::errors::Error::InternalError(
-                    ErrorCode::InternalError,
-                ));
+            None => {
+                let now = chrono::Local::now().fixed_offset();
+                let user = User {
+                    id: user_id,
+                    username: req.username.clone(),
+                    created_at: now,
+                    updated_at: now,
+                    deleted_at: None,
+                };
+                (user, false)
             }
         };
 
-        if !verify_password {
-            return Err(crate::errors::Error::AuthorizationFail(
-                ErrorCode::InvalidUsernameOrPassword,
-            ));
-        }
-
+        let jwt_config = &app_state.server_config.jwt;
         let token = match jwt::generate_token_with_defaults(
             &user.id,
-            &app_state.server_config.jwt.secret,
-            app_state.server_config.jwt.expires_in.as_secs() as i64,
+            &jwt_config.secret,
+            jwt_config.expires_in.as_secs() as i64,
         ) {
             Ok(token) => token,
             Err(e) => {
@@ -104,13 +133,8 @@ pub mod api {
         };
         let resp = UserLoginResponse {
             token,
-            user: User {
-                id: user.id,
-                username: user.username,
-                created_at: user.created_at,
-                updated_at: user.updated_at,
-                deleted_at: user.deleted_at,
-            },
+            must_change_password,
+            user,
         };
         Ok(WebResponse::with_result(resp).into())
     }
@@ -142,4 +166,159 @@ pub mod api {
 
         Ok(WebResponse::with_result(user.into()).into())
     }
+
+    /// Revoke the current token. Fails unless the secured scope rejects revoked tokens, see
+    /// `Jwt::from_app_state`, otherwise the token would stay valid.
+    #[post("/logout")]
+    async fn logout(
+        claims: Option<web::ReqData<jwt::Claims>>,
+        revocation: Option<web::ReqData<jwt::RevocationEnabled>>,
+        app_state: web::Data<AppState>,
+    ) -> actix_web::Result<web::Json<WebResponse<()>>, crate::errors::Error> {
+        let Some(claims) = claims else {
+            return Err(crate::errors::Error::AuthorizationFail(
+                ErrorCode::Unauthorized,
+            ));
+        };
+        if revocation.is_none() {
+            return Err(crate::errors::Error::BadRequest(
+                ErrorCode::OperationNotAllow,
+                "未启用 token 吊销，无法注销".into(),
+            ));
+        }
+
+        // 旧版本签发的 token 没有 jti，只能等待过期
+        if let Some(jti) = claims.jti {
+            let expires_at = chrono::DateTime::from_timestamp(claims.exp as i64, 0)
+                .unwrap_or_default()
+                .fixed_offset();
+            revoked_tokens::revoke_token(&app_state.db_conn, jti, expires_at).await?;
+        }
+
+        Ok(WebResponse::with_result(()).into())
+    }
+
+    /// Export all users as CSV, without password hashes or lockout state
+    #[post("/export")]
+    async fn export_users(
+        claims: Option<web::ReqData<jwt::Claims>>,
+        app_state: web::Data<AppState>,
+    ) -> actix_web::Result<HttpResponse, crate::errors::Error> {
+        if claims.is_none() {
+            return Err(crate::errors::Error::AuthorizationFail(
+                ErrorCode::Unauthorized,
+            ));
+        }
+
+        let users: Vec<User> = users::find_all_users(&app_state.db_conn)
+            .await?
+            .into_iter()
+            .map(User::from)
+            .collect();
+        Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .body(to_csv(&users)))
+    }
+
+    /// Change the password of the logged-in user, clears `mustChangePassword`
+    #[post("/change-password")]
+    async fn change_password(
+        claims: Option<web::ReqData<jwt::Claims>>,
+        app_state: web::Data<AppState>,
+        req: web::Json<ChangePasswordRequest>,
+    ) -> actix_web::Result<web::Json<WebResponse<User>>, crate::errors::Error> {
+        let db_conn = &app_state.db_conn;
+        let Some(claims) = claims else {
+            return Err(crate::errors::Error::AuthorizationFail(
+                ErrorCode::Unauthorized,
+            ));
+        };
+        req.validate()?;
+        app_state
+            .server_config
+            .jwt
+            .password_policy
+            .check("newPassword", &req.new_password)?;
+
+        let user = match users::find_user_by_id(db_conn, claims.sub).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                return Err(crate::errors::Error::AuthorizationFail(ErrorCode::InvalidUsernameOrPassword));
+            }
+            Err(e) => {
+                tracing::error!(error = ?e);
+                return Err(crate::errors::Error::DbErr(e));
+            }
+        };
+
+        match bcrypt::verify(req.old_password.clone(), &user.password) {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(crate::errors::Error::AuthorizationFail(
+                    ErrorCode::InvalidUsernameOrPassword,
+                ));
+            }
+            Err(e) => {
+                tracing::error!(error = ?e);
+                return Err(crate::errors::Error::InternalError(
+                    ErrorCode::InternalError,
+                ));
+            }
+        }
+
+        let password = match bcrypt::hash(req.new_password.clone(), app_state.server_config.jwt.bcrypt_cost) {
+            Ok(password) => password,
+            Err(e) => {
+                tracing::error!(error = ?e);
+                return Err(crate::errors::Error::InternalError(
+                    ErrorCode::InternalError,
+                ));
+            }
+        };
+
+        let user = users::change_password(db_conn, user.id, password).await?;
+        Ok(WebResponse::with_result(user.into()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{errors::Error, service::web::service::ErrorCode};
+
+    fn login(username: &str, password: &str) -> UserLoginRequest {
+        UserLoginRequest {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    #[test]
+    fn test_login_request_validation() {
+        assert!(login("admin", "admin").validate().is_ok());
+
+        match login(" ", "admin").validate() {
+            Err(Error::BadRequest(code, message)) => {
+                assert!(matches!(code, ErrorCode::ValidationError));
+                assert_eq!(message, "username: must not be empty");
+            }
+            _ => panic!("expected validation error"),
+        }
+
+        let long_password = "x".repeat(MAX_PASSWORD_LEN + 1);
+        match login("admin", &long_password).validate() {
+            Err(Error::BadRequest(_, message)) => assert!(message.starts_with("password:")),
+            _ => panic!("expected validation error"),
+        }
+
+        // bcrypt 按字节截断，40 个汉字为 120 字节
+        let cjk_password = "密".repeat(40);
+        match login("admin", &cjk_password).validate() {
+            Err(Error::BadRequest(_, message)) => {
+                assert_eq!(message, "password: must be at most 72 bytes")
+            }
+            _ => panic!("expected validation error"),
+        }
+        assert!(login("admin", &"密".repeat(24)).validate().is_ok());
+    }
 }
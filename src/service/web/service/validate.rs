@@ -0,0 +1,147 @@
+// Request body validation, run by handlers before touching the database.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::Error, service::web::service::ErrorCode};
+
+/// Validate a request body, failing with `ErrorCode::ValidationError` and a
+/// message naming the offending field.
+pub trait Validate {
+    fn validate(&self) -> Result<(), Error>;
+}
+
+pub fn validation_error(field: &str, message: &str) -> Error {
+    Error::BadRequest(ErrorCode::ValidationError, format!("{}: {}", field, message))
+}
+
+/// Require `value` to be non-blank and at most `max_len` characters.
+pub fn check_length(field: &str, value: &str, max_len: usize) -> Result<(), Error> {
+    if value.trim().is_empty() {
+        return Err(validation_error(field, "must not be empty"));
+    }
+    if value.chars().count() > max_len {
+        return Err(validation_error(
+            field,
+            &format!("must be at most {} characters", max_len),
+        ));
+    }
+    Ok(())
+}
+
+/// Require `value` to be non-blank and at most `max_bytes` bytes of UTF-8, for limits such
+/// as bcrypt's that count bytes rather than characters.
+pub fn check_byte_length(field: &str, value: &str, max_bytes: usize) -> Result<(), Error> {
+    if value.trim().is_empty() {
+        return Err(validation_error(field, "must not be empty"));
+    }
+    if value.len() > max_bytes {
+        return Err(validation_error(
+            field,
+            &format!("must be at most {} bytes", max_bytes),
+        ));
+    }
+    Ok(())
+}
+
+/// Rules for new passwords, `jwt.password_policy` in the config.
+///
+/// Checked when a password is set, not at login, so existing accounts keep working.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PasswordPolicy {
+    #[serde(default = "default_min_length")]
+    pub min_length: usize,
+    #[serde(default)]
+    pub require_lowercase: bool,
+    #[serde(default)]
+    pub require_uppercase: bool,
+    #[serde(default)]
+    pub require_digit: bool,
+    /// Any ASCII punctuation character
+    #[serde(default)]
+    pub require_symbol: bool,
+}
+
+fn default_min_length() -> usize {
+    8
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: default_min_length(),
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// `ErrorCode::ValidationError` naming the first rule `password` breaks
+    pub fn check(&self, field: &str, password: &str) -> Result<(), Error> {
+        if password.chars().count() < self.min_length {
+            return Err(validation_error(
+                field,
+                &format!("must be at least {} characters", self.min_length),
+            ));
+        }
+        let contains = |matches: fn(char) -> bool| password.chars().any(matches);
+        let missing = if self.require_lowercase && !contains(|c| c.is_lowercase()) {
+            Some("a lowercase letter")
+        } else if self.require_uppercase && !contains(|c| c.is_uppercase()) {
+            Some("an uppercase letter")
+        } else if self.require_digit && !contains(|c| c.is_ascii_digit()) {
+            Some("a digit")
+        } else if self.require_symbol && !contains(|c| c.is_ascii_punctuation()) {
+            Some("a symbol")
+        } else {
+            None
+        };
+        match missing {
+            Some(class) => Err(validation_error(field, &format!("must contain {}", class))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(result: Result<(), Error>) -> String {
+        match result {
+            Err(Error::BadRequest(ErrorCode::ValidationError, message)) => message,
+            _ => panic!("expected validation error"),
+        }
+    }
+
+    #[test]
+    fn test_password_policy() {
+        let policy = PasswordPolicy::default();
+        assert!(policy.check("password", "").is_err());
+        assert_eq!(
+            message(policy.check("password", "short")),
+            "password: must be at least 8 characters"
+        );
+        assert!(policy.check("password", "long enough").is_ok());
+
+        let policy = PasswordPolicy {
+            min_length: 4,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+        };
+        let check = |password| message(policy.check("newPassword", password));
+        assert_eq!(check("ABC1!"), "newPassword: must contain a lowercase letter");
+        assert_eq!(check("abc1!"), "newPassword: must contain an uppercase letter");
+        assert_eq!(check("Abcd!"), "newPassword: must contain a digit");
+        assert_eq!(check("Abc12"), "newPassword: must contain a symbol");
+        assert!(policy.check("newPassword", "Abc1!").is_ok());
+
+        let policy: PasswordPolicy = serde_json::from_str(r#"{"require_digit": true}"#).unwrap();
+        assert_eq!(policy.min_length, 8);
+        assert!(policy.require_digit);
+    }
+}
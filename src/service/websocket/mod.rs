@@ -1,27 +1,59 @@
 pub mod protocol;
+pub mod topic;
 
-use std::{sync::Arc, time::Duration};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use crate::config::Sys;
+use crate::{
+    config::Sys,
+    errors::ServerStartError,
+    service::metrics::{Metrics, metrics},
+    utils::{
+        hex_dump::{HexFormat, hex_dump},
+        history::{Direction, History, HistoryEntry},
+        id::{ConnectionId, Peer},
+        recorder::Recorder,
+        retry::ReconnectPolicy,
+        supervise::{SupervisedSlot, TaskFailure, supervise},
+    },
+};
 use bytes::Bytes;
 use dashmap::DashMap;
-use futures::{SinkExt, StreamExt, stream::SplitSink};
+use futures::{SinkExt, StreamExt, future::BoxFuture, stream::SplitSink};
 use serde::{Deserialize, Serialize};
 use tokio::{
     net::{TcpListener, TcpStream},
     select,
-    sync::{broadcast, mpsc},
+    sync::{OwnedSemaphorePermit, Semaphore, broadcast, mpsc, oneshot},
 };
 use tokio_tungstenite::{WebSocketStream, accept_async, tungstenite::Message};
+use tracing::Instrument;
+use uuid::Uuid;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct WebSocketConfig {
     pub host: String,
     pub port: u16,
+    /// Open connections at once, further clients are closed right after the handshake with
+    /// [`CloseReason::LimitExceeded`]. 0 means no limit.
     pub max_connections: u32,
     pub broadcast_channel_capacity: usize,
     #[serde(with = "crate::utils::datetime::string_to_duration")]
     pub heartbeat_interval: Duration,
+    /// Close a connection that sent nothing for this many heartbeat intervals with
+    /// [`CloseReason::HeartbeatTimeout`], 0 (the default) keeps silent connections open
+    #[serde(default)]
+    pub heartbeat_timeout_intervals: u32,
+    /// Keep the last N text/binary messages in memory, 0 disables the history
+    #[serde(default)]
+    pub history_capacity: usize,
+    /// WebSocket handshakes in progress at once. Further connections are not accepted
+    /// and wait in the listen backlog until a handshake completes or times out.
+    #[serde(default = "default_max_pending_handshakes")]
+    pub max_pending_handshakes: usize,
+}
+
+fn default_max_pending_handshakes() -> usize {
+    32
 }
 
 impl Default for WebSocketConfig {
@@ -29,58 +61,218 @@ impl Default for WebSocketConfig {
         WebSocketConfig {
             host: "127.0.0.1".to_string(),
             port: 8081,
-            max_connections: 100,
+            max_connections: 0,
             broadcast_channel_capacity: 128,
             heartbeat_interval: Duration::from_secs(30),
+            heartbeat_timeout_intervals: 0,
+            history_capacity: 0,
+            max_pending_handshakes: default_max_pending_handshakes(),
         }
     }
 }
 
+/// Envelope version written by [`WsMessage::new`].
+///
+/// Versioning policy: the version only changes when the envelope itself changes in a way
+/// older peers would misread (renamed or re-typed `topic`/`payload`, new required fields).
+/// New optional fields and new topics keep the version. A message without `v` is version 1.
+/// The server handles versions up to this one and answers newer ones with an
+/// [`UNSUPPORTED_VERSION_TOPIC`] message instead of forwarding them, older versions are
+/// adapted to the current envelope once there are any.
+pub const WS_PROTOCOL_VERSION: u8 = 1;
+
+/// Topic of the reply to a client message with an unsupported `v`, its payload is
+/// `{"v": <received>, "supported": <WS_PROTOCOL_VERSION>}`
+pub const UNSUPPORTED_VERSION_TOPIC: &str = "__unsupported_version";
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct WsMessage<T> {
     pub topic: String,
     pub payload: T,
+    /// Envelope version, see [`WS_PROTOCOL_VERSION`]. `None` for peers that omit it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v: Option<u8>,
+}
+
+impl<T> WsMessage<T> {
+    pub fn new(topic: impl Into<String>, payload: T) -> Self {
+        WsMessage {
+            topic: topic.into(),
+            payload,
+            v: Some(WS_PROTOCOL_VERSION),
+        }
+    }
+
+    /// Envelope version, a missing `v` counts as version 1
+    pub fn version(&self) -> u8 {
+        self.v.unwrap_or(1)
+    }
+}
+
+/// Reply to a client message whose `v` is not a supported version, `None` when supported
+fn check_version(value: &serde_json::Value) -> Option<Message> {
+    let version = value.get("v")?;
+    let supported = version
+        .as_u64()
+        .is_some_and(|v| (1..=WS_PROTOCOL_VERSION as u64).contains(&v));
+    if supported {
+        return None;
+    }
+    let payload = serde_json::json!({ "v": version, "supported": WS_PROTOCOL_VERSION });
+    Some(WsMessage::new(UNSUPPORTED_VERSION_TOPIC, payload).into())
 }
 
-impl<T> Into<Message> for WsMessage<T>
+impl<T> WsMessage<T>
 where
     T: Serialize,
 {
-    fn into(self) -> Message {
-        match serde_json::to_string(&self) {
-            Ok(json_str) => {
-                Message::Text(json_str.into())
-            }
-            Err(e) => {
-                tracing::error!("Failed to serialize WebSocket message: {}", e);
-                Message::Text(r#"{"topic":"error","payload":"serialization failed"}"#.into())
-            }
-        }
+    /// Encode the message as a JSON text frame.
+    ///
+    /// Fails if `T` cannot be represented as JSON (e.g. a map with non-string keys).
+    pub fn encode(&self) -> Result<Message, serde_json::Error> {
+        serde_json::to_string(self).map(|json_str| Message::Text(json_str.into()))
+    }
+
+    pub fn try_into_message(self) -> Result<Message, serde_json::Error> {
+        self.encode()
     }
 }
 
+/// A `serde_json::Value` payload always serializes, so this conversion is infallible.
+impl From<WsMessage<serde_json::Value>> for Message {
+    fn from(value: WsMessage<serde_json::Value>) -> Self {
+        let json_str = serde_json::to_string(&value).expect("serde_json::Value always serializes");
+        Message::Text(json_str.into())
+    }
+}
+
+/// Why a WebSocket connection was removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The client sent a close frame or ended the stream
+    ClientClosed,
+    ReadError(String),
+    /// Nothing received for `heartbeat_timeout_intervals` heartbeat intervals
+    HeartbeatTimeout,
+    /// The server was dropped or the application dropped the message receiver
+    ServerShutdown,
+    /// Rejected because `max_connections` was reached
+    LimitExceeded,
+}
+
+/// Connection events, each carrying the connection's id and peer address
 #[derive(Debug)]
 pub enum WebSocketMessage {
-    NewConnected(String),
-    Message(String, Message),
+    NewConnected(Peer),
+    Message(Peer, Message),
+    Disconnected(Peer, CloseReason),
+}
+
+/// Time allowed to flush messages still queued for a connection before its close frame
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Time allowed for the WebSocket handshake, so a stalled client cannot keep its slot
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client messages `{"topic": "subscribe", "payload": "sensors/+/temp"}` (or an array of
+/// filters) manage the connection's subscriptions for [`WebSocketServer::publish`].
+/// They are handled by the server and not forwarded to the application.
+pub const SUBSCRIBE_TOPIC: &str = "subscribe";
+pub const UNSUBSCRIBE_TOPIC: &str = "unsubscribe";
+
+/// Per-connection state shared between the connection task and the server
+struct Connection {
+    addr: SocketAddr,
+    sender: mpsc::Sender<Message>,
+    subscriptions: Vec<TopicFilter>,
+    /// `Claims.sub` of the authenticated user, see [`WebSocketServer::bind_user`]
+    user: Option<Uuid>,
+}
+
+/// Connections, retained messages and callbacks shared by the server and the connection tasks
+#[derive(Default)]
+struct Registry {
+    connections: DashMap<ConnectionId, Connection>,
+    /// Last retained message per topic, see [`WebSocketServer::publish_retained`]
+    retained: DashMap<String, Message>,
+    hooks: LifecycleHooks,
+}
+
+type ConnectionMap = Arc<Registry>;
+
+type ConnectHook = Arc<dyn Fn(Peer) -> BoxFuture<'static, ()> + Send + Sync>;
+type DisconnectHook =
+    Arc<dyn Fn(Peer, Option<Uuid>, CloseReason) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Callbacks set with [`WebSocketServer::on_connect`] / [`WebSocketServer::on_disconnect`]
+#[derive(Default)]
+struct LifecycleHooks {
+    on_connect: std::sync::RwLock<Option<ConnectHook>>,
+    on_disconnect: std::sync::RwLock<Option<DisconnectHook>>,
 }
 
 #[derive(Clone)]
 pub struct WebSocketServer {
-    writer_map: Arc<DashMap<String, mpsc::Sender<Message>>>,
+    writer_map: ConnectionMap,
     websocket_config: WebSocketConfig,
     sys_config: Sys,
     broadcast_sender: broadcast::Sender<Message>,
+    capture: Capture,
+    reconnect_policy: ReconnectPolicy,
+    listener: SupervisedSlot,
+}
+
+pub type WsHistory = History<HistoryEntry<String>>;
+
+/// History representation of a message: text as is, binary as truncated hex.
+fn history_data(message: &Message) -> Option<String> {
+    match message {
+        Message::Text(text) => Some(text.to_string()),
+        Message::Binary(data) => Some(hex_dump(data, HexFormat::default().with_max_bytes(64))),
+        _ => None,
+    }
+}
+
+/// Optional in-memory history and file recorder every message goes through.
+#[derive(Clone)]
+struct Capture {
+    history: Option<Arc<WsHistory>>,
+    recorder: Option<Arc<Recorder>>,
+}
+
+impl Capture {
+    fn record(&self, direction: Direction, peer: Option<&str>, message: &Message) {
+        if let Some(history) = &self.history
+            && let Some(data) = history_data(message)
+        {
+            history.push(HistoryEntry::new(direction, peer.map(String::from), data));
+        }
+        if let Some(recorder) = &self.recorder {
+            match message {
+                Message::Text(text) => recorder.record_or_log(direction, peer, text.as_bytes()),
+                Message::Binary(data) => recorder.record_or_log(direction, peer, data),
+                _ => {}
+            }
+        }
+    }
 }
 
 impl WebSocketServer {
-    fn new(websocket_config: WebSocketConfig, sys_config: Sys) -> Self {
+    pub fn new(websocket_config: WebSocketConfig, sys_config: Sys) -> Self {
         let capacity = websocket_config.broadcast_channel_capacity;
+        let history = (websocket_config.history_capacity > 0)
+            .then(|| Arc::new(History::new(websocket_config.history_capacity)));
         WebSocketServer {
-            writer_map: Arc::new(DashMap::new()),
+            writer_map: Arc::new(Registry::default()),
             websocket_config,
             sys_config,
             broadcast_sender: broadcast::channel(capacity).0,
+            capture: Capture {
+                history,
+                recorder: None,
+            },
+            reconnect_policy: ReconnectPolicy::default(),
+            listener: SupervisedSlot::default(),
         }
     }
 
@@ -88,73 +280,372 @@ impl WebSocketServer {
         Arc::new(Self::new(websocket_config, sys_config))
     }
 
-    pub async fn start(&self) -> std::io::Result<mpsc::Receiver<WebSocketMessage>> {
-        let (read_sender, read_recver) = mpsc::channel::<WebSocketMessage>(1024);
+    /// Write every text/binary message to `recorder`, see [`crate::utils::recorder`]
+    pub fn with_recorder(mut self, recorder: Arc<Recorder>) -> Self {
+        self.capture.recorder = Some(recorder);
+        self
+    }
+
+    /// Restarts of the listen loop after it panics or exits, e.g. on a burst of accept
+    /// errors. Defaults to [`ReconnectPolicy::default`].
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    /// Whether the listen loop gave up after the restarts of its reconnect policy
+    pub fn listener_failed(&self) -> bool {
+        self.listener.is_finished()
+    }
+
+    /// Receiver of the [`TaskFailure`] sent when the listen loop gives up, `None` before
+    /// the server is started or once taken.
+    pub fn take_listener_failure(&self) -> Option<oneshot::Receiver<TaskFailure>> {
+        self.listener.take_failure()
+    }
+
+    pub async fn start(&self) -> Result<mpsc::Receiver<WebSocketMessage>, ServerStartError> {
         let addr = format!(
             "{}:{}",
             self.websocket_config.host, self.websocket_config.port
         );
-        let listener = TcpListener::bind(&addr).await?;
-
-        tracing::info!("WebSocket server listening on {}", addr);
-
-        let writer_map = self.writer_map.clone();
-        let websocket_config = self.websocket_config.clone();
-        let sys_config = self.sys_config.clone();
-        let broadcast_sender = self.broadcast_sender.clone();
-        tokio::spawn(async move {
-            start_listening(
-                listener,
-                writer_map,
-                read_sender,
-                websocket_config,
-                sys_config,
-                broadcast_sender,
-            )
-            .await;
+        let listener = TcpListener::bind(&addr)
+            .await
+            .map_err(|e| ServerStartError::new(&addr, e))?;
+        self.start_with_listener(listener)
+            .await
+            .map_err(|e| ServerStartError::new(&addr, e))
+    }
+
+    /// Serve on an already bound listener, e.g. from socket activation or a port-0 bind in tests.
+    pub async fn start_with_listener(
+        &self,
+        listener: TcpListener,
+    ) -> std::io::Result<mpsc::Receiver<WebSocketMessage>> {
+        let (read_sender, read_recver) = mpsc::channel::<WebSocketMessage>(1024);
+
+        tracing::info!("WebSocket server listening on {}", listener.local_addr()?);
+
+        let listener = Arc::new(listener);
+        let handshakes = Arc::new(Semaphore::new(
+            self.websocket_config.max_pending_handshakes,
+        ));
+        let max_connections = self.websocket_config.max_connections as usize;
+        let context = ConnectionContext {
+            slots: (max_connections > 0).then(|| Arc::new(Semaphore::new(max_connections))),
+            writer_map: self.writer_map.clone(),
+            read_sender,
+            websocket_config: self.websocket_config.clone(),
+            sys_config: self.sys_config.clone(),
+            broadcast_sender: self.broadcast_sender.clone(),
+            capture: self.capture.clone(),
+        };
+        // 监听任务异常退出时自动重启
+        let supervised = supervise("websocket-listener", self.reconnect_policy.clone(), move || {
+            start_listening(listener.clone(), handshakes.clone(), context.clone())
         });
+        self.listener.set(supervised);
 
         Ok(read_recver)
     }
 
+    /// Run `callback` with the [`Peer`] of every new connection, before its messages are
+    /// read. Messages sent to its id from the callback, e.g. an initial state, are delivered
+    /// first. Rejected connections (`max_connections`) do not trigger it.
+    ///
+    /// `NewConnected` is still sent on the message channel, after the callback returns.
+    pub fn on_connect<F, Fut>(&self, callback: F)
+    where
+        F: Fn(Peer) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let hook: ConnectHook = Arc::new(move |peer| Box::pin(callback(peer)));
+        *self.writer_map.hooks.on_connect.write().unwrap() = Some(hook);
+    }
+
+    /// Run `callback` once a connection that triggered `on_connect` is closed, with its
+    /// [`Peer`], the user bound with [`bind_user`](Self::bind_user) if any, and the reason.
+    ///
+    /// `Disconnected` is still sent on the message channel, after the callback returns.
+    pub fn on_disconnect<F, Fut>(&self, callback: F)
+    where
+        F: Fn(Peer, Option<Uuid>, CloseReason) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let hook: DisconnectHook =
+            Arc::new(move |peer, user, reason| Box::pin(callback(peer, user, reason)));
+        *self.writer_map.hooks.on_disconnect.write().unwrap() = Some(hook);
+    }
+
+    pub fn connection_count(&self) -> usize {
+        self.writer_map.connections.len()
+    }
+
+    /// Recent messages from oldest to newest, empty when the history is disabled.
+    pub fn recent(&self) -> Vec<HistoryEntry<String>> {
+        self.capture
+            .history
+            .as_ref()
+            .map(|history| history.recent())
+            .unwrap_or_default()
+    }
+
     pub async fn broadcast(&self, message: Message) {
+        self.capture.record(Direction::Out, None, &message);
         let _ = self.broadcast_sender.send(message);
     }
 
-    pub async fn send(&self, id: &str, message: Message) {
-        if let Some(writer) = self.writer_map.get_mut(id) {
-            let _ = writer.send(message).await;
+    pub async fn send(&self, id: ConnectionId, message: Message) {
+        let connection = self
+            .writer_map
+            .connections
+            .get(&id)
+            .map(|connection| (connection.addr, connection.sender.clone()));
+        if let Some((addr, sender)) = connection {
+            self.capture.record(Direction::Out, Some(&addr.to_string()), &message);
+            let _ = sender.send(message).await;
+        }
+    }
+
+    pub async fn broadcast_ws<T: Serialize>(
+        &self,
+        message: &WsMessage<T>,
+    ) -> Result<(), serde_json::Error> {
+        self.broadcast(message.encode()?).await;
+        Ok(())
+    }
+
+    /// Broadcast `payload` under `topic` as a [`WsMessage`] to every connection
+    pub async fn broadcast_json<T: Serialize>(&self, topic: &str, payload: &T) -> crate::Result<()> {
+        self.broadcast_ws(&WsMessage::new(topic, payload)).await?;
+        Ok(())
+    }
+
+    /// Send `message` to every connection subscribed to a filter matching its topic,
+    /// returns the number of receiving connections.
+    pub async fn publish<T: Serialize>(
+        &self,
+        message: &WsMessage<T>,
+    ) -> Result<usize, serde_json::Error> {
+        let encoded = message.encode()?;
+        Ok(self
+            .send_where(encoded, |connection| {
+                connection
+                    .subscriptions
+                    .iter()
+                    .any(|filter| filter.matches(&message.topic))
+            })
+            .await)
+    }
+
+    /// Like [`publish`](Self::publish), also keeping the message as the topic's retained
+    /// message, delivered to every connection that subscribes to a matching filter later.
+    pub async fn publish_retained<T: Serialize>(
+        &self,
+        message: &WsMessage<T>,
+    ) -> Result<usize, serde_json::Error> {
+        self.writer_map
+            .retained
+            .insert(message.topic.clone(), message.encode()?);
+        self.publish(message).await
+    }
+
+    /// Drop the retained message of `topic`
+    pub fn clear_retained(&self, topic: &str) {
+        self.writer_map.retained.remove(topic);
+    }
+
+    /// Associate connection `id` with an authenticated user, usually the `Claims.sub` of a
+    /// token validated by the application. Returns `false` if the connection is gone.
+    pub fn bind_user(&self, id: ConnectionId, user_id: Uuid) -> bool {
+        match self.writer_map.connections.get_mut(&id) {
+            Some(mut connection) => {
+                connection.user = Some(user_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// User bound to connection `id` with [`bind_user`](Self::bind_user)
+    pub fn user_of(&self, id: ConnectionId) -> Option<Uuid> {
+        self.writer_map.connections.get(&id).and_then(|connection| connection.user)
+    }
+
+    /// Ids of every open connection bound to `user_id`
+    pub fn connections_for_user(&self, user_id: Uuid) -> Vec<ConnectionId> {
+        self.writer_map
+            .connections
+            .iter()
+            .filter(|connection| connection.user == Some(user_id))
+            .map(|connection| *connection.key())
+            .collect()
+    }
+
+    /// Queue `message` on every connection without waiting, returning `(id, accepted)`
+    /// for each. `false` means the peer's send buffer was full (or it just closed).
+    ///
+    /// This reports acceptance by the connection's channel, not delivery over the network:
+    /// a message can still be lost if the connection drops before it is written.
+    pub fn broadcast_with_receipts(&self, message: Message) -> Vec<(ConnectionId, bool)> {
+        let receivers: Vec<(Peer, mpsc::Sender<Message>)> = self.senders(|_| true);
+        receivers
+            .into_iter()
+            .map(|(peer, sender)| {
+                let accepted = sender.try_send(message.clone()).is_ok();
+                if accepted {
+                    self.capture.record(Direction::Out, Some(&peer.addr.to_string()), &message);
+                } else {
+                    tracing::warn!("WebSocket {} did not accept a critical broadcast", peer);
+                }
+                (peer.id, accepted)
+            })
+            .collect()
+    }
+
+    /// Send `message` to all connections of `user_id`, returns the number of connections.
+    pub async fn send_to_user(&self, user_id: Uuid, message: Message) -> usize {
+        self.send_where(message, |connection| connection.user == Some(user_id))
+            .await
+    }
+
+    async fn send_where(&self, message: Message, predicate: impl Fn(&Connection) -> bool) -> usize {
+        // 先收集发送端，避免在 await 期间持有 DashMap 的引用
+        let receivers = self.senders(predicate);
+        for (peer, sender) in &receivers {
+            self.capture.record(Direction::Out, Some(&peer.addr.to_string()), &message);
+            let _ = sender.send(message.clone()).await;
         }
+        receivers.len()
+    }
+
+    /// Peers and senders of the connections matching `predicate`
+    fn senders(&self, predicate: impl Fn(&Connection) -> bool) -> Vec<(Peer, mpsc::Sender<Message>)> {
+        self.writer_map
+            .connections
+            .iter()
+            .filter(|connection| predicate(connection.value()))
+            .map(|connection| {
+                let peer = Peer {
+                    id: *connection.key(),
+                    addr: connection.addr,
+                };
+                (peer, connection.sender.clone())
+            })
+            .collect()
+    }
+
+    /// Topic filters the connection `id` is subscribed to
+    pub fn subscriptions(&self, id: ConnectionId) -> Vec<TopicFilter> {
+        self.writer_map
+            .connections
+            .get(&id)
+            .map(|connection| connection.subscriptions.clone())
+            .unwrap_or_default()
+    }
+
+    pub async fn send_ws<T: Serialize>(
+        &self,
+        id: ConnectionId,
+        message: &WsMessage<T>,
+    ) -> Result<(), serde_json::Error> {
+        self.send(id, message.encode()?).await;
+        Ok(())
     }
 }
 
 async fn start_listening(
-    listener: TcpListener,
-    writer_map: Arc<DashMap<String, mpsc::Sender<Message>>>,
-    read_sender: mpsc::Sender<WebSocketMessage>,
-    websocket_config: WebSocketConfig,
-    sys_config: Sys,
-    broadcast_sender: broadcast::Sender<Message>,
+    listener: Arc<TcpListener>,
+    handshakes: Arc<Semaphore>,
+    context: ConnectionContext,
 ) {
-    while let Ok((stream, _)) = listener.accept().await {
-        let writer_map = writer_map.clone();
-        tokio::spawn(handle_connection(
-            stream,
-            writer_map,
-            read_sender.clone(),
-            websocket_config.clone(),
-            sys_config.clone(),
-            broadcast_sender.clone(),
-        ));
+    loop {
+        // 握手并发达到上限时暂停 accept，多余的连接留在内核队列中
+        let Ok(permit) = handshakes.clone().acquire_owned().await else {
+            return;
+        };
+        let Ok((stream, peer_addr)) = listener.accept().await else {
+            return;
+        };
+        let peer = Peer::new(peer_addr);
+        let context = context.clone();
+        tokio::spawn(
+            async move {
+                let Some(ws_stream) = accept_handshake(stream, permit).await else {
+                    return;
+                };
+                handle_connection(ws_stream, peer, context).await
+            }
+            .instrument(tracing::info_span!("conn", peer = %peer_addr, id = %peer.id)),
+        );
+    }
+}
+
+/// Run the WebSocket handshake, releasing `permit` once it completes, fails or times out
+async fn accept_handshake(
+    stream: TcpStream,
+    permit: OwnedSemaphorePermit,
+) -> Option<WebSocketStream<TcpStream>> {
+    let result = tokio::time::timeout(HANDSHAKE_TIMEOUT, accept_async(stream)).await;
+    drop(permit);
+    match result {
+        Ok(Ok(stream)) => Some(stream),
+        Ok(Err(e)) => {
+            tracing::error!("Error accepting WebSocket connection: {}", e);
+            None
+        }
+        Err(_) => {
+            tracing::warn!("WebSocket handshake timed out");
+            None
+        }
     }
 }
 
+/// Apply a subscribe/unsubscribe request whose payload is a filter or an array of filters,
+/// returns the retained messages matching the newly added filters.
+fn update_subscriptions(
+    writer_map: &ConnectionMap,
+    peer: &Peer,
+    subscribe: bool,
+    payload: Option<&serde_json::Value>,
+) -> Vec<Message> {
+    let filters: Vec<&str> = match payload {
+        Some(serde_json::Value::String(filter)) => vec![filter.as_str()],
+        Some(serde_json::Value::Array(filters)) => filters.iter().filter_map(|v| v.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    let Some(mut connection) = writer_map.connections.get_mut(&peer.id) else {
+        return Vec::new();
+    };
+    let mut added = Vec::new();
+    for filter in filters {
+        match TopicFilter::new(filter) {
+            Ok(filter) if subscribe => {
+                if !connection.subscriptions.contains(&filter) {
+                    connection.subscriptions.push(filter.clone());
+                    added.push(filter);
+                }
+            }
+            Ok(filter) => connection.subscriptions.retain(|f| *f != filter),
+            Err(e) => tracing::warn!("Ignoring subscription from {}: {}", peer, e),
+        }
+    }
+    drop(connection);
+
+    writer_map
+        .retained
+        .iter()
+        .filter(|retained| added.iter().any(|filter| filter.matches(retained.key())))
+        .map(|retained| retained.value().clone())
+        .collect()
+}
+
 async fn handle_websocket_message(
     message: &Result<Message, tokio_tungstenite::tungstenite::Error>,
-    writer_map: &Arc<DashMap<String, mpsc::Sender<Message>>>,
+    writer_map: &ConnectionMap,
     writer: &mut SplitSink<WebSocketStream<TcpStream>, Message>,
     read_sender: &mpsc::Sender<WebSocketMessage>,
-    peer_addr: &str,
+    peer: &Peer,
     sys_config: &Sys,
 ) -> bool {
     match message {
@@ -173,7 +664,25 @@ async fn handle_websocket_message(
                     }
                 };
 
+                if let Some(reply) = check_version(&value) {
+                    tracing::warn!("Unsupported message version from {}: {}", peer, msg);
+                    let _ = writer.send(reply).await;
+                    return true;
+                }
+
                 if let Some(topic) = value.get("topic").and_then(|v| v.as_str()) {
+                    if topic == SUBSCRIBE_TOPIC || topic == UNSUBSCRIBE_TOPIC {
+                        let retained = update_subscriptions(
+                            writer_map,
+                            peer,
+                            topic == SUBSCRIBE_TOPIC,
+                            value.get("payload"),
+                        );
+                        for message in retained {
+                            let _ = writer.send(message).await;
+                        }
+                        return true;
+                    }
                     if topic == "syncSysTime" && sys_config.sync_time_from_client {
                         if let Some(payload) = value.get("payload") {
                             match payload {
@@ -244,20 +753,20 @@ async fn handle_websocket_message(
                     }
                 }
                 let _ = read_sender
-                    .send(WebSocketMessage::Message(peer_addr.into(), data.clone()))
+                    .send(WebSocketMessage::Message(*peer, data.clone()))
                     .await;
                 return true;
             }
             _ => {
                 let _ = read_sender
-                    .send(WebSocketMessage::Message(peer_addr.into(), data.clone()))
+                    .send(WebSocketMessage::Message(*peer, data.clone()))
                     .await;
                 return true;
             }
         },
         Err(e) => {
             tracing::error!("WebSocket Error: {}", e);
-            writer_map.remove(peer_addr);
+            // 连接由 handle_connection 移除，断开回调仍能取到绑定的用户
             return false;
         }
     }
@@ -265,74 +774,136 @@ async fn handle_websocket_message(
 
 async fn handle_message(
     message: &Option<Result<Message, tokio_tungstenite::tungstenite::Error>>,
-    writer_map: &Arc<DashMap<String, mpsc::Sender<Message>>>,
+    writer_map: &ConnectionMap,
     writer: &mut SplitSink<WebSocketStream<TcpStream>, Message>,
     read_sender: &mpsc::Sender<WebSocketMessage>,
-    peer_addr: &str,
+    peer: &Peer,
     sys_config: &Sys,
 ) -> bool {
     match message {
         Some(msg) => {
-            handle_websocket_message(&msg, writer_map, writer, read_sender, peer_addr, sys_config)
+            if let Ok(Message::Text(_) | Message::Binary(_)) = msg {
+                Metrics::incr(&metrics().ws_messages_in);
+            }
+            handle_websocket_message(&msg, writer_map, writer, read_sender, peer, sys_config)
                 .await
         }
         None => {
-            writer_map.remove(peer_addr);
             return false;
         }
     }
 }
 
-async fn handle_connection(
-    raw_stream: TcpStream,
-    writer_map: Arc<DashMap<String, mpsc::Sender<Message>>>,
+/// Server state shared by the listener and every connection task
+#[derive(Clone)]
+struct ConnectionContext {
+    /// One permit per open connection, `None` without `max_connections`
+    slots: Option<Arc<Semaphore>>,
+    writer_map: ConnectionMap,
     read_sender: mpsc::Sender<WebSocketMessage>,
     websocket_config: WebSocketConfig,
     sys_config: Sys,
     broadcast_sender: broadcast::Sender<Message>,
+    capture: Capture,
+}
+
+/// Close a connection over `max_connections`
+async fn reject_connection(
+    mut ws_stream: WebSocketStream<TcpStream>,
+    peer: Peer,
+    websocket_config: &WebSocketConfig,
+    read_sender: &mpsc::Sender<WebSocketMessage>,
 ) {
-    let ws_stream = match accept_async(raw_stream).await {
-        Ok(stream) => stream,
-        Err(e) => {
-            tracing::error!("Error accepting WebSocket connection: {}", e);
-            return;
-        }
-    };
+    tracing::warn!(
+        "WebSocket connection limit {} reached, rejecting {}",
+        websocket_config.max_connections,
+        peer
+    );
+    let _ = ws_stream.close(None).await;
+    let _ = read_sender
+        .send(WebSocketMessage::Disconnected(
+            peer,
+            CloseReason::LimitExceeded,
+        ))
+        .await;
+}
 
-    let peer_addr = match ws_stream.get_ref().peer_addr() {
-        Ok(addr) => addr.to_string(),
-        Err(e) => {
-            tracing::error!("Failed to get peer address: {}", e);
+async fn handle_connection(
+    ws_stream: WebSocketStream<TcpStream>,
+    peer: Peer,
+    context: ConnectionContext,
+) {
+    let ConnectionContext {
+        slots,
+        writer_map,
+        read_sender,
+        websocket_config,
+        sys_config,
+        broadcast_sender,
+        capture,
+    } = context;
+    let peer_addr = peer.addr.to_string();
+
+    // 原子地占用名额，许可在连接关闭前一直持有
+    let _slot = match slots.map(Semaphore::try_acquire_owned) {
+        Some(Ok(permit)) => Some(permit),
+        None => None,
+        Some(Err(_)) => {
+            reject_connection(ws_stream, peer, &websocket_config, &read_sender).await;
             return;
         }
     };
 
-    tracing::info!("New WebSocket connection: {}", peer_addr);
+    tracing::info!("New WebSocket connection: {}", peer);
 
     let (writer_send, mut writer_recv) = mpsc::channel::<Message>(100);
     {
-        writer_map.insert(peer_addr.clone(), writer_send);
+        writer_map.connections.insert(
+            peer.id,
+            Connection {
+                addr: peer.addr,
+                sender: writer_send,
+                subscriptions: Vec::new(),
+                user: None,
+            },
+        );
     }
 
     let (mut writer, mut reader) = ws_stream.split();
 
+    // 先订阅广播，应用收到 NewConnected 后立即广播的消息不会丢
+    let mut broadcast_receiver = broadcast_sender.subscribe();
+    drop(broadcast_sender);
+
+    let on_connect = writer_map.hooks.on_connect.read().unwrap().clone();
+    if let Some(on_connect) = on_connect {
+        on_connect(peer).await;
+    }
     let _ = read_sender
-        .send(WebSocketMessage::NewConnected(peer_addr.clone()))
+        .send(WebSocketMessage::NewConnected(peer))
         .await;
-
-    let mut broadcast_receiver = broadcast_sender.subscribe();
-    loop {
+    let mut last_seen = tokio::time::Instant::now();
+    let reason = loop {
         select! {
             message = reader.next() => {
-                if !handle_message(&message, &writer_map, &mut writer, &read_sender, &peer_addr, &sys_config).await {
-                    break;
+                if let Some(Ok(msg)) = &message {
+                    last_seen = tokio::time::Instant::now();
+                    capture.record(Direction::In, Some(&peer_addr), msg);
+                }
+                if !handle_message(&message, &writer_map, &mut writer, &read_sender, &peer, &sys_config).await {
+                    break match &message {
+                        Some(Err(e)) => CloseReason::ReadError(e.to_string()),
+                        _ => CloseReason::ClientClosed,
+                    };
                 }
             },
 
             send_msg = writer_recv.recv() => {
                 match send_msg {
                     Some(msg) => {
-                        let _ = writer.send(msg).await;
+                        if writer.send(msg).await.is_ok() {
+                            Metrics::incr(&metrics().ws_messages_out);
+                        }
                     },
                     None => {},
                 }
@@ -341,7 +912,12 @@ async fn handle_connection(
             broadcast_msg = broadcast_receiver.recv() => {
                 match broadcast_msg {
                     Ok(msg) => {
-                        let _ = writer.send(msg).await;
+                        if writer.send(msg).await.is_ok() {
+                            Metrics::incr(&metrics().ws_messages_out);
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        break CloseReason::ServerShutdown;
                     }
                     Err(e) => {
                         tracing::error!("Error receiving broadcast message: {}", e);
@@ -349,17 +925,470 @@ async fn handle_connection(
                 }
             }
 
+            _ = read_sender.closed() => {
+                break CloseReason::ServerShutdown;
+            }
+
             _ = tokio::time::sleep(websocket_config.heartbeat_interval) => {
+                let intervals = websocket_config.heartbeat_timeout_intervals;
+                if intervals > 0
+                    && last_seen.elapsed() >= websocket_config.heartbeat_interval * intervals
+                {
+                    break CloseReason::HeartbeatTimeout;
+                }
                 let _ = writer.send(Message::Ping(bytes::Bytes::new())).await;
             }
         }
+    };
+
+    tracing::info!("WebSocket connection {} closed: {:?}", peer, reason);
+    let user = writer_map
+        .connections
+        .remove(&peer.id)
+        .and_then(|(_, connection)| connection.user);
+    if !matches!(reason, CloseReason::ClientClosed | CloseReason::ReadError(_)) {
+        // 尽力发出已排队的消息，再发送关闭帧
+        let drain = async {
+            while let Ok(msg) = writer_recv.try_recv() {
+                if writer.send(msg).await.is_err() {
+                    break;
+                }
+                Metrics::incr(&metrics().ws_messages_out);
+            }
+        };
+        if tokio::time::timeout(DRAIN_TIMEOUT, drain).await.is_err() {
+            tracing::warn!("Dropped queued messages of {} on close", peer);
+        }
+        let _ = writer.close().await;
+    }
+    let on_disconnect = writer_map.hooks.on_disconnect.read().unwrap().clone();
+    if let Some(on_disconnect) = on_disconnect {
+        on_disconnect(peer, user, reason.clone()).await;
     }
+    let _ = read_sender
+        .send(WebSocketMessage::Disconnected(peer, reason))
+        .await;
 }
 
 pub type ArcWebSocketServer = Arc<WebSocketServer>;
 
+pub use topic::{TopicFilter, TopicFilterBuilder, TopicFilterError};
+
 // Re-export protocol items for convenience
 pub use protocol::{
     build_binary_payload, parse_binary_message, PROTOCOL_VERSION, WsBinaryHeader,
     WS_BINARY_MAGIC, MSG_TYPE_CAMERA_FRAME, MSG_TYPE_INSPECTION_RESULT,
-};
\ No newline at end of file
+};
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::net::TcpSocket;
+    use tokio_tungstenite::client_async;
+
+    use super::*;
+    use crate::testkit::{spawn_ws_server, spawn_ws_server_with, tcp_client, ws_client, ws_connect};
+
+    #[test]
+    fn test_encode_ws_message() {
+        let message = WsMessage::new("status", serde_json::json!({"ok": true}));
+        match message.encode().expect("Should encode") {
+            Message::Text(text) => {
+                assert_eq!(text.as_str(), r#"{"topic":"status","payload":{"ok":true},"v":1}"#)
+            }
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_version() {
+        let (_server, addr, mut receiver) = spawn_ws_server().await;
+        let (mut client, _) = ws_connect(addr, &mut receiver).await;
+
+        let future = r#"{"topic":"cmd","payload":1,"v":9}"#;
+        client.send(Message::text(future)).await.unwrap();
+        let reply = client.next().await.unwrap().unwrap();
+        let reply: WsMessage<serde_json::Value> =
+            serde_json::from_str(reply.to_text().unwrap()).unwrap();
+        assert_eq!(reply.topic, UNSUPPORTED_VERSION_TOPIC);
+        assert_eq!(reply.payload, serde_json::json!({"v": 9, "supported": 1}));
+
+        // 当前版本和未带版本号的消息照常转发给应用
+        for text in [
+            r#"{"topic":"cmd","payload":2,"v":1}"#,
+            r#"{"topic":"cmd","payload":3}"#,
+        ] {
+            client.send(Message::text(text)).await.unwrap();
+            match receiver.recv().await {
+                Some(WebSocketMessage::Message(_, message)) => {
+                    assert_eq!(message.to_text().unwrap(), text)
+                }
+                other => panic!("unexpected event: {other:?}"),
+            }
+        }
+        let legacy: WsMessage<u8> =
+            serde_json::from_str(r#"{"topic":"cmd","payload":3}"#).unwrap();
+        assert_eq!(legacy.version(), 1);
+    }
+
+    #[test]
+    fn test_encode_non_string_keys_fails() {
+        let mut payload = HashMap::new();
+        payload.insert((1, 2), "value");
+        let message = WsMessage::new("bad", payload);
+        assert!(message.encode().is_err());
+        assert!(message.try_into_message().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_reasons() {
+        let config = WebSocketConfig {
+            max_connections: 1,
+            ..Default::default()
+        };
+        let (server, addr, mut receiver) = spawn_ws_server_with(config).await;
+
+        let (mut first, _) = ws_connect(addr, &mut receiver).await;
+
+        let _second = ws_client(addr).await;
+        assert!(matches!(
+            receiver.recv().await,
+            Some(WebSocketMessage::Disconnected(_, CloseReason::LimitExceeded))
+        ));
+
+        first.close(None).await.unwrap();
+        loop {
+            match receiver.recv().await {
+                Some(WebSocketMessage::Disconnected(_, reason)) => {
+                    assert_eq!(reason, CloseReason::ClientClosed);
+                    break;
+                }
+                Some(_) => continue,
+                None => panic!("receiver closed"),
+            }
+        }
+        assert_eq!(server.connection_count(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_max_connections_concurrent() {
+        let config = WebSocketConfig {
+            max_connections: 2,
+            ..Default::default()
+        };
+        let (server, addr, mut receiver) = spawn_ws_server_with(config).await;
+
+        // 同时握手的连接不能都通过上限检查
+        let clients = futures::future::join_all((0..6).map(|_| ws_client(addr))).await;
+        let (mut connected, mut rejected) = (0, 0);
+        while connected + rejected < clients.len() {
+            match receiver.recv().await {
+                Some(WebSocketMessage::NewConnected(_)) => connected += 1,
+                Some(WebSocketMessage::Disconnected(_, CloseReason::LimitExceeded)) => {
+                    rejected += 1
+                }
+                other => panic!("unexpected message: {:?}", other),
+            }
+        }
+        assert_eq!((connected, rejected), (2, 4));
+        assert_eq!(server.connection_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_timeout() {
+        let config = WebSocketConfig {
+            heartbeat_interval: Duration::from_millis(50),
+            heartbeat_timeout_intervals: 2,
+            ..Default::default()
+        };
+        let (_server, addr, mut receiver) = spawn_ws_server_with(config).await;
+
+        // 客户端不读不写，也不会应答 Ping
+        let (_silent, peer) = ws_connect(addr, &mut receiver).await;
+        let message = tokio::time::timeout(Duration::from_secs(2), receiver.recv()).await;
+        match message {
+            Ok(Some(WebSocketMessage::Disconnected(closed, reason))) => {
+                assert_eq!(closed.id, peer.id);
+                assert_eq!(reason, CloseReason::HeartbeatTimeout);
+            }
+            other => panic!("expected heartbeat timeout, got {:?}", other),
+        }
+
+        // 默认不断开静默连接
+        let config = WebSocketConfig {
+            heartbeat_interval: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let (server, addr, mut receiver) = spawn_ws_server_with(config).await;
+        let (_silent, _) = ws_connect(addr, &mut receiver).await;
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(server.connection_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_pending_handshakes_bounded() {
+        let config = WebSocketConfig {
+            max_pending_handshakes: 2,
+            ..Default::default()
+        };
+        let (_server, addr, mut receiver) = spawn_ws_server_with(config).await;
+
+        // 只建立 TCP 连接、不发送握手请求的客户端占满名额
+        let mut stalled = Vec::new();
+        for _ in 0..4 {
+            stalled.push(tcp_client(addr).await);
+        }
+        let pending = tokio::spawn(ws_client(addr));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!pending.is_finished());
+
+        // 停滞的连接关闭后，排队的连接才被接受并完成握手
+        drop(stalled);
+        let _client = tokio::time::timeout(Duration::from_secs(5), pending)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            receiver.recv().await,
+            Some(WebSocketMessage::NewConnected(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_from_same_address() {
+        let (server, addr, mut receiver) = spawn_ws_server().await;
+
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.set_reuseaddr(true).unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let local = socket.local_addr().unwrap();
+        let stream = socket.connect(addr).await.unwrap();
+        // 以 RST 关闭，客户端端口不进入 TIME_WAIT，可以立即复用
+        stream.set_zero_linger().unwrap();
+        let (first, _) = client_async(format!("ws://{addr}"), stream).await.unwrap();
+        let first_peer = match receiver.recv().await {
+            Some(WebSocketMessage::NewConnected(peer)) => peer,
+            other => panic!("unexpected event: {other:?}"),
+        };
+        assert_eq!(first_peer.addr, local);
+        drop(first);
+
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.set_reuseaddr(true).unwrap();
+        socket.bind(local).unwrap();
+        let stream = socket.connect(addr).await.unwrap();
+        let (mut second, _) = client_async(format!("ws://{addr}"), stream).await.unwrap();
+
+        // 旧连接的断开和新连接的建立顺序不定
+        let mut second_peer = None;
+        let mut first_closed = false;
+        while second_peer.is_none() || !first_closed {
+            match receiver.recv().await {
+                Some(WebSocketMessage::NewConnected(peer)) => second_peer = Some(peer),
+                Some(WebSocketMessage::Disconnected(peer, _)) => {
+                    assert_eq!(peer, first_peer);
+                    first_closed = true;
+                }
+                other => panic!("unexpected event: {other:?}"),
+            }
+        }
+        let second_peer = second_peer.unwrap();
+        assert_eq!(second_peer.addr, first_peer.addr);
+        assert_ne!(second_peer.id, first_peer.id);
+
+        // 旧连接的清理不会移除新连接
+        assert_eq!(server.connection_count(), 1);
+        let message = Message::text("still here");
+        server.send(second_peer.id, message.clone()).await;
+        assert_eq!(second.next().await.unwrap().unwrap(), message);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_json() {
+        let (server, addr, mut receiver) = spawn_ws_server().await;
+        let (mut client, _) = ws_connect(addr, &mut receiver).await;
+
+        let payload = serde_json::json!({ "running": true, "speed": 120 });
+        server.broadcast_json("status", &payload).await.unwrap();
+        let received = client.next().await.unwrap().unwrap();
+        let decoded: WsMessage<serde_json::Value> =
+            serde_json::from_str(received.to_text().unwrap()).unwrap();
+        assert_eq!(decoded.topic, "status");
+        assert_eq!(decoded.payload, payload);
+
+        let invalid = HashMap::from([((1, 2), "non-string key")]);
+        assert!(matches!(
+            server.broadcast_json("status", &invalid).await,
+            Err(crate::errors::Error::Json(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_with_receipts() {
+        let (server, addr, mut receiver) = spawn_ws_server().await;
+        let (mut client, peer) = ws_connect(addr, &mut receiver).await;
+
+        // 缓冲区已满的连接
+        let (sender, _saturated) = mpsc::channel(1);
+        sender.try_send(Message::text("pending")).unwrap();
+        let saturated = ConnectionId::generate();
+        server.writer_map.connections.insert(
+            saturated,
+            Connection {
+                addr: peer.addr,
+                sender,
+                subscriptions: Vec::new(),
+                user: None,
+            },
+        );
+
+        let message = Message::text("stop");
+        let mut receipts = server.broadcast_with_receipts(message.clone());
+        receipts.sort();
+        let mut expected = vec![(peer.id, true), (saturated, false)];
+        expected.sort();
+        assert_eq!(receipts, expected);
+        assert_eq!(client.next().await.unwrap().unwrap(), message);
+    }
+
+    #[tokio::test]
+    async fn test_queued_messages_flushed_on_shutdown() {
+        let (server, addr, mut receiver) = spawn_ws_server().await;
+        let (mut client, peer) = ws_connect(addr, &mut receiver).await;
+
+        let message = Message::text("last words");
+        server.send(peer.id, message.clone()).await;
+        drop(receiver);
+
+        assert_eq!(client.next().await.unwrap().unwrap(), message);
+        assert!(matches!(client.next().await, Some(Ok(Message::Close(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_publish_to_subscribers() {
+        let (server, addr, mut receiver) = spawn_ws_server().await;
+        let (mut client, peer) = ws_connect(addr, &mut receiver).await;
+
+        let subscribe = WsMessage::new(SUBSCRIBE_TOPIC, ["sensors/+/temp", "alarms/#", "bad/#/x"]);
+        client.send(subscribe.encode().unwrap()).await.unwrap();
+        while server.subscriptions(peer.id).len() < 2 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(server.subscriptions(peer.id).len(), 2);
+
+        let skipped = WsMessage::new("sensors/1/humidity", 40);
+        assert_eq!(server.publish(&skipped).await.unwrap(), 0);
+        let delivered = WsMessage::new("sensors/1/temp", 21);
+        assert_eq!(server.publish(&delivered).await.unwrap(), 1);
+
+        let received = client.next().await.unwrap().unwrap();
+        assert_eq!(received, delivered.encode().unwrap());
+
+        let unsubscribe = WsMessage::new(UNSUBSCRIBE_TOPIC, "alarms/#");
+        client.send(unsubscribe.encode().unwrap()).await.unwrap();
+        while server.subscriptions(peer.id).len() > 1 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(
+            server.publish(&WsMessage::new("alarms/fire", true)).await.unwrap(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_callbacks() {
+        let (server, addr, mut receiver) = spawn_ws_server().await;
+        let (event_sender, mut events) = mpsc::unbounded_channel();
+
+        let connected = event_sender.clone();
+        let welcome = server.clone();
+        server.on_connect(move |peer| {
+            let connected = connected.clone();
+            let welcome = welcome.clone();
+            async move {
+                welcome.send(peer.id, Message::text("welcome")).await;
+                let _ = connected.send((peer, None, None));
+            }
+        });
+        server.on_disconnect(move |peer, user, reason| {
+            let _ = event_sender.send((peer, user, Some(reason)));
+            async {}
+        });
+
+        let (mut client, peer) = ws_connect(addr, &mut receiver).await;
+        assert_eq!(events.recv().await, Some((peer, None, None)));
+        assert_eq!(client.next().await.unwrap().unwrap(), Message::text("welcome"));
+
+        let user = Uuid::new_v4();
+        assert!(server.bind_user(peer.id, user));
+        client.close(None).await.unwrap();
+        assert_eq!(
+            events.recv().await,
+            Some((peer, Some(user), Some(CloseReason::ClientClosed)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_to_user() {
+        let (server, addr, mut receiver) = spawn_ws_server().await;
+
+        let mut clients = Vec::new();
+        let mut peers = Vec::new();
+        for _ in 0..3 {
+            let (client, peer) = ws_connect(addr, &mut receiver).await;
+            clients.push(client);
+            peers.push(peer);
+        }
+
+        let user = Uuid::new_v4();
+        assert!(server.bind_user(peers[0].id, user));
+        assert!(server.bind_user(peers[2].id, user));
+        assert!(!server.bind_user(ConnectionId::generate(), user));
+        assert_eq!(server.user_of(peers[0].id), Some(user));
+        assert_eq!(server.user_of(peers[1].id), None);
+        let mut connections = server.connections_for_user(user);
+        connections.sort();
+        let mut expected = vec![peers[0].id, peers[2].id];
+        expected.sort();
+        assert_eq!(connections, expected);
+
+        let message = Message::text("hello");
+        assert_eq!(server.send_to_user(user, message.clone()).await, 2);
+        assert_eq!(clients[0].next().await.unwrap().unwrap(), message);
+        assert_eq!(clients[2].next().await.unwrap().unwrap(), message);
+        assert_eq!(server.send_to_user(Uuid::new_v4(), message).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_retained_delivered_on_subscribe() {
+        let (server, addr, _receiver) = spawn_ws_server().await;
+
+        let retained = WsMessage::new("sensors/1/temp", 21);
+        assert_eq!(server.publish_retained(&retained).await.unwrap(), 0);
+        server
+            .publish(&WsMessage::new("sensors/2/temp", 22))
+            .await
+            .unwrap();
+
+        let mut client = ws_client(addr).await;
+        let subscribe = WsMessage::new(SUBSCRIBE_TOPIC, "sensors/+/temp");
+        client.send(subscribe.encode().unwrap()).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(5), client.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, retained.encode().unwrap());
+
+        // 清除后新的订阅不再收到保留消息
+        server.clear_retained("sensors/1/temp");
+        let mut late = ws_client(addr).await;
+        late.send(subscribe.encode().unwrap()).await.unwrap();
+        let live = WsMessage::new("sensors/3/temp", 23);
+        while server.publish(&live).await.unwrap() < 2 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(late.next().await.unwrap().unwrap(), live.encode().unwrap());
+    }
+}
@@ -0,0 +1,220 @@
+// MQTT style topic filters for WebSocket subscriptions.
+
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TopicFilterError {
+    #[error("topic filter is empty")]
+    Empty,
+    #[error("'#' must be the last level of a topic filter: {0}")]
+    MultiLevelNotLast(String),
+    #[error("wildcards must occupy a whole level: {0}")]
+    PartialWildcard(String),
+}
+
+/// A subscription filter such as `sensors/+/temp` or `sensors/#`.
+///
+/// `+` matches exactly one level (which may be empty), `#` matches the parent level and
+/// any number of levels below it. As in MQTT, wildcards at the first level do not match
+/// topics starting with `$`. Filters without wildcards are compared as plain strings.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TopicFilter {
+    filter: String,
+    wildcard: bool,
+}
+
+impl TopicFilter {
+    pub fn new(filter: impl Into<String>) -> Result<Self, TopicFilterError> {
+        let filter = filter.into();
+        if filter.is_empty() {
+            return Err(TopicFilterError::Empty);
+        }
+
+        let levels: Vec<&str> = filter.split('/').collect();
+        for (index, level) in levels.iter().enumerate() {
+            if level.contains(['+', '#']) && level.len() > 1 {
+                return Err(TopicFilterError::PartialWildcard(filter));
+            }
+            if *level == "#" && index != levels.len() - 1 {
+                return Err(TopicFilterError::MultiLevelNotLast(filter));
+            }
+        }
+
+        let wildcard = filter.contains(['+', '#']);
+        Ok(Self { filter, wildcard })
+    }
+
+    pub fn builder() -> TopicFilterBuilder {
+        TopicFilterBuilder::default()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.filter
+    }
+
+    pub fn is_wildcard(&self) -> bool {
+        self.wildcard
+    }
+
+    pub fn matches(&self, topic: &str) -> bool {
+        if !self.wildcard {
+            return self.filter == topic;
+        }
+        if topic.starts_with('$') && !self.filter.starts_with('$') {
+            return false;
+        }
+
+        let mut topic_levels = topic.split('/');
+        for filter_level in self.filter.split('/') {
+            match (filter_level, topic_levels.next()) {
+                ("#", _) => return true,
+                (_, None) => return false,
+                ("+", Some(_)) => {}
+                (filter_level, Some(topic_level)) if filter_level == topic_level => {}
+                _ => return false,
+            }
+        }
+        topic_levels.next().is_none()
+    }
+}
+
+impl Display for TopicFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.filter)
+    }
+}
+
+impl TryFrom<&str> for TopicFilter {
+    type Error = TopicFilterError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+/// Builds a [`TopicFilter`] level by level, e.g.
+/// `TopicFilter::builder().with_level("sensors").with_any_level().with_level("temp").build()`.
+#[derive(Debug, Clone, Default)]
+pub struct TopicFilterBuilder {
+    levels: Vec<String>,
+    /// First literal level containing a separator or wildcard
+    invalid: Option<String>,
+}
+
+impl TopicFilterBuilder {
+    /// Literal level; `/`, `+` and `#` are not allowed inside it
+    pub fn with_level(mut self, level: impl Into<String>) -> Self {
+        let level = level.into();
+        if self.invalid.is_none() && level.contains(['/', '+', '#']) {
+            self.invalid = Some(level.clone());
+        }
+        self.levels.push(level);
+        self
+    }
+
+    /// Single-level wildcard `+`
+    pub fn with_any_level(mut self) -> Self {
+        self.levels.push("+".into());
+        self
+    }
+
+    /// Multi-level wildcard `#`, always the last level
+    pub fn with_all_below(mut self) -> Result<TopicFilter, TopicFilterError> {
+        self.levels.push("#".into());
+        self.build()
+    }
+
+    pub fn build(self) -> Result<TopicFilter, TopicFilterError> {
+        if let Some(level) = self.invalid {
+            return Err(TopicFilterError::PartialWildcard(level));
+        }
+        TopicFilter::new(self.levels.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_filter_matrix() {
+        let cases = [
+            // 精确匹配
+            ("sensors/1/temp", "sensors/1/temp", true),
+            ("sensors/1/temp", "sensors/2/temp", false),
+            ("sensors/1/temp", "sensors/1/temp/", false),
+            // 单层通配
+            ("sensors/+/temp", "sensors/1/temp", true),
+            ("sensors/+/temp", "sensors//temp", true),
+            ("sensors/+/temp", "sensors/1/2/temp", false),
+            ("sensors/+/temp", "sensors/1", false),
+            ("sensors/+", "sensors/", true),
+            ("sensors/+", "sensors", false),
+            ("+", "sensors", true),
+            ("+", "", true),
+            ("+", "a/b", false),
+            ("+/+", "/x", true),
+            ("/+", "/x", true),
+            ("/+", "x", false),
+            // 多层通配
+            ("sensors/#", "sensors", true),
+            ("sensors/#", "sensors/", true),
+            ("sensors/#", "sensors/1/temp", true),
+            ("sensors/#", "sensor", false),
+            ("sensors/#", "other/1", false),
+            ("sensors/+/#", "sensors/1", true),
+            ("sensors/+/#", "sensors", false),
+            ("#", "a/b/c", true),
+            ("#", "", true),
+            // 以 $ 开头的系统主题不被首层通配匹配
+            ("#", "$SYS/uptime", false),
+            ("+/uptime", "$SYS/uptime", false),
+            ("$SYS/#", "$SYS/uptime", true),
+        ];
+        for (filter, topic, expected) in cases {
+            let parsed = TopicFilter::new(filter).unwrap();
+            assert_eq!(
+                parsed.matches(topic),
+                expected,
+                "filter {:?} topic {:?}",
+                filter,
+                topic
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_filters() {
+        assert_eq!(TopicFilter::new(""), Err(TopicFilterError::Empty));
+        assert!(matches!(
+            TopicFilter::new("sensors/#/temp"),
+            Err(TopicFilterError::MultiLevelNotLast(_))
+        ));
+        for filter in ["sensors#", "sensors/te+mp", "++", "#/"] {
+            assert!(TopicFilter::new(filter).is_err(), "{}", filter);
+        }
+        assert!(!TopicFilter::new("sensors/1").unwrap().is_wildcard());
+    }
+
+    #[test]
+    fn test_builder() {
+        let filter = TopicFilter::builder()
+            .with_level("sensors")
+            .with_any_level()
+            .with_level("temp")
+            .build()
+            .unwrap();
+        assert_eq!(filter.as_str(), "sensors/+/temp");
+        assert!(filter.matches("sensors/7/temp"));
+
+        let filter = TopicFilter::builder()
+            .with_level("sensors")
+            .with_all_below()
+            .unwrap();
+        assert_eq!(filter.to_string(), "sensors/#");
+
+        assert!(TopicFilter::builder().with_level("a/b").build().is_err());
+        assert!(TopicFilter::builder().with_level("a+").build().is_err());
+        assert!(TopicFilter::builder().with_level("+").build().is_err());
+    }
+}
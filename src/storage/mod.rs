@@ -1,5 +1,35 @@
+pub mod timeseries;
+pub mod validation;
+
 use std::path::{Path, PathBuf};
 
+pub use timeseries::{Aggregation, TimeSeries};
+pub use validation::SeriesRules;
+
+#[derive(thiserror::Error, Debug)]
+pub enum StorageError {
+    #[error("TSink Error: {0}")]
+    Tsink(#[from] tsink::TsinkError),
+    #[error("Metric name is empty")]
+    EmptyMetric,
+    /// A metric or label name has a character outside [`SeriesRules::name_chars`]
+    #[error("Invalid character in name '{0}'")]
+    InvalidName(String),
+    #[error("Label with an empty name or value on '{metric}'")]
+    EmptyLabel { metric: String },
+    #[error("'{metric}' has {count} labels, at most {max} allowed")]
+    TooManyLabels {
+        metric: String,
+        count: usize,
+        max: usize,
+    },
+    #[error("Label '{name}' has a name or value longer than {max} bytes")]
+    LabelTooLong { name: String, max: usize },
+    /// Aggregation bucket shorter than one timestamp unit
+    #[error("Invalid aggregation bucket {0:?}")]
+    InvalidBucket(std::time::Duration),
+}
+
 fn get_td_path(app_name: &str) -> Option<PathBuf> {
     // Differentiate operating systems
     if cfg!(target_os = "linux") {
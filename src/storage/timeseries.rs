@@ -0,0 +1,346 @@
+// Time-series store over tsink that can be flushed to disk on demand or on an interval.
+//
+// tsink keeps recent points in memory partitions and buffers its write-ahead log, so points
+// written since the last flush can be lost on a power cut. A flush closes the store, which
+// writes every partition to disk and syncs the log, then opens it again. Each flush writes
+// new partition files: a short interval bounds the data lost on power loss but costs IO and
+// flash wear, a long one the opposite. On eMMC/SD cards an interval of minutes is a sensible
+// start, flush explicitly after writes that must not be lost.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tsink::{DataPoint, Label, Row, Storage, StorageBuilder, TimestampPrecision, TsinkError};
+
+use super::{SeriesRules, StorageError};
+
+/// Settings applied to the builder on every open, see [`TimeSeries::open_with`]
+type Configure = Box<dyn Fn(StorageBuilder) -> StorageBuilder + Send + Sync>;
+
+/// How [`TimeSeries::aggregate`] reduces the points of a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Aggregation {
+    Avg,
+    Min,
+    Max,
+    Sum,
+    /// Number of points
+    Count,
+    /// Value of the newest point
+    Last,
+}
+
+/// Persistent time-series store with an explicit [`flush`](Self::flush).
+pub struct TimeSeries {
+    data_path: PathBuf,
+    configure: Configure,
+    storage: RwLock<Arc<dyn Storage>>,
+    rules: SeriesRules,
+    precision: TimestampPrecision,
+}
+
+impl TimeSeries {
+    /// Open (or create) the store in `data_path`
+    pub fn open(data_path: impl Into<PathBuf>) -> Result<Self, StorageError> {
+        Self::open_with(data_path, |builder| builder)
+    }
+
+    /// Like [`open`](Self::open), `configure` sets retention, precision, ... on the builder.
+    /// It is applied again each time a flush reopens the store.
+    pub fn open_with<F>(
+        data_path: impl Into<PathBuf>,
+        configure: F,
+    ) -> Result<Self, StorageError>
+    where
+        F: Fn(StorageBuilder) -> StorageBuilder + Send + Sync + 'static,
+    {
+        let data_path = data_path.into();
+        let configure: Configure = Box::new(configure);
+        let storage = build(&data_path, &configure)?;
+        Ok(Self {
+            data_path,
+            configure,
+            storage: RwLock::new(storage),
+            rules: SeriesRules::default(),
+            precision: TimestampPrecision::Nanoseconds,
+        })
+    }
+
+    /// Check inserted rows against `rules` instead of [`SeriesRules::default`]
+    pub fn with_rules(mut self, rules: SeriesRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Unit of the stored timestamps, nanoseconds by default. Set it to the precision given
+    /// to the builder in [`open_with`](Self::open_with), [`aggregate`](Self::aggregate)
+    /// converts its bucket width with it.
+    pub fn with_timestamp_precision(mut self, precision: TimestampPrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Store in the application's data directory, the one of [`super::persistent_storage`]
+    pub fn persistent(app_name: &str) -> Result<Self, StorageError> {
+        let data_path = super::get_td_path(app_name).ok_or_else(|| {
+            TsinkError::InvalidConfiguration("no data directory for the time-series store".into())
+        })?;
+        Self::open(data_path)
+    }
+
+    pub fn data_path(&self) -> &Path {
+        &self.data_path
+    }
+
+    /// Insert `rows` if all of them pass the [`SeriesRules`], otherwise none is written
+    pub fn insert(&self, rows: &[Row]) -> Result<(), StorageError> {
+        for row in rows {
+            self.rules.validate(row)?;
+        }
+        self.insert_unchecked(rows)
+    }
+
+    /// Like [`insert`](Self::insert), normalizing rows with [`SeriesRules::sanitize`]
+    /// instead of rejecting them. Fails only for a row without a usable metric name.
+    pub fn insert_sanitized(&self, rows: &[Row]) -> Result<(), StorageError> {
+        let rows = rows
+            .iter()
+            .map(|row| self.rules.sanitize(row))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.insert_unchecked(&rows)
+    }
+
+    fn insert_unchecked(&self, rows: &[Row]) -> Result<(), StorageError> {
+        Ok(self.storage.read().unwrap().insert_rows(rows)?)
+    }
+
+    /// Points of the series `metric` + `labels` in `[start, end)`, oldest first
+    pub fn select(
+        &self,
+        metric: &str,
+        labels: &[Label],
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<DataPoint>, StorageError> {
+        Ok(self.storage.read().unwrap().select(metric, labels, start, end)?)
+    }
+
+    /// Downsample the series `metric` + `labels` in `[start, end)` into `bucket` wide buckets,
+    /// returned as `(bucket_start, value)` oldest first. Buckets are aligned to multiples of
+    /// `bucket` since the epoch, so they stay put when the range moves. Empty buckets are left
+    /// out.
+    pub fn aggregate(
+        &self,
+        metric: &str,
+        labels: &[Label],
+        start: i64,
+        end: i64,
+        bucket: Duration,
+        agg: Aggregation,
+    ) -> Result<Vec<(i64, f64)>, StorageError> {
+        let width = bucket_width(bucket, self.precision)?;
+        let points = self.select(metric, labels, start, end)?;
+        let mut buckets = Vec::new();
+        // 点已按时间排序，同一桶的点相邻
+        for chunk in points.chunk_by(|a, b| {
+            a.timestamp.div_euclid(width) == b.timestamp.div_euclid(width)
+        }) {
+            let bucket_start = chunk[0].timestamp.div_euclid(width) * width;
+            buckets.push((bucket_start, reduce(chunk, agg)));
+        }
+        Ok(buckets)
+    }
+
+    /// Write every buffered point to disk. Inserts and queries wait while it runs.
+    pub fn flush(&self) -> Result<(), StorageError> {
+        let mut storage = self.storage.write().unwrap();
+        storage.close()?;
+        *storage = build(&self.data_path, &self.configure)?;
+        Ok(())
+    }
+
+    /// Flush every `flush_interval` until the store is dropped, errors are logged.
+    pub fn spawn_maintenance(self: &Arc<Self>, flush_interval: Duration) -> JoinHandle<()> {
+        let store = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(flush_interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(store) = store.upgrade() else {
+                    break;
+                };
+                match tokio::task::spawn_blocking(move || store.flush()).await {
+                    Ok(Ok(())) => tracing::debug!("Time-series store flushed"),
+                    Ok(Err(e)) => tracing::error!("Failed to flush time-series store: {}", e),
+                    Err(e) => tracing::error!("Time-series flush task failed: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Write everything to disk and stop the store's background work, call it last
+    pub fn close(&self) -> Result<(), StorageError> {
+        Ok(self.storage.read().unwrap().close()?)
+    }
+}
+
+/// `bucket` in units of `precision`
+fn bucket_width(bucket: Duration, precision: TimestampPrecision) -> Result<i64, StorageError> {
+    let width = match precision {
+        TimestampPrecision::Nanoseconds => bucket.as_nanos(),
+        TimestampPrecision::Microseconds => bucket.as_micros(),
+        TimestampPrecision::Milliseconds => bucket.as_millis(),
+        TimestampPrecision::Seconds => bucket.as_secs() as u128,
+    };
+    match i64::try_from(width) {
+        Ok(width) if width > 0 => Ok(width),
+        _ => Err(StorageError::InvalidBucket(bucket)),
+    }
+}
+
+fn reduce(points: &[DataPoint], agg: Aggregation) -> f64 {
+    let values = points.iter().map(|p| p.value);
+    match agg {
+        Aggregation::Avg => values.sum::<f64>() / points.len() as f64,
+        Aggregation::Min => values.fold(f64::INFINITY, f64::min),
+        Aggregation::Max => values.fold(f64::NEG_INFINITY, f64::max),
+        Aggregation::Sum => values.sum(),
+        Aggregation::Count => points.len() as f64,
+        Aggregation::Last => points[points.len() - 1].value,
+    }
+}
+
+fn build(data_path: &Path, configure: &Configure) -> Result<Arc<dyn Storage>, StorageError> {
+    Ok(configure(StorageBuilder::new().with_data_path(data_path)).build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_path() -> PathBuf {
+        std::env::temp_dir().join(format!("lean-link-tsdb-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn rows(metric: &str, points: &[(i64, f64)]) -> Vec<Row> {
+        let labels = vec![Label::new("device", "pump-1")];
+        points
+            .iter()
+            .map(|(timestamp, value)| {
+                Row::with_labels(metric, labels.clone(), DataPoint::new(*timestamp, *value))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_flush_survives_reopen() {
+        let path = store_path();
+        let store = TimeSeries::open(&path).unwrap();
+        store
+            .insert(&rows("temperature", &[(1_000, 20.5), (2_000, 21.0)]))
+            .unwrap();
+        store.flush().unwrap();
+        // 刷盘后的实例仍可写入
+        store.insert(&rows("temperature", &[(3_000, 21.5)])).unwrap();
+        store.flush().unwrap();
+
+        // 不关闭旧实例，模拟断电后重新打开
+        let reopened = TimeSeries::open(&path).unwrap();
+        let labels = [Label::new("device", "pump-1")];
+        let points = reopened.select("temperature", &labels, 0, 10_000).unwrap();
+        let values: Vec<(i64, f64)> = points.iter().map(|p| (p.timestamp, p.value)).collect();
+        assert_eq!(values, vec![(1_000, 20.5), (2_000, 21.0), (3_000, 21.5)]);
+
+        reopened.close().unwrap();
+        drop(store);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_insert_validates_rows() {
+        let path = store_path();
+        let store = TimeSeries::open(&path).unwrap();
+        let labels = [Label::new("device", "pump-1")];
+
+        // 一行不合法则整批都不写入
+        let mut batch = rows("temperature", &[(1_000, 20.5)]);
+        batch.push(Row::new("bad metric", DataPoint::new(2_000, 1.0)));
+        assert!(matches!(store.insert(&batch), Err(StorageError::InvalidName(_))));
+        assert!(store.select("temperature", &labels, 0, 10_000).unwrap().is_empty());
+
+        store.insert_sanitized(&batch).unwrap();
+        assert_eq!(store.select("temperature", &labels, 0, 10_000).unwrap().len(), 1);
+        assert_eq!(store.select("bad_metric", &[], 0, 10_000).unwrap().len(), 1);
+
+        store.close().unwrap();
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let path = store_path();
+        let store = TimeSeries::open_with(&path, |builder| {
+            builder.with_timestamp_precision(TimestampPrecision::Milliseconds)
+        })
+        .unwrap()
+        .with_timestamp_precision(TimestampPrecision::Milliseconds);
+        // 每 10 秒一个桶：[0, 10s) 三个点，[10s, 20s) 空，[20s, 30s) 两个点
+        let points = [
+            (1_000, 20.0),
+            (4_000, 26.0),
+            (9_999, 23.0),
+            (20_000, 30.0),
+            (25_000, 10.0),
+        ];
+        store.insert(&rows("temperature", &points)).unwrap();
+        let labels = [Label::new("device", "pump-1")];
+        let ten_secs = Duration::from_secs(10);
+        let aggregate = |start, bucket, agg| {
+            store.aggregate("temperature", &labels, start, 30_000, bucket, agg)
+        };
+
+        assert_eq!(
+            aggregate(0, ten_secs, Aggregation::Avg).unwrap(),
+            vec![(0, 23.0), (20_000, 20.0)]
+        );
+        assert_eq!(
+            aggregate(0, ten_secs, Aggregation::Min).unwrap(),
+            vec![(0, 20.0), (20_000, 10.0)]
+        );
+        assert_eq!(
+            aggregate(0, ten_secs, Aggregation::Max).unwrap(),
+            vec![(0, 26.0), (20_000, 30.0)]
+        );
+        assert_eq!(
+            aggregate(0, ten_secs, Aggregation::Sum).unwrap(),
+            vec![(0, 69.0), (20_000, 40.0)]
+        );
+        assert_eq!(
+            aggregate(0, ten_secs, Aggregation::Count).unwrap(),
+            vec![(0, 3.0), (20_000, 2.0)]
+        );
+        assert_eq!(
+            aggregate(0, ten_secs, Aggregation::Last).unwrap(),
+            vec![(0, 23.0), (20_000, 10.0)]
+        );
+
+        // 桶按纪元对齐，与查询起点无关
+        let shifted = aggregate(3_000, ten_secs, Aggregation::Count).unwrap();
+        assert_eq!(shifted, vec![(0, 2.0), (20_000, 2.0)]);
+        // 毫秒精度下不足 1ms 的桶宽无效
+        assert!(matches!(
+            aggregate(0, Duration::from_micros(10), Aggregation::Avg),
+            Err(StorageError::InvalidBucket(_))
+        ));
+
+        store.close().unwrap();
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
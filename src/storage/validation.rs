@@ -0,0 +1,178 @@
+// Checks on metric and label names before points reach the time-series store.
+
+use regex::Regex;
+use tsink::{Label, Row};
+
+use super::StorageError;
+
+/// What [`TimeSeries::insert`](super::TimeSeries::insert) accepts as a series.
+///
+/// Metric and label names are non-empty and made of `name_chars` only. Label values may hold
+/// any character but must not be empty. Label names and values are at most `max_label_len`
+/// bytes, a row has at most `max_labels` labels.
+#[derive(Debug, Clone)]
+pub struct SeriesRules {
+    /// Matches one allowed character of a metric or label name
+    pub name_chars: Regex,
+    pub max_labels: usize,
+    pub max_label_len: usize,
+}
+
+impl Default for SeriesRules {
+    fn default() -> Self {
+        SeriesRules {
+            name_chars: Regex::new(r"[A-Za-z0-9_.:-]").unwrap(),
+            max_labels: 16,
+            max_label_len: 128,
+        }
+    }
+}
+
+impl SeriesRules {
+    fn allowed(&self, c: char) -> bool {
+        self.name_chars.is_match(c.encode_utf8(&mut [0; 4]))
+    }
+
+    fn check_name(&self, name: &str) -> Result<(), StorageError> {
+        if name.chars().all(|c| self.allowed(c)) {
+            Ok(())
+        } else {
+            Err(StorageError::InvalidName(name.to_string()))
+        }
+    }
+
+    /// Reject a row breaking any rule
+    pub fn validate(&self, row: &Row) -> Result<(), StorageError> {
+        let metric = row.metric();
+        if metric.is_empty() {
+            return Err(StorageError::EmptyMetric);
+        }
+        self.check_name(metric)?;
+        let labels = row.labels();
+        if labels.len() > self.max_labels {
+            return Err(StorageError::TooManyLabels {
+                metric: metric.to_string(),
+                count: labels.len(),
+                max: self.max_labels,
+            });
+        }
+        for label in labels {
+            if label.name.is_empty() || label.value.is_empty() {
+                return Err(StorageError::EmptyLabel {
+                    metric: metric.to_string(),
+                });
+            }
+            if label.name.len() > self.max_label_len || label.value.len() > self.max_label_len {
+                return Err(StorageError::LabelTooLong {
+                    name: label.name.clone(),
+                    max: self.max_label_len,
+                });
+            }
+            self.check_name(&label.name)?;
+        }
+        Ok(())
+    }
+
+    /// Normalize a row instead of rejecting it: disallowed name characters become `_`,
+    /// long label names and values are truncated, labels with an empty name or value and those past
+    /// `max_labels` are dropped. Only a metric name left empty is an error.
+    pub fn sanitize(&self, row: &Row) -> Result<Row, StorageError> {
+        let metric = self.sanitize_name(row.metric().trim());
+        if metric.is_empty() {
+            return Err(StorageError::EmptyMetric);
+        }
+        let labels = row
+            .labels()
+            .iter()
+            .filter_map(|label| {
+                let name = self.sanitize_name(label.name.trim());
+                let name = truncate(&name, self.max_label_len);
+                let value = truncate(label.value.trim(), self.max_label_len);
+                (!name.is_empty() && !value.is_empty()).then(|| Label::new(name, value))
+            })
+            .take(self.max_labels)
+            .collect();
+        Ok(Row::with_labels(metric, labels, row.data_point()))
+    }
+
+    fn sanitize_name(&self, name: &str) -> String {
+        name.chars()
+            .map(|c| if self.allowed(c) { c } else { '_' })
+            .collect()
+    }
+}
+
+/// Longest prefix of `s` of at most `max` bytes ending on a character boundary
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let end = (0..=max).rev().find(|i| s.is_char_boundary(*i)).unwrap_or(0);
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use tsink::DataPoint;
+
+    use super::*;
+
+    fn row(metric: &str, labels: &[(&str, &str)]) -> Row {
+        let labels = labels
+            .iter()
+            .map(|(name, value)| Label::new(*name, *value))
+            .collect();
+        Row::with_labels(metric, labels, DataPoint::new(1_000, 1.0))
+    }
+
+    #[test]
+    fn test_validate() {
+        let rules = SeriesRules {
+            max_labels: 2,
+            max_label_len: 8,
+            ..Default::default()
+        };
+        assert!(rules.validate(&row("pump.temp", &[("line", "A-1")])).is_ok());
+        assert!(matches!(
+            rules.validate(&row("", &[])),
+            Err(StorageError::EmptyMetric)
+        ));
+        assert!(matches!(
+            rules.validate(&row("pump temp", &[])),
+            Err(StorageError::InvalidName(name)) if name == "pump temp"
+        ));
+        assert!(matches!(
+            rules.validate(&row("temp", &[("line/no", "1")])),
+            Err(StorageError::InvalidName(name)) if name == "line/no"
+        ));
+        assert!(matches!(
+            rules.validate(&row("temp", &[("a", "1"), ("b", "2"), ("c", "3")])),
+            Err(StorageError::TooManyLabels { count: 3, max: 2, .. })
+        ));
+        assert!(matches!(
+            rules.validate(&row("temp", &[("line", "123456789")])),
+            Err(StorageError::LabelTooLong { max: 8, .. })
+        ));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let rules = SeriesRules {
+            max_labels: 2,
+            max_label_len: 8,
+            ..Default::default()
+        };
+        let labels = [("line/no", "一二三"), ("", "x"), ("b", "2"), ("c", "3")];
+        let sanitized = rules.sanitize(&row(" pump temp ", &labels)).unwrap();
+        assert_eq!(sanitized.metric(), "pump_temp");
+        assert_eq!(
+            sanitized.labels(),
+            &[Label::new("line_no", "一二"), Label::new("b", "2")]
+        );
+        assert!(rules.validate(&sanitized).is_ok());
+        assert!(matches!(
+            rules.sanitize(&row("  ", &[])),
+            Err(StorageError::EmptyMetric)
+        ));
+    }
+}
@@ -0,0 +1,100 @@
+// Helpers for integration tests: servers on ephemeral ports and matching clients.
+//
+// Every server binds `127.0.0.1:0`, so tests can run in parallel without port clashes.
+
+use std::net::SocketAddr;
+
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(any(feature = "web", feature = "websocket", feature = "socket"))]
+use tokio::sync::mpsc;
+
+#[cfg(any(feature = "web", feature = "websocket"))]
+use crate::{
+    config::Sys,
+    service::websocket::{ArcWebSocketServer, WebSocketConfig, WebSocketMessage, WebSocketServer},
+    utils::id::Peer,
+};
+#[cfg(feature = "socket")]
+use crate::service::socket::{SocketConfig, SocketMessage, SocketServer};
+
+/// Listener on a free local port, for servers started with `start_with_listener`
+pub async fn ephemeral_listener() -> (TcpListener, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind ephemeral port");
+    let addr = listener.local_addr().expect("ephemeral listener address");
+    (listener, addr)
+}
+
+#[cfg(any(feature = "web", feature = "websocket"))]
+pub type WsClient = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<TcpStream>>;
+
+/// WebSocket server with the default config on an ephemeral port
+#[cfg(any(feature = "web", feature = "websocket"))]
+pub async fn spawn_ws_server() -> (ArcWebSocketServer, SocketAddr, mpsc::Receiver<WebSocketMessage>) {
+    spawn_ws_server_with(WebSocketConfig::default()).await
+}
+
+/// WebSocket server with `config` on an ephemeral port, `host`/`port` are ignored
+#[cfg(any(feature = "web", feature = "websocket"))]
+pub async fn spawn_ws_server_with(
+    config: WebSocketConfig,
+) -> (ArcWebSocketServer, SocketAddr, mpsc::Receiver<WebSocketMessage>) {
+    let (listener, addr) = ephemeral_listener().await;
+    let server = WebSocketServer::new_arc(config, Sys::default());
+    let receiver = server
+        .start_with_listener(listener)
+        .await
+        .expect("start websocket server");
+    (server, addr, receiver)
+}
+
+/// Connect a WebSocket client to a server from [`spawn_ws_server`]
+#[cfg(any(feature = "web", feature = "websocket"))]
+pub async fn ws_client(addr: SocketAddr) -> WsClient {
+    tokio_tungstenite::connect_async(format!("ws://{}", addr))
+        .await
+        .expect("connect websocket client")
+        .0
+}
+
+/// Connect a WebSocket client and wait for the server to report it, returns its [`Peer`]
+#[cfg(any(feature = "web", feature = "websocket"))]
+pub async fn ws_connect(
+    addr: SocketAddr,
+    receiver: &mut mpsc::Receiver<WebSocketMessage>,
+) -> (WsClient, Peer) {
+    let client = ws_client(addr).await;
+    loop {
+        match receiver.recv().await {
+            Some(WebSocketMessage::NewConnected(peer)) => break (client, peer),
+            Some(_) => continue,
+            None => panic!("websocket server stopped"),
+        }
+    }
+}
+
+/// Socket server with the default config on an ephemeral port
+#[cfg(feature = "socket")]
+pub async fn spawn_socket_server() -> (SocketServer, SocketAddr, mpsc::Receiver<SocketMessage>) {
+    spawn_socket_server_with(SocketConfig::default()).await
+}
+
+/// Socket server with `config` on an ephemeral port, `host`/`port` are ignored
+#[cfg(feature = "socket")]
+pub async fn spawn_socket_server_with(
+    config: SocketConfig,
+) -> (SocketServer, SocketAddr, mpsc::Receiver<SocketMessage>) {
+    let (listener, addr) = ephemeral_listener().await;
+    let server = SocketServer::new(config);
+    let receiver = server
+        .start_with_listener(listener)
+        .await
+        .expect("start socket server");
+    (server, addr, receiver)
+}
+
+/// Plain TCP client for a server from [`spawn_socket_server`]
+pub async fn tcp_client(addr: SocketAddr) -> TcpStream {
+    TcpStream::connect(addr).await.expect("connect tcp client")
+}
@@ -0,0 +1,253 @@
+// In-memory key/value cache with per-entry expiry.
+
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry as MapEntry;
+
+struct Entry<V> {
+    value: V,
+    expires_at: Instant,
+    last_access: Instant,
+}
+
+/// Concurrent cache whose entries expire after a time to live.
+///
+/// Expired entries are never returned; they are dropped when read or by
+/// [`TtlCache::cleanup_expired`], which callers should run periodically on long-lived caches.
+/// With a max size, an insert overflowing the cache drops the expired entries, then the least
+/// recently used ones. Finding them scans the whole cache, so keep bounded caches small.
+pub struct TtlCache<K, V> {
+    entries: DashMap<K, Entry<V>>,
+    default_ttl: Duration,
+    max_size: Option<usize>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(default_ttl: Duration) -> Self {
+        Self {
+            entries: DashMap::new(),
+            default_ttl,
+            max_size: None,
+        }
+    }
+
+    /// Keep at most `max_size` entries
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size.max(1));
+        self
+    }
+
+    /// Value of `key` unless it is missing or expired
+    pub fn get(&self, key: &K) -> Option<V> {
+        let now = Instant::now();
+        if let Some(mut entry) = self.entries.get_mut(key) {
+            if entry.expires_at > now {
+                entry.last_access = now;
+                return Some(entry.value.clone());
+            }
+        } else {
+            return None;
+        }
+        self.entries.remove_if(key, |_, entry| entry.expires_at <= now);
+        None
+    }
+
+    /// Insert with the default time to live, returns the previous unexpired value
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.insert_ttl(key, value, self.default_ttl)
+    }
+
+    pub fn insert_ttl(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let now = Instant::now();
+        let entry = Entry {
+            value,
+            expires_at: now + ttl,
+            last_access: now,
+        };
+        let previous = self
+            .entries
+            .insert(key, entry)
+            .filter(|previous| previous.expires_at > now)
+            .map(|previous| previous.value);
+        if let Some(max_size) = self.max_size
+            && self.entries.len() > max_size
+        {
+            self.evict(max_size, now);
+        }
+        previous
+    }
+
+    /// Replace the value of `key` by `f` of its unexpired value, with the default time to
+    /// live. The entry stays locked while `f` runs, so concurrent updates of a key never
+    /// overwrite each other. Returns the new value
+    pub fn update(&self, key: K, f: impl FnOnce(Option<V>) -> V) -> V {
+        let now = Instant::now();
+        let value = match self.entries.entry(key) {
+            MapEntry::Occupied(mut occupied) => {
+                let current = occupied.get();
+                let value = f((current.expires_at > now).then(|| current.value.clone()));
+                occupied.insert(Entry {
+                    value: value.clone(),
+                    expires_at: now + self.default_ttl,
+                    last_access: now,
+                });
+                value
+            }
+            MapEntry::Vacant(vacant) => {
+                let value = f(None);
+                vacant.insert(Entry {
+                    value: value.clone(),
+                    expires_at: now + self.default_ttl,
+                    last_access: now,
+                });
+                value
+            }
+        };
+        // 淘汰会锁住各分片，须在释放 entry 之后进行
+        if let Some(max_size) = self.max_size
+            && self.entries.len() > max_size
+        {
+            self.evict(max_size, now);
+        }
+        value
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let now = Instant::now();
+        self.entries
+            .remove(key)
+            .filter(|(_, entry)| entry.expires_at > now)
+            .map(|(_, entry)| entry.value)
+    }
+
+    /// Drop expired entries, returns how many were removed
+    pub fn cleanup_expired(&self) -> usize {
+        let now = Instant::now();
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| entry.expires_at > now);
+        before.saturating_sub(self.entries.len())
+    }
+
+    /// Number of entries, including expired ones not cleaned up yet
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
+
+    /// Shrink to `target` entries: expired ones first, then least recently used
+    fn evict(&self, target: usize, now: Instant) {
+        self.entries.retain(|_, entry| entry.expires_at > now);
+        while self.entries.len() > target {
+            // 迭代器持有分片读锁，先取出 key 再删除
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|entry| entry.last_access)
+                .map(|entry| entry.key().clone());
+            match oldest {
+                Some(key) => self.entries.remove(&key),
+                None => break,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_expiry() {
+        let cache = TtlCache::new(Duration::from_millis(30));
+        cache.insert("short", 1);
+        cache.insert_ttl("long", 2, Duration::from_secs(60));
+        assert_eq!(cache.get(&"short"), Some(1));
+        assert_eq!(cache.insert("short", 3), Some(1));
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(cache.get(&"short"), None);
+        assert_eq!(cache.get(&"long"), Some(2));
+        assert_eq!(cache.len(), 1);
+
+        cache.insert_ttl("gone", 4, Duration::ZERO);
+        assert_eq!(cache.insert("gone", 5), None);
+        cache.insert_ttl("gone", 6, Duration::ZERO);
+        assert_eq!(cache.cleanup_expired(), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_max_size_evicts_least_recently_used() {
+        let cache = TtlCache::new(Duration::from_secs(60)).with_max_size(2);
+        cache.insert(1, "a");
+        std::thread::sleep(Duration::from_millis(2));
+        cache.insert(2, "b");
+        std::thread::sleep(Duration::from_millis(2));
+        // 访问 1 后，2 成为最久未使用
+        assert_eq!(cache.get(&1), Some("a"));
+        cache.insert(3, "c");
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some("a"));
+        assert_eq!(cache.get(&3), Some("c"));
+        // 更新已有 key 不触发淘汰
+        cache.insert(3, "d");
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_concurrent_access() {
+        let cache = Arc::new(TtlCache::new(Duration::from_secs(60)).with_max_size(64));
+        std::thread::scope(|scope| {
+            for thread in 0..8u32 {
+                let cache = cache.clone();
+                scope.spawn(move || {
+                    for i in 0..500u32 {
+                        let key = thread * 1000 + i % 100;
+                        cache.insert(key, i);
+                        let _ = cache.get(&key);
+                        if i % 50 == 0 {
+                            cache.cleanup_expired();
+                        }
+                    }
+                });
+            }
+        });
+        assert!(cache.len() <= 64);
+        assert!(!cache.is_empty());
+    }
+
+    #[test]
+    fn test_update() {
+        let cache = Arc::new(TtlCache::new(Duration::from_secs(60)));
+        assert_eq!(cache.update("count", |count| count.unwrap_or(0) + 1), 1);
+
+        // 并发的读改写不会丢失更新
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                let cache = cache.clone();
+                scope.spawn(move || {
+                    for _ in 0..500 {
+                        cache.update("count", |count| count.unwrap_or(0) + 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(cache.get(&"count"), Some(4001));
+
+        cache.insert_ttl("gone", 5, Duration::ZERO);
+        assert_eq!(cache.update("gone", |value| value.unwrap_or(0) + 1), 1);
+    }
+}
@@ -0,0 +1,605 @@
+// Flatten serde-serializable records into CSV.
+//
+// Structs are walked field by field with a small serializer so the columns keep the field
+// order, going through `serde_json::Value` would sort them. Nested structs become
+// `outer.inner` columns. Anything without a fixed shape (sequences, maps, enum variants
+// carrying data) is written as JSON text into one cell, so the columns of a type do not
+// depend on the data.
+
+use serde::{Serialize, ser};
+use serde_json::{Map, Value};
+
+type Error = serde_json::Error;
+
+/// Cells of one record as `(column, value)`, in field order
+type Cells = Vec<(String, String)>;
+
+/// Render `records` as CSV with a header row of the (serde renamed) field names.
+///
+/// `None` and unit values are empty cells, unit enum variants their name. The columns are
+/// the union over all records, so a nested `Option` that is `None` in the first record still
+/// gets the columns of a later `Some`. Records that fail to serialize are logged and left out.
+///
+/// Text cells starting with `=`, `+`, `-`, `@`, tab or carriage return get a leading `'`, so
+/// spreadsheets do not run them as formulas. Numbers such as `-1` are left as is.
+pub fn to_csv<T: Serialize>(records: &[T]) -> String {
+    let rows = flatten_all(records);
+    let columns = columns_of(&rows);
+    let mut csv = csv_header(&columns);
+    for cells in &rows {
+        write_row(&mut csv, &columns, cells);
+    }
+    csv
+}
+
+/// Columns [`to_csv`] would write for `records`
+pub fn csv_columns<T: Serialize>(records: &[T]) -> Vec<String> {
+    columns_of(&flatten_all(records))
+}
+
+/// The header row for `columns` with its trailing newline, empty without columns
+pub fn csv_header(columns: &[String]) -> String {
+    if columns.is_empty() {
+        return String::new();
+    }
+    let mut header = columns
+        .iter()
+        .map(|column| field(column))
+        .collect::<Vec<_>>()
+        .join(",");
+    header.push('\n');
+    header
+}
+
+/// Only the data rows of [`to_csv`], laid out as `columns`. Lets a large export be written
+/// batch by batch under one header, cells of a column not in `columns` are dropped.
+///
+/// Columns taken from the first batch with [`csv_columns`] miss the fields of a nested
+/// `Option` struct that is `None` throughout that batch, so later values of those fields
+/// are dropped. Types without nested `Option` structs always get every column.
+pub fn to_csv_rows<T: Serialize>(records: &[T], columns: &[String]) -> String {
+    let mut csv = String::new();
+    for cells in flatten_all(records) {
+        write_row(&mut csv, columns, &cells);
+    }
+    csv
+}
+
+fn flatten_all<T: Serialize>(records: &[T]) -> Vec<Cells> {
+    records
+        .iter()
+        .filter_map(|record| {
+            let mut cells = Cells::new();
+            match record.serialize(Flattener::new(String::new(), &mut cells)) {
+                Ok(()) => Some(cells),
+                Err(e) => {
+                    tracing::error!("Failed to serialize CSV record: {}", e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn columns_of(rows: &[Cells]) -> Vec<String> {
+    let mut columns = Vec::new();
+    for cells in rows {
+        for (column, _) in cells {
+            add_column(&mut columns, column);
+        }
+    }
+    columns
+}
+
+/// `name` is a field nested somewhere below `parent`
+fn is_below(name: &str, parent: &str) -> bool {
+    name.len() > parent.len() && name.starts_with(parent) && name[parent.len()..].starts_with('.')
+}
+
+fn add_column(columns: &mut Vec<String>, name: &str) {
+    // 已有同名列，或 name 是之前已展开的 None 嵌套结构
+    if columns.iter().any(|c| c == name || is_below(c, name)) {
+        return;
+    }
+    // 之前为 None 的嵌套结构现在有了字段：替换占位列，或接在同组列之后
+    for (end, _) in name.rmatch_indices('.') {
+        let parent = &name[..end];
+        if let Some(last) = columns
+            .iter()
+            .rposition(|c| c == parent || is_below(c, parent))
+        {
+            if columns[last] == parent {
+                columns[last] = name.to_string();
+            } else {
+                columns.insert(last + 1, name.to_string());
+            }
+            return;
+        }
+    }
+    columns.push(name.to_string());
+}
+
+fn write_row(csv: &mut String, columns: &[String], cells: &[(String, String)]) {
+    for (i, column) in columns.iter().enumerate() {
+        if i > 0 {
+            csv.push(',');
+        }
+        if let Some((_, value)) = cells.iter().find(|(name, _)| name == column) {
+            csv.push_str(&field(value));
+        }
+    }
+    csv.push('\n');
+}
+
+fn field(value: &str) -> String {
+    // 以公式字符开头的文本加 ' 前缀，防止表格软件把单元格当作公式执行
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) && value.parse::<f64>().is_err() {
+        return quote(&format!("'{}", value));
+    }
+    quote(value)
+}
+
+fn quote(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Serializes one value into the cells below `prefix`
+struct Flattener<'a> {
+    prefix: String,
+    cells: &'a mut Cells,
+}
+
+impl<'a> Flattener<'a> {
+    fn new(prefix: String, cells: &'a mut Cells) -> Self {
+        Self { prefix, cells }
+    }
+
+    fn cell(self, value: impl ToString) -> Result<(), Error> {
+        let column = if self.prefix.is_empty() {
+            "value".to_string()
+        } else {
+            self.prefix
+        };
+        self.cells.push((column, value.to_string()));
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for Flattener<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = JsonSeq<'a>;
+    type SerializeTuple = JsonSeq<'a>;
+    type SerializeTupleStruct = JsonSeq<'a>;
+    type SerializeTupleVariant = JsonSeq<'a>;
+    type SerializeMap = JsonMap<'a>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = JsonMap<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.cell(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        self.cell(Value::from(v).to_string())
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.cell("")
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.cell("")
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.cell("")
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        self.cell(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let mut map = Map::new();
+        map.insert(variant.to_string(), serde_json::to_value(value)?);
+        self.cell(Value::Object(map))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<JsonSeq<'a>, Error> {
+        Ok(JsonSeq::new(self, None, len.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<JsonSeq<'a>, Error> {
+        Ok(JsonSeq::new(self, None, len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<JsonSeq<'a>, Error> {
+        Ok(JsonSeq::new(self, None, len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<JsonSeq<'a>, Error> {
+        Ok(JsonSeq::new(self, Some(variant), len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<JsonMap<'a>, Error> {
+        Ok(JsonMap::new(self, None))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<JsonMap<'a>, Error> {
+        Ok(JsonMap::new(self, Some(variant)))
+    }
+}
+
+impl ser::SerializeStruct for Flattener<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let prefix = if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.prefix, key)
+        };
+        value.serialize(Flattener::new(prefix, self.cells))
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Wrap `value` as `{variant: value}` like serde_json does for enum variants with data
+fn tagged(variant: Option<&'static str>, value: Value) -> Value {
+    match variant {
+        Some(variant) => {
+            let mut map = Map::new();
+            map.insert(variant.to_string(), value);
+            Value::Object(map)
+        }
+        None => value,
+    }
+}
+
+/// Collects a sequence, tuple or tuple variant into one JSON cell
+struct JsonSeq<'a> {
+    target: Flattener<'a>,
+    variant: Option<&'static str>,
+    items: Vec<Value>,
+}
+
+impl<'a> JsonSeq<'a> {
+    fn new(target: Flattener<'a>, variant: Option<&'static str>, len: usize) -> Self {
+        Self {
+            target,
+            variant,
+            items: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(serde_json::to_value(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        let value = tagged(self.variant, Value::Array(self.items));
+        self.target.cell(value)
+    }
+}
+
+impl ser::SerializeSeq for JsonSeq<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for JsonSeq<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for JsonSeq<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for JsonSeq<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+/// Collects a map or struct variant into one JSON cell
+struct JsonMap<'a> {
+    target: Flattener<'a>,
+    variant: Option<&'static str>,
+    map: Map<String, Value>,
+    key: Option<String>,
+}
+
+impl<'a> JsonMap<'a> {
+    fn new(target: Flattener<'a>, variant: Option<&'static str>) -> Self {
+        Self {
+            target,
+            variant,
+            map: Map::new(),
+            key: None,
+        }
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        let value = tagged(self.variant, Value::Object(self.map));
+        self.target.cell(value)
+    }
+}
+
+impl ser::SerializeMap for JsonMap<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        // 与 serde_json 一致，非字符串的键写成其文本
+        let key = match serde_json::to_value(key)? {
+            Value::String(key) => key,
+            key => key.to_string(),
+        };
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().unwrap_or_default();
+        self.map.insert(key, serde_json::to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for JsonMap<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.map.insert(key.to_string(), serde_json::to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Location {
+        line: String,
+        station_no: u32,
+    }
+
+    #[derive(Serialize)]
+    enum Status {
+        Online,
+        Fault(u16),
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Device {
+        id: u32,
+        name: String,
+        serial_no: Option<String>,
+        location: Option<Location>,
+        status: Status,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let devices = [
+            Device {
+                id: 1,
+                name: "pump, \"main\"".into(),
+                serial_no: None,
+                location: None,
+                status: Status::Online,
+                tags: vec![],
+            },
+            Device {
+                id: 2,
+                name: "valve".into(),
+                serial_no: Some("SN-2".into()),
+                location: Some(Location {
+                    line: "A".into(),
+                    station_no: 3,
+                }),
+                status: Status::Fault(7),
+                tags: vec!["hot".into()],
+            },
+        ];
+
+        // 第一条的 location 为 None，列仍按第二条展开并保持字段顺序
+        assert_eq!(
+            to_csv(&devices),
+            "id,name,serialNo,location.line,location.stationNo,status,tags\n\
+             1,\"pump, \"\"main\"\"\",,,,Online,[]\n\
+             2,valve,SN-2,A,3,\"{\"\"Fault\"\":7}\",\"[\"\"hot\"\"]\"\n"
+        );
+
+        // 分批导出：表头取自一批，其余批次按同样的列输出
+        let columns = csv_columns(&devices[1..]);
+        assert_eq!(
+            csv_header(&columns),
+            "id,name,serialNo,location.line,location.stationNo,status,tags\n"
+        );
+        assert_eq!(
+            to_csv_rows(&devices[..1], &columns),
+            "1,\"pump, \"\"main\"\"\",,,,Online,[]\n"
+        );
+    }
+
+    #[test]
+    fn test_formula_injection() {
+        let cells = ["=1+1", "+SUM(A1)", "-2+3", "@cmd", "-1.5", "a=b", "=x,y"];
+        assert_eq!(
+            to_csv(&cells),
+            "value\n'=1+1\n'+SUM(A1)\n'-2+3\n'@cmd\n-1.5\na=b\n\"'=x,y\"\n"
+        );
+    }
+
+    #[test]
+    fn test_to_csv_empty_and_scalar() {
+        assert_eq!(to_csv::<Device>(&[]), "");
+        assert_eq!(to_csv(&[1, 2]), "value\n1\n2\n");
+    }
+}
@@ -1,10 +1,31 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
 use chrono::{DateTime, FixedOffset, Local};
 use serde::Serializer;
 
-/// Helper function to convert DateTime<FixedOffset> to local time and serialize
-fn convert_to_local_rfc3339(dt: &DateTime<FixedOffset>) -> String {
-    let local_time: DateTime<Local> = dt.with_timezone(&Local);
-    local_time.to_rfc3339()
+/// Offset in seconds used by the serializers below, `i32::MIN` when unset
+static OUTPUT_OFFSET_SECS: AtomicI32 = AtomicI32::new(i32::MIN);
+
+/// Set the timezone timestamps are serialized in, `None` to use the process `Local` timezone.
+/// Applied from `Sys::tz_offset_minutes` at startup.
+pub fn set_output_offset(offset: Option<FixedOffset>) {
+    let secs = offset.map_or(i32::MIN, |offset| offset.local_minus_utc());
+    OUTPUT_OFFSET_SECS.store(secs, Ordering::Relaxed);
+}
+
+pub fn output_offset() -> Option<FixedOffset> {
+    match OUTPUT_OFFSET_SECS.load(Ordering::Relaxed) {
+        i32::MIN => None,
+        secs => FixedOffset::east_opt(secs),
+    }
+}
+
+/// Convert to the configured output offset (or `Local`) and format as RFC 3339
+pub fn convert_to_local_rfc3339(dt: &DateTime<FixedOffset>) -> String {
+    match output_offset() {
+        Some(offset) => dt.with_timezone(&offset).to_rfc3339(),
+        None => dt.with_timezone(&Local).to_rfc3339(),
+    }
 }
 
 pub fn to_local_time<S>(dt: &DateTime<FixedOffset>, serializer: S) -> Result<S::Ok, S::Error>
@@ -74,51 +95,116 @@ pub mod local_time_option {
     }
 }
 
+/// Human readable durations: a number followed by a unit (`"500ms"`, `"30s"`, `"1.5m"`,
+/// `"2h"`, `"1d"`). For compatibility with older configs a bare number is accepted too:
+/// a numeric value is milliseconds, a numeric string is seconds.
 pub mod string_to_duration {
-    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+    use serde::{Deserializer, Serializer, de::Error};
     use std::time::Duration;
 
     pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_str(&format!("{}s", duration.as_secs()))
+        if duration.subsec_millis() == 0 {
+            serializer.serialize_str(&format!("{}s", duration.as_secs()))
+        } else {
+            serializer.serialize_str(&format!("{}ms", duration.as_millis()))
+        }
     }
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let s = String::deserialize(deserializer)?;
-
+    pub fn parse(s: &str) -> Result<Duration, String> {
         // Number is an integer followed (without a space) by a unit of time.​​
         // eg: "30s", "1m", "2h", "500ms", "1.5s"
         let re = regex::Regex::new(r"^\s*(\d+\.?\d*)\s*([a-zA-Z]+)\s*$").unwrap();
 
-        if let Some(caps) = re.captures(&s) {
-            let value: f64 = caps[1].parse().map_err(D::Error::custom)?;
+        if let Some(caps) = re.captures(s) {
+            let value: f64 = caps[1].parse().map_err(|e| format!("{}", e))?;
             let unit = &caps[2].to_lowercase();
 
             match unit.as_str() {
                 "ms" | "millis" | "millisecond" | "milliseconds" => {
                     Ok(Duration::from_millis(value as u64))
                 }
-                "s" | "sec" | "second" | "seconds" => Ok(Duration::from_secs(value as u64)),
-                "m" | "min" | "minute" | "minutes" => {
-                    Ok(Duration::from_secs((value * 60.0) as u64))
-                }
-                "h" | "hour" | "hours" => Ok(Duration::from_secs((value * 3600.0) as u64)),
-                "d" | "day" | "days" => Ok(Duration::from_secs((value * 86400.0) as u64)),
-                _ => Err(D::Error::custom(format!("Unknown time unit: {}", unit))),
+                "s" | "sec" | "second" | "seconds" => Ok(Duration::from_secs_f64(value)),
+                "m" | "min" | "minute" | "minutes" => Ok(Duration::from_secs_f64(value * 60.0)),
+                "h" | "hour" | "hours" => Ok(Duration::from_secs_f64(value * 3600.0)),
+                "d" | "day" | "days" => Ok(Duration::from_secs_f64(value * 86400.0)),
+                _ => Err(format!("Unknown time unit: {}", unit)),
             }
         } else {
             // If no unit is present, try to parse as a plain number (seconds)
-            match s.parse::<u64>() {
+            match s.trim().parse::<u64>() {
                 Ok(secs) => Ok(Duration::from_secs(secs)),
-                Err(_) => Err(D::Error::custom(format!("Invalid duration format: {}", s))),
+                Err(_) => Err(format!("Invalid duration format: {}", s)),
             }
         }
     }
+
+    struct DurationVisitor;
+
+    impl serde::de::Visitor<'_> for DurationVisitor {
+        type Value = Duration;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a duration such as \"30s\" or a number of milliseconds")
+        }
+
+        fn visit_str<E: Error>(self, v: &str) -> Result<Duration, E> {
+            parse(v).map_err(E::custom)
+        }
+
+        fn visit_u64<E: Error>(self, v: u64) -> Result<Duration, E> {
+            Ok(Duration::from_millis(v))
+        }
+
+        fn visit_i64<E: Error>(self, v: i64) -> Result<Duration, E> {
+            u64::try_from(v)
+                .map(Duration::from_millis)
+                .map_err(|_| E::custom(format!("Negative duration: {}", v)))
+        }
+
+        fn visit_f64<E: Error>(self, v: f64) -> Result<Duration, E> {
+            if v.is_finite() && v >= 0.0 {
+                Ok(Duration::from_secs_f64(v / 1000.0))
+            } else {
+                Err(E::custom(format!("Invalid duration: {}", v)))
+            }
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(DurationVisitor)
+    }
+}
+
+/// [`string_to_duration`] for optional fields, pair with `#[serde(default)]`
+pub mod string_to_duration_option {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match duration {
+            Some(duration) => super::string_to_duration::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "super::string_to_duration")] Duration);
+
+        Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(duration)| duration))
+    }
 }
 
 pub mod duration_seconds {
@@ -333,3 +419,75 @@ pub fn duration_to_seconds_string(duration: std::time::Duration, decimals: usize
     let total_seconds = duration.as_secs_f64();
     format!("{:.*}", decimals, total_seconds)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    struct Timeout {
+        #[serde(with = "string_to_duration")]
+        timeout: Duration,
+    }
+
+    fn timeout(yaml: &str) -> Result<Duration, serde_yaml_bw::Error> {
+        serde_yaml_bw::from_str::<Timeout>(yaml).map(|t| t.timeout)
+    }
+
+    #[test]
+    fn test_string_to_duration_formats() {
+        assert_eq!(timeout("timeout: 30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(timeout("timeout: 500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(timeout("timeout: 1.5m").unwrap(), Duration::from_secs(90));
+        assert_eq!(timeout("timeout: \"2h\"").unwrap(), Duration::from_secs(7200));
+        // 兼容旧配置：数字为毫秒，数字字符串为秒
+        assert_eq!(timeout("timeout: 100").unwrap(), Duration::from_millis(100));
+        assert_eq!(timeout("timeout: \"100\"").unwrap(), Duration::from_secs(100));
+        assert!(timeout("timeout: 5 weeks").is_err());
+        assert!(timeout("timeout: -1").is_err());
+
+        let json = serde_json::to_value(Timeout {
+            timeout: Duration::from_millis(1500),
+        })
+        .unwrap();
+        assert_eq!(json["timeout"], "1500ms");
+        let parsed: Timeout = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.timeout, Duration::from_millis(1500));
+    }
+
+    #[derive(serde::Serialize)]
+    struct Stamp {
+        #[serde(serialize_with = "to_local_time")]
+        at: DateTime<FixedOffset>,
+        #[serde(serialize_with = "to_local_time_option")]
+        until: Option<DateTime<FixedOffset>>,
+    }
+
+    #[test]
+    fn test_fixed_output_offset() {
+        let at = DateTime::parse_from_rfc3339("2026-01-02T03:04:05+00:00").unwrap();
+        let stamp = Stamp {
+            at,
+            until: Some(at),
+        };
+
+        set_output_offset(FixedOffset::east_opt(8 * 3600));
+        assert_eq!(output_offset(), FixedOffset::east_opt(8 * 3600));
+        assert_eq!(
+            serde_json::to_value(&stamp).unwrap(),
+            serde_json::json!({
+                "at": "2026-01-02T11:04:05+08:00",
+                "until": "2026-01-02T11:04:05+08:00",
+            })
+        );
+
+        set_output_offset(None);
+        assert_eq!(output_offset(), None);
+        assert_eq!(
+            convert_to_local_rfc3339(&at),
+            at.with_timezone(&Local).to_rfc3339()
+        );
+    }
+}
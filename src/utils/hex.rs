@@ -0,0 +1,136 @@
+// Hex strings entered by users, e.g. frames in config files.
+//
+// Also re-exports the `hex` crate, previously available as `utils::hex`.
+
+pub use ::hex::*;
+
+use bytes::Bytes;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HexError {
+    #[error("Invalid hex character {character:?} at position {position}")]
+    InvalidChar { character: char, position: usize },
+    /// A group of digits between separators has an odd length, e.g. `0x1`
+    #[error("Odd number of hex digits in {0:?}")]
+    OddLength(String),
+}
+
+/// Parse hex bytes such as `01 0A FF`, `0x01 0x0a 0xff`, `01:0A:FF` or `010AFF`.
+///
+/// Spaces, colons and dashes separate groups, each group may start with `0x` and holds one or
+/// more whole bytes. An empty string gives empty bytes.
+pub fn parse(text: &str) -> Result<Bytes, HexError> {
+    let mut bytes = Vec::with_capacity(text.len() / 2);
+    let mut position = 0;
+    for group in text.split(|c: char| c.is_whitespace() || c == ':' || c == '-') {
+        let start = position;
+        position += group.len() + 1;
+        if group.is_empty() {
+            continue;
+        }
+
+        let (digits, offset) = match group.strip_prefix("0x").or(group.strip_prefix("0X")) {
+            Some(digits) => (digits, start + 2),
+            None => (group, start),
+        };
+        let invalid = digits.char_indices().find(|(_, c)| !c.is_ascii_hexdigit());
+        if let Some((index, character)) = invalid {
+            return Err(HexError::InvalidChar {
+                character,
+                position: offset + index,
+            });
+        }
+        if digits.is_empty() {
+            return Err(HexError::OddLength(group.to_string()));
+        }
+        let decoded = ::hex::decode(digits).map_err(|_| HexError::OddLength(group.to_string()))?;
+        bytes.extend(decoded);
+    }
+    Ok(Bytes::from(bytes))
+}
+
+/// Uppercase hex of `data` with `sep` between bytes, e.g. `format(&[1, 255], ":")` is `01:FF`.
+/// See [`crate::utils::hex_dump::hex_dump`] for log output.
+pub fn format(data: &[u8], sep: &str) -> String {
+    data.iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+/// Serde helper storing `Bytes` as a hex string, for frames set in config
+pub mod string_to_bytes {
+    use bytes::Bytes;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S>(bytes: &Bytes, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&super::format(bytes, " "))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Bytes, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        super::parse(&text).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_formats() {
+        let expected = Bytes::from_static(&[0x01, 0x0A, 0xFF]);
+        for text in ["01 0A FF", "0x01 0x0a 0xFF", "01:0a:ff", "01-0A-FF", "0X010aff"] {
+            assert_eq!(parse(text).unwrap(), expected, "{:?}", text);
+        }
+        assert_eq!(parse(" 01\t0A  FF ").unwrap(), expected);
+        assert_eq!(parse("").unwrap(), Bytes::new());
+        assert_eq!(format(&expected, ":"), "01:0A:FF");
+        assert_eq!(format(&expected, ""), "010AFF");
+        assert_eq!(parse(&format(&expected, " ")).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        assert_eq!(
+            parse("01 0G"),
+            Err(HexError::InvalidChar {
+                character: 'G',
+                position: 4
+            })
+        );
+        assert_eq!(
+            parse("0x1x"),
+            Err(HexError::InvalidChar {
+                character: 'x',
+                position: 3
+            })
+        );
+        assert_eq!(parse("01 0x1"), Err(HexError::OddLength("0x1".into())));
+        assert_eq!(parse("0x"), Err(HexError::OddLength("0x".into())));
+        assert_eq!(
+            parse("01,02").unwrap_err().to_string(),
+            "Invalid hex character ',' at position 2"
+        );
+    }
+
+    #[test]
+    fn test_serde_helper() {
+        #[derive(serde::Deserialize, serde::Serialize)]
+        struct Heartbeat {
+            #[serde(with = "string_to_bytes")]
+            command: Bytes,
+        }
+
+        let heartbeat: Heartbeat = serde_yaml_bw::from_str("command: \"AA 55 01\"").unwrap();
+        assert_eq!(heartbeat.command, Bytes::from_static(&[0xAA, 0x55, 0x01]));
+        assert_eq!(serde_yaml_bw::to_string(&heartbeat).unwrap().trim(), "command: AA 55 01");
+        assert!(serde_yaml_bw::from_str::<Heartbeat>("command: \"AA 5\"").is_err());
+    }
+}
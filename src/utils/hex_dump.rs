@@ -0,0 +1,121 @@
+// Hex dump formatting for frame logs.
+
+/// Output format of [`hex_dump`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HexFormat {
+    pub uppercase: bool,
+    pub separator: &'static str,
+    /// Append the printable ASCII of the dumped bytes, `.` for the rest
+    pub ascii: bool,
+    /// Dump at most this many bytes and append `…(N more)`
+    pub max_bytes: Option<usize>,
+}
+
+impl Default for HexFormat {
+    fn default() -> Self {
+        Self {
+            uppercase: true,
+            separator: " ",
+            ascii: false,
+            max_bytes: None,
+        }
+    }
+}
+
+impl HexFormat {
+    pub fn with_lowercase(mut self) -> Self {
+        self.uppercase = false;
+        self
+    }
+
+    pub fn with_separator(mut self, separator: &'static str) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    pub fn with_ascii(mut self) -> Self {
+        self.ascii = true;
+        self
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+}
+
+/// Format bytes as hex, e.g. `01 0A FF`.
+/// Example with ascii: `48 69 00 |Hi.|`, truncated: `48 69 …(1 more)`
+pub fn hex_dump(data: &[u8], format: HexFormat) -> String {
+    let shown = match format.max_bytes {
+        Some(max_bytes) => &data[..data.len().min(max_bytes)],
+        None => data,
+    };
+
+    let mut output = shown
+        .iter()
+        .map(|byte| {
+            if format.uppercase {
+                format!("{:02X}", byte)
+            } else {
+                format!("{:02x}", byte)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(format.separator);
+
+    if format.ascii {
+        let ascii: String = shown
+            .iter()
+            .map(|byte| {
+                if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        output.push_str(&format!(" |{}|", ascii));
+    }
+
+    if shown.len() < data.len() {
+        output.push_str(&format!(" …({} more)", data.len() - shown.len()));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_dump_default() {
+        assert_eq!(hex_dump(&[0x01, 0x0A, 0xFF], HexFormat::default()), "01 0A FF");
+        assert_eq!(hex_dump(&[], HexFormat::default()), "");
+    }
+
+    #[test]
+    fn test_hex_dump_lowercase_and_separator() {
+        let format = HexFormat::default().with_lowercase().with_separator("");
+        assert_eq!(hex_dump(&[0xAB, 0xCD], format), "abcd");
+
+        let format = HexFormat::default().with_separator(":");
+        assert_eq!(hex_dump(&[0xAB, 0xCD], format), "AB:CD");
+    }
+
+    #[test]
+    fn test_hex_dump_ascii() {
+        let format = HexFormat::default().with_ascii();
+        assert_eq!(hex_dump(b"Hi \x00", format), "48 69 20 00 |Hi .|");
+    }
+
+    #[test]
+    fn test_hex_dump_max_bytes() {
+        let format = HexFormat::default().with_max_bytes(2);
+        assert_eq!(hex_dump(&[1, 2, 3, 4], format), "01 02 …(2 more)");
+        assert_eq!(hex_dump(&[1, 2], format), "01 02");
+
+        let format = format.with_ascii();
+        assert_eq!(hex_dump(b"abc", format), "61 62 |ab| …(1 more)");
+    }
+}
@@ -0,0 +1,110 @@
+// Bounded in-memory history of recent items, e.g. the last N frames of a transport.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+/// Keeps the most recent `capacity` items, dropping the oldest when full.
+#[derive(Debug)]
+pub struct History<T> {
+    items: Mutex<VecDeque<T>>,
+    capacity: usize,
+}
+
+impl<T> History<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn push(&self, item: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut items = self.items.lock().unwrap();
+        if items.len() == self.capacity {
+            items.pop_front();
+        }
+        items.push_back(item);
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.items.lock().unwrap().clear();
+    }
+}
+
+impl<T: Clone> History<T> {
+    /// Items from oldest to newest.
+    pub fn recent(&self) -> Vec<T> {
+        self.items.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// One recorded transport item.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry<T> {
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    pub direction: Direction,
+    /// Remote peer, `None` for broadcasts and point-to-point links
+    pub peer: Option<String>,
+    pub data: T,
+}
+
+impl<T> HistoryEntry<T> {
+    pub fn new(direction: Direction, peer: Option<String>, data: T) -> Self {
+        Self {
+            timestamp: chrono::Local::now().timestamp_millis(),
+            direction,
+            peer,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_keeps_latest() {
+        let history = History::new(3);
+        for i in 0..5 {
+            history.push(i);
+        }
+        assert_eq!(history.recent(), vec![2, 3, 4]);
+        assert_eq!(history.len(), 3);
+
+        history.clear();
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn test_history_zero_capacity() {
+        let history = History::new(0);
+        history.push(1);
+        assert!(history.recent().is_empty());
+    }
+}
@@ -0,0 +1,90 @@
+// Id generation for entities and application records.
+
+use std::{fmt, net::SocketAddr, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// New time-ordered (UUIDv7) id, used for every entity primary key
+pub fn new_id() -> Uuid {
+    Uuid::now_v7()
+}
+
+/// Parse an id from its text form, e.g. a path or query parameter
+pub fn parse_id(id: &str) -> Result<Uuid, crate::errors::Error> {
+    Ok(Uuid::parse_str(id)?)
+}
+
+/// Id of one accepted connection, unique even when a peer reconnects from the same address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ConnectionId(Uuid);
+
+impl ConnectionId {
+    pub fn generate() -> Self {
+        ConnectionId(new_id())
+    }
+
+    pub fn as_uuid(&self) -> Uuid {
+        self.0
+    }
+}
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for ConnectionId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s).map(ConnectionId)
+    }
+}
+
+/// A server connection as reported in events: its id and the address it came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Peer {
+    pub id: ConnectionId,
+    pub addr: SocketAddr,
+}
+
+impl Peer {
+    /// New connection from `addr` with a freshly generated id
+    pub fn new(addr: SocketAddr) -> Self {
+        Peer {
+            id: ConnectionId::generate(),
+            addr,
+        }
+    }
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.addr, self.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_id_is_time_ordered() {
+        let ids: Vec<Uuid> = (0..1000).map(|_| new_id()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(ids[0].get_version_num(), 7);
+    }
+
+    #[test]
+    fn test_parse_id() {
+        let id = new_id();
+        assert_eq!(parse_id(&id.to_string()).unwrap(), id);
+        assert!(matches!(
+            parse_id("not-an-id"),
+            Err(crate::errors::Error::InvalidId(_))
+        ));
+    }
+}
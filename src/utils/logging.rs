@@ -0,0 +1,334 @@
+// Config-driven tracing subscriber setup.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::Subscriber;
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, fmt::MakeWriter, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per event, for log shippers
+    Json,
+}
+
+fn default_level() -> String {
+    "info".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    /// New file every day, named `<stem>.YYYY-MM-DD.<ext>`
+    #[default]
+    Daily,
+    /// New file every hour, named `<stem>.YYYY-MM-DD-HH.<ext>`
+    Hourly,
+    /// New file once the current one exceeds `max_size_mb`, older files become `<path>.1`, `<path>.2`, ...
+    Size,
+}
+
+fn default_max_files() -> usize {
+    7
+}
+
+fn default_max_size_mb() -> u64 {
+    10
+}
+
+/// Rotating log file written next to stdout logging.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub rotation: LogRotation,
+    /// Rotated files kept besides the current one, older files are deleted
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+    /// Size limit for `rotation: size`
+    #[serde(default = "default_max_size_mb")]
+    pub max_size_mb: u64,
+}
+
+/// The `logging` config section.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LogConfig {
+    /// Filter directives such as `info` or `info,sea_orm=warn`; `RUST_LOG` takes precedence
+    #[serde(default = "default_level")]
+    pub level: String,
+    #[serde(default)]
+    pub format: LogFormat,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<LogFileConfig>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: default_level(),
+            format: LogFormat::default(),
+            file: None,
+        }
+    }
+}
+
+/// Writer that starts a new file once `max_bytes` would be exceeded, shifting older files
+/// to `<path>.1` .. `<path>.<max_files>`.
+pub struct SizeRollingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl SizeRollingFile {
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64, max_files: usize) -> std::io::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated(index);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRollingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Keeps the background log file writer alive; hold it until the program exits so
+/// buffered lines are flushed.
+#[must_use]
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+}
+
+fn file_writer(config: &LogFileConfig) -> crate::Result<Box<dyn Write + Send>> {
+    let logging_error = |e: &dyn std::fmt::Display| {
+        crate::errors::Error::Logging(format!("{}: {}", config.path.display(), e))
+    };
+    if config.rotation == LogRotation::Size {
+        let max_bytes = config.max_size_mb.saturating_mul(1024 * 1024);
+        let writer = SizeRollingFile::new(&config.path, max_bytes, config.max_files)
+            .map_err(|e| logging_error(&e))?;
+        return Ok(Box::new(writer));
+    }
+
+    let directory = config.path.parent().unwrap_or(Path::new("."));
+    let mut builder = RollingFileAppender::builder().rotation(match config.rotation {
+        LogRotation::Hourly => Rotation::HOURLY,
+        _ => Rotation::DAILY,
+    });
+    if let Some(stem) = config.path.file_stem() {
+        builder = builder.filename_prefix(stem.to_string_lossy());
+    }
+    if let Some(extension) = config.path.extension() {
+        builder = builder.filename_suffix(extension.to_string_lossy());
+    }
+    // tracing-appender 的保留数量包含当前文件
+    let appender = builder
+        .max_log_files(config.max_files + 1)
+        .build(directory)
+        .map_err(|e| logging_error(&e))?;
+    Ok(Box::new(appender))
+}
+
+fn fmt_layer<S, W>(format: LogFormat, ansi: bool, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_ansi(ansi)
+        .with_writer(writer);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+fn subscriber<W, F>(
+    config: &LogConfig,
+    directives: &str,
+    writer: W,
+    file_writer: Option<F>,
+) -> crate::Result<impl Subscriber + Send + Sync + use<W, F>>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+    F: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let filter =
+        EnvFilter::try_new(directives).map_err(|e| crate::errors::Error::Logging(e.to_string()))?;
+    Ok(Registry::default()
+        .with(filter)
+        .with(fmt_layer(config.format, true, writer))
+        .with(file_writer.map(|writer| fmt_layer(config.format, false, writer))))
+}
+
+/// Install the global tracing subscriber described by `config`, logging to stdout and,
+/// when `file` is configured, to a rotating log file.
+///
+/// Call it once at startup instead of `tracing_subscriber::fmt().init()` and keep the
+/// returned guard alive; fails if the filter is invalid, the log file cannot be opened
+/// or a global subscriber is already set.
+pub fn init_tracing(config: &LogConfig) -> crate::Result<LogGuard> {
+    let directives = std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| config.level.clone());
+    let (file_writer, guard) = match &config.file {
+        Some(file) => {
+            let (writer, guard) = tracing_appender::non_blocking(file_writer(file)?);
+            (Some(writer), Some(guard))
+        }
+        None => (None, None),
+    };
+    subscriber(config, &directives, std::io::stdout, file_writer)?
+        .try_init()
+        .map_err(|e| crate::errors::Error::Logging(e.to_string()))?;
+    Ok(LogGuard { _file: guard })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_json_format_and_level() {
+        let config: LogConfig = serde_yaml_bw::from_str("level: warn\nformat: json").unwrap();
+        let buffer = Buffer::default();
+        let subscriber = subscriber(&config, &config.level, buffer.clone(), None::<Buffer>).unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("hidden");
+            tracing::warn!(code = 7, "shown");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["level"], "WARN");
+        assert_eq!(lines[0]["fields"]["message"], "shown");
+        assert_eq!(lines[0]["fields"]["code"], 7);
+    }
+
+    #[test]
+    fn test_invalid_level() {
+        assert_eq!(LogConfig::default().format, LogFormat::Text);
+        assert!(subscriber(&LogConfig::default(), "info,=[", std::io::sink, None::<Buffer>).is_err());
+    }
+
+    #[test]
+    fn test_file_layer_coexists_with_stdout() {
+        let config: LogConfig =
+            serde_yaml_bw::from_str("file:\n  path: /tmp/app.log\n  rotation: hourly").unwrap();
+        let file = config.file.clone().unwrap();
+        assert_eq!(file.rotation, LogRotation::Hourly);
+        assert_eq!(file.max_files, 7);
+
+        let (stdout, log_file) = (Buffer::default(), Buffer::default());
+        let subscriber = subscriber(&config, "info", stdout.clone(), Some(log_file.clone())).unwrap();
+        tracing::subscriber::with_default(subscriber, || tracing::info!("both"));
+        for buffer in [stdout, log_file] {
+            assert!(String::from_utf8_lossy(&buffer.0.lock().unwrap()).contains("both"));
+        }
+    }
+
+    #[test]
+    fn test_size_rolling_file() {
+        let dir = std::env::temp_dir().join(format!("lean-link-logs-{}", crate::new_id()));
+        let path = dir.join("app.log");
+        let mut writer = SizeRollingFile::new(&path, 10, 2).unwrap();
+        for line in ["aaaaaaaa\n", "bbbbbbbb\n", "cccccccc\n", "dddddddd\n"] {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), "dddddddd\n");
+        assert_eq!(read(&dir.join("app.log.1")), "cccccccc\n");
+        assert_eq!(read(&dir.join("app.log.2")), "bbbbbbbb\n");
+        assert!(!dir.join("app.log.3").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,7 +1,20 @@
 pub mod file;
 pub mod datetime;
 pub mod bcd;
+pub mod cache;
+pub mod csv;
 pub mod i2c;
+pub mod hex;
+pub mod hex_dump;
+pub mod history;
+pub mod id;
+pub mod logging;
+pub mod recorder;
+pub mod retry;
+pub mod supervise;
+pub mod time_source;
+pub use cache::TtlCache;
+pub use csv::to_csv;
+pub use retry::{ReconnectPolicy, retry, retry_if};
+pub use supervise::{Supervised, TaskFailure, supervise};
 pub use rust_xlsxwriter;
-
-pub use hex;
\ No newline at end of file
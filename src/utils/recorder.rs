@@ -0,0 +1,338 @@
+// Capture transport traffic to a file and replay it for offline reproduction.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::mpsc,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream},
+    task::JoinHandle,
+};
+
+use crate::utils::{
+    hex,
+    hex_dump::{HexFormat, hex_dump},
+    history::{Direction, HistoryEntry},
+};
+
+/// On-disk layout of a recording.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RecordFormat {
+    /// Each record is a big-endian `u32` length followed by `i64` timestamp (ms),
+    /// `u8` direction (0 in, 1 out), `u16` peer length, the peer and the data
+    #[default]
+    Binary,
+    /// One `HistoryEntry` JSON object per line, data as hex text
+    Ndjson,
+}
+
+/// Frames queued for the writer thread, further frames fail until it catches up
+const RECORD_QUEUE_CAPACITY: usize = 4096;
+
+/// Appends every frame passed to [`Recorder::record`] to a file.
+///
+/// Frames are encoded by the caller and written by a dedicated thread, so disk latency never
+/// blocks the transport. Dropping the recorder waits until every queued frame is written.
+///
+/// Attach it with `SerialPort::with_recorder`, `SocketServer::with_recorder` or
+/// `WebSocketServer::with_recorder`.
+#[derive(Debug)]
+pub struct Recorder {
+    sender: Option<mpsc::SyncSender<Vec<u8>>>,
+    writer: Option<std::thread::JoinHandle<()>>,
+    format: RecordFormat,
+}
+
+impl Recorder {
+    /// Create (or truncate) the recording at `path`
+    pub fn create(path: impl AsRef<Path>, format: RecordFormat) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(RECORD_QUEUE_CAPACITY);
+        let writer = std::thread::Builder::new()
+            .name("recorder".into())
+            .spawn(move || {
+                while let Ok(record) = receiver.recv() {
+                    // 队列排空后再 flush，崩溃时只丢失最后一批
+                    let result = std::iter::once(record)
+                        .chain(receiver.try_iter())
+                        .try_for_each(|record| file.write_all(&record))
+                        .and_then(|_| file.flush());
+                    if let Err(e) = result {
+                        tracing::error!("Failed to write recording: {}", e);
+                    }
+                }
+            })?;
+        Ok(Self {
+            sender: Some(sender),
+            writer: Some(writer),
+            format,
+        })
+    }
+
+    pub fn format(&self) -> RecordFormat {
+        self.format
+    }
+
+    /// Queue one timestamped frame for the writer thread.
+    ///
+    /// Fails when the frame can't be encoded or the writer falls more than
+    /// `RECORD_QUEUE_CAPACITY` frames behind; write errors are logged by the writer.
+    pub fn record(&self, direction: Direction, peer: Option<&str>, data: &[u8]) -> std::io::Result<()> {
+        let entry = HistoryEntry::new(direction, peer.map(String::from), data);
+        let mut record = Vec::new();
+        match self.format {
+            RecordFormat::Binary => {
+                let peer = entry.peer.as_deref().unwrap_or_default().as_bytes();
+                let peer_len = u16::try_from(peer.len()).map_err(|_| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "peer name too long")
+                })?;
+                let len = u32::try_from(8 + 1 + 2 + peer.len() + data.len()).map_err(|_| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "frame too large")
+                })?;
+                record.reserve(4 + len as usize);
+                record.extend_from_slice(&len.to_be_bytes());
+                record.extend_from_slice(&entry.timestamp.to_be_bytes());
+                record.push(match direction {
+                    Direction::In => 0,
+                    Direction::Out => 1,
+                });
+                record.extend_from_slice(&peer_len.to_be_bytes());
+                record.extend_from_slice(peer);
+                record.extend_from_slice(data);
+            }
+            RecordFormat::Ndjson => {
+                let entry = HistoryEntry {
+                    timestamp: entry.timestamp,
+                    direction,
+                    peer: entry.peer,
+                    data: hex_dump(data, HexFormat::default().with_separator("")),
+                };
+                serde_json::to_writer(&mut record, &entry)?;
+                record.push(b'\n');
+            }
+        }
+        let sender = self.sender.as_ref().expect("sender is only taken on drop");
+        sender.try_send(record).map_err(|e| match e {
+            mpsc::TrySendError::Full(_) => {
+                std::io::Error::new(std::io::ErrorKind::WouldBlock, "recording queue full")
+            }
+            mpsc::TrySendError::Disconnected(_) => {
+                std::io::Error::new(std::io::ErrorKind::BrokenPipe, "recorder stopped")
+            }
+        })
+    }
+
+    /// Like [`record`](Self::record), logging instead of returning the error; used by transports.
+    #[cfg(any(feature = "serialport", feature = "socket", feature = "websocket"))]
+    pub(crate) fn record_or_log(&self, direction: Direction, peer: Option<&str>, data: &[u8]) {
+        if let Err(e) = self.record(direction, peer, data) {
+            tracing::error!("Failed to record frame: {}", e);
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // 关闭队列，等写线程写完剩余的帧
+        drop(self.sender.take());
+        if let Some(writer) = self.writer.take()
+            && writer.join().is_err()
+        {
+            tracing::error!("Recorder writer thread panicked");
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Frames loaded from a recording made by [`Recorder`].
+#[derive(Debug, Clone, Default)]
+pub struct Replayer {
+    entries: Vec<HistoryEntry<Bytes>>,
+}
+
+impl Replayer {
+    pub fn open(path: impl AsRef<Path>, format: RecordFormat) -> std::io::Result<Self> {
+        Self::from_reader(File::open(path)?, format)
+    }
+
+    pub fn from_reader(reader: impl Read, format: RecordFormat) -> std::io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut entries = Vec::new();
+        match format {
+            RecordFormat::Binary => loop {
+                let mut len = [0u8; 4];
+                match reader.read_exact(&mut len) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                    Err(e) => return Err(e),
+                }
+                let mut record = vec![0u8; u32::from_be_bytes(len) as usize];
+                reader.read_exact(&mut record)?;
+                if record.len() < 11 {
+                    return Err(invalid_data("record too short"));
+                }
+                let timestamp = i64::from_be_bytes(record[..8].try_into().unwrap());
+                let direction = match record[8] {
+                    0 => Direction::In,
+                    1 => Direction::Out,
+                    _ => return Err(invalid_data("invalid direction")),
+                };
+                let peer_len = u16::from_be_bytes([record[9], record[10]]) as usize;
+                let data_start = 11 + peer_len;
+                if record.len() < data_start {
+                    return Err(invalid_data("record too short"));
+                }
+                let peer = (peer_len > 0)
+                    .then(|| String::from_utf8(record[11..data_start].to_vec()))
+                    .transpose()
+                    .map_err(|_| invalid_data("invalid peer"))?;
+                entries.push(HistoryEntry {
+                    timestamp,
+                    direction,
+                    peer,
+                    data: Bytes::copy_from_slice(&record[data_start..]),
+                });
+            },
+            RecordFormat::Ndjson => {
+                let mut line = String::new();
+                while reader.read_line(&mut line)? > 0 {
+                    if !line.trim().is_empty() {
+                        let entry: HistoryEntry<String> = serde_json::from_str(&line)?;
+                        let data = hex::parse(&entry.data).map_err(|e| invalid_data(&e.to_string()))?;
+                        entries.push(HistoryEntry {
+                            timestamp: entry.timestamp,
+                            direction: entry.direction,
+                            peer: entry.peer,
+                            data,
+                        });
+                    }
+                    line.clear();
+                }
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    /// All recorded frames in recording order
+    pub fn entries(&self) -> &[HistoryEntry<Bytes>] {
+        &self.entries
+    }
+
+    /// Frames received by the recorded transport
+    pub fn incoming(&self) -> impl Iterator<Item = &Bytes> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.direction == Direction::In)
+            .map(|entry| &entry.data)
+    }
+
+    /// Write the incoming frames to `writer`, keeping the recorded gaps when `realtime` is set.
+    pub async fn play<W: AsyncWrite + Unpin>(&self, writer: &mut W, realtime: bool) -> std::io::Result<()> {
+        let mut last_timestamp = None;
+        for entry in self.entries.iter().filter(|entry| entry.direction == Direction::In) {
+            if realtime && let Some(last) = last_timestamp {
+                let gap = entry.timestamp.saturating_sub(last).max(0) as u64;
+                tokio::time::sleep(Duration::from_millis(gap)).await;
+            }
+            last_timestamp = Some(entry.timestamp);
+            writer.write_all(&entry.data).await?;
+        }
+        writer.flush().await
+    }
+
+    /// Mock transport that yields the incoming frames then EOF, e.g. wrapped in a
+    /// `Framed` with the codec under test. The handle resolves to everything written
+    /// to the transport once it is dropped or shut down.
+    pub fn into_transport(self, realtime: bool) -> (DuplexStream, JoinHandle<std::io::Result<Bytes>>) {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let handle = tokio::spawn(async move {
+            let (mut reader, mut writer) = tokio::io::split(server);
+            let mut written = Vec::new();
+            let (played, read) = tokio::join!(
+                async {
+                    self.play(&mut writer, realtime).await?;
+                    writer.shutdown().await
+                },
+                reader.read_to_end(&mut written)
+            );
+            played?;
+            read?;
+            Ok(Bytes::from(written))
+        });
+        (client, handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::{Framed, LinesCodec};
+
+    use super::*;
+
+    fn record_path(format: RecordFormat) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("lean-link-recorder-{:?}-{}", format, uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        for format in [RecordFormat::Binary, RecordFormat::Ndjson] {
+            let path = record_path(format);
+            let recorder = Recorder::create(&path, format).unwrap();
+            recorder.record(Direction::In, None, b"hello\n").unwrap();
+            recorder.record(Direction::Out, Some("127.0.0.1:9000"), &[0x00, 0xff]).unwrap();
+            recorder.record(Direction::In, Some("127.0.0.1:9000"), b"world\n").unwrap();
+            drop(recorder);
+
+            let replayer = Replayer::open(&path, format).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let entries = replayer.entries();
+            assert_eq!(entries.len(), 3);
+            assert_eq!(entries[0].peer, None);
+            assert_eq!(entries[1].direction, Direction::Out);
+            assert_eq!(entries[1].peer.as_deref(), Some("127.0.0.1:9000"));
+            assert_eq!(entries[1].data, Bytes::from_static(&[0x00, 0xff]));
+            assert_eq!(replayer.incoming().count(), 2);
+
+            let (transport, handle) = replayer.into_transport(false);
+            let mut framed = Framed::new(transport, LinesCodec::new());
+            assert_eq!(framed.next().await.unwrap().unwrap(), "hello");
+            assert_eq!(framed.next().await.unwrap().unwrap(), "world");
+            assert!(framed.next().await.is_none());
+            framed.send("ack").await.unwrap();
+            drop(framed);
+            assert_eq!(handle.await.unwrap().unwrap(), Bytes::from_static(b"ack\n"));
+        }
+    }
+
+    #[test]
+    fn test_drop_writes_queued_frames() {
+        let path = record_path(RecordFormat::Binary);
+        let recorder = Recorder::create(&path, RecordFormat::Binary).unwrap();
+        for i in 0..1000u32 {
+            recorder.record(Direction::In, None, &i.to_be_bytes()).unwrap();
+        }
+        // drop 等待写线程写完队列中的帧
+        drop(recorder);
+
+        let replayer = Replayer::open(&path, RecordFormat::Binary).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replayer.entries().len(), 1000);
+        assert_eq!(replayer.entries()[999].data, Bytes::copy_from_slice(&999u32.to_be_bytes()));
+    }
+
+    #[test]
+    fn test_replay_truncated_record() {
+        let result = Replayer::from_reader(&[0u8, 0, 0, 20, 1, 2][..], RecordFormat::Binary);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,142 @@
+// Retry async fallible operations with exponential backoff.
+
+use std::{fmt::Display, future::Future, time::Duration};
+
+/// Retry policy for transient transport errors, with exponential backoff.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub max_retries: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before retry `attempt` (starting at 1)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+    }
+}
+
+/// Run `op` until it succeeds, retrying every `io` error up to `policy.max_retries` times.
+pub async fn retry<T, F, Fut>(policy: &ReconnectPolicy, op: F) -> std::io::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::io::Result<T>>,
+{
+    retry_if(policy, |_| true, op).await
+}
+
+/// Run `op` until it succeeds, retrying errors accepted by `is_retryable` up to
+/// `policy.max_retries` times. Other errors and the last failure are returned as is.
+pub async fn retry_if<T, E, P, F, Fut>(
+    policy: &ReconnectPolicy,
+    is_retryable: P,
+    mut op: F,
+) -> Result<T, E>
+where
+    E: Display,
+    P: Fn(&E) -> bool,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Err(e) if is_retryable(&e) && attempt < policy.max_retries => {
+                attempt += 1;
+                let delay = policy.delay(attempt);
+                tracing::warn!(
+                    "attempt failed: {}, retry {}/{} in {:?}",
+                    e,
+                    attempt,
+                    policy.max_retries,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => {
+                if attempt > 0 {
+                    tracing::error!("giving up after {} retries: {}", attempt, e);
+                }
+                break Err(e);
+            }
+            result => break result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    fn fast_policy() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reconnect_policy_delay() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(10), Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_retry_succeeds_after_failures() {
+        let calls = AtomicU32::new(0);
+        let result = retry(&fast_policy(), || async {
+            let calls = calls.fetch_add(1, Ordering::Relaxed) + 1;
+            if calls < 3 {
+                Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset))
+            } else {
+                Ok(calls)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up() {
+        let calls = AtomicU32::new(0);
+        let result: std::io::Result<()> = retry(&fast_policy(), || async {
+            calls.fetch_add(1, Ordering::Relaxed);
+            Err(std::io::Error::from(std::io::ErrorKind::TimedOut))
+        })
+        .await;
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(calls.load(Ordering::Relaxed), 4);
+
+        // 不可重试的错误立即返回
+        let calls = AtomicU32::new(0);
+        let result: Result<(), String> = retry_if(
+            &fast_policy(),
+            |e: &String| e != "fatal",
+            || async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err("fatal".to_string())
+            },
+        )
+        .await;
+        assert_eq!(result.unwrap_err(), "fatal");
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
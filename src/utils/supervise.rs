@@ -0,0 +1,205 @@
+// Restart background tasks that panic or exit.
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{sync::oneshot, task::JoinHandle, time::Instant};
+
+use crate::utils::retry::ReconnectPolicy;
+
+/// A run lasting this long counts as healthy and resets the restart budget
+const STABLE_RUN: Duration = Duration::from_secs(60);
+
+/// Why a supervised task was given up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskFailure {
+    pub name: String,
+    /// Restarts attempted before giving up
+    pub restarts: u32,
+    /// Panic message, or "exited" when the task returned
+    pub reason: String,
+}
+
+/// Handle of a task started with [`supervise`]
+pub struct Supervised {
+    pub handle: JoinHandle<()>,
+    /// Resolves once the restart budget is exhausted
+    pub failure: oneshot::Receiver<TaskFailure>,
+}
+
+/// Spawn the future made by `factory` and restart it whenever it panics or returns.
+///
+/// Restarts are delayed by `policy` (exponential backoff) and limited to
+/// `policy.max_retries` in a row; a run lasting over a minute resets the count. After the
+/// last failure the supervisor logs an error, sends a [`TaskFailure`] and stops.
+/// Aborting `handle` stops the supervisor, the running task keeps going until it ends.
+pub fn supervise<F, Fut>(
+    name: impl Into<String>,
+    policy: ReconnectPolicy,
+    mut factory: F,
+) -> Supervised
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let name = name.into();
+    let (failure_sender, failure) = oneshot::channel();
+    let handle = tokio::spawn(async move {
+        let mut restarts = 0;
+        loop {
+            let started = Instant::now();
+            let reason = match tokio::spawn(factory()).await {
+                Ok(()) => "exited".to_string(),
+                Err(e) if e.is_panic() => panic_message(e.into_panic()),
+                Err(e) => e.to_string(),
+            };
+            if started.elapsed() >= STABLE_RUN {
+                restarts = 0;
+            }
+
+            if restarts >= policy.max_retries {
+                tracing::error!(
+                    "Task {} failed ({}), giving up after {} restarts",
+                    name,
+                    reason,
+                    restarts
+                );
+                let _ = failure_sender.send(TaskFailure {
+                    name,
+                    restarts,
+                    reason,
+                });
+                return;
+            }
+
+            restarts += 1;
+            let delay = policy.delay(restarts);
+            tracing::warn!(
+                "Task {} failed ({}), restart {}/{} in {:?}",
+                name,
+                reason,
+                restarts,
+                policy.max_retries,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+    });
+    Supervised { handle, failure }
+}
+
+/// Latest listen loop started with [`supervise`] by a server, shared by the server's clones
+/// so its failure can still be observed
+#[derive(Clone, Default)]
+pub(crate) struct SupervisedSlot {
+    handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+    failure: Arc<Mutex<Option<oneshot::Receiver<TaskFailure>>>>,
+}
+
+impl SupervisedSlot {
+    pub(crate) fn set(&self, supervised: Supervised) {
+        *self.handle.lock().unwrap() = Some(supervised.handle);
+        *self.failure.lock().unwrap() = Some(supervised.failure);
+    }
+
+    /// Whether the supervisor stopped, i.e. gave up on the task
+    pub(crate) fn is_finished(&self) -> bool {
+        self.handle
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|handle| handle.is_finished())
+    }
+
+    pub(crate) fn take_failure(&self) -> Option<oneshot::Receiver<TaskFailure>> {
+        self.failure.lock().unwrap().take()
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "panicked".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    };
+
+    use super::*;
+
+    fn policy(max_retries: u32) -> ReconnectPolicy {
+        ReconnectPolicy {
+            max_retries,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restart_after_panic() {
+        let runs = Arc::new(AtomicU32::new(0));
+        let (done_sender, done) = tokio::sync::mpsc::channel(1);
+
+        let counter = runs.clone();
+        let supervised = supervise("flaky", policy(3), move || {
+            let run = counter.fetch_add(1, Ordering::SeqCst);
+            let done_sender = done_sender.clone();
+            async move {
+                if run == 0 {
+                    panic!("first run fails");
+                }
+                let _ = done_sender.send(()).await;
+                std::future::pending::<()>().await;
+            }
+        });
+
+        let mut done = done;
+        done.recv().await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        supervised.handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failure_reported() {
+        let supervised = supervise("broken", policy(2), || async {
+            panic!("always fails");
+        });
+        let failure = supervised.failure.await.unwrap();
+        assert_eq!(
+            failure,
+            TaskFailure {
+                name: "broken".into(),
+                restarts: 2,
+                reason: "always fails".into(),
+            }
+        );
+        supervised.handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_slot_keeps_failure() {
+        let slot = SupervisedSlot::default();
+        assert!(!slot.is_finished());
+        assert!(slot.take_failure().is_none());
+
+        slot.clone().set(supervise("listener", policy(0), || async {}));
+        let failure = slot.take_failure().unwrap().await.unwrap();
+        assert_eq!(failure.reason, "exited");
+        // 失败结果只能取走一次
+        assert!(slot.take_failure().is_none());
+        while !slot.is_finished() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// Decide at startup where the system time comes from.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Sys;
+
+/// Source the system time was taken from, resolved once by [`resolve_time_source`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeSource {
+    /// System time was set from the DS1307 RTC
+    Rtc,
+    /// NTP reports the clock as synchronized
+    Ntp,
+    /// The system clock is used as is and may be wrong
+    #[serde(alias = "system")]
+    SystemOnly,
+    /// No source in `sys.time_source_priority` was available
+    Failed,
+}
+
+/// Try each source of `priority` in order with `probe`, returning the first that succeeds.
+pub fn resolve_with<P>(priority: &[TimeSource], mut probe: P) -> TimeSource
+where
+    P: FnMut(TimeSource) -> Result<(), String>,
+{
+    for source in priority {
+        match source {
+            TimeSource::SystemOnly => return TimeSource::SystemOnly,
+            TimeSource::Failed => {}
+            source => match probe(*source) {
+                Ok(()) => return *source,
+                Err(e) => tracing::warn!("Time source {:?} unavailable: {}", source, e),
+            },
+        }
+    }
+    TimeSource::Failed
+}
+
+/// Resolve the time source from `sys.time_source_priority`, setting the system time from
+/// the RTC when it is selected. Non-Linux targets always use the system clock.
+#[cfg(target_os = "linux")]
+pub fn resolve_time_source(sys: &Sys) -> TimeSource {
+    use crate::utils::{datetime::set_local_time_from_ds1307, i2c::path_to_i2c_bus};
+
+    let source = resolve_with(&sys.time_source_priority(), |source| match source {
+        TimeSource::Rtc => {
+            let bus = path_to_i2c_bus(&sys.rtc_i2c_dev)?;
+            set_local_time_from_ds1307(bus, sys.rtc_i2c_addr)
+        }
+        TimeSource::Ntp => ntp_synchronized(),
+        _ => Ok(()),
+    });
+    if source == TimeSource::Failed {
+        tracing::error!("No time source available, system time may be wrong");
+    } else {
+        tracing::info!("Time source: {:?}", source);
+    }
+    source
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn resolve_time_source(_sys: &Sys) -> TimeSource {
+    TimeSource::SystemOnly
+}
+
+#[cfg(target_os = "linux")]
+fn ntp_synchronized() -> Result<(), String> {
+    let output = std::process::Command::new("timedatectl")
+        .args(["show", "--property=NTPSynchronized", "--value"])
+        .output()
+        .map_err(|e| format!("failed to spawn 'timedatectl': {}", e))?;
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "yes" => Ok(()),
+        value => Err(format!("NTPSynchronized={}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_with_priority() {
+        let priority = [TimeSource::Rtc, TimeSource::Ntp, TimeSource::SystemOnly];
+
+        let mut probed = Vec::new();
+        let source = resolve_with(&priority, |source| {
+            probed.push(source);
+            match source {
+                TimeSource::Rtc => Err("no rtc".into()),
+                _ => Ok(()),
+            }
+        });
+        assert_eq!(source, TimeSource::Ntp);
+        assert_eq!(probed, [TimeSource::Rtc, TimeSource::Ntp]);
+
+        let source = resolve_with(&priority, |_| Err("down".into()));
+        assert_eq!(source, TimeSource::SystemOnly);
+
+        let source = resolve_with(&[TimeSource::Ntp, TimeSource::Rtc], |_| Err("down".into()));
+        assert_eq!(source, TimeSource::Failed);
+        assert_eq!(resolve_with(&[], |_| Ok(())), TimeSource::Failed);
+    }
+
+    #[test]
+    fn test_priority_from_config() {
+        let sys: Sys = serde_json::from_value(serde_json::json!({
+            "time_source_priority": ["ntp", "rtc", "system"]
+        }))
+        .unwrap();
+        assert_eq!(
+            sys.time_source_priority(),
+            [TimeSource::Ntp, TimeSource::Rtc, TimeSource::SystemOnly]
+        );
+
+        // 未配置时沿用 sync_time_from_rtc
+        let sys: Sys = serde_json::from_value(serde_json::json!({ "sync_time_from_rtc": true })).unwrap();
+        assert_eq!(
+            sys.time_source_priority(),
+            [TimeSource::Rtc, TimeSource::Ntp, TimeSource::SystemOnly]
+        );
+        assert_eq!(
+            Sys::default().time_source_priority(),
+            [TimeSource::Ntp, TimeSource::SystemOnly]
+        );
+    }
+}